@@ -0,0 +1,62 @@
+use benches::fixtures::{self, Fixture};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use service::{
+    parallel::read_source_parallel,
+    pipeline::read_pipelined,
+    service::{read_transactions, CsvSource, ParseMode},
+    sharded::read_source_sharded,
+};
+
+const WORKERS: usize = 4;
+
+fn bench_sequential(c: &mut Criterion, group: &str, fixtures: &[Fixture]) {
+    let mut group = c.benchmark_group(group);
+    group.sample_size(10);
+    for fixture in fixtures {
+        group.throughput(Throughput::Elements(fixture.rows));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(&fixture.name),
+            &fixture.csv,
+            |b, csv| b.iter(|| read_transactions(csv.as_slice()).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+fn ingest(c: &mut Criterion) {
+    let fixture = fixtures::uniform();
+    let mut group = c.benchmark_group("ingest");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(fixture.rows));
+    let csv = fixture.csv.as_slice();
+    group.bench_function("sequential", |b| b.iter(|| read_transactions(csv).unwrap()));
+    group.bench_function("threads", |b| {
+        b.iter(|| read_source_parallel(CsvSource::new(csv), WORKERS).unwrap())
+    });
+    group.bench_function("rayon", |b| {
+        b.iter(|| read_source_sharded(CsvSource::new(csv), WORKERS).unwrap())
+    });
+    group.bench_function("pipelined", |b| {
+        b.iter(|| {
+            read_pipelined(
+                || Ok(CsvSource::new(csv)),
+                ParseMode::Strict,
+                domain::domain::Accounts::new(),
+                WORKERS,
+            )
+            .unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn disputes(c: &mut Criterion) {
+    bench_sequential(c, "disputes", &fixtures::dispute_heavy());
+}
+
+fn clients(c: &mut Criterion) {
+    bench_sequential(c, "clients", &fixtures::client_distributions());
+}
+
+criterion_group!(benches, ingest, disputes, clients);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+pub mod fixtures {
+    use service::generate::{generate, GeneratorOptions};
+
+    pub const ROWS: u64 = 100_000;
+
+    pub struct Fixture {
+        pub name: String,
+        pub rows: u64,
+        pub csv: Vec<u8>,
+    }
+
+    impl Fixture {
+        pub fn new(name: &str, clients: u16, rows: u64, dispute_rate: f64) -> Fixture {
+            let mut csv = Vec::new();
+            generate(
+                &mut csv,
+                &GeneratorOptions {
+                    clients,
+                    transactions: rows,
+                    dispute_rate,
+                    seed: 0,
+                },
+            )
+            .expect("fixture is generated");
+            Fixture {
+                name: name.to_string(),
+                rows,
+                csv,
+            }
+        }
+    }
+
+    pub fn uniform() -> Fixture {
+        Fixture::new("uniform", 1000, ROWS, 0.01)
+    }
+
+    // the generator caps the dispute rate at 0.5
+    pub fn dispute_heavy() -> Vec<Fixture> {
+        [0.0, 0.1, 0.25, 0.5]
+            .into_iter()
+            .map(|x| Fixture::new(&format!("dispute_rate_{}", x), 1000, ROWS, x))
+            .collect()
+    }
+
+    pub fn client_distributions() -> Vec<Fixture> {
+        [10, 1000, 60_000]
+            .into_iter()
+            .map(|x| Fixture::new(&format!("clients_{}", x), x, ROWS, 0.01))
+            .collect()
+    }
+}
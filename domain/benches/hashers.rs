@@ -0,0 +1,70 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::BuildHasher,
+    hint::black_box,
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use domain::domain::{Accounts, FxHashMap, FxHashSet, Transaction};
+use rust_decimal_macros::dec;
+
+const IDS: u32 = 100_000;
+
+// every tenth tx id is a duplicate of an earlier one
+fn tx_ids() -> Vec<u32> {
+    (0..IDS)
+        .map(|x| if x % 10 == 9 { x / 2 } else { x })
+        .collect()
+}
+
+fn dedup<S: BuildHasher>(ids: &[u32], mut set: HashSet<u32, S>) -> usize {
+    ids.iter().filter(|x| set.insert(**x)).count()
+}
+
+fn log<S: BuildHasher>(ids: &[u32], mut map: HashMap<u32, u32, S>) -> u32 {
+    for tx in ids {
+        map.insert(*tx, *tx);
+    }
+    ids.iter().filter_map(|x| map.get(x)).sum()
+}
+
+fn hashers(c: &mut Criterion) {
+    let ids = tx_ids();
+    let mut group = c.benchmark_group("tx_dedup");
+    group.bench_function(BenchmarkId::new("siphash", IDS), |b| {
+        b.iter(|| dedup(black_box(&ids), HashSet::new()))
+    });
+    group.bench_function(BenchmarkId::new("fxhash", IDS), |b| {
+        b.iter(|| dedup(black_box(&ids), FxHashSet::default()))
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("transaction_log");
+    group.bench_function(BenchmarkId::new("siphash", IDS), |b| {
+        b.iter(|| log(black_box(&ids), HashMap::new()))
+    });
+    group.bench_function(BenchmarkId::new("fxhash", IDS), |b| {
+        b.iter(|| log(black_box(&ids), FxHashMap::default()))
+    });
+    group.finish();
+}
+
+fn accounts(c: &mut Criterion) {
+    let ids = tx_ids();
+    c.bench_function("accounts_add_transaction", |b| {
+        b.iter(|| {
+            let mut accounts = Accounts::new();
+            for tx in &ids {
+                let transaction = match tx % 4 {
+                    3 => Transaction::Withdrawal { amount: dec!(1.0) },
+                    _ => Transaction::Deposit { amount: dec!(2.0) },
+                };
+                accounts.add_transaction((tx % 1000) as u16, *tx, transaction);
+            }
+            accounts
+        })
+    });
+}
+
+criterion_group!(benches, hashers, accounts);
+criterion_main!(benches);
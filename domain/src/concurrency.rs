@@ -0,0 +1,71 @@
+use std::{collections::HashSet, sync::Mutex};
+
+use crate::domain::{opens_transaction, RegistryHasher, Transaction};
+
+const SHARDS: usize = 64;
+
+// A tx id registry that threads can share, for workers applying the transactions of different
+// clients that must still not reuse each other's tx ids: a worker registers the tx id of a
+// deposit, withdrawal or hold before applying it and skips it as a duplicate when that fails.
+// The ids are spread over shards with a lock each, so registering only waits on ids of the same
+// shard, and only for the insert.
+pub struct SharedRegistry {
+    shards: Vec<Mutex<HashSet<u32, RegistryHasher>>>,
+}
+
+impl Default for SharedRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SharedRegistry {
+    pub fn new() -> SharedRegistry {
+        SharedRegistry::with_hasher(RegistryHasher::default())
+    }
+
+    pub fn with_hasher(hasher: RegistryHasher) -> SharedRegistry {
+        SharedRegistry {
+            shards: (0..SHARDS)
+                .map(|_| Mutex::new(HashSet::with_hasher(hasher.clone())))
+                .collect(),
+        }
+    }
+
+    fn shard(&self, tx: u32) -> &Mutex<HashSet<u32, RegistryHasher>> {
+        &self.shards[tx as usize % SHARDS]
+    }
+
+    // returns false if the transaction is a deposit, withdrawal or hold with an already used tx
+    // id, like `TransactionRegistry::register`
+    pub fn register(&self, tx: u32, transaction: &Transaction) -> bool {
+        !opens_transaction(transaction) || self.insert(tx)
+    }
+
+    // false if the tx id was already registered
+    pub fn insert(&self, tx: u32) -> bool {
+        self.shard(tx).lock().unwrap().insert(tx)
+    }
+
+    pub fn contains(&self, tx: u32) -> bool {
+        self.shard(tx).lock().unwrap().contains(&tx)
+    }
+
+    // the shards are counted one at a time
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|x| x.lock().unwrap().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // the ids in no particular order; the shards are copied one at a time, so ids registered
+    // meanwhile may be missing
+    pub fn ids(&self) -> Vec<u32> {
+        self.shards
+            .iter()
+            .flat_map(|x| x.lock().unwrap().iter().copied().collect::<Vec<_>>())
+            .collect()
+    }
+}
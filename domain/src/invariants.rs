@@ -0,0 +1,207 @@
+use core::{error::Error, fmt};
+
+use rust_decimal::Decimal;
+
+use crate::domain::{
+    AccountStore, Accounts, TransactionActionState, TransactionState, UserAccount,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum Violation {
+    NegativeHeld {
+        client: u16,
+        held: Decimal,
+    },
+    // the balances differ from what the transaction log adds up to
+    BalanceMismatch {
+        client: u16,
+        available: Decimal,
+        held: Decimal,
+        expected_available: Decimal,
+        expected_held: Decimal,
+    },
+    // a pending hold whose log entry is not held, or a held entry without a pending hold
+    PendingHoldMismatch {
+        client: u16,
+        tx: u32,
+    },
+    LockedAccountChanged {
+        client: u16,
+    },
+    // the open dispute or chargeback counts differ from the states in the transaction log
+    CountMismatch {
+        client: u16,
+    },
+    NegativeTotal {
+        client: u16,
+        total: Decimal,
+    },
+    LockedWithoutChargeback {
+        client: u16,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::NegativeHeld { client, held } => {
+                write!(f, "client {} has negative held funds {}", client, held)
+            }
+            Violation::BalanceMismatch {
+                client,
+                available,
+                held,
+                expected_available,
+                expected_held,
+            } => write!(
+                f,
+                "client {} has available {} and held {}, its transaction log adds up to {} and {}",
+                client, available, held, expected_available, expected_held
+            ),
+            Violation::PendingHoldMismatch { client, tx } => {
+                write!(
+                    f,
+                    "client {} has an inconsistent pending hold {}",
+                    client, tx
+                )
+            }
+            Violation::LockedAccountChanged { client } => {
+                write!(f, "locked account of client {} changed", client)
+            }
+            Violation::CountMismatch { client } => write!(
+                f,
+                "client {} has dispute or chargeback counts that differ from its transaction log",
+                client
+            ),
+            Violation::NegativeTotal { client, total } => {
+                write!(f, "client {} has a negative total {}", client, total)
+            }
+            Violation::LockedWithoutChargeback { client } => {
+                write!(f, "client {} is locked without a chargeback", client)
+            }
+        }
+    }
+}
+
+impl Error for Violation {}
+
+// Returns the first violated invariant. The balances are compared with the transaction log,
+// so accounts restored from balances without their log (`restore_user_account`) only pass
+// while their log is empty.
+pub fn check<S: AccountStore>(accounts: &Accounts<S>) -> Result<(), Violation> {
+    for (client, account) in accounts.iter() {
+        check_account(client, &account)?;
+    }
+    Ok(())
+}
+
+pub fn check_account(client: u16, account: &UserAccount) -> Result<(), Violation> {
+    if account.held < Decimal::ZERO {
+        return Err(Violation::NegativeHeld {
+            client,
+            held: account.held,
+        });
+    }
+    if account.transaction_log.is_empty() {
+        return Ok(());
+    }
+
+    let (mut expected_available, mut expected_held) = (Decimal::ZERO, Decimal::ZERO);
+    let (mut open_disputes, mut chargebacks) = (0, 0);
+    for (tx, log) in &account.transaction_log {
+        match log.state {
+            TransactionState::Dispute => open_disputes += 1,
+            TransactionState::Chargeback | TransactionState::Refunded => chargebacks += 1,
+            _ => {}
+        }
+        match (&log.amount, &log.state) {
+            (TransactionActionState::Deposit { amount }, TransactionState::Resolve) => {
+                expected_available += amount
+            }
+            (TransactionActionState::Deposit { amount }, TransactionState::Dispute) => {
+                expected_held += amount
+            }
+            (TransactionActionState::Withdrawal { amount }, TransactionState::Dispute)
+            | (TransactionActionState::Hold { amount }, TransactionState::Held) => {
+                expected_available -= amount;
+                expected_held += amount;
+            }
+            // the refund cancels out the withdrawal
+            (TransactionActionState::Withdrawal { .. }, TransactionState::Refunded) => {}
+            (TransactionActionState::Withdrawal { amount }, _)
+            | (TransactionActionState::Hold { amount }, TransactionState::Captured) => {
+                expected_available -= amount
+            }
+            _ => {}
+        }
+        let held = matches!(log.state, TransactionState::Held);
+        if held != account.pending_holds.contains_key(tx) {
+            return Err(Violation::PendingHoldMismatch { client, tx: *tx });
+        }
+    }
+    if let Some(tx) = account
+        .pending_holds
+        .keys()
+        .find(|x| !account.transaction_log.contains_key(*x))
+    {
+        return Err(Violation::PendingHoldMismatch { client, tx: *tx });
+    }
+
+    if open_disputes != account.open_disputes() || chargebacks != account.chargeback_count() {
+        return Err(Violation::CountMismatch { client });
+    }
+
+    if account.available != expected_available || account.held != expected_held {
+        return Err(Violation::BalanceMismatch {
+            client,
+            available: account.available,
+            held: account.held,
+            expected_available,
+            expected_held,
+        });
+    }
+    Ok(())
+}
+
+// The checks cheap enough to run after every transaction, unlike `check_account` they don't go
+// through the transaction log. A chargeback of a deposit that was already withdrawn leaves a
+// negative total, which is only a violation when it isn't allowed.
+pub fn check_balances(
+    client: u16,
+    account: &UserAccount,
+    allow_negative_total: bool,
+) -> Result<(), Violation> {
+    if account.held < Decimal::ZERO {
+        return Err(Violation::NegativeHeld {
+            client,
+            held: account.held,
+        });
+    }
+    let total = account.available + account.held;
+    if !allow_negative_total && total < Decimal::ZERO {
+        return Err(Violation::NegativeTotal { client, total });
+    }
+    Ok(())
+}
+
+// for an account a transaction just locked; `lock_account` locks without a chargeback
+pub fn check_locked(client: u16, account: &UserAccount) -> Result<(), Violation> {
+    if account.locked && account.chargeback_count() == 0 {
+        return Err(Violation::LockedWithoutChargeback { client });
+    }
+    Ok(())
+}
+
+// every account locked in `before` must be unchanged in `after`, which only holds with the
+// default `LockedAccountPolicy`
+pub fn check_locked_unchanged<S: AccountStore, T: AccountStore>(
+    before: &Accounts<S>,
+    after: &Accounts<T>,
+) -> Result<(), Violation> {
+    for (client, account) in before.iter().filter(|x| x.1.locked) {
+        if after.get_user_account(client).as_deref() != Some(&*account) {
+            return Err(Violation::LockedAccountChanged { client });
+        }
+    }
+    Ok(())
+}
@@ -1,499 +1,3549 @@
+// Without the default `std` feature the account state machine only needs `alloc`, e.g. on an
+// embedded target; `SharedAccounts` needs the std locks and is left out.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod concurrency;
+pub mod invariants;
+
 pub mod domain {
-    use std::collections::{hash_map::Iter, HashMap, HashSet};
+    use alloc::{
+        string::{String, ToString},
+        sync::Arc,
+        vec::Vec,
+    };
+    use core::{
+        error::Error,
+        fmt,
+        hash::{BuildHasher, Hasher},
+        ops::Deref,
+    };
+    #[cfg(not(feature = "std"))]
+    use hashbrown::{
+        hash_map::{IntoIter, Iter},
+        HashMap, HashSet,
+    };
+    #[cfg(feature = "std")]
+    use std::{
+        collections::{
+            hash_map::{Entry, IntoIter, Iter},
+            HashMap, HashSet,
+        },
+        hash::{DefaultHasher, RandomState},
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Mutex, RwLock,
+        },
+    };
 
-    use rust_decimal::Decimal;
+    use rust_decimal::{Decimal, RoundingStrategy};
     use rust_decimal_macros::dec;
+    use rustc_hash::FxHasher;
+    // client and tx ids are small integers, where FxHash is much cheaper than SipHash
+    #[cfg(feature = "std")]
+    pub use rustc_hash::{FxHashMap, FxHashSet};
+    use serde::{Deserialize, Serialize};
 
+    #[cfg(feature = "std")]
+    use crate::concurrency::SharedRegistry;
+
+    // std's maps are hashbrown's, which works with alloc alone
+    #[cfg(not(feature = "std"))]
+    pub type FxHashMap<K, V> = HashMap<K, V, rustc_hash::FxBuildHasher>;
+    #[cfg(not(feature = "std"))]
+    pub type FxHashSet<V> = HashSet<V, rustc_hash::FxBuildHasher>;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
     pub enum Transaction {
         Deposit { amount: Decimal },
         Withdrawal { amount: Decimal },
         Dispute,
         Resolve,
         Chargeback,
+        Hold { amount: Decimal, expires_after: u32 },
+        Capture,
+        Release,
     }
 
-    #[derive(Debug, PartialEq)]
+    // the input type name and amount, e.g. `deposit 1.5` or `hold 2 expiring after 10`
+    impl fmt::Display for Transaction {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Transaction::Deposit { amount } => write!(f, "deposit {}", amount),
+                Transaction::Withdrawal { amount } => write!(f, "withdrawal {}", amount),
+                Transaction::Dispute => f.write_str("dispute"),
+                Transaction::Resolve => f.write_str("resolve"),
+                Transaction::Chargeback => f.write_str("chargeback"),
+                Transaction::Hold {
+                    amount,
+                    expires_after,
+                } => write!(f, "hold {} expiring after {}", amount, expires_after),
+                Transaction::Capture => f.write_str("capture"),
+                Transaction::Release => f.write_str("release"),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
     pub enum TransactionState {
         Resolve,
         Dispute,
         Chargeback,
+        // a charged back withdrawal whose amount went back to available
+        Refunded,
+        Held,
+        Captured,
+        Released,
     }
 
-    #[derive(Debug, PartialEq)]
+    impl fmt::Display for TransactionState {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(match self {
+                TransactionState::Resolve => "resolve",
+                TransactionState::Dispute => "dispute",
+                TransactionState::Chargeback => "chargeback",
+                TransactionState::Refunded => "refunded",
+                TransactionState::Held => "held",
+                TransactionState::Captured => "captured",
+                TransactionState::Released => "released",
+            })
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
     pub enum TransactionActionState {
         Deposit { amount: Decimal },
         Withdrawal { amount: Decimal },
+        Hold { amount: Decimal },
     }
 
-    #[derive(Debug, PartialEq)]
+    impl fmt::Display for TransactionActionState {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                TransactionActionState::Deposit { amount } => write!(f, "deposit {}", amount),
+                TransactionActionState::Withdrawal { amount } => {
+                    write!(f, "withdrawal {}", amount)
+                }
+                TransactionActionState::Hold { amount } => write!(f, "hold {}", amount),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
     pub struct TransactionLog {
         pub amount: TransactionActionState,
         pub state: TransactionState,
+        // of the last transaction applied to the entry, 0 when unknown (e.g. loaded from a database)
+        #[serde(default)]
+        pub seq: u64,
     }
 
-    pub struct Accounts {
-        user_accounts: HashMap<u16, UserAccount>,
-        transaction_ids: HashSet<u32>,
+    // e.g. `deposit 1.5 (dispute)`
+    impl fmt::Display for TransactionLog {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{} ({})", self.amount, self.state)
+        }
     }
 
-    impl Accounts {
-        pub fn new() -> Accounts {
-            Accounts {
-                user_accounts: HashMap::new(),
-                transaction_ids: HashSet::new(),
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum RejectionReason {
+        DuplicateTransaction,
+        InsufficientFunds,
+        AccountLocked,
+        AccountNotFound,
+        UnknownTransaction,
+        InvalidTransactionState,
+        AmountOverflow,
+        // by a `Middleware`
+        Declined,
+        // by `Limits`
+        AmountLimitExceeded,
+        BalanceLimitExceeded,
+        // with `OrderingPolicy::Strict`, a reference to a tx id that wasn't seen yet
+        OutOfOrder,
+        // with `DuplicatePolicy::Error`, a reused tx id with a different client or payload
+        ConflictingDuplicate,
+    }
+
+    impl fmt::Display for RejectionReason {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(match self {
+                RejectionReason::DuplicateTransaction => "duplicate_transaction",
+                RejectionReason::InsufficientFunds => "insufficient_funds",
+                RejectionReason::AccountLocked => "account_locked",
+                RejectionReason::AccountNotFound => "account_not_found",
+                RejectionReason::UnknownTransaction => "unknown_transaction",
+                RejectionReason::InvalidTransactionState => "invalid_transaction_state",
+                RejectionReason::AmountOverflow => "amount_overflow",
+                RejectionReason::Declined => "declined",
+                RejectionReason::AmountLimitExceeded => "amount_limit_exceeded",
+                RejectionReason::BalanceLimitExceeded => "balance_limit_exceeded",
+                RejectionReason::OutOfOrder => "out_of_order",
+                RejectionReason::ConflictingDuplicate => "conflicting_duplicate",
+            })
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum TransactionOutcome {
+        Applied,
+        Rejected(RejectionReason),
+    }
+
+    impl fmt::Display for TransactionOutcome {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                TransactionOutcome::Applied => f.write_str("applied"),
+                TransactionOutcome::Rejected(reason) => write!(f, "rejected: {}", reason),
             }
         }
+    }
 
-        pub fn get_user_accounts(&self) -> Iter<u16, UserAccount> {
-            self.user_accounts.iter()
+    #[derive(Debug, PartialEq)]
+    pub struct IdempotencyConflict {
+        pub key: String,
+    }
+
+    impl fmt::Display for IdempotencyConflict {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "idempotency key {} is already used by a different transaction",
+                self.key
+            )
         }
+    }
 
-        pub fn get_user_account(&self, client: u16) -> Option<&UserAccount> {
-            self.user_accounts.get(&client)
+    impl Error for IdempotencyConflict {}
+
+    #[derive(Debug, PartialEq)]
+    pub struct MergeConflict {
+        pub client: u16,
+    }
+
+    impl fmt::Display for MergeConflict {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "client {} exists in both accounts", self.client)
         }
+    }
 
-        pub fn add_transaction(&mut self, client: u16, tx: u32, transaction: Transaction) {
-            if (matches!(transaction, Transaction::Deposit { amount: _ })
-                || matches!(transaction, Transaction::Withdrawal { amount: _ }))
-                && !self.transaction_ids.insert(tx)
-            {
-                return;
-            }
+    impl Error for MergeConflict {}
 
-            if let Some(x) = self.user_accounts.get_mut(&client) {
-                x.change_account_state(tx, transaction);
-            } else if let Some(account) = UserAccount::new(tx, transaction) {
-                self.user_accounts.insert(client, account);
-            }
+    // An operation the `Transaction` enum doesn't cover, registered on `Accounts` under its type
+    // name. The handler changes the client's account directly and is responsible for keeping its
+    // balances and log consistent; unknown clients and locked accounts reject the transaction
+    // before the handler is called.
+    pub trait TransactionHandler: Send + Sync {
+        fn apply(
+            &self,
+            account: &mut UserAccount,
+            tx: u32,
+            amount: Option<Decimal>,
+        ) -> TransactionOutcome;
+
+        // whether the tx id is a new transaction, registered like a deposit's, instead of a
+        // reference to an earlier one
+        fn opens_transaction(&self) -> bool {
+            false
         }
     }
 
-    #[derive(Debug, PartialEq)]
-    pub struct UserAccount {
-        pub available: Decimal,
-        pub held: Decimal,
-        pub locked: bool,
-        pub transaction_log: HashMap<u32, TransactionLog>,
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub enum DisputePolicy {
+        #[default]
+        DepositsAndWithdrawals,
+        // disputes of withdrawals are rejected as an invalid transaction state
+        DepositsOnly,
+        // as `DepositsAndWithdrawals`, but a chargeback of a withdrawal also returns the
+        // withdrawn amount to available instead of only releasing the held amount
+        DepositsAndRefundedWithdrawals,
     }
 
-    impl UserAccount {
-        fn new(tx: u32, transaction: Transaction) -> Option<UserAccount> {
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub enum LockedAccountPolicy {
+        #[default]
+        RejectAll,
+        // resolves and chargebacks are still applied, so disputes open when the account was
+        // locked don't hold their funds forever; everything else is rejected
+        SettleOpenDisputes,
+    }
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub enum OrderingPolicy {
+        // a dispute, resolve, chargeback, capture or release of an unknown tx is rejected as
+        // `UnknownTransaction`, whether or not the tx comes later
+        #[default]
+        Lenient,
+        // those referring to a tx id that no transaction used yet are rejected as `OutOfOrder`
+        // instead, so input files with references ahead of their transaction can be told apart
+        Strict,
+    }
+
+    // What a deposit, withdrawal or hold reusing a tx id does. The original is looked up in the
+    // account logs; when it isn't logged any more (e.g. its client was forgotten or restored
+    // from an output) the duplicate is always rejected as `DuplicateTransaction`.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub enum DuplicatePolicy {
+        // rejected as `DuplicateTransaction`, whatever its payload
+        #[default]
+        Ignore,
+        // as `Ignore` for a replay of the original, but one with a different client, type or
+        // amount is rejected as `ConflictingDuplicate`
+        Error,
+        // the original is undone and the duplicate applied in its place; only an undisputed
+        // deposit or withdrawal of an unlocked account can be replaced, other originals reject
+        // the duplicate as `InvalidTransactionState`. The original is kept when the duplicate is
+        // rejected.
+        LastWriteWins,
+    }
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub enum FirstTransactionPolicy {
+        // the first record of a client must be a deposit, anything else is rejected as
+        // `AccountNotFound` and leaves no account behind
+        #[default]
+        DepositOnly,
+        // any first record creates a zero-balance account and is applied to it, so a leading
+        // withdrawal or dispute is recorded as a rejection and the client is still in the output
+        AnyRecord,
+    }
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    pub enum PrecisionPolicy {
+        // amounts are applied as given, only the outputs are rounded
+        #[default]
+        Exact,
+        // amounts are rounded before they are applied, so balances never have more places
+        Round {
+            decimal_places: u32,
+            strategy: RoundingStrategy,
+        },
+    }
+
+    impl PrecisionPolicy {
+        fn apply(&self, transaction: Transaction) -> Transaction {
+            let PrecisionPolicy::Round {
+                decimal_places,
+                strategy,
+            } = *self
+            else {
+                return transaction;
+            };
+            let round = |x: Decimal| x.round_dp_with_strategy(decimal_places, strategy);
             match transaction {
-                Transaction::Deposit { amount } => Option::Some(UserAccount {
-                    available: amount,
-                    held: dec!(0),
-                    locked: false,
-                    transaction_log: HashMap::from([(
-                        tx,
-                        TransactionLog {
-                            amount: TransactionActionState::Deposit { amount: amount },
-                            state: TransactionState::Resolve,
-                        },
-                    )]),
-                }),
-                _ => Option::None,
+                Transaction::Deposit { amount } => Transaction::Deposit {
+                    amount: round(amount),
+                },
+                Transaction::Withdrawal { amount } => Transaction::Withdrawal {
+                    amount: round(amount),
+                },
+                Transaction::Hold {
+                    amount,
+                    expires_after,
+                } => Transaction::Hold {
+                    amount: round(amount),
+                    expires_after,
+                },
+                x => x,
             }
         }
+    }
 
-        fn change_account_state(&mut self, tx: u32, transaction: Transaction) {
-            if self.locked {
-                return;
+    // Caps on deposits, withdrawals and holds, None is unlimited. `max_total` is only checked
+    // by deposits, so a dispute or a refund can still take the total above it.
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    pub struct Limits {
+        pub max_amount: Option<Decimal>,
+        pub max_total: Option<Decimal>,
+    }
+
+    impl Limits {
+        fn exceeded(&self, total: Decimal, transaction: &Transaction) -> Option<RejectionReason> {
+            let (Transaction::Deposit { amount }
+            | Transaction::Withdrawal { amount }
+            | Transaction::Hold { amount, .. }) = transaction
+            else {
+                return None;
+            };
+            if self.max_amount.is_some_and(|x| *amount > x) {
+                return Some(RejectionReason::AmountLimitExceeded);
             }
-            match transaction {
-                Transaction::Deposit { amount } => {
-                    self.transaction_log.insert(
-                        tx,
-                        TransactionLog {
-                            amount: TransactionActionState::Deposit { amount: amount },
-                            state: TransactionState::Resolve,
-                        },
-                    );
-                    self.available = self.available + amount;
-                }
+            let deposit = matches!(transaction, Transaction::Deposit { .. });
+            let over_total = |max| total.checked_add(*amount).is_none_or(|x| x > max);
+            (deposit && self.max_total.is_some_and(over_total))
+                .then_some(RejectionReason::BalanceLimitExceeded)
+        }
+    }
 
-                Transaction::Dispute => {
-                    if let Some(x) = self.transaction_log.get_mut(&tx) {
-                        if matches!(x.state, TransactionState::Resolve) {
-                            match x.amount {
-                                TransactionActionState::Deposit { amount } => {
-                                    *x = TransactionLog {
-                                        amount: TransactionActionState::Deposit { amount: amount },
-                                        state: TransactionState::Dispute,
-                                    };
-                                    self.available = self.available - amount;
-                                    self.held = self.held + amount;
-                                }
-                                TransactionActionState::Withdrawal { amount } => {
-                                    *x = TransactionLog {
-                                        amount: TransactionActionState::Withdrawal {
-                                            amount: amount,
-                                        },
-                                        state: TransactionState::Dispute,
-                                    };
-                                    self.held = self.held + amount;
-                                }
-                            }
-                        }
-                    }
-                }
+    // How `Accounts` applies transactions, the defaults are the behavior of `Accounts::new()`
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    pub struct Policies {
+        pub dispute: DisputePolicy,
+        pub precision: PrecisionPolicy,
+        pub locked_account: LockedAccountPolicy,
+        pub first_transaction: FirstTransactionPolicy,
+        pub limits: Limits,
+        pub ordering: OrderingPolicy,
+        pub duplicate: DuplicatePolicy,
+    }
 
-                Transaction::Resolve => {
-                    if let Some(x) = self.transaction_log.get_mut(&tx) {
-                        if matches!(x.state, TransactionState::Dispute) {
-                            match x.amount {
-                                TransactionActionState::Deposit { amount } => {
-                                    *x = TransactionLog {
-                                        amount: TransactionActionState::Deposit { amount: amount },
-                                        state: TransactionState::Resolve,
-                                    };
-                                    self.available = self.available + amount;
-                                    self.held = self.held - amount;
-                                }
-                                TransactionActionState::Withdrawal { amount } => {
-                                    *x = TransactionLog {
-                                        amount: TransactionActionState::Withdrawal {
-                                            amount: amount,
-                                        },
-                                        state: TransactionState::Resolve,
-                                    };
-                                    self.held = self.held - amount;
-                                }
-                            }
-                        }
-                    }
-                }
+    // The hasher of the tx id registry, the one map whose keys come from the input without bound.
+    // FxHash is fast but predictable, so untrusted inputs can pick colliding tx ids or idempotency
+    // keys; SipHash with random keys (`RegistryHasher::sip()`, needs std) can't be flooded that way.
+    #[derive(Debug, Clone, Default)]
+    pub enum RegistryHasher {
+        #[default]
+        Fx,
+        #[cfg(feature = "std")]
+        Sip(RandomState),
+    }
 
-                Transaction::Chargeback => {
-                    if let Some(x) = self.transaction_log.get_mut(&tx) {
-                        if matches!(x.state, TransactionState::Dispute) {
-                            match x.amount {
-                                TransactionActionState::Deposit { amount } => {
-                                    *x = TransactionLog {
-                                        amount: TransactionActionState::Deposit { amount: amount },
-                                        state: TransactionState::Chargeback,
-                                    };
-                                    self.held = self.held - amount;
-                                    self.locked = true;
-                                }
-                                TransactionActionState::Withdrawal { amount } => {
-                                    *x = TransactionLog {
-                                        amount: TransactionActionState::Withdrawal {
-                                            amount: amount,
-                                        },
-                                        state: TransactionState::Chargeback,
-                                    };
-                                    self.held = self.held - amount;
-                                    self.locked = true;
-                                }
-                            }
-                        }
-                    }
-                }
+    impl RegistryHasher {
+        #[cfg(feature = "std")]
+        pub fn sip() -> RegistryHasher {
+            RegistryHasher::Sip(RandomState::new())
+        }
+    }
 
-                Transaction::Withdrawal { amount } => {
-                    self.withdrawal(amount, tx);
-                }
+    pub enum RegistryHasherState {
+        Fx(FxHasher),
+        #[cfg(feature = "std")]
+        Sip(DefaultHasher),
+    }
+
+    impl Hasher for RegistryHasherState {
+        fn finish(&self) -> u64 {
+            match self {
+                RegistryHasherState::Fx(x) => x.finish(),
+                #[cfg(feature = "std")]
+                RegistryHasherState::Sip(x) => x.finish(),
             }
         }
 
-        fn withdrawal(&mut self, amount: Decimal, tx: u32) {
-            if self.available >= amount {
-                self.transaction_log.insert(
-                    tx,
-                    TransactionLog {
-                        amount: TransactionActionState::Withdrawal { amount: amount },
-                        state: TransactionState::Resolve,
-                    },
-                );
-                self.available = self.available - amount;
+        fn write(&mut self, bytes: &[u8]) {
+            match self {
+                RegistryHasherState::Fx(x) => x.write(bytes),
+                #[cfg(feature = "std")]
+                RegistryHasherState::Sip(x) => x.write(bytes),
             }
         }
-    }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
+        fn write_u32(&mut self, i: u32) {
+            match self {
+                RegistryHasherState::Fx(x) => x.write_u32(i),
+                #[cfg(feature = "std")]
+                RegistryHasherState::Sip(x) => x.write_u32(i),
+            }
+        }
+    }
 
-    use rust_decimal_macros::dec;
+    impl BuildHasher for RegistryHasher {
+        type Hasher = RegistryHasherState;
 
-    use crate::domain::{
-        Accounts, Transaction, TransactionActionState, TransactionLog, TransactionState,
-        UserAccount,
-    };
+        fn build_hasher(&self) -> RegistryHasherState {
+            match self {
+                RegistryHasher::Fx => RegistryHasherState::Fx(FxHasher::default()),
+                #[cfg(feature = "std")]
+                RegistryHasher::Sip(x) => RegistryHasherState::Sip(x.build_hasher()),
+            }
+        }
+    }
 
-    #[test]
-    fn first_transaction_should_be_added_only_if_transaction_state_is_deposit() {
-        let mut accounts = Accounts::new();
-        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
-        accounts.add_transaction(2, 2, Transaction::Deposit { amount: dec!(1000) });
-        accounts.add_transaction(3, 3, Transaction::Withdrawal { amount: dec!(1000) });
-        accounts.add_transaction(4, 4, Transaction::Dispute );
-        accounts.add_transaction(5, 5, Transaction::Chargeback );
-        accounts.add_transaction(6, 6, Transaction::Resolve );
+    type IdempotencyKeys = HashMap<String, (u16, u32, Transaction), RegistryHasher>;
 
-        assert_eq!(
-            accounts.get_user_account(1),
-            Some(&UserAccount {
-                available: dec!(100),
-                held: dec!(0),
-                locked: false,
-                transaction_log: HashMap::from([(
-                    1,
-                    TransactionLog {
-                        amount: TransactionActionState::Deposit { amount: dec!(100) },
-                        state: TransactionState::Resolve,
-                    },
-                )]),
-            })
-        );
-        assert_eq!(
-            accounts.get_user_account(2),
-            Some(&UserAccount {
-                available: dec!(1000),
-                held: dec!(0),
-                locked: false,
-                transaction_log: HashMap::from([(
-                    2,
-                    TransactionLog {
-                        amount: TransactionActionState::Deposit { amount: dec!(1000) },
-                        state: TransactionState::Resolve,
-                    },
-                )]),
-            })
-        );
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Decision {
+        Allow,
+        Reject(RejectionReason),
     }
 
-    #[test]
-    fn deposit_and_withdrawal_should_be_ignored_if_same_transaction_id_is_already_existed() {
-        let mut accounts = Accounts::new();
-        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
-        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
-        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
-        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
-        accounts.add_transaction(1, 1, Transaction::Withdrawal { amount: dec!(400) });
+    // Hooks around every transaction added to `Accounts`, in the order they were added. The
+    // account is None when the client has none yet. The first `before` rejecting the transaction
+    // stops it before it registers its tx id or reaches the account, so it leaves no trace (and
+    // no history); `after` gets the outcome of the others, rejected ones included. Custom
+    // transactions of a `TransactionHandler` don't go through the middleware.
+    pub trait Middleware: Send + Sync {
+        fn before(
+            &self,
+            _client: u16,
+            _tx: u32,
+            _transaction: &Transaction,
+            _account: Option<&UserAccount>,
+        ) -> Decision {
+            Decision::Allow
+        }
 
-        assert_eq!(
-            accounts.get_user_account(1),
-            Some(&UserAccount {
-                available: dec!(100),
-                held: dec!(0),
-                locked: false,
-                transaction_log: HashMap::from([(
-                    1,
-                    TransactionLog {
-                        amount: TransactionActionState::Deposit { amount: dec!(100) },
-                        state: TransactionState::Resolve,
-                    },
-                ),]),
-            })
-        );
+        fn after(
+            &self,
+            _client: u16,
+            _tx: u32,
+            _transaction: &Transaction,
+            _account: Option<&UserAccount>,
+            _outcome: TransactionOutcome,
+        ) {
+        }
     }
 
-    #[test]
-    fn money_should_be_withdrawal_if_current_amount_is_bigger_than_withdrawal_amount() {
-        let mut accounts = Accounts::new();
-        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(1000) });
-        accounts.add_transaction(1, 2, Transaction::Deposit { amount: dec!(1000) });
-        accounts.add_transaction(1, 3, Transaction::Withdrawal { amount: dec!(1500) });
+    const MAX_LOG_CAPACITY: usize = 1024;
 
-        assert_eq!(
-            accounts.get_user_account(1),
-            Some(&UserAccount {
-                available: dec!(500),
-                held: dec!(0),
-                locked: false,
-                transaction_log: HashMap::from([
-                    (
-                        1,
-                        TransactionLog {
-                            amount: TransactionActionState::Deposit { amount: dec!(1000) },
-                            state: TransactionState::Resolve,
-                        },
-                    ),
-                    (
-                        2,
-                        TransactionLog {
-                            amount: TransactionActionState::Deposit { amount: dec!(1000) },
-                            state: TransactionState::Resolve,
-                        },
-                    ),
-                    (
-                        3,
-                        TransactionLog {
-                            amount: TransactionActionState::Withdrawal { amount: dec!(1500) },
-                            state: TransactionState::Resolve,
-                        },
-                    )
-                ]),
-            })
-        );
+    // deserialized registries hash with FxHash
+    #[derive(Clone, Default, Serialize, Deserialize)]
+    pub struct TransactionRegistry {
+        transaction_ids: HashSet<u32, RegistryHasher>,
+        idempotency_keys: IdempotencyKeys,
+        // of the last applied transaction, they count up from 1
+        #[serde(default)]
+        seq: u64,
     }
 
-    #[test]
-    fn money_should_not_be_withdrawal_if_current_amount_is_less_than_withdrawal_amount() {
-        let mut accounts = Accounts::new();
-        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(1000) });
-        accounts.add_transaction(1, 2, Transaction::Deposit { amount: dec!(1000) });
-        accounts.add_transaction(1, 2, Transaction::Dispute);
-        accounts.add_transaction(1, 3, Transaction::Withdrawal { amount: dec!(1500) });
+    impl TransactionRegistry {
+        pub fn new() -> TransactionRegistry {
+            TransactionRegistry::default()
+        }
 
-        assert_eq!(
-            accounts.get_user_account(1),
-            Some(&UserAccount {
-                available: dec!(1000),
-                held: dec!(1000),
-                locked: false,
-                transaction_log: HashMap::from([
-                    (
-                        1,
-                        TransactionLog {
-                            amount: TransactionActionState::Deposit { amount: dec!(1000) },
-                            state: TransactionState::Resolve,
-                        },
-                    ),
-                    (
-                        2,
-                        TransactionLog {
-                            amount: TransactionActionState::Deposit { amount: dec!(1000) },
-                            state: TransactionState::Dispute,
-                        },
-                    )
-                ]),
-            })
-        );
-    }
+        pub fn with_capacity(transactions: usize) -> TransactionRegistry {
+            TransactionRegistry {
+                transaction_ids: HashSet::with_capacity_and_hasher(
+                    transactions,
+                    Default::default(),
+                ),
+                idempotency_keys: HashMap::default(),
+                seq: 0,
+            }
+        }
+
+        pub fn with_hasher(hasher: RegistryHasher) -> TransactionRegistry {
+            TransactionRegistry {
+                transaction_ids: HashSet::with_hasher(hasher.clone()),
+                idempotency_keys: HashMap::with_hasher(hasher),
+                seq: 0,
+            }
+        }
+
+        // returns false if the transaction is a deposit, withdrawal or hold with an already used tx id
+        pub fn register(&mut self, tx: u32, transaction: &Transaction) -> bool {
+            !opens_transaction(transaction) || self.transaction_ids.insert(tx)
+        }
+
+        // returns false if the same transaction was already registered with the key
+        pub fn register_idempotency_key(
+            &mut self,
+            key: &str,
+            client: u16,
+            tx: u32,
+            transaction: &Transaction,
+        ) -> Result<bool, IdempotencyConflict> {
+            register_idempotency_key(&mut self.idempotency_keys, key, client, tx, transaction)
+        }
+
+        // the tx ids are kept, only the idempotency keys and their transactions are dropped
+        fn forget_client(&mut self, client: u16) {
+            self.idempotency_keys.retain(|_, (x, _, _)| *x != client);
+        }
+
+        pub fn merge(&mut self, other: TransactionRegistry) {
+            self.transaction_ids.extend(other.transaction_ids);
+            self.idempotency_keys.extend(other.idempotency_keys);
+            self.seq = self.seq.max(other.seq);
+        }
+    }
+
+    // deposits, withdrawals and holds open a new tx id, the other transactions refer to one
+    pub(crate) fn opens_transaction(transaction: &Transaction) -> bool {
+        matches!(
+            transaction,
+            Transaction::Deposit { .. } | Transaction::Withdrawal { .. } | Transaction::Hold { .. }
+        )
+    }
+
+    // the log entry a deposit, withdrawal or hold opens
+    fn action_state(transaction: &Transaction) -> Option<TransactionActionState> {
+        match *transaction {
+            Transaction::Deposit { amount } => Some(TransactionActionState::Deposit { amount }),
+            Transaction::Withdrawal { amount } => {
+                Some(TransactionActionState::Withdrawal { amount })
+            }
+            Transaction::Hold { amount, .. } => Some(TransactionActionState::Hold { amount }),
+            _ => None,
+        }
+    }
+
+    // with `DuplicatePolicy::Error`, given the client and entry the tx id is logged with
+    fn duplicate_rejection(
+        client: u16,
+        transaction: &Transaction,
+        original: Option<(u16, TransactionLog)>,
+    ) -> RejectionReason {
+        match original {
+            Some((x, log))
+                if x != client || action_state(transaction).as_ref() != Some(&log.amount) =>
+            {
+                RejectionReason::ConflictingDuplicate
+            }
+            _ => RejectionReason::DuplicateTransaction,
+        }
+    }
+
+    fn register_idempotency_key(
+        keys: &mut IdempotencyKeys,
+        key: &str,
+        client: u16,
+        tx: u32,
+        transaction: &Transaction,
+    ) -> Result<bool, IdempotencyConflict> {
+        if let Some(x) = keys.get(key) {
+            if x.0 == client && x.1 == tx && x.2 == *transaction {
+                return Ok(false);
+            }
+            return Err(IdempotencyConflict {
+                key: key.to_string(),
+            });
+        }
+
+        keys.insert(key.to_string(), (client, tx, transaction.clone()));
+        Ok(true)
+    }
+
+    pub trait AccountStore {
+        type Ref<'a>: Deref<Target = UserAccount>
+        where
+            Self: 'a;
+
+        fn get(&self, client: u16) -> Option<Self::Ref<'_>>;
+
+        // f gets None if the client has no account yet; changes to the account are stored
+        fn update<R>(&mut self, client: u16, f: impl FnOnce(Option<&mut UserAccount>) -> R) -> R;
+
+        fn insert(&mut self, client: u16, account: UserAccount);
+
+        fn iter(&self) -> impl Iterator<Item = (u16, Self::Ref<'_>)>;
+    }
+
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+    pub struct MemoryStore {
+        user_accounts: FxHashMap<u16, UserAccount>,
+    }
+
+    impl MemoryStore {
+        pub fn with_capacity(clients: usize) -> MemoryStore {
+            MemoryStore {
+                user_accounts: FxHashMap::with_capacity_and_hasher(clients, Default::default()),
+            }
+        }
+    }
+
+    impl AccountStore for MemoryStore {
+        type Ref<'a> = &'a UserAccount;
+
+        fn get(&self, client: u16) -> Option<&UserAccount> {
+            self.user_accounts.get(&client)
+        }
+
+        fn update<R>(&mut self, client: u16, f: impl FnOnce(Option<&mut UserAccount>) -> R) -> R {
+            f(self.user_accounts.get_mut(&client))
+        }
+
+        fn insert(&mut self, client: u16, account: UserAccount) {
+            self.user_accounts.insert(client, account);
+        }
+
+        fn iter(&self) -> impl Iterator<Item = (u16, &UserAccount)> {
+            self.user_accounts.iter().map(|x| (*x.0, x.1))
+        }
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct Accounts<S: AccountStore = MemoryStore> {
+        user_accounts: S,
+        registry: TransactionRegistry,
+        // initial transaction log capacity of new accounts
+        #[serde(skip)]
+        log_capacity: usize,
+        #[serde(skip)]
+        history: Option<History>,
+        #[serde(skip)]
+        handlers: FxHashMap<&'static str, Arc<dyn TransactionHandler>>,
+        #[serde(skip)]
+        middleware: Vec<Arc<dyn Middleware>>,
+        #[serde(skip)]
+        policies: Policies,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Operation {
+        pub client: u16,
+        pub tx: u32,
+        pub transaction: Transaction,
+        pub outcome: TransactionOutcome,
+        // `last_seq` once the operation was added, the same as the previous one's when rejected
+        pub seq: u64,
+    }
+
+    // the accounts when the history was enabled, and every transaction added since then
+    #[derive(Clone)]
+    struct History {
+        base: MemoryStore,
+        registry: TransactionRegistry,
+        operations: Vec<Operation>,
+    }
+
+    impl Default for Accounts {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl Accounts {
+        pub fn new() -> Accounts {
+            Accounts::with_store(MemoryStore::default())
+        }
+
+        pub fn with_registry(registry: TransactionRegistry) -> Accounts {
+            Accounts {
+                user_accounts: MemoryStore::default(),
+                registry,
+                log_capacity: 0,
+                history: None,
+                handlers: FxHashMap::default(),
+                middleware: Vec::new(),
+                policies: Policies::default(),
+            }
+        }
+
+        // the per-account log capacity is capped, a bad estimate only over-allocates a little
+        pub fn with_capacity(clients: usize, transactions: usize) -> Accounts {
+            Accounts {
+                user_accounts: MemoryStore::with_capacity(clients),
+                registry: TransactionRegistry::with_capacity(transactions),
+                log_capacity: (transactions / clients.max(1)).min(MAX_LOG_CAPACITY),
+                history: None,
+                handlers: FxHashMap::default(),
+                middleware: Vec::new(),
+                policies: Policies::default(),
+            }
+        }
+
+        pub fn get_user_accounts(&self) -> Iter<'_, u16, UserAccount> {
+            self.user_accounts.user_accounts.iter()
+        }
+
+        pub fn len(&self) -> usize {
+            self.user_accounts.user_accounts.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.user_accounts.user_accounts.is_empty()
+        }
+
+        pub fn merge(&mut self, other: Accounts) -> Result<(), MergeConflict> {
+            if let Some(client) = other
+                .user_accounts
+                .user_accounts
+                .keys()
+                .find(|x| self.user_accounts.user_accounts.contains_key(*x))
+            {
+                return Err(MergeConflict { client: *client });
+            }
+
+            self.user_accounts
+                .user_accounts
+                .extend(other.user_accounts.user_accounts);
+            self.registry.merge(other.registry);
+            Ok(())
+        }
+    }
+
+    // the accounts in no particular order, like `get_user_accounts`; the registry, history and
+    // handlers are dropped
+    impl IntoIterator for Accounts {
+        type Item = (u16, UserAccount);
+        type IntoIter = IntoIter<u16, UserAccount>;
+
+        fn into_iter(self) -> IntoIter<u16, UserAccount> {
+            self.user_accounts.user_accounts.into_iter()
+        }
+    }
+
+    impl<'a> IntoIterator for &'a Accounts {
+        type Item = (&'a u16, &'a UserAccount);
+        type IntoIter = Iter<'a, u16, UserAccount>;
+
+        fn into_iter(self) -> Iter<'a, u16, UserAccount> {
+            self.get_user_accounts()
+        }
+    }
+
+    impl<S: AccountStore> Accounts<S> {
+        pub fn with_store(store: S) -> Accounts<S> {
+            Accounts {
+                user_accounts: store,
+                registry: TransactionRegistry::new(),
+                log_capacity: 0,
+                history: None,
+                handlers: FxHashMap::default(),
+                middleware: Vec::new(),
+                policies: Policies::default(),
+            }
+        }
+
+        pub fn store(&self) -> &S {
+            &self.user_accounts
+        }
+
+        pub fn policies(&self) -> &Policies {
+            &self.policies
+        }
+
+        // the sequence number of the last applied transaction, logged with its entry
+        pub fn last_seq(&self) -> u64 {
+            self.registry.seq
+        }
+
+        // applies to the transactions added from now on
+        pub fn set_policies(&mut self, policies: Policies) {
+            self.policies = policies;
+        }
+
+        // Records every transaction added from now on, rejected ones included since they can still
+        // register a tx id or age pending holds, so earlier states can be rebuilt with `state_at`.
+        pub fn enable_history(&mut self) {
+            let mut base = MemoryStore::default();
+            for (client, account) in self.user_accounts.iter() {
+                base.insert(client, (*account).clone());
+            }
+            self.history = Some(History {
+                base,
+                registry: self.registry.clone(),
+                operations: Vec::new(),
+            });
+        }
+
+        pub fn history(&self) -> Option<&[Operation]> {
+            self.history.as_ref().map(|x| x.operations.as_slice())
+        }
+
+        // The accounts as of sequence number `seq`, i.e. after the transaction logged with it and
+        // the rejected ones following it, just before the next one was applied. The operations are
+        // replayed from the start of the history on every call. None if the history is not enabled
+        // or `seq` is before it was enabled or after `last_seq`.
+        pub fn state_at(&self, seq: u64) -> Option<Accounts> {
+            let history = self.history.as_ref()?;
+            if seq < history.registry.seq || seq > self.registry.seq {
+                return None;
+            }
+            let applied = history.operations.partition_point(|x| x.seq <= seq);
+            let operations = &history.operations[..applied];
+            let mut accounts = Accounts {
+                user_accounts: history.base.clone(),
+                registry: history.registry.clone(),
+                log_capacity: self.log_capacity,
+                history: None,
+                handlers: self.handlers.clone(),
+                middleware: Vec::new(),
+                policies: self.policies,
+            };
+            for operation in operations {
+                accounts.apply_transaction(
+                    operation.client,
+                    operation.tx,
+                    operation.transaction.clone(),
+                );
+            }
+            Some(accounts)
+        }
+
+        pub fn iter(&self) -> impl Iterator<Item = (u16, S::Ref<'_>)> {
+            self.user_accounts.iter()
+        }
+
+        pub fn get_user_account(&self, client: u16) -> Option<S::Ref<'_>> {
+            self.user_accounts.get(client)
+        }
+
+        // scans every account, tx ids are not indexed by client
+        pub fn find_transaction(&self, tx: u32) -> Option<(u16, TransactionLog)> {
+            self.user_accounts.iter().find_map(|(client, account)| {
+                account
+                    .transaction_log
+                    .get(&tx)
+                    .map(|x| (client, x.clone()))
+            })
+        }
+
+        // rejections are logged at debug level, chargebacks (which lock the account) at warn
+        pub fn add_transaction(
+            &mut self,
+            client: u16,
+            tx: u32,
+            transaction: Transaction,
+        ) -> TransactionOutcome {
+            let chargeback = transaction == Transaction::Chargeback;
+            let account = self.user_accounts.get(client);
+            let declined = self.middleware.iter().find_map(|x| {
+                match x.before(client, tx, &transaction, account.as_deref()) {
+                    Decision::Allow => None,
+                    Decision::Reject(reason) => Some(reason),
+                }
+            });
+            drop(account);
+            if let Some(reason) = declined {
+                let outcome = TransactionOutcome::Rejected(reason);
+                log_outcome(client, tx, chargeback, outcome);
+                return outcome;
+            }
+
+            let recorded = (self.history.is_some() || !self.middleware.is_empty())
+                .then(|| transaction.clone());
+            let outcome = self.apply_transaction(client, tx, transaction);
+            log_outcome(client, tx, chargeback, outcome);
+            let Some(transaction) = recorded else {
+                return outcome;
+            };
+            let account = self.user_accounts.get(client);
+            for middleware in &self.middleware {
+                middleware.after(client, tx, &transaction, account.as_deref(), outcome);
+            }
+            drop(account);
+            if let Some(history) = &mut self.history {
+                history.operations.push(Operation {
+                    client,
+                    tx,
+                    transaction,
+                    outcome,
+                    seq: self.registry.seq,
+                });
+            }
+            outcome
+        }
+
+        fn apply_transaction(
+            &mut self,
+            client: u16,
+            tx: u32,
+            transaction: Transaction,
+        ) -> TransactionOutcome {
+            let transaction = self.policies.precision.apply(transaction);
+            let mut revoked = None;
+            if !self.registry.register(tx, &transaction) {
+                let original = match self.policies.duplicate {
+                    DuplicatePolicy::Ignore => None,
+                    _ => self.find_transaction(tx),
+                };
+                match (self.policies.duplicate, original) {
+                    (DuplicatePolicy::Error, original) => {
+                        return TransactionOutcome::Rejected(duplicate_rejection(
+                            client,
+                            &transaction,
+                            original,
+                        ));
+                    }
+                    (DuplicatePolicy::LastWriteWins, Some((original, _))) => {
+                        let revoke = |x: Option<&mut UserAccount>| {
+                            x.map_or(Err(RejectionReason::DuplicateTransaction), |x| x.revoke(tx))
+                        };
+                        match self.user_accounts.update(original, revoke) {
+                            Ok(log) => revoked = Some((original, log)),
+                            Err(reason) => return TransactionOutcome::Rejected(reason),
+                        }
+                    }
+                    _ => {
+                        return TransactionOutcome::Rejected(RejectionReason::DuplicateTransaction)
+                    }
+                }
+            }
+
+            if self.policies.ordering == OrderingPolicy::Strict
+                && !opens_transaction(&transaction)
+                && !self.registry.transaction_ids.contains(&tx)
+            {
+                return TransactionOutcome::Rejected(RejectionReason::OutOfOrder);
+            }
+
+            let seq = self.registry.seq + 1;
+            let mut created = None;
+            let outcome = self.user_accounts.update(client, |account| match account {
+                Some(x) => x.change_account_state(tx, transaction, &self.policies, seq),
+                None if self.policies.first_transaction == FirstTransactionPolicy::AnyRecord => {
+                    let account = created.insert(UserAccount::empty(self.log_capacity));
+                    account.change_account_state(tx, transaction, &self.policies, seq)
+                }
+                None => {
+                    match UserAccount::new(tx, transaction, self.log_capacity, &self.policies, seq)
+                    {
+                        Ok(x) => {
+                            created = Some(x);
+                            TransactionOutcome::Applied
+                        }
+                        Err(reason) => TransactionOutcome::Rejected(reason),
+                    }
+                }
+            });
+            if let Some(account) = created {
+                self.user_accounts.insert(client, account);
+            }
+            match (outcome, revoked) {
+                (TransactionOutcome::Applied, _) => self.registry.seq = seq,
+                (_, Some((original, log))) => self.user_accounts.update(original, |x| {
+                    if let Some(x) = x {
+                        x.reinstate(tx, log)
+                    }
+                }),
+                _ => {}
+            }
+            outcome
+        }
+
+        // a handler registered again for the same type replaces the previous one
+        pub fn register_handler<H: TransactionHandler + 'static>(
+            &mut self,
+            transaction_type: &'static str,
+            handler: H,
+        ) {
+            self.handlers.insert(transaction_type, Arc::new(handler));
+        }
+
+        pub fn add_middleware<M: Middleware + 'static>(&mut self, middleware: M) {
+            self.middleware.push(Arc::new(middleware));
+        }
+
+        // the registered name of the type, None when there is no handler for it
+        pub fn handled_type(&self, transaction_type: &str) -> Option<&'static str> {
+            self.handlers.get_key_value(transaction_type).map(|x| *x.0)
+        }
+
+        // None when no handler is registered for the type. Custom transactions are not recorded
+        // in the history, so `state_at` doesn't replay them.
+        pub fn add_custom_transaction(
+            &mut self,
+            transaction_type: &str,
+            client: u16,
+            tx: u32,
+            amount: Option<Decimal>,
+        ) -> Option<TransactionOutcome> {
+            let handler = self.handlers.get(transaction_type)?.clone();
+            let outcome =
+                if handler.opens_transaction() && !self.registry.transaction_ids.insert(tx) {
+                    TransactionOutcome::Rejected(RejectionReason::DuplicateTransaction)
+                } else {
+                    self.user_accounts.update(client, |account| match account {
+                        Some(x) if x.locked => {
+                            TransactionOutcome::Rejected(RejectionReason::AccountLocked)
+                        }
+                        Some(x) => handler.apply(x, tx, amount),
+                        None => TransactionOutcome::Rejected(RejectionReason::AccountNotFound),
+                    })
+                };
+            log_outcome(client, tx, false, outcome);
+            Some(outcome)
+        }
+
+        // restored accounts have no transaction log, so disputes on earlier transactions are ignored
+        pub fn restore_user_account(
+            &mut self,
+            client: u16,
+            available: Decimal,
+            held: Decimal,
+            locked: bool,
+        ) {
+            self.user_accounts
+                .insert(client, UserAccount::with_balances(available, held, locked));
+        }
+
+        // locks the account like a chargeback does, false when there is no such account
+        pub fn lock_account(&mut self, client: u16) -> bool {
+            self.user_accounts.update(client, |x| match x {
+                Some(account) => {
+                    account.locked = true;
+                    true
+                }
+                None => false,
+            })
+        }
+
+        // Admin operations, e.g. for an operator after a review. They are not recorded in the
+        // history, so `state_at` doesn't replay them.
+
+        // false when there is no such account; the chargebacks stay counted
+        pub fn unlock_account(&mut self, client: u16) -> bool {
+            self.user_accounts.update(client, |x| match x {
+                Some(account) => {
+                    account.locked = false;
+                    true
+                }
+                None => false,
+            })
+        }
+
+        // Credits the available funds, or debits them when the amount is negative, outside of any
+        // transaction; locked accounts can be adjusted too. Rejected when it would leave the
+        // available funds negative.
+        pub fn adjust_balance(&mut self, client: u16, amount: Decimal) -> TransactionOutcome {
+            self.user_accounts.update(client, |x| {
+                let Some(account) = x else {
+                    return TransactionOutcome::Rejected(RejectionReason::AccountNotFound);
+                };
+                match account.available.checked_add(amount) {
+                    Some(x) if x.checked_add(account.held).is_none() => {
+                        TransactionOutcome::Rejected(RejectionReason::AmountOverflow)
+                    }
+                    Some(x) if x < dec!(0) => {
+                        TransactionOutcome::Rejected(RejectionReason::InsufficientFunds)
+                    }
+                    Some(x) => {
+                        account.available = x;
+                        TransactionOutcome::Applied
+                    }
+                    None => TransactionOutcome::Rejected(RejectionReason::AmountOverflow),
+                }
+            })
+        }
+
+        // Resolves a disputed transaction of whichever client logged it, even when the account is
+        // locked; unlike a resolve of the client, the pending holds don't age. None when no account
+        // logged the tx.
+        pub fn force_resolve(&mut self, tx: u32) -> Option<(u16, TransactionOutcome)> {
+            let (client, _) = self.find_transaction(tx)?;
+            let seq = self.registry.seq + 1;
+            let outcome = self.user_accounts.update(client, |x| match x {
+                Some(x) => x.force_resolve(tx, seq),
+                None => TransactionOutcome::Rejected(RejectionReason::AccountNotFound),
+            });
+            if outcome == TransactionOutcome::Applied {
+                self.registry.seq = seq;
+            }
+            log_outcome(client, tx, false, outcome);
+            Some((client, outcome))
+        }
+
+        // Erases the transaction history of the client and keeps its balances. The erased tx ids
+        // stay registered as tombstones so they can't be reused; disputes, captures and the like of
+        // erased transactions are rejected as unknown from now on, so anything still held stays
+        // held. The recorded history forgets the client's operations too. Returns the number of
+        // erased transactions, None when there is no such account.
+        pub fn forget_client(&mut self, client: u16) -> Option<usize> {
+            let forget = |account: &mut UserAccount| {
+                let erased = account.transaction_log.len();
+                account.transaction_log = FxHashMap::default();
+                account.pending_holds = FxHashMap::default();
+                erased
+            };
+            let erased = self.user_accounts.update(client, |x| x.map(forget))?;
+            self.registry.forget_client(client);
+            if let Some(history) = &mut self.history {
+                history.base.update(client, |x| x.map(forget));
+                history.registry.forget_client(client);
+                history.operations.retain(|x| x.client != client);
+            }
+            Some(erased)
+        }
+
+        // the logged tx ids are registered so they can't be reused
+        pub fn restore_account(&mut self, client: u16, mut account: UserAccount) {
+            account.count_log();
+            self.registry
+                .transaction_ids
+                .extend(account.transaction_log.keys());
+            self.registry.seq = self.registry.seq.max(account.last_seq());
+            self.user_accounts.insert(client, account);
+        }
+
+        pub fn add_idempotent_transaction(
+            &mut self,
+            key: &str,
+            client: u16,
+            tx: u32,
+            transaction: Transaction,
+        ) -> Result<TransactionOutcome, IdempotencyConflict> {
+            if self
+                .registry
+                .register_idempotency_key(key, client, tx, &transaction)?
+            {
+                Ok(self.add_transaction(client, tx, transaction))
+            } else {
+                Ok(TransactionOutcome::Rejected(
+                    RejectionReason::DuplicateTransaction,
+                ))
+            }
+        }
+    }
+
+    fn log_outcome(client: u16, tx: u32, chargeback: bool, outcome: TransactionOutcome) {
+        match outcome {
+            TransactionOutcome::Rejected(reason) => {
+                tracing::debug!(client, tx, %reason, "transaction rejected")
+            }
+            TransactionOutcome::Applied if chargeback => {
+                tracing::warn!(client, tx, "chargeback applied, account locked")
+            }
+            TransactionOutcome::Applied => {}
+        }
+    }
+
+    #[cfg(feature = "std")]
+    const SHARDS: usize = 64;
+
+    #[cfg(feature = "std")]
+    type Shard = RwLock<FxHashMap<u16, RwLock<UserAccount>>>;
+
+    // Accounts behind locks, for callers applying transactions from several threads.
+    // Every account has its own lock and the shard maps are only write locked to add an
+    // account, so transactions of different clients never wait on each other once their
+    // accounts exist. Tx ids are registered in a `SharedRegistry`.
+    #[cfg(feature = "std")]
+    pub struct SharedAccounts {
+        shards: Vec<Shard>,
+        transaction_ids: SharedRegistry,
+        idempotency_keys: Mutex<IdempotencyKeys>,
+        // taken under the account's lock once a transaction is applied, so there are no gaps
+        seq: AtomicU64,
+        log_capacity: usize,
+        policies: Policies,
+    }
+
+    #[cfg(feature = "std")]
+    impl Default for SharedAccounts {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl From<Accounts> for SharedAccounts {
+        fn from(accounts: Accounts) -> Self {
+            let hasher = accounts.registry.transaction_ids.hasher();
+            let mut shared = SharedAccounts {
+                transaction_ids: SharedRegistry::with_hasher(hasher.clone()),
+                seq: AtomicU64::new(accounts.registry.seq),
+                log_capacity: accounts.log_capacity,
+                policies: accounts.policies,
+                ..SharedAccounts::new()
+            };
+            for (client, account) in accounts.user_accounts.user_accounts {
+                shared.shards[client as usize % SHARDS]
+                    .get_mut()
+                    .unwrap()
+                    .insert(client, RwLock::new(account));
+            }
+            for tx in accounts.registry.transaction_ids {
+                shared.transaction_ids.insert(tx);
+            }
+            shared.idempotency_keys = Mutex::new(accounts.registry.idempotency_keys);
+            shared
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl SharedAccounts {
+        pub fn new() -> SharedAccounts {
+            SharedAccounts {
+                shards: (0..SHARDS).map(|_| RwLock::default()).collect(),
+                transaction_ids: SharedRegistry::new(),
+                idempotency_keys: Mutex::default(),
+                seq: AtomicU64::new(0),
+                log_capacity: 0,
+                policies: Policies::default(),
+            }
+        }
+
+        fn shard(&self, client: u16) -> &Shard {
+            &self.shards[client as usize % SHARDS]
+        }
+
+        // a copy, the account may change as soon as the lock is released
+        pub fn get_user_account(&self, client: u16) -> Option<UserAccount> {
+            let accounts = self.shard(client).read().unwrap();
+            accounts.get(&client).map(|x| x.read().unwrap().clone())
+        }
+
+        pub fn find_transaction(&self, tx: u32) -> Option<(u16, TransactionLog)> {
+            self.shards.iter().find_map(|shard| {
+                shard.read().unwrap().iter().find_map(|(client, account)| {
+                    let account = account.read().unwrap();
+                    account
+                        .transaction_log
+                        .get(&tx)
+                        .map(|x| (*client, x.clone()))
+                })
+            })
+        }
+
+        pub fn add_transaction(
+            &self,
+            client: u16,
+            tx: u32,
+            transaction: Transaction,
+        ) -> TransactionOutcome {
+            let chargeback = transaction == Transaction::Chargeback;
+            let outcome = self.apply_transaction(client, tx, transaction);
+            log_outcome(client, tx, chargeback, outcome);
+            outcome
+        }
+
+        fn apply_transaction(
+            &self,
+            client: u16,
+            tx: u32,
+            transaction: Transaction,
+        ) -> TransactionOutcome {
+            let transaction = self.policies.precision.apply(transaction);
+            let mut revoked = None;
+            if !self.transaction_ids.register(tx, &transaction) {
+                let original = match self.policies.duplicate {
+                    DuplicatePolicy::Ignore => None,
+                    _ => self.find_transaction(tx),
+                };
+                match (self.policies.duplicate, original) {
+                    (DuplicatePolicy::Error, original) => {
+                        return TransactionOutcome::Rejected(duplicate_rejection(
+                            client,
+                            &transaction,
+                            original,
+                        ));
+                    }
+                    (DuplicatePolicy::LastWriteWins, Some((original, _))) => {
+                        match self.update_account(original, |x| x.revoke(tx)) {
+                            Some(Ok(log)) => revoked = Some((original, log)),
+                            Some(Err(reason)) => return TransactionOutcome::Rejected(reason),
+                            None => {
+                                return TransactionOutcome::Rejected(
+                                    RejectionReason::DuplicateTransaction,
+                                )
+                            }
+                        }
+                    }
+                    _ => {
+                        return TransactionOutcome::Rejected(RejectionReason::DuplicateTransaction)
+                    }
+                }
+            }
+            if self.policies.ordering == OrderingPolicy::Strict
+                && !opens_transaction(&transaction)
+                && !self.transaction_ids.contains(tx)
+            {
+                return TransactionOutcome::Rejected(RejectionReason::OutOfOrder);
+            }
+
+            let outcome = self.change_account_state(client, tx, transaction);
+            // other threads may have changed the original's account meanwhile
+            if let (TransactionOutcome::Rejected(_), Some((original, log))) = (outcome, revoked) {
+                self.update_account(original, |x| x.reinstate(tx, log));
+            }
+            outcome
+        }
+
+        // f gets the account under its write lock, None when there is no such account
+        fn update_account<R>(
+            &self,
+            client: u16,
+            f: impl FnOnce(&mut UserAccount) -> R,
+        ) -> Option<R> {
+            let accounts = self.shard(client).read().unwrap();
+            let mut account = accounts.get(&client)?.write().unwrap();
+            Some(f(&mut account))
+        }
+
+        fn change_account_state(
+            &self,
+            client: u16,
+            tx: u32,
+            transaction: Transaction,
+        ) -> TransactionOutcome {
+            let shard = self.shard(client);
+            if let Some(account) = shard.read().unwrap().get(&client) {
+                return self.sequenced(&mut account.write().unwrap(), tx, transaction);
+            }
+            // another thread may have added the account between the two locks
+            match shard.write().unwrap().entry(client) {
+                Entry::Occupied(mut x) => {
+                    self.sequenced(x.get_mut().get_mut().unwrap(), tx, transaction)
+                }
+                Entry::Vacant(x)
+                    if self.policies.first_transaction == FirstTransactionPolicy::AnyRecord =>
+                {
+                    let mut account = UserAccount::empty(self.log_capacity);
+                    let outcome = self.sequenced(&mut account, tx, transaction);
+                    x.insert(RwLock::new(account));
+                    outcome
+                }
+                Entry::Vacant(x) => {
+                    match UserAccount::new(tx, transaction, self.log_capacity, &self.policies, 0) {
+                        Ok(mut account) => {
+                            self.stamp(&mut account, tx);
+                            x.insert(RwLock::new(account));
+                            TransactionOutcome::Applied
+                        }
+                        Err(reason) => TransactionOutcome::Rejected(reason),
+                    }
+                }
+            }
+        }
+
+        // applied with sequence number 0, the entry gets the next one once the outcome is known
+        fn sequenced(
+            &self,
+            account: &mut UserAccount,
+            tx: u32,
+            transaction: Transaction,
+        ) -> TransactionOutcome {
+            let outcome = account.change_account_state(tx, transaction, &self.policies, 0);
+            if outcome == TransactionOutcome::Applied {
+                self.stamp(account, tx);
+            }
+            outcome
+        }
+
+        fn stamp(&self, account: &mut UserAccount, tx: u32) {
+            let seq = self.seq.fetch_add(1, Ordering::Relaxed) + 1;
+            if let Some(x) = account.transaction_log.get_mut(&tx) {
+                x.seq = seq;
+            }
+        }
+
+        pub fn add_idempotent_transaction(
+            &self,
+            key: &str,
+            client: u16,
+            tx: u32,
+            transaction: Transaction,
+        ) -> Result<TransactionOutcome, IdempotencyConflict> {
+            if register_idempotency_key(
+                &mut self.idempotency_keys.lock().unwrap(),
+                key,
+                client,
+                tx,
+                &transaction,
+            )? {
+                Ok(self.add_transaction(client, tx, transaction))
+            } else {
+                Ok(TransactionOutcome::Rejected(
+                    RejectionReason::DuplicateTransaction,
+                ))
+            }
+        }
+
+        // the logged tx ids are registered so they can't be reused
+        pub fn restore_account(&self, client: u16, mut account: UserAccount) {
+            account.count_log();
+            self.seq.fetch_max(account.last_seq(), Ordering::Relaxed);
+            for tx in account.transaction_log.keys() {
+                self.transaction_ids.insert(*tx);
+            }
+            self.shard(client)
+                .write()
+                .unwrap()
+                .insert(client, RwLock::new(account));
+        }
+
+        // the shards are copied one at a time, so transactions applied meanwhile may be missing
+        pub fn snapshot(&self) -> Accounts {
+            let mut accounts = Accounts::with_registry(self.registry());
+            for shard in &self.shards {
+                for (client, account) in shard.read().unwrap().iter() {
+                    accounts
+                        .user_accounts
+                        .insert(*client, account.read().unwrap().clone());
+                }
+            }
+            accounts.log_capacity = self.log_capacity;
+            accounts.policies = self.policies;
+            accounts
+        }
+
+        pub fn into_accounts(self) -> Accounts {
+            let mut accounts = Accounts::with_registry(self.registry());
+            for shard in self.shards {
+                for (client, account) in shard.into_inner().unwrap() {
+                    accounts
+                        .user_accounts
+                        .insert(client, account.into_inner().unwrap());
+                }
+            }
+            accounts.log_capacity = self.log_capacity;
+            accounts.policies = self.policies;
+            accounts
+        }
+
+        fn registry(&self) -> TransactionRegistry {
+            let idempotency_keys = self.idempotency_keys.lock().unwrap().clone();
+            let mut transaction_ids = HashSet::with_hasher(idempotency_keys.hasher().clone());
+            transaction_ids.extend(self.transaction_ids.ids());
+            TransactionRegistry {
+                transaction_ids,
+                idempotency_keys,
+                seq: self.seq.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(from = "StoredAccount")]
+    pub struct UserAccount {
+        pub available: Decimal,
+        pub held: Decimal,
+        pub locked: bool,
+        pub transaction_log: FxHashMap<u32, TransactionLog>,
+        pub pending_holds: FxHashMap<u32, u32>,
+        // kept up to date by the transactions, see `open_disputes` and `chargeback_count`
+        pub(crate) open_disputes: usize,
+        pub(crate) chargebacks: usize,
+    }
+
+    // The serialized account. The counters are recounted from the log when it is read, so an
+    // account serialized without them (or with stale ones) starts from the right counts.
+    #[derive(Deserialize)]
+    struct StoredAccount {
+        available: Decimal,
+        held: Decimal,
+        locked: bool,
+        transaction_log: FxHashMap<u32, TransactionLog>,
+        pending_holds: FxHashMap<u32, u32>,
+        #[serde(default)]
+        #[allow(dead_code)]
+        open_disputes: usize,
+        #[serde(default)]
+        #[allow(dead_code)]
+        chargebacks: usize,
+    }
+
+    impl From<StoredAccount> for UserAccount {
+        fn from(stored: StoredAccount) -> Self {
+            let mut account = UserAccount {
+                available: stored.available,
+                held: stored.held,
+                locked: stored.locked,
+                transaction_log: stored.transaction_log,
+                pending_holds: stored.pending_holds,
+                open_disputes: 0,
+                chargebacks: 0,
+            };
+            account.count_log();
+            account
+        }
+    }
+
+    // the balances on one line, unrounded, without the logs
+    impl fmt::Display for UserAccount {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "available {}, held {}, total {}",
+                self.available,
+                self.held,
+                self.available + self.held
+            )?;
+            if self.locked {
+                f.write_str(", locked")?;
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct SimulationResult {
+        pub account: UserAccount,
+        pub outcomes: Vec<TransactionOutcome>,
+    }
+
+    impl SimulationResult {
+        pub fn all_applied(&self) -> bool {
+            self.outcomes
+                .iter()
+                .all(|x| *x == TransactionOutcome::Applied)
+        }
+    }
+
+    impl UserAccount {
+        // logged transactions in the dispute state
+        pub fn open_disputes(&self) -> usize {
+            self.open_disputes
+        }
+
+        // logged transactions that were charged back; only one unless the account settles its
+        // open disputes once locked, see `LockedAccountPolicy`
+        pub fn chargeback_count(&self) -> usize {
+            self.chargebacks
+        }
+
+        // an account without transaction log, e.g. restored from an output
+        pub fn with_balances(available: Decimal, held: Decimal, locked: bool) -> UserAccount {
+            UserAccount {
+                available,
+                held,
+                locked,
+                ..UserAccount::default()
+            }
+        }
+
+        fn last_seq(&self) -> u64 {
+            self.transaction_log
+                .values()
+                .map(|x| x.seq)
+                .max()
+                .unwrap_or(0)
+        }
+
+        // for accounts whose log was filled in directly, e.g. when loaded from a database
+        fn count_log(&mut self) {
+            let count = |states: &[TransactionState]| {
+                self.transaction_log
+                    .values()
+                    .filter(|x| states.contains(&x.state))
+                    .count()
+            };
+            (self.open_disputes, self.chargebacks) = (
+                count(&[TransactionState::Dispute]),
+                count(&[TransactionState::Chargeback, TransactionState::Refunded]),
+            );
+        }
+
+        // Applies the transactions to a copy of the account, the account itself is unchanged.
+        // Transactions are paired with their tx id since disputes and holds refer to earlier ones;
+        // tx ids are only checked against this account's log, not against other clients. The
+        // simulated entries get sequence number 0.
+        pub fn simulate(&self, transactions: &[(u32, Transaction)]) -> SimulationResult {
+            let mut account = self.clone();
+            let outcomes = transactions
+                .iter()
+                .map(|(tx, transaction)| {
+                    if opens_transaction(transaction) && account.transaction_log.contains_key(tx) {
+                        TransactionOutcome::Rejected(RejectionReason::DuplicateTransaction)
+                    } else {
+                        account.change_account_state(
+                            *tx,
+                            transaction.clone(),
+                            &Policies::default(),
+                            0,
+                        )
+                    }
+                })
+                .collect();
+            SimulationResult { account, outcomes }
+        }
+
+        fn empty(log_capacity: usize) -> UserAccount {
+            UserAccount {
+                available: dec!(0),
+                held: dec!(0),
+                locked: false,
+                transaction_log: FxHashMap::with_capacity_and_hasher(
+                    log_capacity,
+                    Default::default(),
+                ),
+                pending_holds: FxHashMap::default(),
+                open_disputes: 0,
+                chargebacks: 0,
+            }
+        }
+
+        fn new(
+            tx: u32,
+            transaction: Transaction,
+            log_capacity: usize,
+            policies: &Policies,
+            seq: u64,
+        ) -> Result<UserAccount, RejectionReason> {
+            match transaction {
+                Transaction::Deposit { amount } => {
+                    if let Some(reason) = policies.limits.exceeded(dec!(0), &transaction) {
+                        return Err(reason);
+                    }
+                    let mut transaction_log =
+                        FxHashMap::with_capacity_and_hasher(log_capacity, Default::default());
+                    transaction_log.insert(
+                        tx,
+                        TransactionLog {
+                            amount: TransactionActionState::Deposit { amount },
+                            state: TransactionState::Resolve,
+                            seq,
+                        },
+                    );
+                    Ok(UserAccount {
+                        available: amount,
+                        held: dec!(0),
+                        locked: false,
+                        transaction_log,
+                        pending_holds: FxHashMap::default(),
+                        open_disputes: 0,
+                        chargebacks: 0,
+                    })
+                }
+                _ => Err(RejectionReason::AccountNotFound),
+            }
+        }
+
+        fn change_account_state(
+            &mut self,
+            tx: u32,
+            transaction: Transaction,
+            policies: &Policies,
+            seq: u64,
+        ) -> TransactionOutcome {
+            let settles_dispute =
+                matches!(transaction, Transaction::Resolve | Transaction::Chargeback);
+            if self.locked
+                && !(settles_dispute
+                    && policies.locked_account == LockedAccountPolicy::SettleOpenDisputes)
+            {
+                return TransactionOutcome::Rejected(RejectionReason::AccountLocked);
+            }
+            if !self.fits(tx, &transaction, policies) {
+                return TransactionOutcome::Rejected(RejectionReason::AmountOverflow);
+            }
+            if let Some(reason) = policies
+                .limits
+                .exceeded(self.available.saturating_add(self.held), &transaction)
+            {
+                return TransactionOutcome::Rejected(reason);
+            }
+            let expired_holds = self.age_pending_holds();
+            let outcome = match transaction {
+                Transaction::Deposit { amount } => {
+                    self.transaction_log.insert(
+                        tx,
+                        TransactionLog {
+                            amount: TransactionActionState::Deposit { amount },
+                            state: TransactionState::Resolve,
+                            seq,
+                        },
+                    );
+                    self.available += amount;
+                    TransactionOutcome::Applied
+                }
+
+                Transaction::Dispute => match self.transaction_log.get_mut(&tx) {
+                    Some(x) if matches!(x.state, TransactionState::Resolve) => match x.amount {
+                        TransactionActionState::Deposit { amount } => {
+                            x.state = TransactionState::Dispute;
+                            self.available -= amount;
+                            self.held += amount;
+                            self.open_disputes += 1;
+                            TransactionOutcome::Applied
+                        }
+                        TransactionActionState::Withdrawal { .. }
+                            if policies.dispute == DisputePolicy::DepositsOnly =>
+                        {
+                            TransactionOutcome::Rejected(RejectionReason::InvalidTransactionState)
+                        }
+                        TransactionActionState::Withdrawal { amount } => {
+                            x.state = TransactionState::Dispute;
+                            self.held += amount;
+                            self.open_disputes += 1;
+                            TransactionOutcome::Applied
+                        }
+                        TransactionActionState::Hold { .. } => {
+                            TransactionOutcome::Rejected(RejectionReason::InvalidTransactionState)
+                        }
+                    },
+                    Some(_) => {
+                        TransactionOutcome::Rejected(RejectionReason::InvalidTransactionState)
+                    }
+                    None => TransactionOutcome::Rejected(RejectionReason::UnknownTransaction),
+                },
+
+                Transaction::Resolve => self.resolve(tx),
+
+                Transaction::Chargeback => match self.transaction_log.get_mut(&tx) {
+                    Some(x) if matches!(x.state, TransactionState::Dispute) => match x.amount {
+                        TransactionActionState::Deposit { amount } => {
+                            x.state = TransactionState::Chargeback;
+                            self.held -= amount;
+                            self.locked = true;
+                            self.open_disputes = self.open_disputes.saturating_sub(1);
+                            self.chargebacks += 1;
+                            TransactionOutcome::Applied
+                        }
+                        TransactionActionState::Withdrawal { amount }
+                            if policies.dispute
+                                == DisputePolicy::DepositsAndRefundedWithdrawals =>
+                        {
+                            x.state = TransactionState::Refunded;
+                            self.available += amount;
+                            self.held -= amount;
+                            self.locked = true;
+                            self.open_disputes = self.open_disputes.saturating_sub(1);
+                            self.chargebacks += 1;
+                            TransactionOutcome::Applied
+                        }
+                        TransactionActionState::Withdrawal { amount } => {
+                            x.state = TransactionState::Chargeback;
+                            self.held -= amount;
+                            self.locked = true;
+                            self.open_disputes = self.open_disputes.saturating_sub(1);
+                            self.chargebacks += 1;
+                            TransactionOutcome::Applied
+                        }
+                        TransactionActionState::Hold { .. } => {
+                            TransactionOutcome::Rejected(RejectionReason::InvalidTransactionState)
+                        }
+                    },
+                    Some(_) => {
+                        TransactionOutcome::Rejected(RejectionReason::InvalidTransactionState)
+                    }
+                    None => TransactionOutcome::Rejected(RejectionReason::UnknownTransaction),
+                },
+
+                Transaction::Withdrawal { amount } => self.withdrawal(amount, tx, seq),
+
+                Transaction::Hold {
+                    amount,
+                    expires_after,
+                } => self.hold(amount, expires_after, tx, seq),
+
+                Transaction::Capture => self.capture_hold(tx),
+
+                Transaction::Release => self.release_hold(tx),
+            };
+            if outcome == TransactionOutcome::Applied {
+                if let Some(x) = self.transaction_log.get_mut(&tx) {
+                    x.seq = seq;
+                }
+            }
+            for hold_tx in expired_holds {
+                self.release_hold(hold_tx);
+            }
+            outcome
+        }
+
+        fn resolve(&mut self, tx: u32) -> TransactionOutcome {
+            match self.transaction_log.get_mut(&tx) {
+                Some(x) if matches!(x.state, TransactionState::Dispute) => match x.amount {
+                    TransactionActionState::Deposit { amount } => {
+                        x.state = TransactionState::Resolve;
+                        self.available += amount;
+                        self.held -= amount;
+                        self.open_disputes = self.open_disputes.saturating_sub(1);
+                        TransactionOutcome::Applied
+                    }
+                    TransactionActionState::Withdrawal { amount } => {
+                        x.state = TransactionState::Resolve;
+                        self.held -= amount;
+                        self.open_disputes = self.open_disputes.saturating_sub(1);
+                        TransactionOutcome::Applied
+                    }
+                    TransactionActionState::Hold { .. } => {
+                        TransactionOutcome::Rejected(RejectionReason::InvalidTransactionState)
+                    }
+                },
+                Some(_) => TransactionOutcome::Rejected(RejectionReason::InvalidTransactionState),
+                None => TransactionOutcome::Rejected(RejectionReason::UnknownTransaction),
+            }
+        }
+
+        // a resolve moves funds from held to available, so no lock, limit nor overflow applies
+        fn force_resolve(&mut self, tx: u32, seq: u64) -> TransactionOutcome {
+            let outcome = self.resolve(tx);
+            if outcome == TransactionOutcome::Applied {
+                if let Some(x) = self.transaction_log.get_mut(&tx) {
+                    x.seq = seq;
+                }
+            }
+            outcome
+        }
+
+        // whether the balances and their total still fit in a Decimal once the transaction is applied
+        fn fits(&self, tx: u32, transaction: &Transaction, policies: &Policies) -> bool {
+            let logged = self.transaction_log.get(&tx).map(|x| &x.amount);
+            let (available, held) = match (transaction, logged) {
+                (Transaction::Deposit { amount }, _) => (*amount, dec!(0)),
+                (Transaction::Withdrawal { amount }, _) => (-*amount, dec!(0)),
+                (Transaction::Hold { amount, .. }, _) => (-*amount, *amount),
+                (Transaction::Dispute, Some(TransactionActionState::Deposit { amount })) => {
+                    (-*amount, *amount)
+                }
+                (Transaction::Dispute, Some(TransactionActionState::Withdrawal { amount })) => {
+                    (dec!(0), *amount)
+                }
+                (
+                    Transaction::Resolve | Transaction::Release,
+                    Some(
+                        TransactionActionState::Deposit { amount }
+                        | TransactionActionState::Hold { amount },
+                    ),
+                ) => (*amount, -*amount),
+                (Transaction::Chargeback, Some(TransactionActionState::Withdrawal { amount }))
+                    if policies.dispute == DisputePolicy::DepositsAndRefundedWithdrawals =>
+                {
+                    (*amount, -*amount)
+                }
+                (
+                    Transaction::Resolve | Transaction::Chargeback | Transaction::Capture,
+                    Some(x),
+                ) => {
+                    let (TransactionActionState::Deposit { amount }
+                    | TransactionActionState::Withdrawal { amount }
+                    | TransactionActionState::Hold { amount }) = x;
+                    (dec!(0), -*amount)
+                }
+                _ => return true,
+            };
+            match (
+                self.available.checked_add(available),
+                self.held.checked_add(held),
+            ) {
+                (Some(available), Some(held)) => available.checked_add(held).is_some(),
+                _ => false,
+            }
+        }
+
+        fn withdrawal(&mut self, amount: Decimal, tx: u32, seq: u64) -> TransactionOutcome {
+            if self.available < amount {
+                return TransactionOutcome::Rejected(RejectionReason::InsufficientFunds);
+            }
+            self.transaction_log.insert(
+                tx,
+                TransactionLog {
+                    amount: TransactionActionState::Withdrawal { amount },
+                    state: TransactionState::Resolve,
+                    seq,
+                },
+            );
+            self.available -= amount;
+            TransactionOutcome::Applied
+        }
+
+        fn hold(
+            &mut self,
+            amount: Decimal,
+            expires_after: u32,
+            tx: u32,
+            seq: u64,
+        ) -> TransactionOutcome {
+            if self.available < amount {
+                return TransactionOutcome::Rejected(RejectionReason::InsufficientFunds);
+            }
+            self.transaction_log.insert(
+                tx,
+                TransactionLog {
+                    amount: TransactionActionState::Hold { amount },
+                    state: TransactionState::Held,
+                    seq,
+                },
+            );
+            self.pending_holds.insert(tx, expires_after);
+            self.available -= amount;
+            self.held += amount;
+            TransactionOutcome::Applied
+        }
+
+        fn capture_hold(&mut self, tx: u32) -> TransactionOutcome {
+            match self.transaction_log.get_mut(&tx) {
+                Some(x) => match (&x.amount, &x.state) {
+                    (TransactionActionState::Hold { amount }, TransactionState::Held) => {
+                        self.held -= *amount;
+                        x.state = TransactionState::Captured;
+                        self.pending_holds.remove(&tx);
+                        TransactionOutcome::Applied
+                    }
+                    _ => TransactionOutcome::Rejected(RejectionReason::InvalidTransactionState),
+                },
+                None => TransactionOutcome::Rejected(RejectionReason::UnknownTransaction),
+            }
+        }
+
+        fn release_hold(&mut self, tx: u32) -> TransactionOutcome {
+            match self.transaction_log.get_mut(&tx) {
+                Some(x) => match (&x.amount, &x.state) {
+                    (TransactionActionState::Hold { amount }, TransactionState::Held) => {
+                        self.held -= *amount;
+                        self.available += *amount;
+                        x.state = TransactionState::Released;
+                        self.pending_holds.remove(&tx);
+                        TransactionOutcome::Applied
+                    }
+                    _ => TransactionOutcome::Rejected(RejectionReason::InvalidTransactionState),
+                },
+                None => TransactionOutcome::Rejected(RejectionReason::UnknownTransaction),
+            }
+        }
+
+        // undoes an undisputed deposit or withdrawal and removes it from the log, for
+        // `DuplicatePolicy::LastWriteWins`
+        fn revoke(&mut self, tx: u32) -> Result<TransactionLog, RejectionReason> {
+            if self.locked {
+                return Err(RejectionReason::AccountLocked);
+            }
+            let log = self
+                .transaction_log
+                .get(&tx)
+                .ok_or(RejectionReason::DuplicateTransaction)?;
+            match (&log.amount, &log.state) {
+                (TransactionActionState::Deposit { amount }, TransactionState::Resolve) => {
+                    if self.available < *amount {
+                        return Err(RejectionReason::InsufficientFunds);
+                    }
+                    self.available -= *amount;
+                }
+                (TransactionActionState::Withdrawal { amount }, TransactionState::Resolve) => {
+                    self.available = self
+                        .available
+                        .checked_add(*amount)
+                        .ok_or(RejectionReason::AmountOverflow)?;
+                }
+                _ => return Err(RejectionReason::InvalidTransactionState),
+            }
+            Ok(self.transaction_log.remove(&tx).unwrap())
+        }
+
+        // puts back what `revoke` undid
+        fn reinstate(&mut self, tx: u32, log: TransactionLog) {
+            match log.amount {
+                TransactionActionState::Deposit { amount } => self.available += amount,
+                TransactionActionState::Withdrawal { amount } => self.available -= amount,
+                TransactionActionState::Hold { .. } => {}
+            }
+            self.transaction_log.insert(tx, log);
+        }
+
+        // every transaction applied to the account ages the pending holds by one.
+        // holds that run out are released after the current transaction is applied,
+        // so a capture arriving on the last allowed transaction still wins.
+        fn age_pending_holds(&mut self) -> Vec<u32> {
+            let mut expired = Vec::new();
+            for (tx, remaining) in self.pending_holds.iter_mut() {
+                *remaining = remaining.saturating_sub(1);
+                if *remaining == 0 {
+                    expired.push(*tx);
+                }
+            }
+            expired
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::FxHashMap;
+
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use crate::domain::{
+        Accounts, Decision, DisputePolicy, DuplicatePolicy, FirstTransactionPolicy,
+        IdempotencyConflict, Limits, LockedAccountPolicy, MergeConflict, Middleware,
+        OrderingPolicy, Policies, PrecisionPolicy, RegistryHasher, RejectionReason, SharedAccounts,
+        Transaction, TransactionActionState, TransactionHandler, TransactionLog,
+        TransactionOutcome, TransactionRegistry, TransactionState, UserAccount,
+    };
+    use rust_decimal::RoundingStrategy;
+
+    #[test]
+    fn first_transaction_should_be_added_only_if_transaction_state_is_deposit() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(2, 2, Transaction::Deposit { amount: dec!(1000) });
+        accounts.add_transaction(3, 3, Transaction::Withdrawal { amount: dec!(1000) });
+        accounts.add_transaction(4, 4, Transaction::Dispute);
+        accounts.add_transaction(5, 5, Transaction::Chargeback);
+        accounts.add_transaction(6, 6, Transaction::Resolve);
+
+        assert_eq!(
+            accounts.get_user_account(1),
+            Some(&UserAccount {
+                available: dec!(100),
+                held: dec!(0),
+                locked: false,
+                transaction_log: FxHashMap::from_iter([(
+                    1,
+                    TransactionLog {
+                        amount: TransactionActionState::Deposit { amount: dec!(100) },
+                        state: TransactionState::Resolve,
+                        seq: 1,
+                    },
+                )]),
+                pending_holds: FxHashMap::default(),
+                open_disputes: 0,
+                chargebacks: 0,
+            })
+        );
+        assert_eq!(
+            accounts.get_user_account(2),
+            Some(&UserAccount {
+                available: dec!(1000),
+                held: dec!(0),
+                locked: false,
+                transaction_log: FxHashMap::from_iter([(
+                    2,
+                    TransactionLog {
+                        amount: TransactionActionState::Deposit { amount: dec!(1000) },
+                        state: TransactionState::Resolve,
+                        seq: 2,
+                    },
+                )]),
+                pending_holds: FxHashMap::default(),
+                open_disputes: 0,
+                chargebacks: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn deposit_and_withdrawal_should_be_ignored_if_same_transaction_id_is_already_existed() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(1, 1, Transaction::Withdrawal { amount: dec!(400) });
+
+        assert_eq!(
+            accounts.get_user_account(1),
+            Some(&UserAccount {
+                available: dec!(100),
+                held: dec!(0),
+                locked: false,
+                transaction_log: FxHashMap::from_iter([(
+                    1,
+                    TransactionLog {
+                        amount: TransactionActionState::Deposit { amount: dec!(100) },
+                        state: TransactionState::Resolve,
+                        seq: 1,
+                    },
+                ),]),
+                pending_holds: FxHashMap::default(),
+                open_disputes: 0,
+                chargebacks: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn money_should_be_withdrawal_if_current_amount_is_bigger_than_withdrawal_amount() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(1000) });
+        accounts.add_transaction(1, 2, Transaction::Deposit { amount: dec!(1000) });
+        accounts.add_transaction(1, 3, Transaction::Withdrawal { amount: dec!(1500) });
+
+        assert_eq!(
+            accounts.get_user_account(1),
+            Some(&UserAccount {
+                available: dec!(500),
+                held: dec!(0),
+                locked: false,
+                transaction_log: FxHashMap::from_iter([
+                    (
+                        1,
+                        TransactionLog {
+                            amount: TransactionActionState::Deposit { amount: dec!(1000) },
+                            state: TransactionState::Resolve,
+                            seq: 1,
+                        },
+                    ),
+                    (
+                        2,
+                        TransactionLog {
+                            amount: TransactionActionState::Deposit { amount: dec!(1000) },
+                            state: TransactionState::Resolve,
+                            seq: 2,
+                        },
+                    ),
+                    (
+                        3,
+                        TransactionLog {
+                            amount: TransactionActionState::Withdrawal { amount: dec!(1500) },
+                            state: TransactionState::Resolve,
+                            seq: 3,
+                        },
+                    )
+                ]),
+                pending_holds: FxHashMap::default(),
+                open_disputes: 0,
+                chargebacks: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn money_should_not_be_withdrawal_if_current_amount_is_less_than_withdrawal_amount() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(1000) });
+        accounts.add_transaction(1, 2, Transaction::Deposit { amount: dec!(1000) });
+        accounts.add_transaction(1, 2, Transaction::Dispute);
+        accounts.add_transaction(1, 3, Transaction::Withdrawal { amount: dec!(1500) });
+
+        assert_eq!(
+            accounts.get_user_account(1),
+            Some(&UserAccount {
+                available: dec!(1000),
+                held: dec!(1000),
+                locked: false,
+                transaction_log: FxHashMap::from_iter([
+                    (
+                        1,
+                        TransactionLog {
+                            amount: TransactionActionState::Deposit { amount: dec!(1000) },
+                            state: TransactionState::Resolve,
+                            seq: 1,
+                        },
+                    ),
+                    (
+                        2,
+                        TransactionLog {
+                            amount: TransactionActionState::Deposit { amount: dec!(1000) },
+                            state: TransactionState::Dispute,
+                            seq: 3,
+                        },
+                    )
+                ]),
+                pending_holds: FxHashMap::default(),
+                open_disputes: 1,
+                chargebacks: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn deposit_data_should_be_disputed_if_that_data_is_resolved() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(1, 1, Transaction::Dispute);
+
+        assert_eq!(
+            accounts.get_user_account(1),
+            Some(&UserAccount {
+                available: dec!(0),
+                held: dec!(100),
+                locked: false,
+                transaction_log: FxHashMap::from_iter([(
+                    1,
+                    TransactionLog {
+                        amount: TransactionActionState::Deposit { amount: dec!(100) },
+                        state: TransactionState::Dispute,
+                        seq: 2,
+                    },
+                )]),
+                pending_holds: FxHashMap::default(),
+                open_disputes: 1,
+                chargebacks: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn disputed_deposit_data_should_be_resolved_if_resovle_transaction_data_come() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(1, 1, Transaction::Dispute);
+        accounts.add_transaction(1, 1, Transaction::Resolve);
+
+        assert_eq!(
+            accounts.get_user_account(1),
+            Some(&UserAccount {
+                available: dec!(100),
+                held: dec!(0),
+                locked: false,
+                transaction_log: FxHashMap::from_iter([(
+                    1,
+                    TransactionLog {
+                        amount: TransactionActionState::Deposit { amount: dec!(100) },
+                        state: TransactionState::Resolve,
+                        seq: 3,
+                    },
+                )]),
+                pending_holds: FxHashMap::default(),
+                open_disputes: 0,
+                chargebacks: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn deposite_data_should_be_charge_back_if_that_data_is_disputed() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(1, 1, Transaction::Dispute);
+        accounts.add_transaction(1, 1, Transaction::Chargeback);
+
+        assert_eq!(
+            accounts.get_user_account(1),
+            Some(&UserAccount {
+                available: dec!(0),
+                held: dec!(0),
+                locked: true,
+                transaction_log: FxHashMap::from_iter([(
+                    1,
+                    TransactionLog {
+                        amount: TransactionActionState::Deposit { amount: dec!(100) },
+                        state: TransactionState::Chargeback,
+                        seq: 3,
+                    },
+                )]),
+                pending_holds: FxHashMap::default(),
+                open_disputes: 0,
+                chargebacks: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn withdrawal_data_should_be_charge_back_if_that_data_is_disputed() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(1, 2, Transaction::Withdrawal { amount: dec!(100) });
+        accounts.add_transaction(1, 2, Transaction::Dispute);
+        accounts.add_transaction(1, 2, Transaction::Chargeback);
+
+        assert_eq!(
+            accounts.get_user_account(1),
+            Some(&UserAccount {
+                available: dec!(0),
+                held: dec!(0),
+                locked: true,
+                transaction_log: FxHashMap::from_iter([
+                    (
+                        1,
+                        TransactionLog {
+                            amount: TransactionActionState::Deposit { amount: dec!(100) },
+                            state: TransactionState::Resolve,
+                            seq: 1,
+                        },
+                    ),
+                    (
+                        2,
+                        TransactionLog {
+                            amount: TransactionActionState::Withdrawal { amount: dec!(100) },
+                            state: TransactionState::Chargeback,
+                            seq: 4,
+                        },
+                    ),
+                ]),
+                pending_holds: FxHashMap::default(),
+                open_disputes: 0,
+                chargebacks: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn account_should_be_frozen_if_account_is_locked() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(1, 1, Transaction::Dispute);
+        accounts.add_transaction(1, 1, Transaction::Chargeback);
+        //after chargeback, account is fronze. that means transactions after chargeback should be ignored
+        accounts.add_transaction(1, 2, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(1, 3, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(1, 4, Transaction::Deposit { amount: dec!(100) });
+
+        assert_eq!(
+            accounts.get_user_account(1),
+            Some(&UserAccount {
+                available: dec!(0),
+                held: dec!(0),
+                locked: true,
+                transaction_log: FxHashMap::from_iter([(
+                    1,
+                    TransactionLog {
+                        amount: TransactionActionState::Deposit { amount: dec!(100) },
+                        state: TransactionState::Chargeback,
+                        seq: 3,
+                    },
+                )]),
+                pending_holds: FxHashMap::default(),
+                open_disputes: 0,
+                chargebacks: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn hold_should_move_available_money_to_held_and_capture_should_remove_it() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(
+            1,
+            2,
+            Transaction::Hold {
+                amount: dec!(60),
+                expires_after: 10,
+            },
+        );
+
+        let account = accounts.get_user_account(1).unwrap();
+        assert_eq!(account.available, dec!(40));
+        assert_eq!(account.held, dec!(60));
+
+        accounts.add_transaction(1, 2, Transaction::Capture);
+
+        assert_eq!(
+            accounts.get_user_account(1),
+            Some(&UserAccount {
+                available: dec!(40),
+                held: dec!(0),
+                locked: false,
+                transaction_log: FxHashMap::from_iter([
+                    (
+                        1,
+                        TransactionLog {
+                            amount: TransactionActionState::Deposit { amount: dec!(100) },
+                            state: TransactionState::Resolve,
+                            seq: 1,
+                        },
+                    ),
+                    (
+                        2,
+                        TransactionLog {
+                            amount: TransactionActionState::Hold { amount: dec!(60) },
+                            state: TransactionState::Captured,
+                            seq: 3,
+                        },
+                    ),
+                ]),
+                pending_holds: FxHashMap::default(),
+                open_disputes: 0,
+                chargebacks: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn released_hold_should_return_money_to_available_and_not_be_disputable() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(
+            1,
+            2,
+            Transaction::Hold {
+                amount: dec!(60),
+                expires_after: 10,
+            },
+        );
+        accounts.add_transaction(1, 2, Transaction::Dispute);
+        accounts.add_transaction(1, 2, Transaction::Release);
+        accounts.add_transaction(1, 2, Transaction::Capture);
+
+        let account = accounts.get_user_account(1).unwrap();
+        assert_eq!(account.available, dec!(100));
+        assert_eq!(account.held, dec!(0));
+        assert_eq!(
+            account.transaction_log.get(&2).map(|log| &log.state),
+            Some(&TransactionState::Released)
+        );
+    }
+
+    #[test]
+    fn hold_should_be_released_after_expiry_and_be_ignored_if_money_is_not_enough() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(
+            1,
+            2,
+            Transaction::Hold {
+                amount: dec!(500),
+                expires_after: 1,
+            },
+        );
+        accounts.add_transaction(
+            1,
+            3,
+            Transaction::Hold {
+                amount: dec!(70),
+                expires_after: 2,
+            },
+        );
+        accounts.add_transaction(1, 4, Transaction::Deposit { amount: dec!(10) });
+
+        let account = accounts.get_user_account(1).unwrap();
+        assert_eq!(account.available, dec!(40));
+        assert_eq!(account.held, dec!(70));
+        assert_eq!(account.transaction_log.get(&2), None);
+
+        accounts.add_transaction(1, 5, Transaction::Deposit { amount: dec!(10) });
+        accounts.add_transaction(1, 3, Transaction::Capture);
+
+        let account = accounts.get_user_account(1).unwrap();
+        assert_eq!(account.available, dec!(120));
+        assert_eq!(account.held, dec!(0));
+        assert!(account.pending_holds.is_empty());
+    }
+
+    #[test]
+    fn retried_transaction_with_same_idempotency_key_and_payload_should_be_applied_once() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        for retry in 0..3 {
+            let expected = if retry == 0 {
+                TransactionOutcome::Applied
+            } else {
+                TransactionOutcome::Rejected(RejectionReason::DuplicateTransaction)
+            };
+            assert_eq!(
+                accounts.add_idempotent_transaction("dispute-1", 1, 1, Transaction::Dispute),
+                Ok(expected)
+            );
+            assert_eq!(
+                accounts.add_idempotent_transaction("resolve-1", 1, 1, Transaction::Resolve),
+                Ok(expected)
+            );
+        }
+        assert_eq!(
+            accounts.add_idempotent_transaction("dispute-1", 1, 1, Transaction::Dispute),
+            Ok(TransactionOutcome::Rejected(
+                RejectionReason::DuplicateTransaction
+            ))
+        );
+
+        let account = accounts.get_user_account(1).unwrap();
+        assert_eq!(account.available, dec!(100));
+        assert_eq!(account.held, dec!(0));
+        assert_eq!(
+            account.transaction_log.get(&1).map(|log| &log.state),
+            Some(&TransactionState::Resolve)
+        );
+    }
+
+    #[test]
+    fn same_idempotency_key_with_different_payload_should_be_error() {
+        let mut accounts = Accounts::new();
+        assert_eq!(
+            accounts.add_idempotent_transaction(
+                "key-1",
+                1,
+                1,
+                Transaction::Deposit { amount: dec!(100) }
+            ),
+            Ok(TransactionOutcome::Applied)
+        );
+        assert_eq!(
+            accounts.add_idempotent_transaction(
+                "key-1",
+                1,
+                1,
+                Transaction::Deposit { amount: dec!(200) }
+            ),
+            Err(IdempotencyConflict {
+                key: String::from("key-1")
+            })
+        );
+        assert_eq!(
+            accounts.add_idempotent_transaction(
+                "key-1",
+                2,
+                1,
+                Transaction::Deposit { amount: dec!(100) }
+            ),
+            Err(IdempotencyConflict {
+                key: String::from("key-1")
+            })
+        );
+
+        assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(100));
+        assert_eq!(accounts.get_user_account(2), None);
+    }
+
+    #[test]
+    fn restored_account_should_continue_from_given_balances() {
+        let mut accounts = Accounts::new();
+        accounts.restore_user_account(1, dec!(10), dec!(5), false);
+        accounts.restore_user_account(2, dec!(10), dec!(0), true);
+        accounts.add_transaction(1, 1, Transaction::Withdrawal { amount: dec!(4) });
+        accounts.add_transaction(2, 2, Transaction::Deposit { amount: dec!(4) });
+
+        assert_eq!(
+            accounts.get_user_account(1),
+            Some(&UserAccount {
+                available: dec!(6),
+                held: dec!(5),
+                locked: false,
+                transaction_log: FxHashMap::from_iter([(
+                    1,
+                    TransactionLog {
+                        amount: TransactionActionState::Withdrawal { amount: dec!(4) },
+                        state: TransactionState::Resolve,
+                        seq: 1,
+                    },
+                )]),
+                pending_holds: FxHashMap::default(),
+                open_disputes: 0,
+                chargebacks: 0,
+            })
+        );
+        assert_eq!(accounts.get_user_account(2).unwrap().available, dec!(10));
+    }
+
+    #[test]
+    fn restored_account_should_keep_transaction_log_and_tx_ids() {
+        let mut accounts = Accounts::new();
+        accounts.restore_account(
+            1,
+            UserAccount {
+                available: dec!(10),
+                held: dec!(0),
+                locked: false,
+                transaction_log: FxHashMap::from_iter([(
+                    1,
+                    TransactionLog {
+                        amount: TransactionActionState::Deposit { amount: dec!(10) },
+                        state: TransactionState::Resolve,
+                        seq: 0,
+                    },
+                )]),
+                pending_holds: FxHashMap::default(),
+                open_disputes: 0,
+                chargebacks: 0,
+            },
+        );
+
+        assert_eq!(
+            accounts.add_transaction(2, 1, Transaction::Deposit { amount: dec!(1) }),
+            TransactionOutcome::Rejected(RejectionReason::DuplicateTransaction)
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 1, Transaction::Dispute),
+            TransactionOutcome::Applied
+        );
+        assert_eq!(accounts.get_user_account(1).unwrap().held, dec!(10));
+    }
+
+    #[test]
+    fn accounts_of_different_clients_should_be_merged() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        let mut other = Accounts::new();
+        other.add_transaction(2, 2, Transaction::Deposit { amount: dec!(50) });
+
+        assert_eq!(accounts.merge(other), Ok(()));
+        assert_eq!(accounts.get_user_account(2).unwrap().available, dec!(50));
+
+        // tx ids of the merged accounts are still deduplicated
+        accounts.add_transaction(1, 2, Transaction::Deposit { amount: dec!(100) });
+        assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(100));
+
+        let mut other = Accounts::new();
+        other.add_transaction(1, 3, Transaction::Deposit { amount: dec!(50) });
+        assert_eq!(accounts.merge(other), Err(MergeConflict { client: 1 }));
+        assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(100));
+    }
+
+    #[test]
+    fn rejected_transaction_should_report_the_reason() {
+        let mut accounts = Accounts::new();
+        let deposit = Transaction::Deposit { amount: dec!(100) };
+        let rejected = TransactionOutcome::Rejected;
+
+        assert_eq!(
+            accounts.add_transaction(1, 1, Transaction::Dispute),
+            rejected(RejectionReason::AccountNotFound)
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 1, deposit.clone()),
+            TransactionOutcome::Applied
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 1, deposit.clone()),
+            rejected(RejectionReason::DuplicateTransaction)
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 2, Transaction::Withdrawal { amount: dec!(101) }),
+            rejected(RejectionReason::InsufficientFunds)
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 3, Transaction::Dispute),
+            rejected(RejectionReason::UnknownTransaction)
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 1, Transaction::Resolve),
+            rejected(RejectionReason::InvalidTransactionState)
+        );
+        accounts.add_transaction(1, 1, Transaction::Dispute);
+        assert_eq!(
+            accounts.add_transaction(1, 1, Transaction::Chargeback),
+            TransactionOutcome::Applied
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 4, deposit),
+            rejected(RejectionReason::AccountLocked)
+        );
+    }
+
+    #[test]
+    fn accounts_with_capacity_should_process_transactions_like_default_accounts() {
+        let mut accounts = Accounts::with_capacity(10, 1000);
+        let mut expected = Accounts::new();
+        for (tx, transaction) in [
+            Transaction::Deposit { amount: dec!(3) },
+            Transaction::Withdrawal { amount: dec!(1) },
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            accounts.add_transaction(1, tx as u32, transaction.clone());
+            expected.add_transaction(1, tx as u32, transaction);
+        }
+
+        assert_eq!(accounts.get_user_account(1), expected.get_user_account(1));
+        assert!(
+            accounts
+                .get_user_account(1)
+                .unwrap()
+                .transaction_log
+                .capacity()
+                >= 100
+        );
+        assert_eq!(
+            accounts.add_transaction(2, 0, Transaction::Deposit { amount: dec!(1) }),
+            TransactionOutcome::Rejected(RejectionReason::DuplicateTransaction)
+        );
+    }
+
+    #[test]
+    fn shared_accounts_should_apply_transactions_from_several_threads_like_accounts() {
+        let shared = SharedAccounts::new();
+        thread::scope(|scope| {
+            for client in 0..8u16 {
+                let shared = &shared;
+                scope.spawn(move || {
+                    let tx = client as u32 * 1000;
+                    for i in 0..100 {
+                        shared.add_transaction(
+                            client,
+                            tx + i,
+                            Transaction::Deposit { amount: dec!(2) },
+                        );
+                    }
+                    shared.add_transaction(
+                        client,
+                        tx + 100,
+                        Transaction::Withdrawal { amount: dec!(50) },
+                    );
+                    shared.add_transaction(client, tx, Transaction::Dispute);
+                });
+            }
+        });
+
+        let mut expected = Accounts::new();
+        for client in 0..8u16 {
+            let tx = client as u32 * 1000;
+            for i in 0..100 {
+                expected.add_transaction(client, tx + i, Transaction::Deposit { amount: dec!(2) });
+            }
+            expected.add_transaction(
+                client,
+                tx + 100,
+                Transaction::Withdrawal { amount: dec!(50) },
+            );
+            expected.add_transaction(client, tx, Transaction::Dispute);
+        }
+        // the sequence numbers depend on how the threads interleave
+        let without_seq = |account: Option<&UserAccount>| {
+            account.cloned().map(|mut x| {
+                x.transaction_log.values_mut().for_each(|log| log.seq = 0);
+                x
+            })
+        };
+        for client in 0..8u16 {
+            assert_eq!(
+                without_seq(shared.get_user_account(client).as_ref()),
+                without_seq(expected.get_user_account(client))
+            );
+        }
+        let accounts = shared.into_accounts();
+        assert_eq!(
+            without_seq(accounts.get_user_account(3)),
+            without_seq(expected.get_user_account(3))
+        );
+    }
+
+    #[test]
+    fn shared_accounts_should_apply_a_tx_id_used_from_several_threads_once() {
+        let shared = SharedAccounts::new();
+        let outcomes: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..8u16)
+                .map(|client| {
+                    let shared = &shared;
+                    scope.spawn(move || {
+                        shared.add_transaction(client, 1, Transaction::Deposit { amount: dec!(1) })
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|x| x.join().unwrap()).collect()
+        });
+
+        assert_eq!(
+            outcomes
+                .iter()
+                .filter(|x| **x == TransactionOutcome::Applied)
+                .count(),
+            1
+        );
+        assert_eq!(shared.snapshot().get_user_accounts().count(), 1);
+    }
+
+    #[test]
+    fn shared_accounts_should_keep_the_state_of_the_accounts_it_was_made_from() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(5) });
+        let shared = SharedAccounts::from(accounts);
+
+        assert_eq!(
+            shared.add_transaction(2, 1, Transaction::Deposit { amount: dec!(1) }),
+            TransactionOutcome::Rejected(RejectionReason::DuplicateTransaction)
+        );
+        assert_eq!(
+            shared.add_transaction(1, 1, Transaction::Dispute),
+            TransactionOutcome::Applied
+        );
+        assert_eq!(shared.get_user_account(1).unwrap().held, dec!(5));
+        assert_eq!(
+            shared.find_transaction(1).map(|x| (x.0, x.1.state)),
+            Some((1, TransactionState::Dispute))
+        );
+    }
+
+    #[test]
+    fn shared_accounts_should_be_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SharedAccounts>();
+    }
+
+    #[test]
+    fn transaction_that_would_overflow_the_balances_should_be_rejected() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(
+            1,
+            1,
+            Transaction::Deposit {
+                amount: Decimal::MAX,
+            },
+        );
+
+        assert_eq!(
+            accounts.add_transaction(1, 2, Transaction::Deposit { amount: dec!(1) }),
+            TransactionOutcome::Rejected(RejectionReason::AmountOverflow)
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 1, Transaction::Dispute),
+            TransactionOutcome::Applied
+        );
+        assert_eq!(accounts.get_user_account(1).unwrap().held, Decimal::MAX);
+    }
+
+    #[test]
+    fn state_at_should_rebuild_the_accounts_as_of_the_given_sequence_number() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(9, 1, Transaction::Deposit { amount: dec!(10) });
+        accounts.enable_history();
+        accounts.add_transaction(9, 2, Transaction::Withdrawal { amount: dec!(4) });
+        accounts.add_transaction(9, 3, Transaction::Withdrawal { amount: dec!(40) });
+        accounts.add_transaction(9, 1, Transaction::Dispute);
+
+        let history = accounts.history().unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(
+            history[1].outcome,
+            TransactionOutcome::Rejected(RejectionReason::InsufficientFunds)
+        );
+        assert_eq!(
+            history.iter().map(|x| x.seq).collect::<Vec<_>>(),
+            vec![2, 2, 3]
+        );
+        let dispute = accounts.get_user_account(9).unwrap().transaction_log[&1].seq;
+        assert_eq!(dispute, accounts.last_seq());
+        let before = accounts.state_at(dispute - 1).unwrap();
+        assert_eq!(before.get_user_account(9).unwrap().available, dec!(6));
+        assert_eq!(before.get_user_account(9).unwrap().held, dec!(0));
+        assert_eq!(before.last_seq(), 2);
+        assert_eq!(
+            accounts
+                .state_at(1)
+                .unwrap()
+                .get_user_account(9)
+                .unwrap()
+                .available,
+            dec!(10)
+        );
+        assert_eq!(
+            accounts.state_at(3).unwrap().get_user_account(9),
+            accounts.get_user_account(9)
+        );
+        assert!(accounts.state_at(0).is_none());
+        assert!(accounts.state_at(4).is_none());
+    }
+
+    #[test]
+    fn state_at_should_be_none_without_history() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(1) });
+
+        assert!(accounts.history().is_none());
+        assert!(accounts.state_at(0).is_none());
+    }
+
+    #[test]
+    fn simulate_should_report_outcomes_without_changing_the_account() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(10) });
+        let account = accounts.get_user_account(1).unwrap();
+
+        let result = account.simulate(&[
+            (2, Transaction::Withdrawal { amount: dec!(4) }),
+            (3, Transaction::Withdrawal { amount: dec!(7) }),
+            (1, Transaction::Dispute),
+            (1, Transaction::Deposit { amount: dec!(1) }),
+        ]);
+
+        assert_eq!(
+            result.outcomes,
+            vec![
+                TransactionOutcome::Applied,
+                TransactionOutcome::Rejected(RejectionReason::InsufficientFunds),
+                TransactionOutcome::Applied,
+                TransactionOutcome::Rejected(RejectionReason::DuplicateTransaction),
+            ]
+        );
+        assert!(!result.all_applied());
+        assert_eq!(result.account.available, dec!(-4));
+        assert_eq!(result.account.held, dec!(10));
+        assert_eq!(account.available, dec!(10));
+        assert_eq!(account.transaction_log.len(), 1);
+    }
+
+    #[test]
+    fn forget_client_should_erase_the_history_and_keep_the_balances() {
+        let mut accounts = Accounts::new();
+        accounts.enable_history();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(10) });
+        accounts.add_transaction(1, 2, Transaction::Deposit { amount: dec!(5) });
+        accounts.add_transaction(1, 2, Transaction::Dispute);
+        accounts.add_transaction(2, 3, Transaction::Deposit { amount: dec!(1) });
+
+        assert_eq!(accounts.forget_client(1), Some(2));
+        assert_eq!(accounts.forget_client(3), None);
+        assert_eq!(accounts.history().unwrap().len(), 1);
+        let account = accounts.get_user_account(1).unwrap();
+        assert_eq!((account.available, account.held), (dec!(10), dec!(5)));
+        assert!(account.transaction_log.is_empty());
+        assert_eq!(
+            accounts.add_transaction(1, 2, Transaction::Resolve),
+            TransactionOutcome::Rejected(RejectionReason::UnknownTransaction)
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(1) }),
+            TransactionOutcome::Rejected(RejectionReason::DuplicateTransaction)
+        );
+        assert_eq!(
+            accounts.get_user_account(2).unwrap().transaction_log.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn admin_operations_should_unlock_adjust_and_resolve_locked_accounts() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(10) });
+        accounts.add_transaction(1, 2, Transaction::Deposit { amount: dec!(5) });
+        accounts.add_transaction(1, 1, Transaction::Dispute);
+        accounts.add_transaction(1, 2, Transaction::Dispute);
+        accounts.add_transaction(1, 2, Transaction::Chargeback);
+        assert_eq!(
+            accounts.add_transaction(1, 1, Transaction::Resolve),
+            TransactionOutcome::Rejected(RejectionReason::AccountLocked)
+        );
+
+        assert_eq!(
+            accounts.force_resolve(1),
+            Some((1, TransactionOutcome::Applied))
+        );
+        assert_eq!(
+            accounts.force_resolve(1),
+            Some((
+                1,
+                TransactionOutcome::Rejected(RejectionReason::InvalidTransactionState)
+            ))
+        );
+        assert_eq!(accounts.force_resolve(3), None);
+        assert_eq!(
+            accounts.adjust_balance(1, dec!(-11)),
+            TransactionOutcome::Rejected(RejectionReason::InsufficientFunds)
+        );
+        assert_eq!(
+            accounts.adjust_balance(1, dec!(-2.5)),
+            TransactionOutcome::Applied
+        );
+        assert_eq!(
+            accounts.adjust_balance(2, dec!(1)),
+            TransactionOutcome::Rejected(RejectionReason::AccountNotFound)
+        );
+        let account = accounts.get_user_account(1).unwrap();
+        assert_eq!((account.available, account.held), (dec!(7.5), dec!(0)));
+        assert!(account.locked);
+
+        assert!(accounts.unlock_account(1));
+        assert!(!accounts.unlock_account(2));
+        assert_eq!(
+            accounts.add_transaction(1, 3, Transaction::Withdrawal { amount: dec!(7.5) }),
+            TransactionOutcome::Applied
+        );
+        assert_eq!(accounts.get_user_account(1).unwrap().chargeback_count(), 1);
+    }
+
+    #[test]
+    fn force_resolve_should_not_age_the_pending_holds() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(10) });
+        accounts.add_transaction(1, 2, Transaction::Deposit { amount: dec!(5) });
+        let hold = Transaction::Hold {
+            amount: dec!(3),
+            expires_after: 2,
+        };
+        accounts.add_transaction(1, 3, hold);
+        accounts.add_transaction(1, 2, Transaction::Dispute);
+
+        assert_eq!(
+            accounts.force_resolve(2),
+            Some((1, TransactionOutcome::Applied))
+        );
+        let account = accounts.get_user_account(1).unwrap();
+        assert_eq!(account.pending_holds.get(&3), Some(&1));
+        assert_eq!((account.available, account.held), (dec!(12), dec!(3)));
+        // the next transaction of the client expires it
+        accounts.add_transaction(1, 4, Transaction::Deposit { amount: dec!(1) });
+        let account = accounts.get_user_account(1).unwrap();
+        assert!(account.pending_holds.is_empty());
+        assert_eq!((account.available, account.held), (dec!(16), dec!(0)));
+    }
+
+    struct Bonus;
+
+    impl TransactionHandler for Bonus {
+        fn apply(
+            &self,
+            account: &mut UserAccount,
+            _tx: u32,
+            amount: Option<Decimal>,
+        ) -> TransactionOutcome {
+            match amount {
+                Some(x) => {
+                    account.available += x;
+                    TransactionOutcome::Applied
+                }
+                None => TransactionOutcome::Rejected(RejectionReason::InvalidTransactionState),
+            }
+        }
+
+        fn opens_transaction(&self) -> bool {
+            true
+        }
+    }
 
     #[test]
-    fn deposit_data_should_be_disputed_if_that_data_is_resolved() {
+    fn custom_transaction_should_be_applied_by_its_registered_handler() {
         let mut accounts = Accounts::new();
-        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
-        accounts.add_transaction(1, 1, Transaction::Dispute);
+        accounts.register_handler("bonus", Bonus);
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(10) });
 
+        assert_eq!(accounts.handled_type("bonus"), Some("bonus"));
+        assert_eq!(accounts.handled_type("reversal"), None);
         assert_eq!(
-            accounts.get_user_account(1),
-            Some(&UserAccount {
-                available: dec!(0),
-                held: dec!(100),
-                locked: false,
-                transaction_log: HashMap::from([(
-                    1,
-                    TransactionLog {
-                        amount: TransactionActionState::Deposit { amount: dec!(100) },
-                        state: TransactionState::Dispute,
-                    },
-                )]),
-            })
+            accounts.add_custom_transaction("reversal", 1, 2, None),
+            None
+        );
+        assert_eq!(
+            accounts.add_custom_transaction("bonus", 1, 2, Some(dec!(5))),
+            Some(TransactionOutcome::Applied)
+        );
+        assert_eq!(
+            accounts.add_custom_transaction("bonus", 1, 2, Some(dec!(5))),
+            Some(TransactionOutcome::Rejected(
+                RejectionReason::DuplicateTransaction
+            ))
+        );
+        assert_eq!(
+            accounts.add_custom_transaction("bonus", 2, 3, Some(dec!(5))),
+            Some(TransactionOutcome::Rejected(
+                RejectionReason::AccountNotFound
+            ))
+        );
+        assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(15));
+
+        accounts.lock_account(1);
+        assert_eq!(
+            accounts.add_custom_transaction("bonus", 1, 4, Some(dec!(5))),
+            Some(TransactionOutcome::Rejected(RejectionReason::AccountLocked))
         );
     }
 
     #[test]
-    fn disputed_deposit_data_should_be_resolved_if_resovle_transaction_data_come() {
+    fn policies_should_round_amounts_and_reject_withdrawal_disputes() {
+        let mut accounts =
+            Accounts::with_registry(TransactionRegistry::with_hasher(RegistryHasher::sip()));
+        accounts.set_policies(Policies {
+            dispute: DisputePolicy::DepositsOnly,
+            precision: PrecisionPolicy::Round {
+                decimal_places: 2,
+                strategy: RoundingStrategy::ToZero,
+            },
+            ..Policies::default()
+        });
+        accounts.add_transaction(
+            1,
+            1,
+            Transaction::Deposit {
+                amount: dec!(2.999),
+            },
+        );
+        accounts.add_transaction(
+            1,
+            2,
+            Transaction::Withdrawal {
+                amount: dec!(1.001),
+            },
+        );
+
+        assert_eq!(
+            accounts.add_transaction(1, 2, Transaction::Dispute),
+            TransactionOutcome::Rejected(RejectionReason::InvalidTransactionState)
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(1) }),
+            TransactionOutcome::Rejected(RejectionReason::DuplicateTransaction)
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 1, Transaction::Dispute),
+            TransactionOutcome::Applied
+        );
+        let account = accounts.get_user_account(1).unwrap();
+        assert_eq!((account.available, account.held), (dec!(-1), dec!(2.99)));
+
+        let shared = SharedAccounts::from(accounts);
+        assert_eq!(
+            shared.add_transaction(
+                1,
+                3,
+                Transaction::Deposit {
+                    amount: dec!(0.555)
+                }
+            ),
+            TransactionOutcome::Applied
+        );
+        assert_eq!(
+            shared.add_transaction(1, 3, Transaction::Deposit { amount: dec!(1) }),
+            TransactionOutcome::Rejected(RejectionReason::DuplicateTransaction)
+        );
+        assert_eq!(shared.get_user_account(1).unwrap().available, dec!(-0.45));
+    }
+
+    #[test]
+    fn display_should_give_compact_one_line_forms() {
         let mut accounts = Accounts::new();
-        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(1.5) });
+        accounts.add_transaction(1, 2, Transaction::Withdrawal { amount: dec!(0.5) });
+        let outcome = accounts.add_transaction(1, 3, Transaction::Withdrawal { amount: dec!(5) });
         accounts.add_transaction(1, 1, Transaction::Dispute);
-        accounts.add_transaction(1, 1, Transaction::Resolve);
 
+        assert_eq!(outcome.to_string(), "rejected: insufficient_funds");
+        assert_eq!(TransactionOutcome::Applied.to_string(), "applied");
         assert_eq!(
-            accounts.get_user_account(1),
-            Some(&UserAccount {
-                available: dec!(100),
-                held: dec!(0),
-                locked: false,
-                transaction_log: HashMap::from([(
-                    1,
-                    TransactionLog {
-                        amount: TransactionActionState::Deposit { amount: dec!(100) },
-                        state: TransactionState::Resolve,
-                    },
-                )]),
-            })
+            Transaction::Hold {
+                amount: dec!(2),
+                expires_after: 10
+            }
+            .to_string(),
+            "hold 2 expiring after 10"
+        );
+        let account = accounts.get_user_account(1).unwrap();
+        assert_eq!(
+            account.transaction_log[&1].to_string(),
+            "deposit 1.5 (dispute)"
+        );
+        assert_eq!(account.to_string(), "available -0.5, held 1.5, total 1.0");
+
+        accounts.add_transaction(1, 1, Transaction::Chargeback);
+        assert_eq!(
+            accounts.get_user_account(1).unwrap().to_string(),
+            "available -0.5, held 0.0, total -0.5, locked"
         );
     }
 
     #[test]
-    fn deposite_data_should_be_charge_back_if_that_data_is_disputed() {
+    fn accounts_should_be_iterated_by_reference_and_consumed() {
         let mut accounts = Accounts::new();
-        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
+        assert!(accounts.is_empty());
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(1) });
+        accounts.add_transaction(2, 2, Transaction::Deposit { amount: dec!(2) });
+
+        assert_eq!(accounts.len(), 2);
+        assert_eq!((&accounts).into_iter().len(), 2);
+        let total: Decimal = (&accounts).into_iter().map(|x| x.1.available).sum();
+        assert_eq!(total, dec!(3));
+
+        let mut owned: Vec<(u16, UserAccount)> = accounts.into_iter().collect();
+        owned.sort_by_key(|x| x.0);
+        assert_eq!(owned[1].0, 2);
+        assert_eq!(owned[1].1.available, dec!(2));
+    }
+
+    // declines withdrawals above the limit and counts the applied transactions
+    struct WithdrawalLimit {
+        limit: Decimal,
+        applied: Arc<AtomicUsize>,
+    }
+
+    impl Middleware for WithdrawalLimit {
+        fn before(
+            &self,
+            _client: u16,
+            _tx: u32,
+            transaction: &Transaction,
+            account: Option<&UserAccount>,
+        ) -> Decision {
+            match transaction {
+                Transaction::Withdrawal { amount } if *amount > self.limit => {
+                    Decision::Reject(RejectionReason::Declined)
+                }
+                Transaction::Withdrawal { .. } if account.is_none() => {
+                    Decision::Reject(RejectionReason::AccountNotFound)
+                }
+                _ => Decision::Allow,
+            }
+        }
+
+        fn after(
+            &self,
+            _client: u16,
+            _tx: u32,
+            _transaction: &Transaction,
+            account: Option<&UserAccount>,
+            outcome: TransactionOutcome,
+        ) {
+            if outcome == TransactionOutcome::Applied && account.is_some() {
+                self.applied.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    #[test]
+    fn middleware_should_decline_transactions_before_they_are_applied() {
+        let mut accounts = Accounts::new();
+        accounts.enable_history();
+        let applied = Arc::new(AtomicUsize::new(0));
+        accounts.add_middleware(WithdrawalLimit {
+            limit: dec!(5),
+            applied: applied.clone(),
+        });
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(10) });
+
+        assert_eq!(
+            accounts.add_transaction(1, 2, Transaction::Withdrawal { amount: dec!(6) }),
+            TransactionOutcome::Rejected(RejectionReason::Declined)
+        );
+        assert_eq!(
+            accounts.add_transaction(2, 3, Transaction::Withdrawal { amount: dec!(1) }),
+            TransactionOutcome::Rejected(RejectionReason::AccountNotFound)
+        );
+        // the declined tx id is still free
+        assert_eq!(
+            accounts.add_transaction(1, 2, Transaction::Withdrawal { amount: dec!(4) }),
+            TransactionOutcome::Applied
+        );
+        assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(6));
+        assert_eq!(applied.load(Ordering::Relaxed), 2);
+        assert_eq!(accounts.history().unwrap().len(), 2);
+        assert_eq!(
+            accounts
+                .state_at(2)
+                .unwrap()
+                .get_user_account(1)
+                .unwrap()
+                .available,
+            dec!(6)
+        );
+    }
+
+    #[test]
+    fn open_disputes_of_locked_accounts_should_be_settled_with_the_policy() {
+        let mut accounts = Accounts::new();
+        accounts.set_policies(Policies {
+            locked_account: LockedAccountPolicy::SettleOpenDisputes,
+            ..Policies::default()
+        });
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(5) });
+        accounts.add_transaction(1, 2, Transaction::Deposit { amount: dec!(3) });
+        accounts.add_transaction(1, 3, Transaction::Deposit { amount: dec!(2) });
         accounts.add_transaction(1, 1, Transaction::Dispute);
+        accounts.add_transaction(1, 2, Transaction::Dispute);
+        accounts.add_transaction(1, 3, Transaction::Dispute);
         accounts.add_transaction(1, 1, Transaction::Chargeback);
 
         assert_eq!(
-            accounts.get_user_account(1),
-            Some(&UserAccount {
-                available: dec!(0),
-                held: dec!(0),
-                locked: true,
-                transaction_log: HashMap::from([(
-                    1,
-                    TransactionLog {
-                        amount: TransactionActionState::Deposit { amount: dec!(100) },
-                        state: TransactionState::Chargeback,
-                    },
-                )]),
-            })
+            accounts.add_transaction(1, 4, Transaction::Deposit { amount: dec!(1) }),
+            TransactionOutcome::Rejected(RejectionReason::AccountLocked)
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 2, Transaction::Resolve),
+            TransactionOutcome::Applied
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 3, Transaction::Chargeback),
+            TransactionOutcome::Applied
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 2, Transaction::Dispute),
+            TransactionOutcome::Rejected(RejectionReason::AccountLocked)
+        );
+        let account = accounts.get_user_account(1).unwrap();
+        assert_eq!((account.available, account.held), (dec!(3), dec!(0)));
+        assert_eq!(account.chargeback_count(), 2);
+
+        accounts.set_policies(Policies::default());
+        assert_eq!(
+            accounts.add_transaction(1, 2, Transaction::Resolve),
+            TransactionOutcome::Rejected(RejectionReason::AccountLocked)
         );
     }
 
     #[test]
-    fn withdrawal_data_should_be_charge_back_if_that_data_is_disputed() {
+    fn any_first_record_should_create_an_account_with_the_policy() {
         let mut accounts = Accounts::new();
-        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
-        accounts.add_transaction(1, 2, Transaction::Withdrawal { amount: dec!(100) });
+        assert_eq!(
+            accounts.add_transaction(1, 1, Transaction::Withdrawal { amount: dec!(1) }),
+            TransactionOutcome::Rejected(RejectionReason::AccountNotFound)
+        );
+        assert!(accounts.get_user_account(1).is_none());
+
+        accounts.set_policies(Policies {
+            first_transaction: FirstTransactionPolicy::AnyRecord,
+            ..Policies::default()
+        });
+        assert_eq!(
+            accounts.add_transaction(1, 2, Transaction::Withdrawal { amount: dec!(1) }),
+            TransactionOutcome::Rejected(RejectionReason::InsufficientFunds)
+        );
+        assert_eq!(
+            accounts.add_transaction(2, 3, Transaction::Dispute),
+            TransactionOutcome::Rejected(RejectionReason::UnknownTransaction)
+        );
+        assert_eq!(
+            accounts.add_transaction(3, 4, Transaction::Deposit { amount: dec!(2) }),
+            TransactionOutcome::Applied
+        );
+        for client in [1, 2] {
+            let account = accounts.get_user_account(client).unwrap();
+            assert_eq!((account.available, account.held), (dec!(0), dec!(0)));
+        }
+        assert_eq!(accounts.get_user_account(3).unwrap().available, dec!(2));
+        assert_eq!(accounts.len(), 3);
+    }
+
+    #[test]
+    fn withdrawal_chargebacks_should_be_refunded_with_the_policy() {
+        let mut accounts = Accounts::new();
+        accounts.set_policies(Policies {
+            dispute: DisputePolicy::DepositsAndRefundedWithdrawals,
+            ..Policies::default()
+        });
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(10) });
+        accounts.add_transaction(1, 2, Transaction::Withdrawal { amount: dec!(4) });
         accounts.add_transaction(1, 2, Transaction::Dispute);
-        accounts.add_transaction(1, 2, Transaction::Chargeback);
+        assert_eq!(
+            accounts.add_transaction(1, 2, Transaction::Chargeback),
+            TransactionOutcome::Applied
+        );
 
+        let account = accounts.get_user_account(1).unwrap();
         assert_eq!(
-            accounts.get_user_account(1),
-            Some(&UserAccount {
-                available: dec!(0),
-                held: dec!(0),
-                locked: true,
-                transaction_log: HashMap::from([
-                    (
-                        1,
-                        TransactionLog {
-                            amount: TransactionActionState::Deposit { amount: dec!(100) },
-                            state: TransactionState::Resolve,
-                        },
-                    ),
-                    (
-                        2,
-                        TransactionLog {
-                            amount: TransactionActionState::Withdrawal { amount: dec!(100) },
-                            state: TransactionState::Chargeback,
-                        },
-                    ),
-                ]),
-            })
+            (account.available, account.held, account.locked),
+            (dec!(10), dec!(0), true)
+        );
+        assert_eq!(
+            account.transaction_log[&2].state,
+            TransactionState::Refunded
         );
+        assert_eq!(account.chargeback_count(), 1);
+        assert_eq!(crate::invariants::check(&accounts), Ok(()));
     }
 
     #[test]
-    fn account_should_be_frozen_if_account_is_locked() {
+    fn limits_should_reject_large_transactions_and_deposits_over_the_balance_cap() {
         let mut accounts = Accounts::new();
-        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(100) });
-        accounts.add_transaction(1, 1, Transaction::Dispute);
-        accounts.add_transaction(1, 1, Transaction::Chargeback);
-        //after chargeback, account is fronze. that means transactions after chargeback should be ignored
+        accounts.set_policies(Policies {
+            limits: Limits {
+                max_amount: Some(dec!(100)),
+                max_total: Some(dec!(150)),
+            },
+            ..Policies::default()
+        });
+        let rejected = TransactionOutcome::Rejected;
+
+        assert_eq!(
+            accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(101) }),
+            rejected(RejectionReason::AmountLimitExceeded)
+        );
+        assert!(accounts.get_user_account(1).is_none());
         accounts.add_transaction(1, 2, Transaction::Deposit { amount: dec!(100) });
-        accounts.add_transaction(1, 3, Transaction::Deposit { amount: dec!(100) });
-        accounts.add_transaction(1, 4, Transaction::Deposit { amount: dec!(100) });
+        assert_eq!(
+            accounts.add_transaction(1, 3, Transaction::Deposit { amount: dec!(51) }),
+            rejected(RejectionReason::BalanceLimitExceeded)
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 4, Transaction::Deposit { amount: dec!(50) }),
+            TransactionOutcome::Applied
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 5, Transaction::Withdrawal { amount: dec!(120) }),
+            rejected(RejectionReason::AmountLimitExceeded)
+        );
+        assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(150));
+    }
+
+    #[test]
+    fn applied_transactions_should_log_increasing_sequence_numbers() {
+        let mut accounts = Accounts::new();
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(10) });
+        accounts.add_transaction(2, 2, Transaction::Deposit { amount: dec!(5) });
+        accounts.add_transaction(1, 3, Transaction::Withdrawal { amount: dec!(50) });
+        accounts.add_transaction(1, 1, Transaction::Dispute);
 
+        let seq = |client, tx| accounts.get_user_account(client).unwrap().transaction_log[&tx].seq;
+        assert_eq!((seq(1, 1), seq(2, 2)), (3, 2));
+        assert_eq!(accounts.last_seq(), 3);
+
+        let shared = SharedAccounts::from(accounts.clone());
+        shared.add_transaction(2, 4, Transaction::Withdrawal { amount: dec!(50) });
+        shared.add_transaction(2, 5, Transaction::Deposit { amount: dec!(1) });
+        shared.add_transaction(3, 6, Transaction::Deposit { amount: dec!(1) });
+        // the rejected withdrawal leaves no gap
         assert_eq!(
-            accounts.get_user_account(1),
-            Some(&UserAccount {
-                available: dec!(0),
-                held: dec!(0),
-                locked: true,
-                transaction_log: HashMap::from([(
-                    1,
-                    TransactionLog {
-                        amount: TransactionActionState::Deposit { amount: dec!(100) },
-                        state: TransactionState::Chargeback,
-                    },
-                )]),
-            })
+            shared.get_user_account(2).unwrap().transaction_log[&5].seq,
+            4
+        );
+        assert_eq!(
+            shared.get_user_account(3).unwrap().transaction_log[&6].seq,
+            5
+        );
+        assert_eq!(shared.into_accounts().last_seq(), 5);
+    }
+
+    #[test]
+    fn references_ahead_of_their_transaction_should_be_rejected_in_strict_order() {
+        let mut accounts = Accounts::new();
+        accounts.set_policies(Policies {
+            ordering: OrderingPolicy::Strict,
+            ..Policies::default()
+        });
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(10) });
+        accounts.add_transaction(2, 3, Transaction::Deposit { amount: dec!(1) });
+
+        assert_eq!(
+            accounts.add_transaction(1, 2, Transaction::Dispute),
+            TransactionOutcome::Rejected(RejectionReason::OutOfOrder)
+        );
+        assert_eq!(
+            accounts.add_transaction(2, 1, Transaction::Dispute),
+            TransactionOutcome::Rejected(RejectionReason::UnknownTransaction)
+        );
+        accounts.add_transaction(1, 2, Transaction::Deposit { amount: dec!(5) });
+        assert_eq!(
+            accounts.add_transaction(1, 2, Transaction::Dispute),
+            TransactionOutcome::Applied
+        );
+    }
+
+    #[test]
+    fn duplicates_with_a_different_payload_should_be_rejected_as_conflicting_in_error_mode() {
+        let mut accounts = Accounts::new();
+        accounts.set_policies(Policies {
+            duplicate: DuplicatePolicy::Error,
+            ..Policies::default()
+        });
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(10) });
+
+        assert_eq!(
+            accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(10) }),
+            TransactionOutcome::Rejected(RejectionReason::DuplicateTransaction)
+        );
+        assert_eq!(
+            accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(12) }),
+            TransactionOutcome::Rejected(RejectionReason::ConflictingDuplicate)
+        );
+        assert_eq!(
+            accounts.add_transaction(2, 1, Transaction::Deposit { amount: dec!(10) }),
+            TransactionOutcome::Rejected(RejectionReason::ConflictingDuplicate)
+        );
+        assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(10));
+    }
+
+    #[test]
+    fn duplicates_should_replace_the_original_with_last_write_wins() {
+        let policies = Policies {
+            duplicate: DuplicatePolicy::LastWriteWins,
+            ..Policies::default()
+        };
+        let mut accounts = Accounts::new();
+        accounts.set_policies(policies);
+        accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(10) });
+        accounts.add_transaction(1, 2, Transaction::Withdrawal { amount: dec!(4) });
+        accounts.add_transaction(2, 3, Transaction::Deposit { amount: dec!(1) });
+
+        assert_eq!(
+            accounts.add_transaction(1, 2, Transaction::Withdrawal { amount: dec!(3) }),
+            TransactionOutcome::Applied
+        );
+        assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(7));
+        // moves the deposit to client 1
+        assert_eq!(
+            accounts.add_transaction(1, 3, Transaction::Deposit { amount: dec!(2) }),
+            TransactionOutcome::Applied
         );
+        assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(9));
+        assert_eq!(accounts.get_user_account(2).unwrap().available, dec!(0));
+        assert_eq!(
+            accounts.add_transaction(2, 1, Transaction::Deposit { amount: dec!(10) }),
+            TransactionOutcome::Rejected(RejectionReason::InsufficientFunds)
+        );
+        // the original is kept when the duplicate is rejected
+        assert_eq!(
+            accounts.add_transaction(1, 3, Transaction::Withdrawal { amount: dec!(20) }),
+            TransactionOutcome::Rejected(RejectionReason::InsufficientFunds)
+        );
+        assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(9));
+        accounts.add_transaction(1, 3, Transaction::Dispute);
+        assert_eq!(
+            accounts.add_transaction(1, 3, Transaction::Deposit { amount: dec!(1) }),
+            TransactionOutcome::Rejected(RejectionReason::InvalidTransactionState)
+        );
+
+        let shared = SharedAccounts::from({
+            let mut accounts = Accounts::new();
+            accounts.set_policies(policies);
+            accounts
+        });
+        shared.add_transaction(1, 1, Transaction::Deposit { amount: dec!(10) });
+        assert_eq!(
+            shared.add_transaction(1, 1, Transaction::Deposit { amount: dec!(6) }),
+            TransactionOutcome::Applied
+        );
+        assert_eq!(shared.get_user_account(1).unwrap().available, dec!(6));
+    }
+
+    #[test]
+    fn shared_registry_should_let_only_one_worker_register_a_tx_id() {
+        let registry = crate::concurrency::SharedRegistry::new();
+        let registered = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for tx in 0..1000 {
+                        if registry.register(tx, &Transaction::Deposit { amount: dec!(1) }) {
+                            registered.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(registered.load(Ordering::Relaxed), 1000);
+        assert_eq!(registry.len(), 1000);
+        assert!(registry.register(5, &Transaction::Dispute));
+        assert!(!registry.register(5, &Transaction::Withdrawal { amount: dec!(1) }));
     }
 }
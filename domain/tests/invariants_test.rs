@@ -0,0 +1,111 @@
+use domain::{
+    domain::{Accounts, Transaction, TransactionOutcome},
+    invariants::{self, Violation},
+};
+use proptest::prelude::*;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+// few clients and tx ids, so disputes, holds and chargebacks often hit earlier transactions
+fn transaction() -> impl Strategy<Value = (u16, u32, Transaction)> {
+    let amount = (0i64..100_000).prop_map(|x| Decimal::new(x, 2));
+    let transaction = prop_oneof![
+        3 => amount.clone().prop_map(|amount| Transaction::Deposit { amount }),
+        2 => amount.clone().prop_map(|amount| Transaction::Withdrawal { amount }),
+        1 => (amount, 1u32..5).prop_map(|(amount, expires_after)| Transaction::Hold {
+            amount,
+            expires_after,
+        }),
+        2 => Just(Transaction::Dispute),
+        1 => Just(Transaction::Resolve),
+        1 => Just(Transaction::Chargeback),
+        1 => Just(Transaction::Capture),
+        1 => Just(Transaction::Release),
+    ];
+    (0u16..4, 0u32..32, transaction)
+}
+
+proptest! {
+    #[test]
+    fn any_transaction_sequence_should_keep_the_invariants(
+        transactions in prop::collection::vec(transaction(), 0..200)
+    ) {
+        let mut accounts = Accounts::new();
+        for (client, tx, transaction) in transactions {
+            let before = accounts.clone();
+            accounts.add_transaction(client, tx, transaction);
+            prop_assert_eq!(invariants::check(&accounts), Ok(()));
+            prop_assert_eq!(invariants::check_locked_unchanged(&before, &accounts), Ok(()));
+        }
+    }
+
+    #[test]
+    fn rejected_transactions_should_not_change_the_accounts(
+        transactions in prop::collection::vec(transaction(), 0..200)
+    ) {
+        let mut accounts = Accounts::new();
+        for (client, tx, transaction) in transactions {
+            let before = accounts.get_user_account(client).cloned();
+            if let TransactionOutcome::Rejected(_) = accounts.add_transaction(client, tx, transaction) {
+                // pending holds still age on a rejected transaction of an unlocked account
+                if before.as_ref().is_some_and(|x| x.pending_holds.is_empty() || x.locked) {
+                    prop_assert_eq!(accounts.get_user_account(client), before.as_ref());
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn balances_that_do_not_match_the_log_should_be_reported() {
+    let mut accounts = Accounts::new();
+    accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(5) });
+    let mut account = accounts.get_user_account(1).unwrap().clone();
+    account.available = dec!(6);
+    accounts.restore_account(1, account);
+
+    assert_eq!(
+        invariants::check(&accounts),
+        Err(Violation::BalanceMismatch {
+            client: 1,
+            available: dec!(6),
+            held: dec!(0),
+            expected_available: dec!(5),
+            expected_held: dec!(0),
+        })
+    );
+}
+
+#[test]
+fn negative_held_funds_should_be_reported() {
+    let mut accounts = Accounts::new();
+    accounts.restore_user_account(1, dec!(1), dec!(-1), false);
+
+    assert_eq!(
+        invariants::check(&accounts),
+        Err(Violation::NegativeHeld {
+            client: 1,
+            held: dec!(-1),
+        })
+    );
+}
+
+#[test]
+fn negative_totals_should_be_reported_unless_allowed() {
+    let mut accounts = Accounts::new();
+    accounts.add_transaction(1, 1, Transaction::Deposit { amount: dec!(5) });
+    accounts.add_transaction(1, 2, Transaction::Withdrawal { amount: dec!(5) });
+    accounts.add_transaction(1, 1, Transaction::Dispute);
+    accounts.add_transaction(1, 1, Transaction::Chargeback);
+    let account = accounts.get_user_account(1).unwrap();
+
+    assert_eq!(
+        invariants::check_balances(1, account, false),
+        Err(Violation::NegativeTotal {
+            client: 1,
+            total: dec!(-5),
+        })
+    );
+    assert_eq!(invariants::check_balances(1, account, true), Ok(()));
+    assert_eq!(invariants::check_locked(1, account), Ok(()));
+}
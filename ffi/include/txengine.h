@@ -0,0 +1,97 @@
+/* Generated with cbindgen from ffi/src/lib.rs, do not edit by hand. */
+
+#ifndef TXENGINE_H
+#define TXENGINE_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define ENGINE_DECIMAL_LEN 40
+
+typedef enum EngineResult {
+  ENGINE_RESULT_APPLIED = 0,
+  ENGINE_RESULT_DUPLICATE_TRANSACTION = 1,
+  ENGINE_RESULT_INSUFFICIENT_FUNDS = 2,
+  ENGINE_RESULT_ACCOUNT_LOCKED = 3,
+  ENGINE_RESULT_ACCOUNT_NOT_FOUND = 4,
+  ENGINE_RESULT_UNKNOWN_TRANSACTION = 5,
+  ENGINE_RESULT_INVALID_TRANSACTION_STATE = 6,
+  ENGINE_RESULT_AMOUNT_OVERFLOW = 7,
+  ENGINE_RESULT_DECLINED = 8,
+  ENGINE_RESULT_AMOUNT_LIMIT_EXCEEDED = 9,
+  ENGINE_RESULT_BALANCE_LIMIT_EXCEEDED = 10,
+  ENGINE_RESULT_OUT_OF_ORDER = 11,
+  ENGINE_RESULT_CONFLICTING_DUPLICATE = 12,
+  ENGINE_RESULT_INVALID_ARGUMENT = -1,
+} EngineResult;
+
+typedef struct Engine Engine;
+
+typedef struct EngineAccount {
+  uint16_t client;
+  char available[ENGINE_DECIMAL_LEN];
+  char held[ENGINE_DECIMAL_LEN];
+  char total[ENGINE_DECIMAL_LEN];
+  bool locked;
+} EngineAccount;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an engine without accounts, free it with `engine_free`.
+ */
+struct Engine *engine_new(void);
+
+/**
+ * Applies a transaction of the input record format, e.g. a `"deposit"` of `"1.5"`. `amount`
+ * may be null for disputes, resolves and chargebacks. Holds need an expiry and can't be
+ * applied through this function.
+ *
+ * # Safety
+ * `engine` must come from `engine_new`, and `transaction_type` and a non-null `amount` must be
+ * NUL terminated strings.
+ */
+enum EngineResult engine_apply(struct Engine *engine,
+                               const char *transaction_type,
+                               uint16_t client,
+                               uint32_t tx,
+                               const char *amount);
+
+/**
+ * Fills `account` with the balances of the client, false when there is no such account.
+ *
+ * # Safety
+ * `engine` must come from `engine_new` and `account` must point to a writable `EngineAccount`.
+ */
+bool engine_get_account(const struct Engine *engine, uint16_t client, struct EngineAccount *account);
+
+/**
+ * The accounts in the csv output format, null on failure. Free it with `engine_string_free`.
+ *
+ * # Safety
+ * `engine` must come from `engine_new`.
+ */
+char *engine_export_csv(const struct Engine *engine);
+
+/**
+ * # Safety
+ * `value` must come from `engine_export_csv` and not be freed before; null is ignored.
+ */
+void engine_string_free(char *value);
+
+/**
+ * # Safety
+ * `engine` must come from `engine_new` and not be freed before; null is ignored.
+ */
+void engine_free(struct Engine *engine);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* TXENGINE_H */
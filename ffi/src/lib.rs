@@ -0,0 +1,189 @@
+// A C API over `transaction_engine::Engine` for linking the engine into C and C++ programs;
+// `include/txengine.h` is generated from this file with cbindgen. Amounts cross the boundary as
+// decimal strings so no precision is lost.
+use std::{
+    ffi::{c_char, CStr, CString},
+    ptr,
+};
+
+use transaction_engine::{
+    write_accounts, Decimal, Engine, InputTransactionRecord, RejectionReason, TransactionOutcome,
+};
+
+// long enough for any Decimal with its sign, point and the terminating NUL
+pub const ENGINE_DECIMAL_LEN: usize = 40;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineResult {
+    Applied = 0,
+    DuplicateTransaction = 1,
+    InsufficientFunds = 2,
+    AccountLocked = 3,
+    AccountNotFound = 4,
+    UnknownTransaction = 5,
+    InvalidTransactionState = 6,
+    AmountOverflow = 7,
+    Declined = 8,
+    AmountLimitExceeded = 9,
+    BalanceLimitExceeded = 10,
+    OutOfOrder = 11,
+    ConflictingDuplicate = 12,
+    // a null pointer, an unknown type, or a missing or unparsable amount
+    InvalidArgument = -1,
+}
+
+impl From<TransactionOutcome> for EngineResult {
+    fn from(outcome: TransactionOutcome) -> Self {
+        match outcome {
+            TransactionOutcome::Applied => EngineResult::Applied,
+            TransactionOutcome::Rejected(reason) => match reason {
+                RejectionReason::DuplicateTransaction => EngineResult::DuplicateTransaction,
+                RejectionReason::InsufficientFunds => EngineResult::InsufficientFunds,
+                RejectionReason::AccountLocked => EngineResult::AccountLocked,
+                RejectionReason::AccountNotFound => EngineResult::AccountNotFound,
+                RejectionReason::UnknownTransaction => EngineResult::UnknownTransaction,
+                RejectionReason::InvalidTransactionState => EngineResult::InvalidTransactionState,
+                RejectionReason::AmountOverflow => EngineResult::AmountOverflow,
+                RejectionReason::Declined => EngineResult::Declined,
+                RejectionReason::AmountLimitExceeded => EngineResult::AmountLimitExceeded,
+                RejectionReason::BalanceLimitExceeded => EngineResult::BalanceLimitExceeded,
+                RejectionReason::OutOfOrder => EngineResult::OutOfOrder,
+                RejectionReason::ConflictingDuplicate => EngineResult::ConflictingDuplicate,
+            },
+        }
+    }
+}
+
+// the amounts are NUL terminated decimal strings
+#[repr(C)]
+pub struct EngineAccount {
+    pub client: u16,
+    pub available: [c_char; ENGINE_DECIMAL_LEN],
+    pub held: [c_char; ENGINE_DECIMAL_LEN],
+    pub total: [c_char; ENGINE_DECIMAL_LEN],
+    pub locked: bool,
+}
+
+fn write_decimal(buffer: &mut [c_char; ENGINE_DECIMAL_LEN], value: Decimal) {
+    let value = value.to_string();
+    *buffer = [0; ENGINE_DECIMAL_LEN];
+    for (x, byte) in buffer.iter_mut().zip(value.bytes()) {
+        *x = byte as c_char;
+    }
+}
+
+unsafe fn optional_str<'a>(value: *const c_char) -> Option<&'a str> {
+    if value.is_null() {
+        return None;
+    }
+    CStr::from_ptr(value).to_str().ok()
+}
+
+/// Creates an engine without accounts, free it with `engine_free`.
+#[no_mangle]
+pub extern "C" fn engine_new() -> *mut Engine {
+    Box::into_raw(Box::new(Engine::new()))
+}
+
+/// Applies a transaction of the input record format, e.g. a `"deposit"` of `"1.5"`. `amount`
+/// may be null for disputes, resolves and chargebacks. Holds need an expiry and can't be
+/// applied through this function.
+///
+/// # Safety
+/// `engine` must come from `engine_new`, and `transaction_type` and a non-null `amount` must be
+/// NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn engine_apply(
+    engine: *mut Engine,
+    transaction_type: *const c_char,
+    client: u16,
+    tx: u32,
+    amount: *const c_char,
+) -> EngineResult {
+    let (Some(engine), Some(transaction_type)) = (engine.as_mut(), optional_str(transaction_type))
+    else {
+        return EngineResult::InvalidArgument;
+    };
+    let amount = match optional_str(amount) {
+        Some(x) => match x.trim().parse::<Decimal>() {
+            Ok(x) => Some(x),
+            Err(_) => return EngineResult::InvalidArgument,
+        },
+        None if !amount.is_null() => return EngineResult::InvalidArgument,
+        None => None,
+    };
+    let record = InputTransactionRecord {
+        transaction_type: transaction_type.to_string().into(),
+        client,
+        tx,
+        amount,
+        expires_after: None,
+        idempotency_key: None,
+        tenant: None,
+    };
+    match record.into_record() {
+        Some(x) => engine
+            .apply_record(x)
+            .map_or(EngineResult::InvalidArgument, EngineResult::from),
+        None => EngineResult::InvalidArgument,
+    }
+}
+
+/// Fills `account` with the balances of the client, false when there is no such account.
+///
+/// # Safety
+/// `engine` must come from `engine_new` and `account` must point to a writable `EngineAccount`.
+#[no_mangle]
+pub unsafe extern "C" fn engine_get_account(
+    engine: *const Engine,
+    client: u16,
+    account: *mut EngineAccount,
+) -> bool {
+    let (Some(engine), Some(account)) = (engine.as_ref(), account.as_mut()) else {
+        return false;
+    };
+    let Some(x) = engine.accounts().get_user_account(client) else {
+        return false;
+    };
+    account.client = client;
+    write_decimal(&mut account.available, x.available);
+    write_decimal(&mut account.held, x.held);
+    write_decimal(&mut account.total, x.available + x.held);
+    account.locked = x.locked;
+    true
+}
+
+/// The accounts in the csv output format, null on failure. Free it with `engine_string_free`.
+///
+/// # Safety
+/// `engine` must come from `engine_new`.
+#[no_mangle]
+pub unsafe extern "C" fn engine_export_csv(engine: *const Engine) -> *mut c_char {
+    let Some(engine) = engine.as_ref() else {
+        return ptr::null_mut();
+    };
+    let mut output = Vec::new();
+    if write_accounts(&mut output, engine.accounts()).is_err() {
+        return ptr::null_mut();
+    }
+    CString::new(output).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// # Safety
+/// `value` must come from `engine_export_csv` and not be freed before; null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn engine_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
+
+/// # Safety
+/// `engine` must come from `engine_new` and not be freed before; null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn engine_free(engine: *mut Engine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
@@ -0,0 +1,60 @@
+use std::ffi::{c_char, CStr};
+
+use txengine::{
+    engine_apply, engine_export_csv, engine_free, engine_get_account, engine_new,
+    engine_string_free, EngineAccount, EngineResult, ENGINE_DECIMAL_LEN,
+};
+
+fn decimal(value: &[c_char; ENGINE_DECIMAL_LEN]) -> &str {
+    unsafe { CStr::from_ptr(value.as_ptr()) }.to_str().unwrap()
+}
+
+#[test]
+fn engine_should_apply_transactions_through_the_c_api() {
+    unsafe {
+        let engine = engine_new();
+        assert_eq!(
+            engine_apply(engine, c"deposit".as_ptr(), 1, 1, c"2.5".as_ptr()),
+            EngineResult::Applied
+        );
+        assert_eq!(
+            engine_apply(engine, c"withdrawal".as_ptr(), 1, 2, c"9".as_ptr()),
+            EngineResult::InsufficientFunds
+        );
+        assert_eq!(
+            engine_apply(engine, c"dispute".as_ptr(), 1, 1, std::ptr::null()),
+            EngineResult::Applied
+        );
+        assert_eq!(
+            engine_apply(engine, c"deposit".as_ptr(), 1, 3, c"abc".as_ptr()),
+            EngineResult::InvalidArgument
+        );
+        assert_eq!(
+            engine_apply(engine, c"transfer".as_ptr(), 1, 4, c"1".as_ptr()),
+            EngineResult::InvalidArgument
+        );
+
+        let mut account = EngineAccount {
+            client: 0,
+            available: [0; ENGINE_DECIMAL_LEN],
+            held: [0; ENGINE_DECIMAL_LEN],
+            total: [0; ENGINE_DECIMAL_LEN],
+            locked: true,
+        };
+        assert!(!engine_get_account(engine, 2, &mut account));
+        assert!(engine_get_account(engine, 1, &mut account));
+        assert_eq!(account.client, 1);
+        assert_eq!(decimal(&account.available), "0.0");
+        assert_eq!(decimal(&account.held), "2.5");
+        assert_eq!(decimal(&account.total), "2.5");
+        assert!(!account.locked);
+
+        let csv = engine_export_csv(engine);
+        assert_eq!(
+            CStr::from_ptr(csv).to_str().unwrap(),
+            "client,available,held,total,locked\n1,0.0,2.5,2.5,false\n"
+        );
+        engine_string_free(csv);
+        engine_free(engine);
+    }
+}
@@ -0,0 +1,25 @@
+#![no_main]
+
+use domain::domain::Accounts;
+use libfuzzer_sys::fuzz_target;
+use service::service::{read_source_into, CsvSource, OutputFormat, OutputOptions, ParseMode};
+
+// any input is either read or rejected with an error, and whatever was read can be written
+fuzz_target!(|data: &[u8]| {
+    for mode in [ParseMode::Lenient, ParseMode::Strict] {
+        if let Ok((accounts, _)) = read_source_into(CsvSource::new(data), mode, Accounts::new()) {
+            for format in [
+                OutputFormat::Csv,
+                OutputFormat::Json,
+                OutputFormat::Ndjson,
+                OutputFormat::Table,
+            ] {
+                let _ = format.writer().write_with_options(
+                    &mut Vec::new(),
+                    &accounts,
+                    &OutputOptions::default(),
+                );
+            }
+        }
+    }
+});
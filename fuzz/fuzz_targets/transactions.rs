@@ -0,0 +1,67 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use domain::{
+    domain::{Accounts, Transaction},
+    invariants,
+};
+use libfuzzer_sys::fuzz_target;
+use rust_decimal::Decimal;
+
+#[derive(Debug, Arbitrary)]
+enum Kind {
+    Deposit,
+    Withdrawal,
+    Hold { expires_after: u8 },
+    Dispute,
+    Resolve,
+    Chargeback,
+    Capture,
+    Release,
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    client: u8,
+    tx: u8,
+    kind: Kind,
+    amount: u64,
+    scale: u8,
+}
+
+// amounts are positive like the ones `validate` accepts and have at most four decimal places like
+// the input, so sums stay exact instead of being rounded to 28 digits; small client and tx ids
+// make disputes and holds hit earlier transactions
+fn transaction(input: &Input) -> Transaction {
+    let amount = Decimal::new((input.amount >> 1) as i64, input.scale as u32 % 5);
+    match input.kind {
+        Kind::Deposit => Transaction::Deposit { amount },
+        Kind::Withdrawal => Transaction::Withdrawal { amount },
+        Kind::Hold { expires_after } => Transaction::Hold {
+            amount,
+            expires_after: expires_after as u32,
+        },
+        Kind::Dispute => Transaction::Dispute,
+        Kind::Resolve => Transaction::Resolve,
+        Kind::Chargeback => Transaction::Chargeback,
+        Kind::Capture => Transaction::Capture,
+        Kind::Release => Transaction::Release,
+    }
+}
+
+fuzz_target!(|inputs: Vec<Input>| {
+    let mut accounts = Accounts::new();
+    for input in &inputs {
+        let before = accounts.clone();
+        accounts.add_transaction(
+            (input.client % 8) as u16,
+            (input.tx % 64) as u32,
+            transaction(input),
+        );
+        if let Err(e) = invariants::check(&accounts)
+            .and_then(|_| invariants::check_locked_unchanged(&before, &accounts))
+        {
+            panic!("{}", e);
+        }
+    }
+});
@@ -0,0 +1,66 @@
+use std::{fmt, fs, io, path::Path};
+
+use serde::Deserialize;
+
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub input: Option<String>,
+    pub output: Option<String>,
+    pub format: Option<String>,
+    pub strict: bool,
+    pub rounding: RoundingSection,
+    pub server: ServerSection,
+    pub policies: PoliciesSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoundingSection {
+    pub dp: Option<u32>,
+}
+
+// the policies of `process`, see its flags of the same name
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoliciesSection {
+    pub settle_locked_disputes: bool,
+    pub refund_withdrawal_chargebacks: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSection {
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(io::Error),
+    Invalid(toml::de::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(e) => write!(f, "cannot read config: {}", e),
+            ConfigError::Invalid(e) => write!(f, "invalid config: {}", e),
+        }
+    }
+}
+
+impl Config {
+    // without an explicit path, `config.toml` is only read when it exists
+    pub fn load(path: Option<&str>) -> Result<Config, ConfigError> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => DEFAULT_CONFIG_PATH,
+            None => return Ok(Config::default()),
+        };
+        let content = fs::read_to_string(path).map_err(ConfigError::Read)?;
+        toml::from_str(&content).map_err(ConfigError::Invalid)
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode},
+    layout::{Constraint, Layout},
+    widgets::{Block, Paragraph, Row, Table},
+    Frame,
+};
+use server::server::AppState;
+use service::stats::{account_statistics, AccountStatistics};
+
+const REFRESH_EVERY: Duration = Duration::from_millis(500);
+const TOP_CLIENTS: usize = 10;
+
+// blocks until `q` or Esc is pressed
+pub fn run(state: &AppState) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = (|| {
+        let mut last_count = state.applied_transactions();
+        let mut last_refresh = Instant::now();
+        let mut throughput = 0.0;
+        loop {
+            let statistics = account_statistics(&state.accounts.lock().unwrap(), TOP_CLIENTS);
+            let count = state.applied_transactions();
+            terminal.draw(|frame| draw(frame, count, throughput, &statistics))?;
+
+            if event::poll(REFRESH_EVERY)? {
+                if let Event::Key(key) = event::read()? {
+                    if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                        return Ok(());
+                    }
+                }
+            }
+            let elapsed = last_refresh.elapsed();
+            if elapsed >= REFRESH_EVERY {
+                let count = state.applied_transactions();
+                throughput = (count - last_count) as f64 / elapsed.as_secs_f64();
+                last_count = count;
+                last_refresh = Instant::now();
+            }
+        }
+    })();
+    ratatui::restore();
+    result
+}
+
+fn draw(frame: &mut Frame, applied: u64, throughput: f64, statistics: &AccountStatistics) {
+    let [summary, clients] =
+        Layout::vertical([Constraint::Length(7), Constraint::Min(0)]).areas(frame.area());
+    let lines = [
+        format!("throughput       {:.0} tx/s", throughput),
+        format!("applied          {}", applied),
+        format!("accounts         {}", statistics.accounts),
+        format!("locked accounts  {}", statistics.locked_accounts),
+        format!("open disputes    {}", statistics.open_disputes),
+    ];
+    frame.render_widget(
+        Paragraph::new(lines.join("\n")).block(Block::bordered().title(" ingestion (q to quit) ")),
+        summary,
+    );
+
+    let rows = statistics.top_held.iter().map(|x| {
+        Row::new([
+            x.client.to_string(),
+            x.held.to_string(),
+            x.available.to_string(),
+            x.total.to_string(),
+            x.locked.to_string(),
+        ])
+    });
+    let table = Table::new(rows, [Constraint::Length(10); 5])
+        .header(Row::new(["client", "held", "available", "total", "locked"]))
+        .block(Block::bordered().title(" top clients by held funds "));
+    frame.render_widget(table, clients);
+}
@@ -1,22 +1,1255 @@
 use std::{
-    env,
-    io::{self},
+    collections::HashSet,
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    process::ExitCode,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
 };
 
-fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    let mut input_path = String::from("transactions.csv");
-    let mut output_path = String::from("accounts.csv");
-    if let Some(input_file_path) = args.get(1) {
-        input_path = input_file_path.clone();
+use clap::{Args, Parser, Subcommand};
+use config::Config;
+use domain::domain::{
+    DisputePolicy, DuplicatePolicy, FirstTransactionPolicy, Limits, LockedAccountPolicy,
+    OrderingPolicy, Policies,
+};
+use rust_decimal::Decimal;
+use server::{
+    auth::{ApiKeys, Scope},
+    rate_limit::RateLimit,
+    server::{serve_with_shutdown, shutdown_signal, AppState},
+    shard::{Shard, ShardRouter},
+    tls::TlsIdentity,
+};
+use service::{
+    aml::{AmlMonitor, AmlOptions},
+    compression::{compress, decompress, is_stdio, open_input, Compression, STDIO_PATH},
+    diff::{changed_clients, diff_accounts, AccountDiff},
+    dormancy::{sweep, DormancyReport, DormancyTracker},
+    dry_run::dry_run,
+    generate::{generate, GeneratorOptions},
+    monitor::{InvariantMonitor, MonitorMode},
+    pipeline::read_pipelined,
+    progress::{ProgressReader, ProgressSource},
+    reconcile::Reconciler,
+    reporting::statements,
+    risk::RiskEngine,
+    service::{
+        read_source_into, read_source_observed, CapacityHint, CsvSource, OutputFormat,
+        OutputOptions, ParseMode, ServiceError, SourceError, TransactionSource,
+    },
+    settlement::{settle, settle_tenants, write_settlement, SettlementOptions},
+    snapshot::{load_snapshot, save_snapshot},
+    tenants::{read_source_by_tenant, write_tenant_outputs, Tenants},
+    wal::{Wal, WalSource},
+    watch::{watch, WatchOptions},
+};
+use tokio::net::TcpListener;
+use tracing_subscriber::filter::LevelFilter;
+
+mod config;
+#[cfg(feature = "tui")]
+mod dashboard;
+#[cfg(feature = "otel")]
+mod otel;
+mod repl;
+
+const PROGRESS_EVERY: u64 = 100_000;
+const CHECKPOINT_EVERY: u64 = 100_000;
+const WAL_SYNC_EVERY: usize = 1024;
+
+#[derive(Parser)]
+#[command(about = "Applies transactions to client accounts")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Output format: csv, json, ndjson or table [default: csv]
+    #[arg(long, global = true, alias = "output-format", env = "TXENGINE_FORMAT")]
+    format: Option<OutputFormat>,
+    /// Stop at the first malformed row instead of skipping it
+    #[arg(long, global = true, env = "TXENGINE_STRICT")]
+    strict: bool,
+    /// Config file [default: config.toml when it exists]
+    #[arg(long, global = true, env = "TXENGINE_CONFIG")]
+    config: Option<String>,
+    /// Do not print the accounts, progress or summaries
+    #[arg(long, global = true)]
+    quiet: bool,
+    /// Log level on stderr: off, error, warn, info, debug or trace
+    #[arg(
+        long,
+        global = true,
+        env = "TXENGINE_LOG_LEVEL",
+        default_value = "warn"
+    )]
+    log_level: LevelFilter,
+    /// Write the logs as JSON lines
+    #[arg(long, global = true, env = "TXENGINE_LOG_JSON")]
+    log_json: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Process the input and write the resulting accounts
+    Process(Box<ProcessArgs>),
+    /// Check the input without applying it: schema, amounts, types and tx references
+    Validate {
+        #[arg(env = "TXENGINE_INPUT")]
+        input: Option<String>,
+    },
+    /// Process the input and print a statement of the given clients with every transaction
+    Report {
+        #[arg(env = "TXENGINE_INPUT")]
+        input: Option<String>,
+        /// Client to report, can be repeated; all clients when absent
+        #[arg(long = "client")]
+        clients: Vec<u16>,
+    },
+    /// Compare two accounts csvs and print the clients whose accounts differ
+    Diff { before: String, after: String },
+    /// Write a random but reproducible transaction csv
+    Generate {
+        /// Output csv, `-` for stdout
+        #[arg(default_value = STDIO_PATH)]
+        output: String,
+        #[arg(long, default_value_t = 100)]
+        clients: u16,
+        #[arg(long, default_value_t = 10_000)]
+        transactions: u64,
+        /// Share of the rows that open a dispute, as many rows close one
+        #[arg(long, default_value_t = 0.01)]
+        dispute_rate: f64,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// Apply every csv dropped into a directory and keep rewriting the output
+    Watch {
+        dir: String,
+        /// Output csv, rewritten after new files were applied
+        #[arg(default_value = "accounts.csv")]
+        output: String,
+        /// Where processed files are moved [default: DIR/archive]
+        #[arg(long)]
+        archive_dir: Option<String>,
+        /// Seconds between two scans of the directory
+        #[arg(long, default_value_t = 1)]
+        poll_interval: u64,
+        /// Minimum seconds between two rewrites of the output
+        #[arg(long, default_value_t = 10)]
+        snapshot_interval: u64,
+    },
+    /// Apply transactions typed one per line and inspect the accounts
+    Repl,
+    /// Replay a write-ahead log written by `process --wal` and print the resulting accounts
+    Replay {
+        wal: String,
+        /// Accounts csv the logged run started from
+        #[arg(long)]
+        initial_state: Option<String>,
+    },
+    /// Process the files of several tenants and write the accounts of each to its own file
+    Tenants {
+        /// Input csv as `TENANT=PATH`, or `PATH` to take the tenant of each row from its `tenant` column
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        /// Directory the `TENANT.csv` (or `.json`, ...) outputs are written to
+        #[arg(long, default_value = ".")]
+        output_dir: String,
+        #[command(flatten)]
+        settlement: SettlementArgs,
+    },
+    /// Erase the transaction history of a client from the persisted state, keeping its balances
+    Forget {
+        #[arg(long)]
+        client: u16,
+        /// Checkpoint directory written by `process --checkpoint-dir`
+        #[arg(long, required_unless_present_any = ["wal", "sqlite", "sled"])]
+        checkpoint_dir: Option<String>,
+        /// Write-ahead log written by `process --wal`, rewritten without the client's records
+        #[arg(long)]
+        wal: Option<String>,
+        /// SQLite database written by `save_sqlite` (needs the `sqlite` feature)
+        #[arg(long)]
+        sqlite: Option<String>,
+        /// Sled database of a `SledStore` (needs the `sled` feature)
+        #[arg(long)]
+        sled: Option<String>,
+    },
+    /// Print the JSON Schema of the transaction or account records, or the OpenAPI document of the server
+    Schema {
+        #[arg(value_parser = ["transaction", "account", "openapi"])]
+        of: String,
+    },
+    /// Serve the accounts over HTTP
+    Serve(Box<ServeArgs>),
+    /// Send the transactions of an input to engines sharded by client id, `client % shards`
+    Route {
+        input: String,
+        /// gRPC url of the engine of a shard (`serve --grpc-port`), repeated for every shard in order; engines of this process when absent
+        #[arg(long = "peer")]
+        peers: Vec<String>,
+        /// Number of engines of this process, without --peer
+        #[arg(long, default_value_t = 2, conflicts_with = "peers")]
+        shards: usize,
+        /// Api key sent to the peers
+        #[arg(long, env = "TXENGINE_API_KEY")]
+        api_key: Option<String>,
+        /// Directory the accounts of the engines of this process are written to, as `shard-N.csv`
+        #[arg(long, default_value = ".", conflicts_with = "peers")]
+        output_dir: String,
+    },
+    /// Combine the accounts csvs of shards that each had clients of their own and print them
+    MergeOutput {
+        #[arg(required = true)]
+        inputs: Vec<String>,
+    },
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+    /// Also serve the gRPC API on this port
+    #[arg(long)]
+    grpc_port: Option<u16>,
+    /// Show a terminal dashboard of the ingestion (needs the `tui` feature)
+    #[arg(long)]
+    dashboard: bool,
+    /// Start from the accounts of this snapshot, see `process --save-snapshot`
+    #[arg(long)]
+    snapshot: Option<String>,
+    /// Transactions queued for the accounts beyond which submissions are rejected
+    #[arg(long, default_value_t = server::server::DEFAULT_MAX_QUEUE_DEPTH)]
+    max_queue_depth: usize,
+    /// Transactions each client may submit per second, unlimited by default; change it at
+    /// runtime with `PUT /admin/rate-limit`
+    #[arg(long)]
+    rate_limit: Option<f64>,
+    /// Transactions a client may submit at once [default: the rate limit]
+    #[arg(long, requires = "rate_limit")]
+    rate_burst: Option<f64>,
+    /// API key as `key:scope` (read, submit or admin), sent as a bearer token; without any
+    /// key every route but the /admin ones is open [repeatable]
+    #[arg(long = "api-key", value_parser = ApiKeys::parse_entry, env = "TXENGINE_API_KEYS", value_delimiter = ',', hide_env_values = true)]
+    api_keys: Vec<(String, Scope)>,
+    /// File of API keys, one `key:scope` per line
+    #[arg(long)]
+    api_keys_file: Option<String>,
+    /// PEM certificate (chain) to serve HTTPS and gRPC over TLS, with --tls-key [default: tls_cert of the [server] config]
+    #[arg(long)]
+    tls_cert: Option<String>,
+    /// PEM private key of --tls-cert [default: tls_key of the [server] config]
+    #[arg(long)]
+    tls_key: Option<String>,
+    /// Replay this write-ahead log after the snapshot, then append every submitted transaction and admin action to it
+    #[arg(long)]
+    wal: Option<String>,
+    /// Append every admin action with its outcome to this file, as json lines
+    #[arg(long)]
+    audit_log: Option<String>,
+    /// Write a snapshot of the accounts on shutdown, then commit the write-ahead log
+    #[arg(long)]
+    save_snapshot: Option<String>,
+    /// Write the accounts to this file on shutdown
+    #[arg(long)]
+    output: Option<String>,
+}
+
+#[derive(Args)]
+struct ProcessArgs {
+    /// Input csv, `-` for stdin [default: -]
+    #[arg(env = "TXENGINE_INPUT")]
+    input: Option<String>,
+    /// Output csv, `-` for stdout [default: -]
+    #[arg(env = "TXENGINE_OUTPUT")]
+    output: Option<String>,
+    /// Previous output csv to continue from
+    #[arg(long)]
+    initial_state: Option<String>,
+    /// Print the number of processed records to stderr
+    #[arg(long)]
+    progress: bool,
+    /// Save a checkpoint every 100000 records and resume from it
+    #[arg(long)]
+    checkpoint_dir: Option<String>,
+    /// Print what the input would change and the summary instead of writing the accounts
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "checkpoint_dir",
+            "wal",
+            "pipeline_depth",
+            "monitor_invariants",
+            "reconcile",
+            "risk_report",
+            "suspicious_activity",
+            "dormancy_report",
+            "save_snapshot",
+        ]
+    )]
+    dry_run: bool,
+    /// Also write the accounts with their transaction logs to this binary snapshot
+    #[arg(long)]
+    save_snapshot: Option<String>,
+    /// Write every ignored transaction with its reason to this csv
+    #[arg(long)]
+    rejections: Option<String>,
+    /// Only write these clients, e.g. `1,2`
+    #[arg(long, value_delimiter = ',')]
+    clients: Option<Vec<u16>>,
+    /// Only write locked accounts
+    #[arg(long)]
+    locked_only: bool,
+    /// Only write accounts with held funds
+    #[arg(long)]
+    held_only: bool,
+    /// Only write the accounts that are new or changed compared to this accounts csv, e.g. the
+    /// output of the previous run
+    #[arg(long)]
+    changed_since: Option<String>,
+    /// Also write the open dispute and chargeback counts of every account
+    #[arg(long)]
+    extended: bool,
+    /// Still apply resolves and chargebacks of open disputes once an account is locked
+    #[arg(long, env = "TXENGINE_POLICIES_SETTLE_LOCKED_DISPUTES")]
+    settle_locked_disputes: bool,
+    /// Return the withdrawn amount to available when a disputed withdrawal is charged back
+    #[arg(long, env = "TXENGINE_POLICIES_REFUND_WITHDRAWAL_CHARGEBACKS")]
+    refund_withdrawal_chargebacks: bool,
+    /// Reject deposits, withdrawals and holds above this amount
+    #[arg(long)]
+    max_amount: Option<Decimal>,
+    /// Reject deposits that would take the total of an account above this
+    #[arg(long)]
+    max_balance: Option<Decimal>,
+    /// Reject references to tx ids not seen yet as out of order, and fail when there are any
+    #[arg(long)]
+    strict_order: bool,
+    /// What a deposit, withdrawal or hold reusing a tx id does: ignore it, reject it as a
+    /// conflict when its payload differs, or replace the original with it
+    #[arg(long, value_parser = ["ignore", "error", "last-write-wins"], default_value = "ignore")]
+    duplicates: String,
+    /// Create a zero-balance account for a client whose first record isn't a deposit
+    #[arg(long)]
+    open_on_any_record: bool,
+    /// Round amounts to this many decimal places (default 4)
+    #[arg(long, env = "TXENGINE_ROUNDING_DP")]
+    decimal_places: Option<u32>,
+    /// Pre-size the account maps for this many clients (default estimated from the input size)
+    #[arg(long)]
+    expected_clients: Option<usize>,
+    /// Pre-size the tx id registry for this many transactions (default estimated from the input size)
+    #[arg(long)]
+    expected_transactions: Option<usize>,
+    /// Parse on a separate thread, with at most this many batches of 1024 records in flight
+    #[arg(long)]
+    pipeline_depth: Option<usize>,
+    /// Memory-map the input file instead of reading it (needs the `mmap` feature)
+    #[arg(long)]
+    mmap: bool,
+    /// Append every parsed record to this write-ahead log, committed once the output is written
+    #[arg(long, conflicts_with = "checkpoint_dir")]
+    wal: Option<String>,
+    /// Check the account after every applied transaction, logging violations or stopping at the first
+    #[arg(
+        long,
+        value_parser = ["log", "fail-fast"],
+        conflicts_with_all = ["checkpoint_dir", "pipeline_depth"]
+    )]
+    monitor_invariants: Option<String>,
+    /// Accounts may have a negative total, e.g. after a chargeback of a withdrawn deposit
+    #[arg(long, requires = "monitor_invariants")]
+    allow_negative_total: bool,
+    /// Check that the applied transactions add up to the account totals, exit with 1 if not
+    #[arg(long, conflicts_with_all = ["checkpoint_dir", "pipeline_depth"])]
+    reconcile: bool,
+    /// Run the risk rules over the applied transactions and write the flagged clients to this JSON file
+    #[arg(long, conflicts_with_all = ["checkpoint_dir", "pipeline_depth"])]
+    risk_report: Option<String>,
+    /// Lock accounts as soon as a risk rule flags them
+    #[arg(long, requires = "risk_report")]
+    auto_freeze: bool,
+    /// Write the deposits that cross the AML threshold or look structured below it to this csv
+    #[arg(long, conflicts_with_all = ["checkpoint_dir", "pipeline_depth"])]
+    suspicious_activity: Option<String>,
+    /// Deposits of a client within the window adding up to this are reported
+    #[arg(long, default_value = "10000")]
+    aml_threshold: Decimal,
+    /// AML window, in applied transactions of the run
+    #[arg(long, default_value_t = 100)]
+    aml_window: u64,
+    /// Write the dormant clients, and the swept ones with --escheatment-account, to this JSON file
+    #[arg(long, conflicts_with_all = ["checkpoint_dir", "pipeline_depth"])]
+    dormancy_report: Option<String>,
+    /// Clients without an applied transaction in this many of the run's last ones are dormant
+    #[arg(long, default_value_t = 1000)]
+    dormant_after: u64,
+    /// Move the available funds of the dormant clients to this client's account
+    #[arg(long, requires = "dormancy_report")]
+    escheatment_account: Option<u16>,
+    #[command(flatten)]
+    settlement: SettlementArgs,
+}
+
+#[derive(Args)]
+struct SettlementArgs {
+    /// Write what every client owes or is owed for chargebacks and captured holds to this csv
+    #[arg(long = "settlement")]
+    path: Option<String>,
+    /// Batch ids are this prefix and the batch number, with 1000 lines per batch
+    #[arg(long = "settlement-batch-prefix", default_value = "batch")]
+    batch_prefix: String,
+}
+
+impl SettlementArgs {
+    fn options(&self) -> SettlementOptions {
+        SettlementOptions {
+            batch_prefix: self.batch_prefix.clone(),
+            ..SettlementOptions::default()
+        }
     }
+}
 
-    if let Some(output_file_path) = args.get(2) {
-        output_path = output_file_path.clone();
+// errors are printed to stderr and exit with a failure, like rejected inputs do
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let logs = tracing_subscriber::fmt()
+        .with_max_level(cli.log_level)
+        .with_writer(io::stderr);
+    if cli.log_json {
+        logs.json().init();
+    } else {
+        logs.init();
+    }
+    let config = match Config::load(cli.config.as_deref()) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(cli, config) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
     }
+}
 
-    let result = service::service::read_csv(input_path).expect("csv error");
-    service::service::write_csv(output_path, &result).expect("csv error");
+fn run(cli: Cli, config: Config) -> Result<ExitCode, ServiceError> {
+    // command line arguments and environment variables take precedence over the config file
+    let mode = if cli.strict || config.strict {
+        ParseMode::Strict
+    } else {
+        ParseMode::Lenient
+    };
+    let format = match (cli.format, config.format.as_deref().map(str::parse)) {
+        (Some(x), _) | (None, Some(Ok(x))) => x,
+        (None, Some(Err(e))) => {
+            eprintln!("invalid config: {}", e);
+            return Ok(ExitCode::FAILURE);
+        }
+        (None, None) => OutputFormat::Csv,
+    };
+    let input = |input: Option<String>| {
+        input
+            .or_else(|| config.input.clone())
+            .unwrap_or_else(|| String::from(STDIO_PATH))
+    };
+    match cli.command {
+        Command::Process(mut args) => {
+            args.input = Some(input(args.input));
+            args.output = args.output.or_else(|| config.output.clone());
+            args.decimal_places = args.decimal_places.or(config.rounding.dp);
+            args.settle_locked_disputes |= config.policies.settle_locked_disputes;
+            args.refund_withdrawal_chargebacks |= config.policies.refund_withdrawal_chargebacks;
+            return process(*args, &format, mode, cli.quiet);
+        }
+        Command::Validate { input: path } => {
+            return validate(input(path), format == OutputFormat::Json, cli.quiet)
+        }
+        Command::Report {
+            input: path,
+            clients,
+        } => {
+            let clients: HashSet<u16> = clients.into_iter().collect();
+            let statements = statements(
+                CsvSource::new(open_input(input(path))?),
+                mode,
+                Default::default(),
+                (!clients.is_empty()).then_some(&clients),
+            )?;
+            let mut stdout = BufWriter::new(io::stdout().lock());
+            match format {
+                OutputFormat::Json => {
+                    serde_json::to_writer(&mut stdout, &statements).map_err(io::Error::from)?;
+                    writeln!(stdout)?;
+                }
+                OutputFormat::Ndjson => {
+                    for statement in &statements {
+                        serde_json::to_writer(&mut stdout, statement).map_err(io::Error::from)?;
+                        writeln!(stdout)?;
+                    }
+                }
+                OutputFormat::Csv | OutputFormat::Table => {
+                    for statement in &statements {
+                        writeln!(stdout, "{}", statement)?;
+                    }
+                }
+            }
+            stdout.flush()?;
+        }
+        Command::Diff { before, after } => diff(before, after, format == OutputFormat::Json)?,
+        Command::Generate {
+            output,
+            clients,
+            transactions,
+            dispute_rate,
+            seed,
+        } => {
+            let options = GeneratorOptions {
+                clients,
+                transactions,
+                dispute_rate,
+                seed,
+            };
+            generate(service::compression::create_output(output)?, &options)?;
+        }
+        Command::Watch {
+            dir,
+            output,
+            archive_dir,
+            poll_interval,
+            snapshot_interval,
+        } => {
+            let mut options = WatchOptions::new(dir);
+            if let Some(archive_dir) = archive_dir {
+                options.archive_dir = archive_dir.into();
+            }
+            options.poll_interval = Duration::from_secs(poll_interval);
+            options.snapshot_interval = Duration::from_secs(snapshot_interval);
+            watch_dir(&options, output, &format, mode, cli.quiet)?;
+        }
+        Command::Repl => repl::run(io::stdin().lock(), io::stdout())?,
+        Command::Replay { wal, initial_state } => {
+            let initial_state = initial_state
+                .map(service::service::load_accounts_state)
+                .transpose()?
+                .unwrap_or_default();
+            let (result, _) = service::wal::replay_into(wal, initial_state)?;
+            format.writer().write_with_options(
+                &mut BufWriter::new(io::stdout().lock()),
+                &result,
+                &OutputOptions::default(),
+            )?;
+        }
+        Command::Tenants {
+            inputs,
+            output_dir,
+            settlement,
+        } => {
+            let mut tenants = Tenants::new();
+            for input in &inputs {
+                let (tenant, path) = match input.split_once('=') {
+                    Some((tenant, path)) => (Some(tenant), path),
+                    None => (None, input.as_str()),
+                };
+                let source = CsvSource::new(open_input(path)?);
+                (tenants, _) = read_source_by_tenant(source, mode, tenants, tenant)?;
+            }
+            let paths =
+                write_tenant_outputs(output_dir, &tenants, &format, &OutputOptions::default())?;
+            if let Some(path) = &settlement.path {
+                let lines = settle_tenants(&tenants, &settlement.options());
+                write_settlement(path.clone(), &lines)?;
+            }
+            if !cli.quiet {
+                for path in paths {
+                    eprintln!("wrote {}", path.display());
+                }
+            }
+        }
+        Command::Forget {
+            client,
+            checkpoint_dir,
+            wal,
+            sqlite,
+            sled,
+        } => forget(client, checkpoint_dir, wal, sqlite, sled, cli.quiet)?,
+        Command::Schema { of } => {
+            let schema = match of.as_str() {
+                "transaction" => service::schema::transaction_schema().to_value(),
+                "account" => service::schema::account_schema().to_value(),
+                _ => server::openapi::openapi(),
+            };
+            println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+        }
+        Command::Serve(mut args) => {
+            args.tls_cert = args.tls_cert.or_else(|| config.server.tls_cert.clone());
+            args.tls_key = args.tls_key.or_else(|| config.server.tls_key.clone());
+            return serve(*args, &format, cli.quiet);
+        }
+        Command::Route {
+            input,
+            peers,
+            shards,
+            api_key,
+            output_dir,
+        } => return route(input, peers, shards, api_key, output_dir, cli.quiet),
+        Command::MergeOutput { inputs } => {
+            let merged = service::service::merge_accounts_states(&inputs)?;
+            format.writer().write_with_options(
+                &mut BufWriter::new(io::stdout().lock()),
+                &merged,
+                &OutputOptions::default(),
+            )?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
 
+// every given backend is erased, the erased counts go to stderr
+fn forget(
+    client: u16,
+    checkpoint_dir: Option<String>,
+    wal: Option<String>,
+    sqlite: Option<String>,
+    sled: Option<String>,
+    quiet: bool,
+) -> Result<(), ServiceError> {
+    // nothing is erased when a backend can't be
+    #[cfg(not(feature = "sqlite"))]
+    if sqlite.is_some() {
+        return Err(unsupported("--sqlite needs the `sqlite` feature"));
+    }
+    #[cfg(not(feature = "sled"))]
+    if sled.is_some() {
+        return Err(unsupported("--sled needs the `sled` feature"));
+    }
+    let mut erased = Vec::new();
+    if let Some(dir) = checkpoint_dir {
+        let count = service::checkpoint::forget_client(&dir, client)?;
+        erased.push((dir, count.unwrap_or_default()));
+    }
+    if let Some(path) = wal {
+        let count = service::wal::forget_client(&path, client)?;
+        erased.push((path, count));
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = sqlite {
+        let count = service::sqlite::forget_client_sqlite(&path, client)?;
+        erased.push((path, count));
+    }
+    #[cfg(feature = "sled")]
+    if let Some(path) = sled {
+        let store = service::store::SledStore::open(&path)?;
+        let mut accounts = domain::domain::Accounts::with_store(store);
+        let count = accounts.forget_client(client).unwrap_or_default();
+        accounts.store().flush()?;
+        erased.push((path, count));
+    }
+    if !quiet {
+        for (path, count) in erased {
+            eprintln!(
+                "{}: erased {} transactions of client {}",
+                path, count, client
+            );
+        }
+    }
     Ok(())
 }
+
+#[cfg(not(all(feature = "sqlite", feature = "sled")))]
+fn unsupported(message: &str) -> ServiceError {
+    io::Error::new(io::ErrorKind::Unsupported, message).into()
+}
+
+fn process(
+    args: ProcessArgs,
+    output_format: &OutputFormat,
+    mode: ParseMode,
+    quiet: bool,
+) -> Result<ExitCode, ServiceError> {
+    let mut options = OutputOptions::default();
+    options.filter.clients = args.clients.map(|x| x.into_iter().collect());
+    options.filter.locked_only = args.locked_only;
+    options.filter.held_only = args.held_only;
+    options.extended = args.extended;
+    if let Some(decimal_places) = args.decimal_places {
+        options.rounding.decimal_places = decimal_places;
+    }
+    let show_progress = args.progress && !quiet;
+    let input_path = args.input.unwrap_or_else(|| String::from(STDIO_PATH));
+    let output_path = args.output.unwrap_or_else(|| String::from(STDIO_PATH));
+    if args.checkpoint_dir.is_some() && is_stdio(&input_path) {
+        eprintln!("--checkpoint-dir needs an input file to resume from");
+        return Ok(ExitCode::FAILURE);
+    }
+
+    #[cfg(feature = "otel")]
+    let run = otel::Run::start();
+    let mut capacity = CapacityHint::from_input_path(&input_path);
+    capacity.clients = args.expected_clients.unwrap_or(capacity.clients);
+    capacity.transactions = args.expected_transactions.unwrap_or(capacity.transactions);
+    let mut initial_state = args
+        .initial_state
+        .map(service::service::load_accounts_state)
+        .transpose()?
+        .unwrap_or_else(|| capacity.accounts());
+    let mut policies = Policies::default();
+    if args.settle_locked_disputes {
+        policies.locked_account = LockedAccountPolicy::SettleOpenDisputes;
+    }
+    if args.refund_withdrawal_chargebacks {
+        policies.dispute = DisputePolicy::DepositsAndRefundedWithdrawals;
+    }
+    policies.limits = Limits {
+        max_amount: args.max_amount,
+        max_total: args.max_balance,
+    };
+    if args.strict_order {
+        policies.ordering = OrderingPolicy::Strict;
+    }
+    policies.duplicate = match args.duplicates.as_str() {
+        "error" => DuplicatePolicy::Error,
+        "last-write-wins" => DuplicatePolicy::LastWriteWins,
+        _ => DuplicatePolicy::Ignore,
+    };
+    if args.open_on_any_record {
+        policies.first_transaction = FirstTransactionPolicy::AnyRecord;
+    }
+    initial_state.set_policies(policies);
+    if args.dry_run {
+        let source = CsvSource::new(open_input(&input_path)?);
+        let preview = dry_run(source, mode, &initial_state)?;
+        if let Some(rejections_path) = args.rejections {
+            service::service::write_rejections(rejections_path, &preview.report)?;
+        }
+        if !quiet {
+            print_diffs(&preview.changes);
+            println!("{}", preview.report.summary);
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    let mut wal = args.wal.map(|x| Wal::open(x, WAL_SYNC_EVERY)).transpose()?;
+    let (mut reconciliation, mut risk_report, mut suspicious_activity) = (None, None, None);
+    let mut dormancy_report = None;
+    let (result, report) = if let Some(checkpoint_dir) = args.checkpoint_dir {
+        service::checkpoint::resume(checkpoint_dir, input_path, CHECKPOINT_EVERY, initial_state)?
+    } else {
+        #[cfg(not(feature = "mmap"))]
+        if args.mmap {
+            eprintln!("--mmap needs the `mmap` feature, reading the input without it");
+        }
+        let logged = wal.as_mut();
+        // the source is opened on the parsing thread when pipelined
+        let open_source = || {
+            let (input, file_size): (Box<dyn Read>, _) = if is_stdio(&input_path) {
+                (Box::new(io::stdin().lock()), None)
+            } else {
+                let (file, file_size) = open_file(&input_path, args.mmap)?;
+                (file, Some(file_size))
+            };
+            let reader = ProgressReader::new(input);
+            let bytes_read = reader.bytes_read();
+            let source = ProgressSource::new(
+                CsvSource::new(decompress(reader)?),
+                bytes_read,
+                PROGRESS_EVERY,
+                move |records, bytes_read| match file_size {
+                    Some(file_size) if show_progress => eprint!(
+                        "\rprocessed {} records ({}%)",
+                        records,
+                        bytes_read * 100 / file_size.max(1)
+                    ),
+                    None if show_progress => eprint!("\rprocessed {} records", records),
+                    _ => {}
+                },
+            );
+            let source: Box<dyn TransactionSource + '_> = match logged {
+                Some(wal) => Box::new(WalSource::new(source, wal)),
+                None => Box::new(source),
+            };
+            #[cfg(feature = "otel")]
+            let source = run.time_reads(source);
+            Ok(source)
+        };
+        let result = match args.pipeline_depth {
+            Some(depth) => read_pipelined(open_source, mode, initial_state, depth),
+            None if args.reconcile
+                || args.monitor_invariants.is_some()
+                || args.risk_report.is_some()
+                || args.suspicious_activity.is_some()
+                || args.dormancy_report.is_some() =>
+            {
+                open_source().and_then(|x| {
+                    let mut reconciler = Reconciler::new(&initial_state);
+                    let mut risk = RiskEngine::default().auto_freeze(args.auto_freeze);
+                    let mut aml = AmlMonitor::new(AmlOptions {
+                        threshold: args.aml_threshold,
+                        window: args.aml_window,
+                        ..AmlOptions::default()
+                    });
+                    let mut dormancy = DormancyTracker::new();
+                    let mut monitor = args.monitor_invariants.as_deref().map(|x| {
+                        let mode = match x {
+                            "fail-fast" => MonitorMode::FailFast,
+                            _ => MonitorMode::Log,
+                        };
+                        InvariantMonitor::new(mode).allow_negative_total(args.allow_negative_total)
+                    });
+                    let (mut accounts, report) =
+                        read_source_observed(x, mode, initial_state, |accounts, event| {
+                            if let Some(monitor) = &mut monitor {
+                                monitor.observe(accounts, &event)?;
+                            }
+                            reconciler.observe(&event);
+                            risk.observe(accounts, &event);
+                            aml.observe(&event);
+                            dormancy.observe(&event);
+                            Ok(())
+                        })?;
+                    // reconciled before the sweep, whose transactions are not in the input
+                    if args.reconcile {
+                        reconciliation = Some(reconciler.reconcile(&accounts));
+                    }
+                    let dormant = dormancy.dormant(&accounts, args.dormant_after);
+                    let swept = args
+                        .escheatment_account
+                        .map(|x| sweep(&mut accounts, &dormant, x))
+                        .unwrap_or_default();
+                    dormancy_report = Some(DormancyReport { dormant, swept });
+                    risk_report = Some(risk.into_report());
+                    suspicious_activity = Some(aml.into_activities());
+                    Ok((accounts, report))
+                })
+            }
+            None => open_source().and_then(|x| read_source_into(x, mode, initial_state)),
+        };
+        if show_progress {
+            eprintln!();
+        }
+        result?
+    };
+    #[cfg(feature = "otel")]
+    let ingested = std::time::SystemTime::now();
+    if let (Some(path), Some(risk_report)) = (args.risk_report, risk_report) {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, &risk_report).map_err(io::Error::other)?;
+    }
+    if let (Some(path), Some(dormancy_report)) = (args.dormancy_report, dormancy_report) {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, &dormancy_report).map_err(io::Error::other)?;
+    }
+    if let (Some(path), Some(activities)) = (args.suspicious_activity, suspicious_activity) {
+        service::aml::write_suspicious_activity(path, &activities)?;
+    }
+    if let Some(path) = &args.settlement.path {
+        let lines = settle(&result, &args.settlement.options());
+        write_settlement(path.clone(), &lines)?;
+    }
+    if let Some(rejections_path) = args.rejections {
+        service::service::write_rejections(rejections_path, &report)?;
+    }
+    if let Some(path) = args.save_snapshot {
+        save_snapshot(path, &result)?;
+    }
+    if let Some(baseline) = args.changed_since {
+        let baseline = service::service::load_accounts_state(baseline)?;
+        let changed = changed_clients(&baseline, &result, &options.rounding);
+        options.filter.clients = Some(match options.filter.clients.take() {
+            Some(clients) => clients.intersection(&changed).copied().collect(),
+            None => changed,
+        });
+    }
+    // the accounts are printed unless quiet, and also written to the output file when one is given
+    let print = !is_stdio(&output_path) && !quiet;
+    let output = service::compression::create_output(output_path)?;
+    let mut output: Box<dyn Write> = if print {
+        Box::new(Tee(BufWriter::new(io::stdout().lock()), output))
+    } else {
+        output
+    };
+    output_format
+        .writer()
+        .write_with_options(&mut output, &result, &options)?;
+    // the logged records are part of the written state from now on
+    if let Some(wal) = &mut wal {
+        output.flush()?;
+        wal.commit()?;
+    }
+    #[cfg(feature = "otel")]
+    {
+        output.flush()?;
+        run.finish(ingested, &report.summary);
+    }
+    if report.summary.out_of_order > 0 {
+        eprintln!(
+            "input out of order: {} records refer to a tx id not seen yet",
+            report.summary.out_of_order
+        );
+        return Ok(ExitCode::FAILURE);
+    }
+    match reconciliation {
+        Some(x) if !x.is_balanced() => {
+            eprint!("reconciliation failed: {}", x);
+            Ok(ExitCode::FAILURE)
+        }
+        Some(x) if !quiet => {
+            eprint!("reconciliation: {}", x);
+            Ok(ExitCode::SUCCESS)
+        }
+        _ => Ok(ExitCode::SUCCESS),
+    }
+}
+
+// writes to both writers, so the accounts are only formatted once
+struct Tee<A, B>(A, B);
+
+impl<A: Write, B: Write> Write for Tee<A, B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(buf)?;
+        self.1.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()?;
+        self.1.flush()
+    }
+}
+
+// files that can't be mapped, e.g. pipes, are read normally
+fn open_file(path: &str, mmap: bool) -> Result<(Box<dyn Read>, u64), ServiceError> {
+    #[cfg(feature = "mmap")]
+    if mmap {
+        if let Some(map) = service::mmap::map_file(path)? {
+            let file_size = map.len() as u64;
+            return Ok((Box::new(io::Cursor::new(map)), file_size));
+        }
+    }
+    #[cfg(not(feature = "mmap"))]
+    let _ = mmap;
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    Ok((Box::new(file), file_size))
+}
+
+fn validate(input: String, json: bool, quiet: bool) -> Result<ExitCode, ServiceError> {
+    let report = service::validate::validate(CsvSource::new(open_input(input)?))?;
+    if json {
+        println!("{}", serde_json::to_string(&report).unwrap());
+    } else if !quiet {
+        for issue in &report.issues {
+            println!("line {}: {}", issue.line, issue.message);
+        }
+        println!("{} rows, {} issues", report.rows, report.issues.len());
+    }
+    if report.is_valid() {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+fn diff(before: String, after: String, json: bool) -> Result<(), ServiceError> {
+    let before = service::service::load_accounts_state(before)?;
+    let after = service::service::load_accounts_state(after)?;
+    let diffs = diff_accounts(&before, &after);
+    if json {
+        println!("{}", serde_json::to_string(&diffs).unwrap());
+    } else {
+        print_diffs(&diffs);
+    }
+    Ok(())
+}
+
+// e.g. `client 1 (changed): available 1 -> 2, total 1 -> 2`
+fn print_diffs(diffs: &[AccountDiff]) {
+    for diff in diffs {
+        let mut changes = Vec::new();
+        for (name, change) in [
+            ("available", &diff.available),
+            ("held", &diff.held),
+            ("total", &diff.total),
+        ] {
+            if let Some(change) = change {
+                changes.push(format!("{} {} -> {}", name, change.before, change.after));
+            }
+        }
+        if let Some(change) = &diff.locked {
+            changes.push(format!("locked {} -> {}", change.before, change.after));
+        }
+        println!(
+            "client {} ({}): {}",
+            diff.client,
+            diff.status,
+            changes.join(", ")
+        );
+    }
+}
+
+// written to a temporary file first, so readers never see a partial snapshot
+fn write_output(
+    output_path: &str,
+    output_format: &OutputFormat,
+    accounts: &domain::domain::Accounts,
+) -> Result<(), ServiceError> {
+    let temp_path = format!("{}.tmp", output_path);
+    let file = BufWriter::new(File::create(&temp_path)?);
+    let mut output = compress(file, Compression::from_path(output_path))?;
+    output_format.writer().write(&mut output, accounts)?;
+    output.flush()?;
+    drop(output);
+    std::fs::rename(&temp_path, output_path)?;
+    Ok(())
+}
+
+// stops watching once a snapshot can't be written
+fn watch_dir(
+    options: &WatchOptions,
+    output_path: String,
+    output_format: &OutputFormat,
+    mode: ParseMode,
+    quiet: bool,
+) -> Result<(), ServiceError> {
+    let running = AtomicBool::new(true);
+    let mut failed = None;
+    let watched = watch(
+        options,
+        mode,
+        Default::default(),
+        &running,
+        |accounts| {
+            if let Err(e) = write_output(&output_path, output_format, accounts) {
+                failed = Some(e);
+                running.store(false, Ordering::Relaxed);
+            }
+        },
+        |file| match &file.result {
+            Ok(report) if !quiet => eprintln!(
+                "applied {}: {} rows",
+                file.path.display(),
+                report.summary.total_rows
+            ),
+            Err(e) => eprintln!("failed {}: {}", file.path.display(), e),
+            _ => {}
+        },
+    );
+    match failed {
+        Some(e) => Err(e),
+        None => watched.map(|_| ()),
+    }
+}
+
+// Rows after a malformed one aren't routed, the ones before it stay applied.
+fn route(
+    input: String,
+    peers: Vec<String>,
+    shards: usize,
+    api_key: Option<String>,
+    output_dir: String,
+    quiet: bool,
+) -> Result<ExitCode, ServiceError> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let mut router = match peers.is_empty() {
+        true => ShardRouter::local(shards),
+        false => match runtime.block_on(ShardRouter::connect(&peers, api_key)) {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("{}", e.message());
+                return Ok(ExitCode::FAILURE);
+            }
+        },
+    };
+    let mut source = CsvSource::new(open_input(input)?);
+    let mut error = None;
+    let records = std::iter::from_fn(|| source.next_record())
+        .map_while(|x| match x {
+            Ok(x) => Some(x.into_record()),
+            Err(SourceError::Row(e)) => {
+                error = Some(e.into_error());
+                None
+            }
+            Err(SourceError::Fatal(e)) => {
+                error = Some(e);
+                None
+            }
+        })
+        .flatten();
+    let reports = runtime.block_on(router.route(records));
+    if let Some(e) = error {
+        eprintln!("{}", e);
+        return Ok(ExitCode::FAILURE);
+    }
+    let reports = match reports {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("routing failed: {}", e.message());
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+
+    for (i, shard) in router.shards().iter().enumerate() {
+        let Shard::Local(state) = shard else {
+            continue;
+        };
+        std::fs::create_dir_all(&output_dir)?;
+        let path = std::path::Path::new(&output_dir).join(format!("shard-{}.csv", i));
+        let mut output = BufWriter::new(File::create(&path)?);
+        OutputFormat::Csv.writer().write_with_options(
+            &mut output,
+            &state.accounts.lock().unwrap(),
+            &OutputOptions::default(),
+        )?;
+        output.flush()?;
+        if !quiet {
+            eprintln!("wrote {}", path.display());
+        }
+    }
+    if !quiet {
+        for (i, report) in reports.iter().enumerate() {
+            eprintln!(
+                "shard {}: {} applied, {} rejected",
+                i, report.applied, report.rejected
+            );
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// Serves until ctrl-c or SIGTERM (or the dashboard is closed), then stops taking connections,
+// lets the queued transactions finish and persists the accounts.
+fn serve(
+    args: ServeArgs,
+    output_format: &OutputFormat,
+    quiet: bool,
+) -> Result<ExitCode, ServiceError> {
+    let rate_limit = args.rate_limit.map(|per_second| RateLimit {
+        per_second,
+        burst: args.rate_burst.unwrap_or(per_second),
+    });
+    if rate_limit.is_some_and(|x| !x.is_valid()) {
+        eprintln!("the rate limit must be positive and the burst at least 1");
+        return Ok(ExitCode::FAILURE);
+    }
+    let mut api_keys = match args.api_keys_file.map(ApiKeys::from_file).transpose() {
+        Ok(x) => x.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("failed to read the api keys: {}", e);
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    for (key, scope) in args.api_keys {
+        api_keys.insert(key, scope);
+    }
+    let tls = match (args.tls_cert, args.tls_key) {
+        (Some(cert), Some(key)) => match TlsIdentity::from_files(cert, key) {
+            Ok(x) => Some(x),
+            Err(e) => {
+                eprintln!("invalid tls certificate or key: {}", e);
+                return Ok(ExitCode::FAILURE);
+            }
+        },
+        (None, None) => None,
+        _ => {
+            eprintln!("--tls-cert and --tls-key go together");
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    // serves right away so the probes answer, submissions wait for the recovery below
+    let mut state = AppState::default().recovering();
+    if let Some(path) = &args.wal {
+        state = state.with_wal(Wal::open(path, WAL_SYNC_EVERY)?);
+    }
+    if let Some(path) = &args.audit_log {
+        state = match state.with_audit_log(path) {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("failed to open the audit log: {}", e);
+                return Ok(ExitCode::FAILURE);
+            }
+        };
+    }
+    let state = state
+        .with_max_queue_depth(args.max_queue_depth)
+        .with_rate_limit(rate_limit)
+        .with_api_keys(api_keys);
+    let (listener, grpc_listener) = runtime.block_on(async {
+        let grpc_listener = match args.grpc_port {
+            Some(grpc_port) => Some(TcpListener::bind(("0.0.0.0", grpc_port)).await?),
+            None => None,
+        };
+        io::Result::Ok((
+            TcpListener::bind(("0.0.0.0", args.port)).await?,
+            grpc_listener,
+        ))
+    })?;
+
+    let (shutdown, stopping) = tokio::sync::watch::channel(false);
+    let stopped = move || {
+        let mut stopping = stopping.clone();
+        async move {
+            let _ = stopping.wait_for(|x| *x).await;
+        }
+    };
+    let grpc = grpc_listener.map(|listener| {
+        let (state, tls, stopped) = (state.clone(), tls.clone(), stopped());
+        runtime.spawn(async move {
+            server::grpc::serve_with_shutdown(listener, state, tls, stopped)
+                .await
+                .map_err(io::Error::other)
+        })
+    });
+    let http = runtime.spawn(serve_with_shutdown(listener, state.clone(), tls, stopped()));
+    let mut accounts = args
+        .snapshot
+        .map(load_snapshot)
+        .transpose()?
+        .unwrap_or_default();
+    if let Some(path) = &args.wal {
+        accounts = service::wal::recover_into(path, accounts)?;
+    }
+    state.recovered(accounts);
+    #[cfg(feature = "tui")]
+    if args.dashboard {
+        dashboard::run(&state)?;
+    }
+    #[cfg(not(feature = "tui"))]
+    if args.dashboard {
+        eprintln!("--dashboard needs the `tui` feature, serving without it");
+    }
+    if !(cfg!(feature = "tui") && args.dashboard) {
+        runtime.block_on(shutdown_signal());
+    }
+    shutdown.send_replace(true);
+    // a server that failed or panicked fails the run once both are stopped
+    runtime.block_on(async {
+        let http = http.await.map_err(io::Error::other).and_then(|x| x);
+        if let Some(grpc) = grpc {
+            grpc.await.map_err(io::Error::other).and_then(|x| x)?;
+        }
+        http?;
+        state.drain().await;
+        io::Result::Ok(())
+    })?;
+
+    let accounts = state.accounts.lock().unwrap();
+    if let Some(path) = &args.save_snapshot {
+        save_snapshot(path, &accounts)?;
+    }
+    state.sync_wal(args.save_snapshot.is_some())?;
+    if let Some(output_path) = args.output {
+        write_output(&output_path, output_format, &accounts)?;
+    }
+    if !quiet {
+        eprintln!(
+            "shut down after applying {} transactions",
+            state.applied_transactions()
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
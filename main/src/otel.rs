@@ -0,0 +1,152 @@
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use opentelemetry::{
+    metrics::MeterProvider,
+    trace::{Span, TraceContextExt, Tracer, TracerProvider},
+    Context, KeyValue,
+};
+use opentelemetry_otlp::{MetricExporter, SpanExporter};
+use opentelemetry_sdk::{metrics::SdkMeterProvider, trace::SdkTracerProvider, Resource};
+use service::service::{InputTransactionRecord, ProcessingSummary, SourceError, TransactionSource};
+
+const SERVICE_NAME: &str = "txengine";
+
+// The trace of a run is exported when it finishes, with the OTLP exporters configured from the
+// standard OTEL_* environment variables. Records are read and applied one after the other, so
+// the read and apply spans both cover the ingestion and carry the time spent in each as `busy_ms`.
+pub struct Run {
+    started: SystemTime,
+    read_nanos: Arc<AtomicU64>,
+}
+
+pub struct TimedSource<S> {
+    source: S,
+    read_nanos: Arc<AtomicU64>,
+}
+
+impl<S: TransactionSource> TransactionSource for TimedSource<S> {
+    fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>> {
+        let started = Instant::now();
+        let record = self.source.next_record();
+        self.read_nanos
+            .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        record
+    }
+
+    fn line_number(&self) -> u64 {
+        self.source.line_number()
+    }
+}
+
+impl Run {
+    pub fn start() -> Run {
+        Run {
+            started: SystemTime::now(),
+            read_nanos: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn time_reads<S: TransactionSource>(&self, source: S) -> TimedSource<S> {
+        TimedSource {
+            source,
+            read_nanos: self.read_nanos.clone(),
+        }
+    }
+
+    pub fn finish(self, ingested: SystemTime, summary: &ProcessingSummary) {
+        if env::var("OTEL_SDK_DISABLED").is_ok_and(|x| x.eq_ignore_ascii_case("true")) {
+            return;
+        }
+        if let Err(e) = self.export(ingested, summary) {
+            eprintln!("OpenTelemetry export failed: {}", e);
+        }
+    }
+
+    fn export(
+        &self,
+        ingested: SystemTime,
+        summary: &ProcessingSummary,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let finished = SystemTime::now();
+        let ingestion = ingested.duration_since(self.started).unwrap_or_default();
+        let read = Duration::from_nanos(self.read_nanos.load(Ordering::Relaxed));
+        let apply = ingestion.saturating_sub(read);
+        let write = finished.duration_since(ingested).unwrap_or_default();
+        let rejected = summary.skipped_duplicates
+            + summary.skipped_insufficient_funds
+            + summary.skipped_over_limit
+            + summary.out_of_order
+            + summary.other_rejections;
+
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_resource(resource())
+            .with_batch_exporter(SpanExporter::builder().with_http().build()?)
+            .build();
+        let tracer = tracer_provider.tracer(SERVICE_NAME);
+        let mut run = tracer
+            .span_builder("run")
+            .with_start_time(self.started)
+            .with_attributes([
+                KeyValue::new("rows", summary.total_rows as i64),
+                KeyValue::new("applied", summary.applied as i64),
+                KeyValue::new("rejected", rejected as i64),
+                KeyValue::new("malformed", summary.malformed_rows as i64),
+            ])
+            .start(&tracer);
+        let parent = Context::new().with_remote_span_context(run.span_context().clone());
+        for (name, start, end, busy) in [
+            ("read", self.started, ingested, read),
+            ("apply", self.started, ingested, apply),
+            ("write", ingested, finished, write),
+        ] {
+            tracer
+                .span_builder(name)
+                .with_start_time(start)
+                .with_attributes([KeyValue::new("busy_ms", busy.as_millis() as i64)])
+                .start_with_context(&tracer, &parent)
+                .end_with_timestamp(end);
+        }
+        run.end_with_timestamp(finished);
+
+        let meter_provider = SdkMeterProvider::builder()
+            .with_resource(resource())
+            .with_periodic_exporter(MetricExporter::builder().with_http().build()?)
+            .build();
+        let meter = meter_provider.meter(SERVICE_NAME);
+        for (name, value) in [
+            ("txengine.rows", summary.total_rows),
+            ("txengine.transactions.applied", summary.applied),
+            ("txengine.transactions.rejected", rejected),
+            ("txengine.rows.malformed", summary.malformed_rows),
+        ] {
+            meter.u64_counter(name).build().add(value, &[]);
+        }
+        let phases = meter
+            .f64_histogram("txengine.phase.duration")
+            .with_unit("s")
+            .build();
+        for (phase, duration) in [("read", read), ("apply", apply), ("write", write)] {
+            phases.record(duration.as_secs_f64(), &[KeyValue::new("phase", phase)]);
+        }
+
+        tracer_provider.shutdown()?;
+        meter_provider.shutdown()?;
+        Ok(())
+    }
+}
+
+fn resource() -> Resource {
+    let resource = Resource::builder();
+    if env::var_os("OTEL_SERVICE_NAME").is_some() {
+        resource.build()
+    } else {
+        resource.with_service_name(SERVICE_NAME).build()
+    }
+}
@@ -0,0 +1,107 @@
+use std::io::{self, BufRead, Write};
+
+use domain::domain::{Accounts, TransactionOutcome};
+use service::service::{
+    transaction_type_from, AccountsWriter, CsvWriter, InputTransactionRecord, OutputOptions,
+    OutputRecord, RoundingConfig,
+};
+
+const HELP: &str = "\
+commands:
+  deposit|withdrawal CLIENT TX AMOUNT
+  dispute|resolve|chargeback|capture|release CLIENT TX
+  hold CLIENT TX AMOUNT EXPIRES_AFTER
+  show CLIENT      print one account
+  dump             print all accounts as csv
+  undo             revert the last applied transaction
+  help, quit";
+
+// every applied transaction keeps the previous state, so undo can go back to the start
+pub fn run<R: BufRead, W: Write>(input: R, mut output: W) -> io::Result<()> {
+    let mut accounts = Accounts::new();
+    let mut history: Vec<Accounts> = Vec::new();
+    writeln!(output, "type `help` for the commands")?;
+    for line in input.lines() {
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => continue,
+            ["quit" | "exit"] => break,
+            ["help"] => writeln!(output, "{}", HELP)?,
+            ["dump"] => CsvWriter
+                .write_with_options(&mut output, &accounts, &OutputOptions::default())
+                .map_err(io::Error::other)?,
+            ["undo"] => match history.pop() {
+                Some(previous) => {
+                    accounts = previous;
+                    writeln!(output, "undone")?;
+                }
+                None => writeln!(output, "nothing to undo")?,
+            },
+            ["show", client] => match client.parse() {
+                Ok(client) => match accounts.get_user_account(client) {
+                    Some(account) => {
+                        let record = OutputRecord::new(client, account, &RoundingConfig::default());
+                        writeln!(
+                            output,
+                            "client {}: available {}, held {}, total {}, locked {}",
+                            record.client,
+                            record.available,
+                            record.held,
+                            record.total,
+                            record.locked
+                        )?
+                    }
+                    None => writeln!(output, "client {} not found", client)?,
+                },
+                Err(_) => writeln!(output, "invalid client {}", client)?,
+            },
+            [transaction_type, args @ ..] => {
+                let record = match parse_transaction(transaction_type, args) {
+                    Some(record) => record,
+                    None => {
+                        writeln!(output, "invalid command, type `help` for the commands")?;
+                        continue;
+                    }
+                };
+                let previous = accounts.clone();
+                let outcome =
+                    accounts.add_transaction(record.client, record.tx, record.transaction);
+                if outcome == TransactionOutcome::Applied {
+                    history.push(previous);
+                }
+                writeln!(output, "{}", outcome)?
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_transaction(
+    transaction_type: &str,
+    args: &[&str],
+) -> Option<service::service::TransactionRecord> {
+    let (client, tx, rest) = match args {
+        [client, tx, rest @ ..] => (client.parse().ok()?, tx.parse().ok()?, rest),
+        _ => return None,
+    };
+    let (amount, expires_after) = match rest {
+        [] => (None, None),
+        [amount] => (Some(amount.parse().ok()?), None),
+        [amount, expires_after] => (
+            Some(amount.parse().ok()?),
+            Some(expires_after.parse().ok()?),
+        ),
+        _ => return None,
+    };
+    InputTransactionRecord {
+        transaction_type: transaction_type_from(transaction_type),
+        client,
+        tx,
+        amount,
+        expires_after,
+        idempotency_key: None,
+        tenant: None,
+    }
+    .into_record()
+}
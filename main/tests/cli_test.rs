@@ -0,0 +1,742 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+#[test]
+fn dash_arguments_should_read_stdin_and_write_stdout() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["process", "-", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"type, client, tx, amount\ndeposit, 1, 1, 2.0\nwithdrawal, 1, 2, 0.5\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked\n1,1.5,0,1.5,false\n"
+    );
+}
+
+#[test]
+fn pipeline_depth_should_give_the_same_output() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["process", "-", "-", "--pipeline-depth", "1"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"type, client, tx, amount\ndeposit, 1, 1, 2.0\nwithdrawal, 1, 2, 0.5\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked\n1,1.5,0,1.5,false\n"
+    );
+}
+
+#[test]
+fn validate_should_report_issues_and_fail() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_main"))
+        .arg("validate")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, x, 2, 1.0\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("line 3: "));
+    assert!(stdout.ends_with("2 rows, 1 issues\n"));
+}
+
+#[test]
+fn config_file_should_be_applied_with_environment_overrides() {
+    let dir = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("config");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("transactions.csv"),
+        "type, client, tx, amount\ndeposit, 1, 1, 1.2345\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("config.toml"),
+        "input = \"transactions.csv\"\nformat = \"ndjson\"\n\n[rounding]\ndp = 2\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_main"))
+        .arg("process")
+        .current_dir(&dir)
+        .env("TXENGINE_ROUNDING_DP", "1")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{\"client\":1,\"available\":\"1.2\",\"held\":\"0\",\"total\":\"1.2\",\"locked\":false}\n"
+    );
+}
+
+#[test]
+fn config_policies_should_be_applied_with_environment_overrides() {
+    let dir = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("config-policies");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("transactions.csv"),
+        "type, client, tx, amount\ndeposit, 1, 1, 5.0\nwithdrawal, 1, 2, 2.0\ndispute, 1, 2,\n\
+         chargeback, 1, 2,\ndeposit, 2, 3, 1.0\ndeposit, 2, 4, 2.0\ndispute, 2, 3,\n\
+         dispute, 2, 4,\nchargeback, 2, 3,\nresolve, 2, 4,\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("config.toml"),
+        "input = \"transactions.csv\"\n\n[policies]\nrefund_withdrawal_chargebacks = true\n",
+    )
+    .unwrap();
+    let accounts = |settle_locked_disputes: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_main"))
+            .arg("process")
+            .current_dir(&dir)
+            .env(
+                "TXENGINE_POLICIES_SETTLE_LOCKED_DISPUTES",
+                settle_locked_disputes,
+            )
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let mut lines: Vec<_> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(String::from)
+            .collect();
+        lines.sort();
+        lines
+    };
+
+    // the refunded withdrawal is back in available, the resolve of the locked account is not
+    assert_eq!(accounts("false"), ["1,5,0,5,true", "2,0,2,2,true"]);
+    assert_eq!(accounts("true"), ["1,5,0,5,true", "2,2,0,2,true"]);
+}
+
+#[test]
+fn repl_should_apply_show_and_undo_transactions() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_main"))
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"deposit 1 100 25.0\nwithdrawal 1 101 30\ndispute 1 100\nshow 1\nundo\nshow 1\nshow 2\ndump\nfoo\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "type `help` for the commands
+applied
+rejected: insufficient_funds
+applied
+client 1: available 0.0, held 25.0, total 25.0, locked false
+undone
+client 1: available 25.0, held 0, total 25.0, locked false
+client 2 not found
+client,available,held,total,locked
+1,25.0,0,25.0,false
+invalid command, type `help` for the commands
+"
+    );
+}
+
+#[test]
+fn log_json_should_write_rejections_as_json_lines_to_stderr() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["--log-level", "debug", "--log-json", "process", "-", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 1, 1, 2.0\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    let logs: Vec<serde_json::Value> = String::from_utf8(output.stderr)
+        .unwrap()
+        .lines()
+        .map(|x| serde_json::from_str(x).unwrap())
+        .collect();
+    let rejection = logs
+        .iter()
+        .find(|x| x["fields"]["message"] == "transaction rejected")
+        .unwrap();
+    assert_eq!(rejection["level"], "DEBUG");
+    assert_eq!(rejection["fields"]["reason"], "duplicate_transaction");
+}
+
+#[test]
+fn replay_should_reproduce_the_accounts_of_a_logged_run() {
+    let dir = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("replay");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("transactions.csv"),
+        "type, client, tx, amount\ndeposit, 1, 1, 2.0\nbonus, 1, 2, 1.0\ndispute, 1, 1,\ndeposit, 2, 3, 1.5\n",
+    )
+    .unwrap();
+
+    let processed = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["process", "transactions.csv", "--wal", "run.wal"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    let replayed = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["--format", "json", "replay", "run.wal"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+
+    assert!(processed.status.success());
+    assert!(replayed.status.success());
+    let mut processed: Vec<_> = String::from_utf8(processed.stdout)
+        .unwrap()
+        .lines()
+        .skip(1)
+        .map(String::from)
+        .collect();
+    processed.sort();
+    assert_eq!(processed, ["1,0,2,2,false", "2,1.5,0,1.5,false"]);
+    let replayed = String::from_utf8(replayed.stdout).unwrap();
+    assert!(replayed.contains("\"client\":1,\"available\":\"0\",\"held\":\"2\""));
+    assert!(replayed.contains("\"client\":2,\"available\":\"1.5\""));
+}
+
+#[test]
+fn report_should_print_a_statement_of_the_given_client() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["report", "-", "--client", "1"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 2, 2, 1.0\nwithdrawal, 1, 3, 0.5\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("statement of client 1\n"));
+    assert!(stdout.lines().any(|x| x.split_whitespace().eq([
+        "3",
+        "withdrawal",
+        "0.5",
+        "applied",
+        "1.5",
+        "0",
+        "1.5"
+    ])));
+    assert!(stdout.contains("closing  available 1.5  held 0  total 1.5"));
+    assert!(!stdout.contains("client 2"));
+}
+
+#[test]
+fn process_with_reconcile_should_report_a_balanced_run() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["process", "-", "--reconcile"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 2, 2, 1.0\ndispute, 2, 2,\nchargeback, 2, 2,\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("= 2, accounts total 2\nbalanced"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn process_with_auto_freeze_should_lock_flagged_accounts_and_write_the_risk_report() {
+    let risk_path =
+        std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("cli_risk_report.json");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["process", "-", "--auto-freeze", "--risk-report"])
+        .arg(&risk_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"type, client, tx, amount\ndeposit, 1, 1, 10.0\nwithdrawal, 1, 2, 10.0\ndeposit, 1, 3, 5.0\ndeposit, 2, 4, 1.0\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("1,0,0,0,true"), "{}", stdout);
+    assert!(stdout.contains("2,1,0,1,false"));
+    let risk: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&risk_path).unwrap()).unwrap();
+    assert_eq!(risk["frozen"], serde_json::json!([1]));
+    assert_eq!(risk["flags"][0]["rule"], "rapid_deposit_withdrawal");
+}
+
+#[test]
+fn process_with_suspicious_activity_should_write_the_aml_csv() {
+    let activity_path =
+        std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("cli_suspicious_activity.csv");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args([
+            "process",
+            "-",
+            "--aml-threshold",
+            "100",
+            "--suspicious-activity",
+        ])
+        .arg(&activity_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(
+            b"type, client, tx, amount\ndeposit, 1, 1, 95\ndeposit, 1, 2, 99\ndeposit, 2, 3, 5\n",
+        )
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    let activity = std::fs::read_to_string(&activity_path).unwrap();
+    assert_eq!(
+        activity.lines().collect::<Vec<_>>(),
+        [
+            "client,tx,activity,amount,window_deposits,window_total",
+            "1,2,threshold_crossed,99,2,194",
+            "1,2,structuring,99,2,194"
+        ]
+    );
+}
+
+#[test]
+fn tenants_should_write_one_output_per_tenant() {
+    let dir = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("cli_tenants");
+    std::fs::create_dir_all(&dir).unwrap();
+    let a = dir.join("a_input.csv");
+    let b = dir.join("b_input.csv");
+    std::fs::write(&a, "type, client, tx, amount\ndeposit, 1, 1, 2.0\n").unwrap();
+    std::fs::write(&b, "type, client, tx, amount\ndeposit, 1, 1, 3.0\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_main"))
+        .arg("tenants")
+        .arg(format!("partner-a={}", a.display()))
+        .arg(format!("partner-b={}", b.display()))
+        .arg("--output-dir")
+        .arg(dir.join("out"))
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    for (tenant, row) in [
+        ("partner-a", "1,2,0,2,false"),
+        ("partner-b", "1,3,0,3,false"),
+    ] {
+        let written =
+            std::fs::read_to_string(dir.join("out").join(format!("{}.csv", tenant))).unwrap();
+        assert_eq!(written.lines().nth(1), Some(row));
+    }
+}
+
+#[test]
+fn process_with_settlement_should_write_the_settlement_csv() {
+    let settlement_path =
+        std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("cli_settlement.csv");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args([
+            "process",
+            "-",
+            "--settlement-batch-prefix",
+            "2026-10-14",
+            "--settlement",
+        ])
+        .arg(&settlement_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"type, client, tx, amount\ndeposit, 1, 1, 2.0\ndispute, 1, 1,\nchargeback, 1, 1,\ndeposit, 2, 2, 1.0\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    let settlement = std::fs::read_to_string(&settlement_path).unwrap();
+    assert_eq!(
+        settlement.lines().nth(1),
+        Some("2026-10-14-0001,,1,2,0,0,2,debit")
+    );
+    assert_eq!(settlement.lines().count(), 2);
+}
+
+#[test]
+fn forget_should_remove_the_client_from_the_wal() {
+    let dir = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("cli_forget");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("transactions.csv"),
+        "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 2, 2, 1.5\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_main"))
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .unwrap()
+    };
+
+    assert!(run(&["process", "transactions.csv", "--wal", "run.wal"])
+        .status
+        .success());
+    let forgotten = run(&["forget", "--client", "1", "--wal", "run.wal"]);
+    let replayed = run(&["replay", "run.wal"]);
+
+    assert!(forgotten.status.success());
+    assert!(String::from_utf8(forgotten.stderr)
+        .unwrap()
+        .contains("run.wal: erased 1 transactions of client 1"));
+    assert_eq!(
+        String::from_utf8(replayed.stdout).unwrap(),
+        "client,available,held,total,locked\n2,1.5,0,1.5,false\n"
+    );
+    assert!(!run(&["forget", "--client", "1"]).status.success());
+}
+
+#[test]
+fn schema_should_print_the_transaction_record_schema() {
+    let output = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["schema", "transaction"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(schema["title"], "TransactionRecord");
+    assert!(schema["properties"]["type"].is_object());
+}
+
+#[test]
+fn process_with_strict_order_should_fail_on_references_ahead_of_their_transaction() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["process", "-", "-", "--strict-order"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(
+            b"type, client, tx, amount\ndeposit, 1, 1, 2.0\ndispute, 1, 2,\ndeposit, 1, 2, 1.0\n",
+        )
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked\n1,3,0,3,false\n"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("input out of order: 1 records refer to a tx id not seen yet"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn process_with_fail_fast_invariant_monitor_should_fail_on_a_violation() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["process", "-", "-", "--monitor-invariants", "fail-fast"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(
+            b"type, client, tx, amount\ndeposit, 1, 1, 2.0\nwithdrawal, 1, 2, 2.0\ndispute, 1, 1,\nchargeback, 1, 1,\n",
+        )
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("invariant violated after tx 1: client 1 has a negative total -2"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn process_dry_run_should_print_the_changes_without_writing_the_output() {
+    let dir = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("dry_run");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("transactions.csv"),
+        "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 1, 1, 2.0\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_main"))
+        .current_dir(&dir)
+        .args(["process", "transactions.csv", "accounts.csv", "--dry-run"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(!dir.join("accounts.csv").exists());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client 1 (added): available 0 -> 2, total 0 -> 2\n\
+         2 rows: 1 applied, 1 duplicates, 0 insufficient funds, 0 over limit, \
+         0 out of order, 0 other rejections, 0 unknown types, 0 malformed\n"
+    );
+}
+
+#[test]
+fn process_changed_since_should_only_write_new_and_changed_accounts() {
+    let dir = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("changed_since");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("baseline.csv"),
+        "client,available,held,total,locked\n1,2,0,2,false\n2,1,0,1,false\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("transactions.csv"),
+        "type, client, tx, amount\ndeposit, 3, 3, 1.0\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_main"))
+        .current_dir(&dir)
+        .args([
+            "process",
+            "transactions.csv",
+            "-",
+            "--initial-state",
+            "baseline.csv",
+            "--changed-since",
+            "baseline.csv",
+        ])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked\n3,1,0,1,false\n"
+    );
+}
+
+#[test]
+fn routed_shard_outputs_should_merge_into_the_processed_accounts() {
+    let dir = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("cli_route");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("transactions.csv"),
+        "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 2, 2, 1.5\ndeposit, 3, 3, 4.0\nwithdrawal, 1, 4, 0.5\ndispute, 3, 3,\nwithdrawal, 2, 5, 9.0\n",
+    )
+    .unwrap();
+
+    let routed = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["route", "transactions.csv", "--shards", "2"])
+        .args(["--output-dir", "shards"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(routed.status.success());
+    let stderr = String::from_utf8(routed.stderr).unwrap();
+    assert!(
+        stderr.contains("shard 0: 1 applied, 1 rejected"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("shard 1: 4 applied, 0 rejected"),
+        "{}",
+        stderr
+    );
+
+    let merged = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["merge-output", "shards/shard-0.csv", "shards/shard-1.csv"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    let processed = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["process", "transactions.csv"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(merged.status.success());
+    let sorted = |output: Vec<u8>| {
+        let mut lines: Vec<_> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        lines.sort();
+        lines
+    };
+    assert_eq!(sorted(merged.stdout), sorted(processed.stdout));
+
+    let overlapping = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["merge-output", "shards/shard-0.csv", "shards/shard-0.csv"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(!overlapping.status.success());
+}
+
+#[test]
+fn errors_should_be_printed_with_a_failure_instead_of_a_panic() {
+    let dir = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("errors");
+    let invalid_config = dir.join("invalid-config");
+    std::fs::create_dir_all(&invalid_config).unwrap();
+    std::fs::write(invalid_config.join("config.toml"), "strict = \"maybe\"\n").unwrap();
+
+    for (args, current_dir, message) in [
+        (&["process", "missing.csv"][..], &dir, "io error"),
+        (
+            &["process", "-", "-", "--initial-state", "missing.csv"],
+            &dir,
+            "io error",
+        ),
+        (&["replay", "missing.wal"], &dir, "io error"),
+        (&["diff", "missing.csv", "missing.csv"], &dir, "io error"),
+        (
+            &["--config", "missing.toml", "process"],
+            &dir,
+            "cannot read config",
+        ),
+        (&["process"], &invalid_config, "invalid config"),
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_main"))
+            .args(args)
+            .current_dir(current_dir)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap();
+
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert_eq!(output.status.code(), Some(1), "{:?}: {}", args, stderr);
+        assert!(stderr.contains(message), "{:?}: {}", args, stderr);
+        assert!(!stderr.contains("panicked"), "{:?}: {}", args, stderr);
+    }
+}
+
+#[test]
+fn checkpoint_dir_should_need_an_input_file() {
+    let dir = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("stdin-checkpoint");
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["process", "-", "--checkpoint-dir", "state"])
+        .current_dir(&dir)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(
+        stderr.contains("--checkpoint-dir needs an input file to resume from"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
+
+#[cfg(not(feature = "sqlite"))]
+#[test]
+fn forget_should_fail_without_the_sqlite_feature() {
+    let output = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(["forget", "--client", "1", "--sqlite", "accounts.db"])
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(
+        stderr.contains("--sqlite needs the `sqlite` feature"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
@@ -0,0 +1,46 @@
+use tonic_build::manual::{Builder, Method, Service};
+
+// generated from Rust definitions so building does not need protoc, see proto/ledger.proto
+fn main() {
+    let method = |name: &str, route_name: &str, input_type: &str, output_type: &str| {
+        Method::builder()
+            .name(name)
+            .route_name(route_name)
+            .input_type(input_type)
+            .output_type(output_type)
+            .codec_path("tonic_prost::ProstCodec")
+    };
+    let ledger = Service::builder()
+        .name("Ledger")
+        .package("ledger")
+        .method(
+            method(
+                "submit_transaction",
+                "SubmitTransaction",
+                "service::codecs::protobuf::TransactionMessage",
+                "crate::grpc::SubmitResponse",
+            )
+            .build(),
+        )
+        .method(
+            method(
+                "get_account",
+                "GetAccount",
+                "crate::grpc::AccountRequest",
+                "crate::grpc::AccountMessage",
+            )
+            .build(),
+        )
+        .method(
+            method(
+                "watch_account",
+                "WatchAccount",
+                "crate::grpc::AccountRequest",
+                "crate::grpc::AccountMessage",
+            )
+            .server_streaming()
+            .build(),
+        )
+        .build();
+    Builder::new().compile(&[ledger]);
+}
@@ -0,0 +1,154 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use domain::domain::{RejectionReason, TransactionOutcome};
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use service::{admin::AdminOperation, service::ServiceError};
+
+use crate::{
+    rate_limit::RateLimit,
+    server::{ApiError, AppState, OutcomeResponse},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AdminAction {
+    Unlock { client: u16 },
+    Adjust { client: u16, amount: Decimal },
+    Resolve { tx: u32 },
+    SetRateLimit { limit: Option<RateLimit> },
+}
+
+impl From<AdminOperation> for AdminAction {
+    fn from(operation: AdminOperation) -> Self {
+        match operation {
+            AdminOperation::Unlock { client } => AdminAction::Unlock { client },
+            AdminOperation::Adjust { client, amount } => AdminAction::Adjust { client, amount },
+            AdminOperation::Resolve { tx } => AdminAction::Resolve { tx },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct AuditEntry {
+    /// Seconds since the Unix epoch
+    pub at: u64,
+    #[serde(flatten)]
+    pub action: AdminAction,
+    #[serde(flatten)]
+    pub outcome: OutcomeResponse,
+}
+
+impl AuditEntry {
+    pub fn new(action: AdminAction, outcome: TransactionOutcome) -> AuditEntry {
+        AuditEntry {
+            at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |x| x.as_secs()),
+            action,
+            outcome: outcome.into(),
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct Adjustment {
+    /// Added to the available funds, negative to debit them
+    pub amount: Decimal,
+}
+
+// the routes under /admin, which need an admin key
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/accounts/{client}/unlock", post(unlock))
+        .route("/accounts/{client}/adjust", post(adjust))
+        .route("/transactions/{tx}/resolve", post(resolve))
+        .route("/rate-limit", get(get_rate_limit).put(put_rate_limit))
+        .route("/audit", get(get_audit))
+}
+
+fn record(state: &AppState, entry: AuditEntry) -> Result<(), ApiError> {
+    state.record_admin_action(entry).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("the action was made but not audited: {}", e),
+        )
+    })
+}
+
+// applies and records the operation, targets that don't exist are a 404
+fn audited(state: &AppState, operation: AdminOperation) -> Result<Json<OutcomeResponse>, ApiError> {
+    let outcome = state.apply_admin(operation).map_err(|e| match e {
+        ServiceError::NotReady => (StatusCode::SERVICE_UNAVAILABLE, e.to_string()),
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    })?;
+    record(state, AuditEntry::new(operation.into(), outcome))?;
+    match outcome {
+        TransactionOutcome::Rejected(
+            reason @ (RejectionReason::AccountNotFound | RejectionReason::UnknownTransaction),
+        ) => Err((StatusCode::NOT_FOUND, reason.to_string())),
+        _ => Ok(Json(outcome.into())),
+    }
+}
+
+async fn unlock(
+    State(state): State<AppState>,
+    Path(client): Path<u16>,
+) -> Result<Json<OutcomeResponse>, ApiError> {
+    audited(&state, AdminOperation::Unlock { client })
+}
+
+async fn adjust(
+    State(state): State<AppState>,
+    Path(client): Path<u16>,
+    Json(adjustment): Json<Adjustment>,
+) -> Result<Json<OutcomeResponse>, ApiError> {
+    let amount = adjustment.amount;
+    audited(&state, AdminOperation::Adjust { client, amount })
+}
+
+async fn resolve(
+    State(state): State<AppState>,
+    Path(tx): Path<u32>,
+) -> Result<Json<OutcomeResponse>, ApiError> {
+    audited(&state, AdminOperation::Resolve { tx })
+}
+
+async fn get_rate_limit(State(state): State<AppState>) -> Json<Option<RateLimit>> {
+    Json(state.rate_limiter().limit())
+}
+
+async fn put_rate_limit(
+    State(state): State<AppState>,
+    Json(limit): Json<Option<RateLimit>>,
+) -> Result<Json<Option<RateLimit>>, ApiError> {
+    if let Some(x) = limit.filter(|x| !x.is_valid()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "invalid rate limit of {} per second with a burst of {}",
+                x.per_second, x.burst
+            ),
+        ));
+    }
+    state.rate_limiter().set_limit(limit);
+    record(
+        &state,
+        AuditEntry::new(
+            AdminAction::SetRateLimit { limit },
+            TransactionOutcome::Applied,
+        ),
+    )?;
+    Ok(Json(limit))
+}
+
+async fn get_audit(State(state): State<AppState>) -> Json<Vec<AuditEntry>> {
+    Json(state.audit_trail())
+}
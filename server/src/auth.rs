@@ -0,0 +1,158 @@
+use std::{collections::HashMap, fmt, fs, io, path::Path, str::FromStr};
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::server::{ApiError, AppState};
+
+// Each scope includes the ones before it: a submit key can also read, an admin key can do
+// anything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    Read,
+    Submit,
+    Admin,
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Scope::Read),
+            "submit" => Ok(Scope::Submit),
+            "admin" => Ok(Scope::Admin),
+            _ => Err(format!(
+                "unknown scope {}, expected read, submit or admin",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Scope::Read => "read",
+            Scope::Submit => "submit",
+            Scope::Admin => "admin",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthError {
+    // admin routes need a key even when no key is configured
+    Disabled,
+    Unauthenticated,
+    Forbidden { required: Scope },
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Disabled => f.write_str("admin routes are disabled without an admin key"),
+            AuthError::Unauthenticated => f.write_str("missing or unknown api key"),
+            AuthError::Forbidden { required } => {
+                write!(f, "the api key doesn't have the {} scope", required)
+            }
+        }
+    }
+}
+
+impl AuthError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AuthError::Unauthenticated => StatusCode::UNAUTHORIZED,
+            AuthError::Disabled | AuthError::Forbidden { .. } => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+// Without any key every route but the admin ones is open, with keys every route needs one.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys {
+    keys: HashMap<String, Scope>,
+}
+
+impl ApiKeys {
+    pub fn new() -> ApiKeys {
+        ApiKeys::default()
+    }
+
+    pub fn insert(&mut self, key: impl Into<String>, scope: Scope) {
+        self.keys.insert(key.into(), scope);
+    }
+
+    // the keys of `other` replace the scopes of the same keys
+    pub fn merge(&mut self, other: ApiKeys) {
+        self.keys.extend(other.keys);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    // `key:scope`, e.g. `s3cr3t:read`
+    pub fn parse_entry(entry: &str) -> Result<(String, Scope), String> {
+        let (key, scope) = entry
+            .rsplit_once(':')
+            .ok_or_else(|| format!("invalid api key {}, expected key:scope", entry))?;
+        if key.is_empty() {
+            return Err(String::from("empty api key"));
+        }
+        Ok((key.to_string(), scope.parse()?))
+    }
+
+    // one `key:scope` per line, blank lines and lines starting with # are skipped
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<ApiKeys> {
+        let mut keys = ApiKeys::new();
+        for line in fs::read_to_string(path)?.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, scope) = ApiKeys::parse_entry(line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            keys.insert(key, scope);
+        }
+        Ok(keys)
+    }
+
+    pub fn authorize(&self, key: Option<&str>, required: Scope) -> Result<(), AuthError> {
+        if self.keys.is_empty() {
+            return match required {
+                Scope::Admin => Err(AuthError::Disabled),
+                _ => Ok(()),
+            };
+        }
+        match key.and_then(|x| self.keys.get(x)) {
+            None => Err(AuthError::Unauthenticated),
+            Some(scope) if *scope < required => Err(AuthError::Forbidden { required }),
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+// the key of an `Authorization: Bearer {key}` header
+pub fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+}
+
+pub(crate) async fn authorize(
+    State((state, required)): State<(AppState, Scope)>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    state
+        .api_keys()
+        .authorize(bearer(request.headers()), required)
+        .map_err(|e| (e.status(), e.to_string()))?;
+    Ok(next.run(request).await)
+}
@@ -0,0 +1,201 @@
+use std::{future::Future, pin::Pin};
+
+use service::{
+    codecs::protobuf::TransactionMessage,
+    events::AccountEventKind,
+    service::{InputTransactionRecord, OutputRecord, ServiceError},
+};
+use tokio::net::TcpListener;
+use tokio_stream::{
+    wrappers::{BroadcastStream, TcpListenerStream},
+    Stream, StreamExt,
+};
+use tonic::{transport::ServerTlsConfig, Request, Response, Status};
+
+use crate::{
+    auth::{AuthError, Scope},
+    server::{AppState, OutcomeResponse},
+    tls::TlsIdentity,
+};
+
+include!(concat!(env!("OUT_DIR"), "/ledger.Ledger.rs"));
+
+pub use ledger_client::LedgerClient;
+pub use ledger_server::{Ledger, LedgerServer};
+
+// Mirrors proto/ledger.proto.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SubmitResponse {
+    #[prost(string, tag = "1")]
+    pub outcome: String,
+    #[prost(string, optional, tag = "2")]
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AccountRequest {
+    #[prost(uint32, tag = "1")]
+    pub client: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AccountMessage {
+    #[prost(uint32, tag = "1")]
+    pub client: u32,
+    #[prost(string, tag = "2")]
+    pub available: String,
+    #[prost(string, tag = "3")]
+    pub held: String,
+    #[prost(string, tag = "4")]
+    pub total: String,
+    #[prost(bool, tag = "5")]
+    pub locked: bool,
+}
+
+impl From<OutputRecord> for AccountMessage {
+    fn from(record: OutputRecord) -> Self {
+        AccountMessage {
+            client: record.client as u32,
+            available: record.available.to_string(),
+            held: record.held.to_string(),
+            total: record.total.to_string(),
+            locked: record.locked,
+        }
+    }
+}
+
+impl From<OutcomeResponse> for SubmitResponse {
+    fn from(response: OutcomeResponse) -> Self {
+        SubmitResponse {
+            outcome: response.outcome.to_string(),
+            reason: response.reason,
+        }
+    }
+}
+
+pub struct LedgerService {
+    state: AppState,
+}
+
+impl LedgerService {
+    pub fn new(state: AppState) -> LedgerService {
+        LedgerService { state }
+    }
+
+    // the key is sent like over HTTP, as `authorization: Bearer {key}` metadata
+    fn authorize<T>(&self, request: &Request<T>, required: Scope) -> Result<(), Status> {
+        let key = request
+            .metadata()
+            .get("authorization")
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.strip_prefix("Bearer "));
+        self.state
+            .api_keys()
+            .authorize(key, required)
+            .map_err(|e| match e {
+                AuthError::Unauthenticated => Status::unauthenticated(e.to_string()),
+                _ => Status::permission_denied(e.to_string()),
+            })
+    }
+}
+
+fn client_id(client: u32) -> Result<u16, Status> {
+    u16::try_from(client)
+        .map_err(|_| Status::invalid_argument(format!("client {} is out of range", client)))
+}
+
+// the status of a submission the engine didn't apply nor reject
+pub(crate) fn submit_status(error: ServiceError) -> Status {
+    match error {
+        e @ ServiceError::InvalidRecord { .. } => Status::already_exists(e.to_string()),
+        e @ ServiceError::NotReady => Status::unavailable(e.to_string()),
+        e @ (ServiceError::QueueFull { .. } | ServiceError::RateLimited { .. }) => {
+            Status::resource_exhausted(e.to_string())
+        }
+        e => Status::internal(e.to_string()),
+    }
+}
+
+type AccountStream = Pin<Box<dyn Stream<Item = Result<AccountMessage, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Ledger for LedgerService {
+    async fn submit_transaction(
+        &self,
+        request: Request<TransactionMessage>,
+    ) -> Result<Response<SubmitResponse>, Status> {
+        self.authorize(&request, Scope::Submit)?;
+        let record = InputTransactionRecord::try_from(request.into_inner())
+            .map_err(|e| Status::invalid_argument(e.to_string()))?
+            .into_record()
+            .ok_or_else(|| {
+                Status::invalid_argument("unknown transaction type or missing amount")
+            })?;
+        self.state
+            .apply(record)
+            .await
+            .map(|x| Response::new(OutcomeResponse::from(x).into()))
+            .map_err(submit_status)
+    }
+
+    async fn get_account(
+        &self,
+        request: Request<AccountRequest>,
+    ) -> Result<Response<AccountMessage>, Status> {
+        self.authorize(&request, Scope::Read)?;
+        let client = client_id(request.into_inner().client)?;
+        self.state
+            .account(client)
+            .map(|x| Response::new(x.into()))
+            .ok_or_else(|| Status::not_found(format!("client {} not found", client)))
+    }
+
+    type WatchAccountStream = AccountStream;
+
+    async fn watch_account(
+        &self,
+        request: Request<AccountRequest>,
+    ) -> Result<Response<AccountStream>, Status> {
+        self.authorize(&request, Scope::Read)?;
+        let client = client_id(request.into_inner().client)?;
+        // subscribe before reading the current balance so no update is missed in between
+        let updates = BroadcastStream::new(self.state.subscribe());
+        let current = self.state.account(client);
+        let state = self.state.clone();
+        // a lagged watcher skipped some updates, so it is sent the latest balance instead;
+        // a lock always follows another event of the same transaction, so it adds no update
+        let updates = updates.filter_map(move |update| match update {
+            Ok(event)
+                if event.client != client || event.kind == AccountEventKind::AccountLocked =>
+            {
+                None
+            }
+            _ => state.account(client),
+        });
+        let stream = tokio_stream::iter(current)
+            .chain(updates)
+            .map(|x| Ok(x.into()));
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+pub async fn serve(listener: TcpListener, state: AppState) -> Result<(), tonic::transport::Error> {
+    serve_with_shutdown(listener, state, None, std::future::pending()).await
+}
+
+// stops taking connections once `signal` completes and returns when the open ones are done
+pub async fn serve_with_shutdown(
+    listener: TcpListener,
+    state: AppState,
+    tls: Option<TlsIdentity>,
+    signal: impl Future<Output = ()>,
+) -> Result<(), tonic::transport::Error> {
+    let mut server = tonic::transport::Server::builder();
+    if let Some(tls) = tls {
+        server = server.tls_config(ServerTlsConfig::new().identity(tls.grpc()))?;
+    }
+    server
+        .add_service(LedgerServer::new(LedgerService::new(state)))
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), signal)
+        .await
+}
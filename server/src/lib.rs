@@ -0,0 +1,577 @@
+pub mod admin;
+pub mod auth;
+pub mod grpc;
+pub mod metrics;
+pub mod openapi;
+pub mod rate_limit;
+pub mod shard;
+pub mod tls;
+
+pub mod server {
+    use std::{
+        collections::{HashSet, VecDeque},
+        fs::{File, OpenOptions},
+        future::Future,
+        io::{self, Write},
+        path::Path as FilePath,
+        sync::{
+            atomic::{AtomicBool, AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        admin::{self, AuditEntry},
+        auth::{self, ApiKeys, Scope},
+        metrics::{self, Metrics},
+        openapi::openapi,
+        rate_limit::{RateLimit, RateLimiter},
+        tls::{TlsIdentity, TlsListener},
+    };
+    use axum::{
+        extract::{
+            ws::{Message, WebSocket, WebSocketUpgrade},
+            Path, Query, State,
+        },
+        http::{header, StatusCode},
+        middleware,
+        response::Response,
+        routing::{get, post},
+        Json, Router,
+    };
+    use domain::domain::{Accounts, TransactionActionState, TransactionOutcome};
+    use rust_decimal::Decimal;
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+    use service::admin::AdminOperation;
+    use service::events::{apply_record_with_events, AccountEvent};
+    use service::service::{
+        output_records, transaction_type_name, InputTransactionRecord, OutputOptions, OutputRecord,
+        RoundingConfig, ServiceError, TransactionRecord,
+    };
+    use service::wal::Wal;
+    use tokio::{
+        net::TcpListener,
+        sync::{
+            broadcast::{self, error::RecvError},
+            Semaphore,
+        },
+    };
+
+    const EVENTS_CAPACITY: usize = 1024;
+    pub const DEFAULT_MAX_QUEUE_DEPTH: usize = 1024;
+    // admin actions served by `GET /admin/audit`, the audit log keeps them all
+    const AUDIT_CAPACITY: usize = 1000;
+
+    // The queue is the submissions waiting for the accounts lock plus the one holding it; they
+    // wait on the blocking thread pool, so the runtime keeps serving the other requests.
+    #[derive(Clone)]
+    pub struct AppState {
+        pub accounts: Arc<Mutex<Accounts>>,
+        events: broadcast::Sender<AccountEvent>,
+        applied: Arc<AtomicU64>,
+        metrics: Arc<Metrics>,
+        queue: Arc<Semaphore>,
+        max_queue_depth: usize,
+        rate_limiter: Arc<RateLimiter>,
+        api_keys: Arc<ApiKeys>,
+        audit: Arc<Mutex<VecDeque<AuditEntry>>>,
+        audit_log: Option<Arc<Mutex<File>>>,
+        wal: Option<Arc<Mutex<Wal>>>,
+        // whether the last write to the wal succeeded
+        wal_available: Arc<AtomicBool>,
+        recovered: Arc<AtomicBool>,
+    }
+
+    impl Default for AppState {
+        fn default() -> Self {
+            AppState::new(Accounts::default())
+        }
+    }
+
+    impl AppState {
+        pub fn new(accounts: Accounts) -> AppState {
+            let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+            AppState {
+                accounts: Arc::new(Mutex::new(accounts)),
+                events,
+                applied: Arc::new(AtomicU64::new(0)),
+                metrics: Arc::new(Metrics::new()),
+                queue: Arc::new(Semaphore::new(DEFAULT_MAX_QUEUE_DEPTH)),
+                max_queue_depth: DEFAULT_MAX_QUEUE_DEPTH,
+                rate_limiter: Arc::default(),
+                api_keys: Arc::default(),
+                audit: Arc::default(),
+                audit_log: None,
+                wal: None,
+                wal_available: Arc::new(AtomicBool::new(true)),
+                recovered: Arc::new(AtomicBool::new(true)),
+            }
+        }
+
+        // applies beyond this many queued transactions fail with `ServiceError::QueueFull`
+        pub fn with_max_queue_depth(mut self, depth: usize) -> AppState {
+            self.queue = Arc::new(Semaphore::new(depth));
+            self.max_queue_depth = depth;
+            self
+        }
+
+        // applies of a client beyond the limit fail with `ServiceError::RateLimited`
+        pub fn with_rate_limit(self, limit: Option<RateLimit>) -> AppState {
+            self.rate_limiter.set_limit(limit);
+            self
+        }
+
+        pub fn rate_limiter(&self) -> &RateLimiter {
+            &self.rate_limiter
+        }
+
+        // the keys of the HTTP and gRPC requests, see `ApiKeys`
+        pub fn with_api_keys(mut self, keys: ApiKeys) -> AppState {
+            self.api_keys = Arc::new(keys);
+            self
+        }
+
+        pub fn api_keys(&self) -> &ApiKeys {
+            &self.api_keys
+        }
+
+        // every admin action is appended to the file as a json line, and synced
+        pub fn with_audit_log(mut self, path: impl AsRef<FilePath>) -> io::Result<AppState> {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            self.audit_log = Some(Arc::new(Mutex::new(file)));
+            Ok(self)
+        }
+
+        // the last admin actions since the start, in the order they were made
+        pub fn audit_trail(&self) -> Vec<AuditEntry> {
+            self.audit.lock().unwrap().iter().cloned().collect()
+        }
+
+        pub(crate) fn record_admin_action(&self, entry: AuditEntry) -> io::Result<()> {
+            let mut audit = self.audit.lock().unwrap();
+            if let Some(log) = &self.audit_log {
+                let mut line = serde_json::to_vec(&entry)?;
+                line.push(b'\n');
+                let mut log = log.lock().unwrap();
+                log.write_all(&line)?;
+                log.sync_data()?;
+            }
+            if audit.len() == AUDIT_CAPACITY {
+                audit.pop_front();
+            }
+            audit.push_back(entry);
+            Ok(())
+        }
+
+        // Logged to the wal and broadcast like the transactions; rare enough to be applied
+        // without going through the queue.
+        pub(crate) fn apply_admin(
+            &self,
+            operation: AdminOperation,
+        ) -> Result<TransactionOutcome, ServiceError> {
+            if !self.recovered.load(Ordering::Acquire) {
+                return Err(ServiceError::NotReady);
+            }
+            let mut wal = self.wal.as_ref().map(|x| x.lock().unwrap());
+            if let Some(wal) = &mut wal {
+                let appended = wal.append_admin(&operation);
+                self.wal_available
+                    .store(appended.is_ok(), Ordering::Relaxed);
+                appended?;
+            }
+            let mut accounts = self.accounts.lock().unwrap();
+            drop(wal);
+            let (outcome, event) = operation.apply(&mut accounts);
+            drop(accounts);
+            if let Some(event) = event {
+                let _ = self.events.send(event);
+            }
+            Ok(outcome)
+        }
+
+        // every submitted transaction is appended to the wal before it is applied
+        pub fn with_wal(mut self, wal: Wal) -> AppState {
+            self.wal = Some(Arc::new(Mutex::new(wal)));
+            self
+        }
+
+        // Until `recovered` is called, submissions are rejected with `ServiceError::NotReady` and
+        // the server reports it isn't ready, e.g. while a snapshot and a wal are replayed.
+        pub fn recovering(self) -> AppState {
+            self.recovered.store(false, Ordering::Release);
+            self
+        }
+
+        pub fn recovered(&self, recovered: Accounts) {
+            let mut accounts = self.accounts.lock().unwrap();
+            *accounts = recovered;
+            self.recovered.store(true, Ordering::Release);
+        }
+
+        pub fn readiness(&self) -> Readiness {
+            let recovered = self.recovered.load(Ordering::Acquire);
+            let wal_available = self
+                .wal
+                .as_ref()
+                .map(|_| self.wal_available.load(Ordering::Relaxed));
+            Readiness {
+                ready: recovered && wal_available != Some(false),
+                recovered,
+                wal_available,
+            }
+        }
+
+        // Flushes the wal to disk; `commit` marks its entries as applied, once the accounts were
+        // persisted (e.g. to a snapshot), so they aren't replayed on recovery.
+        pub fn sync_wal(&self, commit: bool) -> Result<(), ServiceError> {
+            let Some(wal) = &self.wal else {
+                return Ok(());
+            };
+            let mut wal = wal.lock().unwrap();
+            let synced = match commit {
+                true => wal.commit(),
+                false => wal.sync(),
+            };
+            self.wal_available.store(synced.is_ok(), Ordering::Relaxed);
+            synced
+        }
+
+        // waits for the queued transactions, e.g. once the servers stopped taking new ones
+        pub async fn drain(&self) {
+            while self.queue_depth() > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+
+        pub fn queue_depth(&self) -> usize {
+            self.max_queue_depth - self.queue.available_permits()
+        }
+
+        pub async fn apply(
+            &self,
+            record: TransactionRecord,
+        ) -> Result<TransactionOutcome, ServiceError> {
+            if !self.rate_limiter.check(record.client) {
+                return Err(ServiceError::RateLimited {
+                    client: record.client,
+                });
+            }
+            let slot = Arc::clone(&self.queue).try_acquire_owned().map_err(|_| {
+                ServiceError::QueueFull {
+                    depth: self.max_queue_depth,
+                }
+            })?;
+            let state = self.clone();
+            tokio::task::spawn_blocking(move || {
+                let _slot = slot;
+                state.apply_blocking(record)
+            })
+            .await
+            .expect("apply panicked")
+        }
+
+        fn apply_blocking(
+            &self,
+            record: TransactionRecord,
+        ) -> Result<TransactionOutcome, ServiceError> {
+            if !self.recovered.load(Ordering::Acquire) {
+                return Err(ServiceError::NotReady);
+            }
+            let transaction_type = transaction_type_name(&record.transaction);
+            // the wal stays locked until the accounts are, so the entries are logged in the
+            // order they are applied without writing to the wal under the accounts lock
+            let mut wal = self.wal.as_ref().map(|x| x.lock().unwrap());
+            if let Some(wal) = &mut wal {
+                let appended = wal.append(&InputTransactionRecord::from(&record));
+                self.wal_available
+                    .store(appended.is_ok(), Ordering::Relaxed);
+                appended?;
+            }
+            let mut accounts = self.accounts.lock().unwrap();
+            drop(wal);
+            let started = Instant::now();
+            let (outcome, events) = apply_record_with_events(&mut accounts, record)?;
+            self.metrics
+                .observe(transaction_type, outcome, started.elapsed());
+            drop(accounts);
+            if outcome == TransactionOutcome::Applied {
+                self.applied.fetch_add(1, Ordering::Relaxed);
+            }
+            for event in events {
+                let _ = self.events.send(event);
+            }
+            Ok(outcome)
+        }
+
+        pub fn applied_transactions(&self) -> u64 {
+            self.applied.load(Ordering::Relaxed)
+        }
+
+        pub fn subscribe(&self) -> broadcast::Receiver<AccountEvent> {
+            self.events.subscribe()
+        }
+
+        pub fn account(&self, client: u16) -> Option<OutputRecord> {
+            let accounts = self.accounts.lock().unwrap();
+            accounts
+                .get_user_account(client)
+                .map(|x| OutputRecord::new(client, x, &RoundingConfig::default()))
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, JsonSchema)]
+    #[schemars(rename = "Outcome")]
+    pub struct OutcomeResponse {
+        pub outcome: &'static str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub reason: Option<String>,
+    }
+
+    impl From<TransactionOutcome> for OutcomeResponse {
+        fn from(outcome: TransactionOutcome) -> Self {
+            match outcome {
+                TransactionOutcome::Applied => OutcomeResponse {
+                    outcome: "applied",
+                    reason: None,
+                },
+                TransactionOutcome::Rejected(reason) => OutcomeResponse {
+                    outcome: "rejected",
+                    reason: Some(reason.to_string()),
+                },
+            }
+        }
+    }
+
+    #[derive(Debug, Serialize, JsonSchema)]
+    pub struct Readiness {
+        pub ready: bool,
+        /// Whether the initial accounts were loaded, e.g. a snapshot and a write-ahead log replayed
+        pub recovered: bool,
+        /// Whether the last write to the write-ahead log succeeded, absent without one
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub wal_available: Option<bool>,
+    }
+
+    #[derive(Debug, Serialize, JsonSchema)]
+    #[schemars(rename = "Transaction")]
+    pub struct TransactionResponse {
+        pub client: u16,
+        pub tx: u32,
+        #[serde(rename = "type")]
+        pub transaction_type: &'static str,
+        pub amount: Decimal,
+        pub state: String,
+    }
+
+    pub(crate) type ApiError = (StatusCode, String);
+
+    pub fn router(state: AppState) -> Router {
+        let scoped = |router: Router<AppState>, scope| {
+            router.route_layer(middleware::from_fn_with_state(
+                (state.clone(), scope),
+                auth::authorize,
+            ))
+        };
+        let read = Router::new()
+            .route("/transactions/{tx}", get(get_transaction))
+            .route("/accounts", get(get_accounts))
+            .route("/accounts/{client}", get(get_account))
+            .route("/events", get(get_events))
+            .route("/metrics", get(get_metrics));
+        let submit = Router::new().route("/transactions", post(post_transaction));
+        Router::new()
+            .merge(scoped(read, Scope::Read))
+            .merge(scoped(submit, Scope::Submit))
+            .nest("/admin", scoped(admin::router(), Scope::Admin))
+            .route("/openapi.json", get(get_openapi))
+            .route("/healthz", get(get_health))
+            .route("/readyz", get(get_readiness))
+            .with_state(state)
+    }
+
+    pub async fn serve(listener: TcpListener, state: AppState) -> std::io::Result<()> {
+        axum::serve(listener, router(state)).await
+    }
+
+    // stops taking connections once `signal` completes and returns when the open ones are done
+    pub async fn serve_with_shutdown(
+        listener: TcpListener,
+        state: AppState,
+        tls: Option<TlsIdentity>,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> std::io::Result<()> {
+        let app = router(state);
+        match tls {
+            Some(tls) => {
+                let listener = TlsListener::new(listener, tls.server_config()?);
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(signal)
+                    .await
+            }
+            None => {
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(signal)
+                    .await
+            }
+        }
+    }
+
+    // ctrl-c, or SIGTERM on unix
+    pub async fn shutdown_signal() {
+        let interrupt = async {
+            tokio::signal::ctrl_c()
+                .await
+                .expect("cannot listen for ctrl-c");
+        };
+        #[cfg(unix)]
+        let terminate = async {
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .expect("cannot listen for SIGTERM")
+                .recv()
+                .await;
+        };
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<()>();
+        tokio::select! {
+            _ = interrupt => {},
+            _ = terminate => {},
+        }
+    }
+
+    async fn post_transaction(
+        State(state): State<AppState>,
+        Json(record): Json<InputTransactionRecord>,
+    ) -> Result<Json<OutcomeResponse>, ApiError> {
+        let record = record.into_record().ok_or((
+            StatusCode::BAD_REQUEST,
+            String::from("unknown transaction type or missing amount"),
+        ))?;
+        match state.apply(record).await {
+            Ok(outcome) => Ok(Json(outcome.into())),
+            Err(e @ ServiceError::InvalidRecord { .. }) => {
+                Err((StatusCode::CONFLICT, e.to_string()))
+            }
+            Err(e @ ServiceError::NotReady) => {
+                Err((StatusCode::SERVICE_UNAVAILABLE, e.to_string()))
+            }
+            Err(e @ (ServiceError::QueueFull { .. } | ServiceError::RateLimited { .. })) => {
+                Err((StatusCode::TOO_MANY_REQUESTS, e.to_string()))
+            }
+            Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+        }
+    }
+
+    async fn get_accounts(State(state): State<AppState>) -> Json<Vec<OutputRecord>> {
+        let accounts = state.accounts.lock().unwrap();
+        Json(output_records(&accounts, &OutputOptions::default()).collect())
+    }
+
+    async fn get_account(
+        State(state): State<AppState>,
+        Path(client): Path<u16>,
+    ) -> Result<Json<OutputRecord>, ApiError> {
+        state.account(client).map(Json).ok_or((
+            StatusCode::NOT_FOUND,
+            format!("client {} not found", client),
+        ))
+    }
+
+    async fn get_transaction(
+        State(state): State<AppState>,
+        Path(tx): Path<u32>,
+    ) -> Result<Json<TransactionResponse>, ApiError> {
+        let accounts = state.accounts.lock().unwrap();
+        let (client, log) = accounts.find_transaction(tx).ok_or((
+            StatusCode::NOT_FOUND,
+            format!("transaction {} not found", tx),
+        ))?;
+        let (transaction_type, amount) = match log.amount {
+            TransactionActionState::Deposit { amount } => ("deposit", amount),
+            TransactionActionState::Withdrawal { amount } => ("withdrawal", amount),
+            TransactionActionState::Hold { amount } => ("hold", amount),
+        };
+        Ok(Json(TransactionResponse {
+            client,
+            tx,
+            transaction_type,
+            amount,
+            state: log.state.to_string(),
+        }))
+    }
+
+    async fn get_metrics(
+        State(state): State<AppState>,
+    ) -> ([(header::HeaderName, &'static str); 1], String) {
+        let queue_depth = state.queue_depth();
+        let accounts = state.accounts.lock().unwrap();
+        (
+            [(header::CONTENT_TYPE, metrics::CONTENT_TYPE)],
+            state.metrics.encode(&accounts, queue_depth),
+        )
+    }
+
+    // the process is up, whatever the state of the accounts
+    async fn get_health() -> &'static str {
+        "ok"
+    }
+
+    async fn get_readiness(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+        let readiness = state.readiness();
+        let status = match readiness.ready {
+            true => StatusCode::OK,
+            false => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        (status, Json(readiness))
+    }
+
+    async fn get_openapi() -> Json<serde_json::Value> {
+        Json(openapi())
+    }
+
+    #[derive(Deserialize)]
+    struct EventsQuery {
+        clients: Option<String>,
+    }
+
+    async fn get_events(
+        State(state): State<AppState>,
+        Query(query): Query<EventsQuery>,
+        upgrade: WebSocketUpgrade,
+    ) -> Result<Response, ApiError> {
+        let clients = query
+            .clients
+            .map(|x| {
+                x.split(',')
+                    .map(|client| client.trim().parse())
+                    .collect::<Result<HashSet<u16>, _>>()
+                    .map_err(|_| (StatusCode::BAD_REQUEST, format!("invalid clients {}", x)))
+            })
+            .transpose()?;
+        let events = state.subscribe();
+        Ok(upgrade.on_upgrade(move |socket| push_events(socket, events, clients)))
+    }
+
+    // a subscriber that falls too far behind skips the events it missed
+    async fn push_events(
+        mut socket: WebSocket,
+        mut events: broadcast::Receiver<AccountEvent>,
+        clients: Option<HashSet<u16>>,
+    ) {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            };
+            if clients.as_ref().is_some_and(|x| !x.contains(&event.client)) {
+                continue;
+            }
+            let payload = serde_json::to_string(&event).unwrap();
+            if socket.send(Message::Text(payload.into())).await.is_err() {
+                return;
+            }
+        }
+    }
+}
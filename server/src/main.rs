@@ -0,0 +1,91 @@
+use std::env;
+
+use server::{
+    auth::ApiKeys,
+    grpc,
+    rate_limit::RateLimit,
+    server::{serve_with_shutdown, shutdown_signal, AppState, DEFAULT_MAX_QUEUE_DEPTH},
+    tls::TlsIdentity,
+};
+use tokio::net::TcpListener;
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let mut port = 8080;
+    let mut grpc_port = None;
+    let mut max_queue_depth = DEFAULT_MAX_QUEUE_DEPTH;
+    let mut rate_limit = None;
+    let mut api_keys = ApiKeys::new();
+    let (mut tls_cert, mut tls_key) = (None, None);
+    let mut audit_log = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => port = parse_port(args.next()),
+            "--grpc-port" => grpc_port = Some(parse_port(args.next())),
+            "--max-queue-depth" => {
+                max_queue_depth = args
+                    .next()
+                    .unwrap_or_default()
+                    .parse()
+                    .expect("invalid queue depth")
+            }
+            "--rate-limit" => {
+                let per_second = args
+                    .next()
+                    .unwrap_or_default()
+                    .parse()
+                    .expect("invalid rate limit");
+                rate_limit = Some(RateLimit {
+                    per_second,
+                    burst: per_second,
+                })
+            }
+            "--api-key" => {
+                let (key, scope) = ApiKeys::parse_entry(&args.next().unwrap_or_default())
+                    .expect("invalid api key");
+                api_keys.insert(key, scope)
+            }
+            "--api-keys-file" => api_keys.merge(
+                ApiKeys::from_file(args.next().unwrap_or_default()).expect("api keys error"),
+            ),
+            "--tls-cert" => tls_cert = args.next(),
+            "--tls-key" => tls_key = args.next(),
+            "--audit-log" => audit_log = args.next(),
+            _ => {}
+        }
+    }
+
+    assert!(
+        rate_limit.is_none_or(|x| x.is_valid()),
+        "invalid rate limit"
+    );
+    let tls = tls_cert
+        .zip(tls_key)
+        .map(|(cert, key)| TlsIdentity::from_files(cert, key))
+        .transpose()?;
+    let mut state = AppState::default()
+        .with_max_queue_depth(max_queue_depth)
+        .with_rate_limit(rate_limit)
+        .with_api_keys(api_keys);
+    if let Some(path) = audit_log {
+        state = state.with_audit_log(path)?;
+    }
+    if let Some(grpc_port) = grpc_port {
+        let listener = TcpListener::bind(("0.0.0.0", grpc_port)).await?;
+        let (state, tls) = (state.clone(), tls.clone());
+        tokio::spawn(async move {
+            grpc::serve_with_shutdown(listener, state, tls, shutdown_signal())
+                .await
+                .expect("gRPC server failed")
+        });
+    }
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    serve_with_shutdown(listener, state.clone(), tls, shutdown_signal()).await?;
+    state.drain().await;
+    Ok(())
+}
+
+fn parse_port(value: Option<String>) -> u16 {
+    value.unwrap_or_default().parse().expect("invalid port")
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use domain::domain::{Accounts, TransactionOutcome};
+use prometheus::{
+    Encoder, Gauge, Histogram, HistogramOpts, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use rust_decimal::prelude::ToPrimitive;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+// the gauges are computed from the accounts when the metrics are scraped
+pub struct Metrics {
+    registry: Registry,
+    transactions: IntCounterVec,
+    rejections: IntCounterVec,
+    apply_latency: Histogram,
+    accounts: IntGauge,
+    locked_accounts: IntGauge,
+    held: Gauge,
+    queue_depth: IntGauge,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        let metrics = Metrics {
+            registry: Registry::new(),
+            transactions: IntCounterVec::new(
+                Opts::new(
+                    "txengine_transactions_total",
+                    "Applied transactions by type",
+                ),
+                &["type"],
+            )
+            .unwrap(),
+            rejections: IntCounterVec::new(
+                Opts::new(
+                    "txengine_rejections_total",
+                    "Rejected transactions by reason",
+                ),
+                &["reason"],
+            )
+            .unwrap(),
+            apply_latency: Histogram::with_opts(
+                HistogramOpts::new(
+                    "txengine_apply_latency_seconds",
+                    "Time to apply a transaction to the accounts",
+                )
+                .buckets(prometheus::exponential_buckets(1e-6, 4.0, 10).unwrap()),
+            )
+            .unwrap(),
+            accounts: IntGauge::new("txengine_accounts", "Accounts").unwrap(),
+            locked_accounts: IntGauge::new("txengine_locked_accounts", "Locked accounts").unwrap(),
+            held: Gauge::new("txengine_held_total", "Funds held over all accounts").unwrap(),
+            queue_depth: IntGauge::new(
+                "txengine_queue_depth",
+                "Transactions waiting for or being applied",
+            )
+            .unwrap(),
+        };
+        for collector in [
+            Box::new(metrics.transactions.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(metrics.rejections.clone()),
+            Box::new(metrics.apply_latency.clone()),
+            Box::new(metrics.accounts.clone()),
+            Box::new(metrics.locked_accounts.clone()),
+            Box::new(metrics.held.clone()),
+            Box::new(metrics.queue_depth.clone()),
+        ] {
+            metrics.registry.register(collector).unwrap();
+        }
+        metrics
+    }
+
+    pub fn observe(&self, transaction_type: &str, outcome: TransactionOutcome, elapsed: Duration) {
+        self.apply_latency.observe(elapsed.as_secs_f64());
+        match outcome {
+            TransactionOutcome::Applied => self
+                .transactions
+                .with_label_values(&[transaction_type])
+                .inc(),
+            TransactionOutcome::Rejected(reason) => self
+                .rejections
+                .with_label_values(&[reason.to_string()])
+                .inc(),
+        }
+    }
+
+    pub fn encode(&self, accounts: &Accounts, queue_depth: usize) -> String {
+        let (mut locked, mut held) = (0, 0.0);
+        for (_, account) in accounts {
+            locked += account.locked as i64;
+            held += account.held.to_f64().unwrap_or_default();
+        }
+        self.accounts.set(accounts.len() as i64);
+        self.locked_accounts.set(locked);
+        self.held.set(held);
+        self.queue_depth.set(queue_depth as i64);
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
@@ -0,0 +1,234 @@
+use schemars::{generate::SchemaSettings, JsonSchema, SchemaGenerator};
+use serde_json::{json, Value};
+use service::service::{InputTransactionRecord, OutputRecord};
+
+use crate::{
+    admin::{Adjustment, AuditEntry},
+    rate_limit::RateLimit,
+    server::{OutcomeResponse, Readiness, TransactionResponse},
+};
+
+fn generator(settings: SchemaSettings) -> SchemaGenerator {
+    settings
+        .with(|x| x.definitions_path = "/components/schemas".into())
+        .into_generator()
+}
+
+fn reference<T: JsonSchema>(generator: &mut SchemaGenerator) -> Value {
+    generator.subschema_for::<T>().to_value()
+}
+
+fn json_response(description: &str, schema: Value) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": schema } }
+    })
+}
+
+fn text_response(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": { "text/plain": { "schema": { "type": "string" } } }
+    })
+}
+
+// an admin operation, which needs an admin key
+fn admin_operation(summary: &str, parameters: Value, request: Option<Value>, ok: Value) -> Value {
+    let mut operation = json!({
+        "summary": summary,
+        "parameters": parameters,
+        "responses": {
+            "200": ok,
+            "401": text_response("Missing or unknown API key"),
+            "403": text_response("Not an admin key, or the server has no admin key")
+        }
+    });
+    if let Some(schema) = request {
+        operation["requestBody"] = json!({
+            "required": true,
+            "content": { "application/json": { "schema": schema } }
+        });
+    }
+    operation
+}
+
+fn id_parameter(name: &str, maximum: u32) -> Value {
+    json!({
+        "name": name,
+        "in": "path",
+        "required": true,
+        "schema": { "type": "integer", "minimum": 0, "maximum": maximum }
+    })
+}
+
+// OpenAPI 3.1 description of the HTTP API, whose schemas are JSON Schema 2020-12 like the ones
+// of `service::schema`. Requests use the deserialize contract, so amounts may be strings or
+// numbers, and responses the serialize one, where amounts are strings.
+pub fn openapi() -> Value {
+    let mut requests = generator(SchemaSettings::draft2020_12().for_deserialize());
+    let mut responses = generator(SchemaSettings::draft2020_12().for_serialize());
+    let transaction_record = reference::<InputTransactionRecord>(&mut requests);
+    let rate_limit = reference::<Option<RateLimit>>(&mut requests);
+    let adjustment = reference::<Adjustment>(&mut requests);
+    let outcome = reference::<OutcomeResponse>(&mut responses);
+    let account = reference::<OutputRecord>(&mut responses);
+    let transaction = reference::<TransactionResponse>(&mut responses);
+    let readiness = reference::<Readiness>(&mut responses);
+    let audit = reference::<Vec<AuditEntry>>(&mut responses);
+    let accounts = json!({ "type": "array", "items": account });
+    let mut schemas = requests.take_definitions(true);
+    schemas.extend(responses.take_definitions(true));
+
+    json!({
+        "openapi": "3.1.0",
+        "info": { "title": "transaction engine", "version": env!("CARGO_PKG_VERSION") },
+        "security": [{ "api_key": [] }],
+        "paths": {
+            "/transactions": {
+                "post": {
+                    "summary": "Apply a transaction",
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": transaction_record } }
+                    },
+                    "responses": {
+                        "200": json_response("Applied or rejected", outcome.clone()),
+                        "400": text_response("Unknown transaction type or missing amount"),
+                        "409": text_response("Idempotency key reused for another transaction"),
+                        "503": text_response("The accounts are still being recovered"),
+                        "429": text_response("Too many transactions queued or submitted by the client")
+                    }
+                }
+            },
+            "/transactions/{tx}": {
+                "get": {
+                    "summary": "A deposit, withdrawal or hold and its state",
+                    "parameters": [id_parameter("tx", u32::MAX)],
+                    "responses": {
+                        "200": json_response("The transaction", transaction),
+                        "404": text_response("No such transaction")
+                    }
+                }
+            },
+            "/accounts": {
+                "get": {
+                    "summary": "Every account",
+                    "responses": {
+                        "200": json_response("The accounts", accounts)
+                    }
+                }
+            },
+            "/accounts/{client}": {
+                "get": {
+                    "summary": "The account of a client",
+                    "parameters": [id_parameter("client", u16::MAX.into())],
+                    "responses": {
+                        "200": json_response("The account", account),
+                        "404": text_response("No such client")
+                    }
+                }
+            },
+            "/events": {
+                "get": {
+                    "summary": "WebSocket pushing account events as JSON",
+                    "parameters": [{
+                        "name": "clients",
+                        "in": "query",
+                        "description": "Comma separated clients to push the events of",
+                        "schema": { "type": "string" }
+                    }],
+                    "responses": {
+                        "101": { "description": "Switching to the WebSocket protocol" },
+                        "400": text_response("Invalid clients")
+                    }
+                }
+            },
+            "/metrics": {
+                "get": {
+                    "summary": "Prometheus metrics",
+                    "responses": { "200": text_response("The metrics") }
+                }
+            },
+            "/admin/accounts/{client}/unlock": {
+                "post": admin_operation(
+                    "Unlock the account of a client",
+                    json!([id_parameter("client", u16::MAX.into())]),
+                    None,
+                    json_response("Applied", outcome.clone()),
+                )
+            },
+            "/admin/accounts/{client}/adjust": {
+                "post": admin_operation(
+                    "Credit or debit the available funds of a client",
+                    json!([id_parameter("client", u16::MAX.into())]),
+                    Some(adjustment),
+                    json_response("Applied or rejected", outcome.clone()),
+                )
+            },
+            "/admin/transactions/{tx}/resolve": {
+                "post": admin_operation(
+                    "Resolve a disputed transaction, even of a locked account",
+                    json!([id_parameter("tx", u32::MAX)]),
+                    None,
+                    json_response("Applied or rejected", outcome),
+                )
+            },
+            "/admin/rate-limit": {
+                "get": admin_operation(
+                    "The rate limit of each client, null when unlimited",
+                    json!([]),
+                    None,
+                    json_response("The rate limit", rate_limit.clone()),
+                ),
+                "put": admin_operation(
+                    "Change the rate limit of each client, null removes it",
+                    json!([]),
+                    Some(rate_limit.clone()),
+                    json_response("The new rate limit", rate_limit),
+                )
+            },
+            "/admin/audit": {
+                "get": admin_operation(
+                    "Every admin action",
+                    json!([]),
+                    None,
+                    json_response("The last 1000 admin actions since the start", audit),
+                )
+            },
+            "/healthz": {
+                "get": {
+                    "summary": "Liveness of the process",
+                    "security": [],
+                    "responses": { "200": text_response("ok") }
+                }
+            },
+            "/readyz": {
+                "get": {
+                    "summary": "Whether the server can take transactions",
+                    "security": [],
+                    "responses": {
+                        "200": json_response("Ready", readiness.clone()),
+                        "503": json_response("Recovering, or the write-ahead log is unavailable", readiness)
+                    }
+                }
+            },
+            "/openapi.json": {
+                "get": {
+                    "summary": "This document",
+                    "security": [],
+                    "responses": { "200": { "description": "The OpenAPI document" } }
+                }
+            }
+        },
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {
+                "api_key": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "An API key with the read, submit or admin scope, needed once the server has any key"
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,78 @@
+use std::{collections::HashMap, sync::Mutex, time::Instant};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RateLimit {
+    /// Transactions a client may submit per second on average
+    pub per_second: f64,
+    /// Transactions a client may submit at once after being idle
+    pub burst: f64,
+}
+
+impl RateLimit {
+    // a burst below one token would reject every transaction
+    pub fn is_valid(&self) -> bool {
+        self.per_second > 0.0 && self.burst >= 1.0
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+// A token bucket per client. Changing the limit keeps the buckets, their tokens are capped by
+// the new burst the next time they are refilled.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    limit: Mutex<Option<RateLimit>>,
+    buckets: Mutex<HashMap<u16, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: Option<RateLimit>) -> RateLimiter {
+        RateLimiter {
+            limit: Mutex::new(limit),
+            buckets: Mutex::default(),
+        }
+    }
+
+    pub fn limit(&self) -> Option<RateLimit> {
+        *self.limit.lock().unwrap()
+    }
+
+    // None removes the limit
+    pub fn set_limit(&self, limit: Option<RateLimit>) {
+        *self.limit.lock().unwrap() = limit;
+        if limit.is_none() {
+            self.buckets.lock().unwrap().clear();
+        }
+    }
+
+    // takes a token from the client's bucket, false when it is empty
+    pub fn check(&self, client: u16) -> bool {
+        self.check_at(client, Instant::now())
+    }
+
+    pub fn check_at(&self, client: u16, now: Instant) -> bool {
+        let Some(limit) = self.limit() else {
+            return true;
+        };
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: limit.burst,
+            refilled: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.refilled);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * limit.per_second).min(limit.burst);
+        bucket.refilled = bucket.refilled.max(now);
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
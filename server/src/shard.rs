@@ -0,0 +1,193 @@
+use serde::Serialize;
+use service::{
+    codecs::protobuf::TransactionMessage,
+    service::{InputTransactionRecord, TransactionRecord},
+};
+use tokio::sync::mpsc;
+use tonic::{transport::Channel, Code, Request, Status};
+
+use crate::{
+    grpc::{self, AccountMessage, AccountRequest, LedgerClient},
+    server::{AppState, OutcomeResponse},
+};
+
+// records waiting for each shard while routing
+const ROUTE_BUFFER: usize = 1024;
+
+// The shard of a client, as `sharded::read_source_sharded` partitions them.
+pub fn shard_of(client: u16, shards: usize) -> usize {
+    client as usize % shards.max(1)
+}
+
+// The engine the clients of a shard are sent to.
+pub enum Shard {
+    Local(AppState),
+    // an engine serving gRPC, e.g. `serve --grpc-port` on another machine
+    Remote {
+        client: LedgerClient<Channel>,
+        api_key: Option<String>,
+    },
+}
+
+impl Shard {
+    pub async fn connect(url: String, api_key: Option<String>) -> Result<Shard, Status> {
+        let client = LedgerClient::connect(url.clone())
+            .await
+            .map_err(|e| Status::unavailable(format!("failed to connect to {}: {}", url, e)))?;
+        Ok(Shard::Remote { client, api_key })
+    }
+
+    pub async fn submit(&mut self, record: TransactionRecord) -> Result<OutcomeResponse, Status> {
+        match self {
+            Shard::Local(state) => state
+                .apply(record)
+                .await
+                .map(OutcomeResponse::from)
+                .map_err(grpc::submit_status),
+            Shard::Remote { client, api_key } => {
+                let message = TransactionMessage::from(InputTransactionRecord::from(&record));
+                let response = client
+                    .submit_transaction(authorized(message, api_key.as_deref())?)
+                    .await?
+                    .into_inner();
+                Ok(OutcomeResponse {
+                    outcome: match response.outcome.as_str() {
+                        "applied" => "applied",
+                        _ => "rejected",
+                    },
+                    reason: response.reason,
+                })
+            }
+        }
+    }
+
+    pub async fn account(&mut self, client: u16) -> Result<Option<AccountMessage>, Status> {
+        match self {
+            Shard::Local(state) => Ok(state.account(client).map(AccountMessage::from)),
+            Shard::Remote {
+                client: ledger,
+                api_key,
+            } => {
+                let request = AccountRequest {
+                    client: u32::from(client),
+                };
+                match ledger
+                    .get_account(authorized(request, api_key.as_deref())?)
+                    .await
+                {
+                    Ok(x) => Ok(Some(x.into_inner())),
+                    Err(e) if e.code() == Code::NotFound => Ok(None),
+                    Err(e) => Err(e),
+                }
+            }
+        }
+    }
+}
+
+fn authorized<T>(message: T, api_key: Option<&str>) -> Result<Request<T>, Status> {
+    let mut request = Request::new(message);
+    if let Some(key) = api_key {
+        let value = format!("Bearer {}", key)
+            .parse()
+            .map_err(|_| Status::invalid_argument("invalid api key"))?;
+        request.metadata_mut().insert("authorization", value);
+    }
+    Ok(request)
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ShardReport {
+    pub applied: u64,
+    pub rejected: u64,
+}
+
+// Sends the transactions of every client to the engine of its shard. Tx ids are only
+// deduplicated within a shard, the engines don't know each other's.
+pub struct ShardRouter {
+    shards: Vec<Shard>,
+}
+
+impl ShardRouter {
+    pub fn new(shards: Vec<Shard>) -> ShardRouter {
+        assert!(!shards.is_empty(), "a router needs at least one shard");
+        ShardRouter { shards }
+    }
+
+    // `count` engines of this process
+    pub fn local(count: usize) -> ShardRouter {
+        ShardRouter::new(
+            (0..count.max(1))
+                .map(|_| Shard::Local(AppState::default()))
+                .collect(),
+        )
+    }
+
+    // one shard per url, in order
+    pub async fn connect(urls: &[String], api_key: Option<String>) -> Result<ShardRouter, Status> {
+        let mut shards = Vec::new();
+        for url in urls {
+            shards.push(Shard::connect(url.clone(), api_key.clone()).await?);
+        }
+        Ok(ShardRouter::new(shards))
+    }
+
+    pub fn shards(&self) -> &[Shard] {
+        &self.shards
+    }
+
+    pub async fn submit(&mut self, record: TransactionRecord) -> Result<OutcomeResponse, Status> {
+        let shard = shard_of(record.client, self.shards.len());
+        self.shards[shard].submit(record).await
+    }
+
+    pub async fn account(&mut self, client: u16) -> Result<Option<AccountMessage>, Status> {
+        let shard = shard_of(client, self.shards.len());
+        self.shards[shard].account(client).await
+    }
+
+    // Every shard is fed from a task of its own, so the shards apply their transactions in
+    // parallel while those of a client keep their order. Stops at the first failing shard, e.g.
+    // an unreachable peer; the records already sent to the others are applied.
+    pub async fn route(
+        &mut self,
+        records: impl IntoIterator<Item = TransactionRecord>,
+    ) -> Result<Vec<ShardReport>, Status> {
+        let mut senders = Vec::new();
+        let mut tasks = Vec::new();
+        for mut shard in self.shards.drain(..) {
+            let (sender, mut receiver) = mpsc::channel::<TransactionRecord>(ROUTE_BUFFER);
+            senders.push(sender);
+            tasks.push(tokio::spawn(async move {
+                let mut report = ShardReport::default();
+                while let Some(record) = receiver.recv().await {
+                    match shard.submit(record).await {
+                        Ok(x) if x.outcome == "applied" => report.applied += 1,
+                        Ok(_) => report.rejected += 1,
+                        Err(e) => return (shard, Err(e)),
+                    }
+                }
+                (shard, Ok(report))
+            }));
+        }
+        for record in records {
+            let shard = shard_of(record.client, senders.len());
+            // the shard failed, its error is returned below
+            if senders[shard].send(record).await.is_err() {
+                break;
+            }
+        }
+        drop(senders);
+
+        let mut reports = Vec::new();
+        let mut error = None;
+        for task in tasks {
+            let (shard, report) = task.await.expect("shard task panicked");
+            self.shards.push(shard);
+            match report {
+                Ok(x) => reports.push(x),
+                Err(e) => error = error.or(Some(e)),
+            }
+        }
+        error.map_or(Ok(reports), Err)
+    }
+}
@@ -0,0 +1,92 @@
+use std::{error::Error, fs, io, net::SocketAddr, path::Path, sync::Arc, time::Duration};
+
+use axum::serve::Listener;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{
+    rustls::{
+        crypto::ring,
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+        ServerConfig,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn invalid(error: impl Into<Box<dyn Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+// A PEM certificate, or chain starting with the server's certificate, and its private key,
+// for both the HTTP and the gRPC server.
+#[derive(Clone)]
+pub struct TlsIdentity {
+    cert: Vec<u8>,
+    key: Vec<u8>,
+}
+
+impl TlsIdentity {
+    // fails when the files can't be read or the key doesn't match the certificate
+    pub fn from_files(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> io::Result<TlsIdentity> {
+        let identity = TlsIdentity {
+            cert: fs::read(cert)?,
+            key: fs::read(key)?,
+        };
+        identity.server_config()?;
+        Ok(identity)
+    }
+
+    pub fn server_config(&self) -> io::Result<Arc<ServerConfig>> {
+        let certs = CertificateDer::pem_slice_iter(&self.cert)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        let key = PrivateKeyDer::from_pem_slice(&self.key).map_err(invalid)?;
+        let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(invalid)?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(invalid)?;
+        Ok(Arc::new(config))
+    }
+
+    pub(crate) fn grpc(&self) -> tonic::transport::Identity {
+        tonic::transport::Identity::from_pem(&self.cert, &self.key)
+    }
+}
+
+// Accepts the TLS connections of a TCP listener for `axum::serve`. Handshakes run one at a time
+// and a client that fails it or takes longer than 10s is dropped.
+pub struct TlsListener {
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+}
+
+impl TlsListener {
+    pub fn new(listener: TcpListener, config: Arc<ServerConfig>) -> TlsListener {
+        TlsListener {
+            listener,
+            acceptor: TlsAcceptor::from(config),
+        }
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        loop {
+            let (stream, address) = Listener::accept(&mut self.listener).await;
+            let handshake = self.acceptor.accept(stream);
+            if let Ok(Ok(stream)) = tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+                return (stream, address);
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        self.listener.local_addr()
+    }
+}
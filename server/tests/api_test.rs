@@ -0,0 +1,475 @@
+use std::{sync::mpsc, thread};
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use domain::domain::{Accounts, Transaction};
+use http_body_util::BodyExt;
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use server::{
+    auth::{ApiKeys, Scope},
+    server::{router, AppState},
+};
+use service::{
+    events::AccountEventKind,
+    wal::{recover, recover_into, Wal},
+};
+use tower::ServiceExt;
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn post(body: Value) -> Request<Body> {
+    Request::post("/transactions")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn get(uri: &str) -> Request<Body> {
+    Request::get(uri).body(Body::empty()).unwrap()
+}
+
+const ADMIN_KEY: &str = "secret";
+
+fn api_keys(keys: &[(&str, Scope)]) -> ApiKeys {
+    let mut api_keys = ApiKeys::new();
+    for (key, scope) in keys {
+        api_keys.insert(*key, *scope);
+    }
+    api_keys
+}
+
+fn with_key(mut request: Request<Body>, key: &str) -> Request<Body> {
+    let value = format!("Bearer {}", key).parse().unwrap();
+    request.headers_mut().insert("authorization", value);
+    request
+}
+
+fn admin(method: &str, uri: &str, body: Option<Value>) -> Request<Body> {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(body.map_or_else(Body::empty, |x| Body::from(x.to_string())))
+        .unwrap();
+    with_key(request, ADMIN_KEY)
+}
+
+#[tokio::test]
+async fn posted_transactions_should_be_visible_through_account_and_transaction_endpoints() {
+    let app = router(AppState::default());
+
+    let (status, body) = send(
+        &app,
+        post(json!({"type": "deposit", "client": 1, "tx": 1, "amount": "2.5"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({"outcome": "applied"}));
+
+    let (_, body) = send(
+        &app,
+        post(json!({"type": "withdrawal", "client": 1, "tx": 2, "amount": "5"})),
+    )
+    .await;
+    assert_eq!(
+        body,
+        json!({"outcome": "rejected", "reason": "insufficient_funds"})
+    );
+
+    send(&app, post(json!({"type": "dispute", "client": 1, "tx": 1}))).await;
+
+    let (status, body) = send(&app, get("/accounts/1")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!({"client": 1, "available": "0.0", "held": "2.5", "total": "2.5", "locked": false})
+    );
+
+    let (_, body) = send(&app, get("/accounts")).await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+
+    let (status, body) = send(&app, get("/transactions/1")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!({"client": 1, "tx": 1, "type": "deposit", "amount": "2.5", "state": "dispute"})
+    );
+}
+
+#[tokio::test]
+async fn unknown_resources_and_invalid_records_should_return_error_statuses() {
+    let app = router(AppState::default());
+
+    let (status, _) = send(&app, get("/accounts/7")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, get("/transactions/7")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send(&app, post(json!({"type": "deposit", "client": 1, "tx": 1}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn metrics_should_count_transactions_rejections_and_accounts() {
+    let app = router(AppState::default());
+    for body in [
+        json!({"type": "deposit", "client": 1, "tx": 1, "amount": "2.5"}),
+        json!({"type": "deposit", "client": 2, "tx": 2, "amount": "1.0"}),
+        json!({"type": "withdrawal", "client": 2, "tx": 3, "amount": "5"}),
+        json!({"type": "dispute", "client": 1, "tx": 1}),
+        json!({"type": "chargeback", "client": 1, "tx": 1}),
+        json!({"type": "dispute", "client": 2, "tx": 2}),
+    ] {
+        send(&app, post(body)).await;
+    }
+
+    let response = app.clone().oneshot(get("/metrics")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    for line in [
+        "txengine_transactions_total{type=\"deposit\"} 2",
+        "txengine_transactions_total{type=\"chargeback\"} 1",
+        "txengine_rejections_total{reason=\"insufficient_funds\"} 1",
+        "txengine_accounts 2",
+        "txengine_locked_accounts 1",
+        "txengine_held_total 1",
+        "txengine_apply_latency_seconds_count 6",
+        "txengine_queue_depth 0",
+    ] {
+        assert!(
+            body.lines().any(|x| x == line),
+            "missing {} in\n{}",
+            line,
+            body
+        );
+    }
+}
+
+#[tokio::test]
+async fn submissions_beyond_the_queue_depth_should_be_rejected_with_too_many_requests() {
+    let state = AppState::default().with_max_queue_depth(4);
+    let app = router(state.clone());
+    let deposit = |tx| post(json!({"type": "deposit", "client": 1, "tx": tx, "amount": "1"}));
+
+    // holding the accounts lock keeps the submissions queued
+    let (locked, release) = (mpsc::channel(), mpsc::channel::<()>());
+    let holder = {
+        let state = state.clone();
+        thread::spawn(move || {
+            let _accounts = state.accounts.lock().unwrap();
+            locked.0.send(()).unwrap();
+            release.1.recv().unwrap();
+        })
+    };
+    locked.1.recv().unwrap();
+    let queued: Vec<_> = (1..=4)
+        .map(|tx| {
+            let (app, request) = (app.clone(), deposit(tx));
+            tokio::spawn(async move { send(&app, request).await })
+        })
+        .collect();
+    while state.queue_depth() < 4 {
+        tokio::task::yield_now().await;
+    }
+    let (status, _) = send(&app, deposit(5)).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(state.queue_depth(), 4);
+    // the queued submissions don't block the runtime, even a single threaded one
+    assert_eq!(send(&app, get("/readyz")).await.0, StatusCode::OK);
+    release.0.send(()).unwrap();
+    holder.join().unwrap();
+
+    for submission in queued {
+        let (status, body) = submission.await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["outcome"], "applied");
+    }
+    assert_eq!(state.queue_depth(), 0);
+}
+
+#[tokio::test]
+async fn rate_limit_should_be_changeable_at_runtime_through_the_admin_endpoint() {
+    let app = router(AppState::default().with_api_keys(api_keys(&[(ADMIN_KEY, Scope::Admin)])));
+    let deposit = |tx| {
+        let body = json!({"type": "deposit", "client": 1, "tx": tx, "amount": "1"});
+        with_key(post(body), ADMIN_KEY)
+    };
+    let put = |body: Value| admin("PUT", "/admin/rate-limit", Some(body));
+
+    let (status, body) = send(&app, admin("GET", "/admin/rate-limit", None)).await;
+    assert_eq!((status, body), (StatusCode::OK, Value::Null));
+    let (status, _) = send(&app, put(json!({"per_second": 0.001, "burst": 0.5}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let limit = json!({"per_second": 0.001, "burst": 2.0});
+    let (status, body) = send(&app, put(limit.clone())).await;
+    assert_eq!((status, body), (StatusCode::OK, limit.clone()));
+    let (_, body) = send(&app, admin("GET", "/admin/rate-limit", None)).await;
+    assert_eq!(body, limit);
+    assert_eq!(send(&app, deposit(1)).await.0, StatusCode::OK);
+    assert_eq!(send(&app, deposit(2)).await.0, StatusCode::OK);
+    assert_eq!(
+        send(&app, deposit(3)).await.0,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    // other clients keep their own budget
+    let body = json!({"type": "deposit", "client": 2, "tx": 4, "amount": "1"});
+    let (status, _) = send(&app, with_key(post(body), ADMIN_KEY)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(&app, put(Value::Null)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(send(&app, deposit(3)).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn admin_routes_should_need_an_admin_key() {
+    let request = || admin("GET", "/admin/audit", None);
+    let (status, _) = send(&router(AppState::default()), request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let app = router(AppState::default().with_api_keys(api_keys(&[(ADMIN_KEY, Scope::Admin)])));
+    let (status, _) = send(&app, get("/admin/audit")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(&app, with_key(request(), "guess")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(send(&app, request()).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn admin_actions_should_change_the_accounts_and_be_audited() {
+    let app = router(AppState::default().with_api_keys(api_keys(&[(ADMIN_KEY, Scope::Admin)])));
+    for body in [
+        json!({"type": "deposit", "client": 1, "tx": 1, "amount": "10"}),
+        json!({"type": "deposit", "client": 1, "tx": 2, "amount": "5"}),
+        json!({"type": "dispute", "client": 1, "tx": 1}),
+        json!({"type": "dispute", "client": 1, "tx": 2}),
+        json!({"type": "chargeback", "client": 1, "tx": 2}),
+    ] {
+        send(&app, with_key(post(body), ADMIN_KEY)).await;
+    }
+
+    let (status, body) = send(&app, admin("POST", "/admin/transactions/1/resolve", None)).await;
+    assert_eq!(
+        (status, body),
+        (StatusCode::OK, json!({"outcome": "applied"}))
+    );
+    let (status, body) = send(
+        &app,
+        admin(
+            "POST",
+            "/admin/accounts/1/adjust",
+            Some(json!({"amount": "-20"})),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["reason"], "insufficient_funds");
+    let (status, _) = send(
+        &app,
+        admin(
+            "POST",
+            "/admin/accounts/1/adjust",
+            Some(json!({"amount": "-2.5"})),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, admin("POST", "/admin/accounts/1/unlock", None)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, admin("POST", "/admin/accounts/9/unlock", None)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, body) = send(&app, with_key(get("/accounts/1"), ADMIN_KEY)).await;
+    assert_eq!(body["available"], "7.5");
+    assert_eq!(body["locked"], false);
+
+    let (_, body) = send(&app, admin("GET", "/admin/audit", None)).await;
+    let actions: Vec<_> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|x| {
+            let mut x = x.clone();
+            x.as_object_mut().unwrap().remove("at");
+            x
+        })
+        .collect();
+    assert_eq!(
+        actions,
+        [
+            json!({"action": "resolve", "tx": 1, "outcome": "applied"}),
+            json!({"action": "adjust", "client": 1, "amount": "-20", "outcome": "rejected", "reason": "insufficient_funds"}),
+            json!({"action": "adjust", "client": 1, "amount": "-2.5", "outcome": "applied"}),
+            json!({"action": "unlock", "client": 1, "outcome": "applied"}),
+            json!({"action": "unlock", "client": 9, "outcome": "rejected", "reason": "account_not_found"}),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn admin_actions_should_be_logged_to_the_wal_broadcast_and_written_to_the_audit_log() {
+    let dir = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("admin_wal");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let state = AppState::default()
+        .with_wal(Wal::open(dir.join("run.wal"), 1024).unwrap())
+        .with_audit_log(dir.join("audit.log"))
+        .unwrap()
+        .with_api_keys(api_keys(&[(ADMIN_KEY, Scope::Admin)]));
+    let app = router(state.clone());
+    let mut events = state.subscribe();
+
+    let deposit = json!({"type": "deposit", "client": 1, "tx": 1, "amount": "10"});
+    send(&app, with_key(post(deposit), ADMIN_KEY)).await;
+    let adjust = admin(
+        "POST",
+        "/admin/accounts/1/adjust",
+        Some(json!({"amount": "-2.5"})),
+    );
+    assert_eq!(send(&app, adjust).await.0, StatusCode::OK);
+    let unknown = admin("POST", "/admin/accounts/9/unlock", None);
+    assert_eq!(send(&app, unknown).await.0, StatusCode::NOT_FOUND);
+
+    assert_eq!(
+        events.recv().await.unwrap().kind,
+        AccountEventKind::DepositApplied
+    );
+    let event = events.recv().await.unwrap();
+    assert_eq!(event.kind, AccountEventKind::BalanceAdjusted);
+    assert_eq!(event.amount, Some(Decimal::new(-25, 1)));
+    // rejected actions aren't broadcast
+    assert!(events.try_recv().is_err());
+
+    state.sync_wal(false).unwrap();
+    let recovered = recover(dir.join("run.wal")).unwrap();
+    assert_eq!(
+        recovered.get_user_account(1).unwrap().available.to_string(),
+        "7.5"
+    );
+    let audit = std::fs::read_to_string(dir.join("audit.log")).unwrap();
+    let audit: Vec<Value> = audit
+        .lines()
+        .map(|x| serde_json::from_str(x).unwrap())
+        .collect();
+    assert_eq!(audit.len(), 2);
+    assert_eq!(audit[0]["action"], "adjust");
+    assert_eq!(audit[1]["reason"], "account_not_found");
+}
+
+#[tokio::test]
+async fn a_conflicting_submission_should_not_stop_the_wal_from_being_recovered() {
+    let dir = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("conflict_wal");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let state = AppState::default().with_wal(Wal::open(dir.join("run.wal"), 1024).unwrap());
+    let app = router(state.clone());
+
+    let deposit = |tx, amount| {
+        post(
+            json!({"type": "deposit", "client": 1, "tx": tx, "amount": amount, "idempotency_key": "a"}),
+        )
+    };
+    assert_eq!(send(&app, deposit(1, "10")).await.0, StatusCode::OK);
+    assert_eq!(send(&app, deposit(2, "5")).await.0, StatusCode::CONFLICT);
+    state.sync_wal(false).unwrap();
+    drop(app);
+
+    // restarted from the log, like `serve --wal` does
+    let recovered = recover_into(dir.join("run.wal"), Accounts::new()).unwrap();
+    let restarted = AppState::default()
+        .with_wal(Wal::open(dir.join("run.wal"), 1024).unwrap())
+        .recovering();
+    restarted.recovered(recovered);
+    let app = router(restarted);
+    let (status, body) = send(&app, get("/accounts/1")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["available"], "10");
+    assert_eq!(send(&app, deposit(3, "5")).await.0, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn api_keys_should_only_allow_the_routes_of_their_scope() {
+    let keys = api_keys(&[("analyst", Scope::Read), ("partner", Scope::Submit)]);
+    let app = router(AppState::default().with_api_keys(keys));
+    let deposit = || post(json!({"type": "deposit", "client": 1, "tx": 1, "amount": "1"}));
+
+    assert_eq!(send(&app, deposit()).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        send(&app, get("/accounts")).await.0,
+        StatusCode::UNAUTHORIZED
+    );
+    let (status, body) = send(&app, with_key(deposit(), "analyst")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body, Value::Null);
+    assert_eq!(
+        send(&app, with_key(deposit(), "partner")).await.0,
+        StatusCode::OK
+    );
+    for key in ["analyst", "partner"] {
+        let (status, body) = send(&app, with_key(get("/accounts/1"), key)).await;
+        assert_eq!((status, &body["available"]), (StatusCode::OK, &json!("1")));
+    }
+    let request = with_key(admin("GET", "/admin/audit", None), "partner");
+    assert_eq!(send(&app, request).await.0, StatusCode::FORBIDDEN);
+    // the document of the API stays public
+    assert_eq!(send(&app, get("/openapi.json")).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn readiness_should_wait_for_the_recovery_while_health_stays_up() {
+    let state = AppState::default()
+        .recovering()
+        .with_api_keys(api_keys(&[(ADMIN_KEY, Scope::Admin)]));
+    let app = router(state.clone());
+
+    // the probes don't need a key
+    let (status, body) = send(&app, get("/readyz")).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body, json!({"ready": false, "recovered": false}));
+    assert_eq!(send(&app, get("/healthz")).await.0, StatusCode::OK);
+    let deposit = json!({"type": "deposit", "client": 1, "tx": 2, "amount": "1.0"});
+    let request = with_key(post(deposit.clone()), ADMIN_KEY);
+    assert_eq!(send(&app, request).await.0, StatusCode::SERVICE_UNAVAILABLE);
+
+    let mut accounts = Accounts::new();
+    accounts.add_transaction(
+        1,
+        1,
+        Transaction::Deposit {
+            amount: Decimal::ONE,
+        },
+    );
+    state.recovered(accounts);
+    let (status, body) = send(&app, get("/readyz")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["ready"], true);
+    let request = with_key(post(deposit), ADMIN_KEY);
+    assert_eq!(send(&app, request).await.0, StatusCode::OK);
+    let request = with_key(get("/accounts/1"), ADMIN_KEY);
+    assert_eq!(send(&app, request).await.1["available"], "2.0");
+}
+
+#[tokio::test]
+async fn openapi_should_describe_the_routes_and_resolve_the_record_schemas() {
+    let app = router(AppState::default());
+
+    let (status, body) = send(&app, get("/openapi.json")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["openapi"], "3.1.0");
+    let request = &body["paths"]["/transactions"]["post"]["requestBody"]["content"]
+        ["application/json"]["schema"];
+    assert_eq!(request["$ref"], "#/components/schemas/TransactionRecord");
+    assert!(body["components"]["schemas"]["TransactionRecord"].is_object());
+    assert!(body["components"]["schemas"]["Account"].is_object());
+}
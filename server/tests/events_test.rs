@@ -0,0 +1,60 @@
+use serde_json::{json, Value};
+use server::server::{serve, AppState};
+use service::service::InputTransactionRecord;
+use tokio::net::TcpListener;
+use tokio_stream::StreamExt;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+fn record(
+    transaction_type: &str,
+    client: u16,
+    tx: u32,
+    amount: Option<&str>,
+) -> InputTransactionRecord {
+    InputTransactionRecord {
+        transaction_type: transaction_type.to_string().into(),
+        client,
+        tx,
+        amount: amount.map(|x| x.parse().unwrap()),
+        expires_after: None,
+        idempotency_key: None,
+        tenant: None,
+    }
+}
+
+#[tokio::test]
+async fn websocket_subscribers_should_receive_events_of_their_clients_only() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let state = AppState::default();
+    tokio::spawn(serve(listener, state.clone()));
+    let (mut socket, _) = connect_async(format!("ws://{}/events?clients=1", address))
+        .await
+        .unwrap();
+
+    for record in [
+        record("deposit", 2, 1, Some("5.0")),
+        record("deposit", 1, 2, Some("2.0")),
+        record("dispute", 1, 2, None),
+        record("chargeback", 1, 2, None),
+    ] {
+        state.apply(record.into_record().unwrap()).await.unwrap();
+    }
+
+    let mut events = Vec::new();
+    while events.len() < 4 {
+        match socket.next().await.unwrap().unwrap() {
+            Message::Text(text) => events.push(serde_json::from_str::<Value>(&text).unwrap()),
+            _ => continue,
+        }
+    }
+    assert_eq!(
+        events,
+        vec![
+            json!({"event": "deposit_applied", "client": 1, "tx": 2, "amount": "2.0"}),
+            json!({"event": "dispute_opened", "client": 1, "tx": 2}),
+            json!({"event": "chargeback_applied", "client": 1, "tx": 2}),
+            json!({"event": "account_locked", "client": 1, "tx": 2}),
+        ]
+    );
+}
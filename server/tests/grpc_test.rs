@@ -0,0 +1,105 @@
+use server::{
+    auth::{ApiKeys, Scope},
+    grpc::{self, ledger_client::LedgerClient, AccountRequest},
+    server::AppState,
+};
+use service::codecs::protobuf::TransactionMessage;
+use tokio::net::TcpListener;
+use tokio_stream::StreamExt;
+use tonic::{Code, Request};
+
+fn deposit(client: u32, tx: u32, amount: &str) -> TransactionMessage {
+    TransactionMessage {
+        r#type: String::from("deposit"),
+        client,
+        tx,
+        amount: Some(String::from(amount)),
+        expires_after: None,
+        idempotency_key: None,
+        tenant: None,
+    }
+}
+
+#[tokio::test]
+async fn grpc_clients_should_submit_transactions_and_watch_balance_updates() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let state = AppState::default();
+    tokio::spawn(grpc::serve(listener, state.clone()));
+    let mut client = LedgerClient::connect(format!("http://{}", address))
+        .await
+        .unwrap();
+
+    let response = client
+        .submit_transaction(deposit(1, 1, "2.5"))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.outcome, "applied");
+
+    let mut updates = client
+        .watch_account(AccountRequest { client: 1 })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(updates.next().await.unwrap().unwrap().available, "2.5");
+
+    client.submit_transaction(deposit(2, 2, "1")).await.unwrap();
+    client.submit_transaction(deposit(1, 3, "1")).await.unwrap();
+    let update = updates.next().await.unwrap().unwrap();
+    assert_eq!(update.client, 1);
+    assert_eq!(update.available, "3.5");
+
+    // the REST and gRPC servers share the same accounts
+    assert_eq!(state.account(2).unwrap().available.to_string(), "1");
+
+    let account = client
+        .get_account(AccountRequest { client: 1 })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(account.total, "3.5");
+    let status = client
+        .get_account(AccountRequest { client: 7 })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+fn with_key<T>(message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    let value = "Bearer analyst".parse().unwrap();
+    request.metadata_mut().insert("authorization", value);
+    request
+}
+
+#[tokio::test]
+async fn grpc_requests_should_need_an_api_key_of_their_scope() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let mut keys = ApiKeys::new();
+    keys.insert("analyst", Scope::Read);
+    tokio::spawn(grpc::serve(
+        listener,
+        AppState::default().with_api_keys(keys),
+    ));
+    let mut client = LedgerClient::connect(format!("http://{}", address))
+        .await
+        .unwrap();
+
+    let status = client
+        .get_account(AccountRequest { client: 1 })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    let status = client
+        .get_account(with_key(AccountRequest { client: 1 }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    let status = client
+        .submit_transaction(with_key(deposit(1, 1, "1")))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+}
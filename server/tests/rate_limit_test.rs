@@ -0,0 +1,41 @@
+use std::time::{Duration, Instant};
+
+use server::rate_limit::{RateLimit, RateLimiter};
+
+#[test]
+fn each_client_should_have_its_own_bucket_refilled_over_time() {
+    let limiter = RateLimiter::new(Some(RateLimit {
+        per_second: 2.0,
+        burst: 3.0,
+    }));
+    let start = Instant::now();
+
+    assert!((0..3).all(|_| limiter.check_at(1, start)));
+    assert!(!limiter.check_at(1, start));
+    // another client isn't starved by the first one
+    assert!(limiter.check_at(2, start));
+
+    assert!(!limiter.check_at(1, start + Duration::from_millis(400)));
+    assert!(limiter.check_at(1, start + Duration::from_millis(500)));
+    assert!(!limiter.check_at(1, start + Duration::from_millis(500)));
+
+    // an idle client gets no more than the burst back
+    let later = start + Duration::from_secs(60);
+    assert!((0..3).all(|_| limiter.check_at(1, later)));
+    assert!(!limiter.check_at(1, later));
+}
+
+#[test]
+fn removing_the_limit_should_let_every_transaction_through() {
+    let limiter = RateLimiter::new(Some(RateLimit {
+        per_second: 1.0,
+        burst: 1.0,
+    }));
+    let now = Instant::now();
+    assert!(limiter.check_at(1, now));
+    assert!(!limiter.check_at(1, now));
+
+    limiter.set_limit(None);
+    assert!((0..100).all(|_| limiter.check_at(1, now)));
+    assert_eq!(limiter.limit(), None);
+}
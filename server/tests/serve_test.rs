@@ -0,0 +1,122 @@
+use std::{path::PathBuf, sync::Arc};
+
+use server::{
+    server::{serve_with_shutdown, AppState},
+    tls::TlsIdentity,
+};
+use service::{
+    service::{InputTransactionRecord, TransactionRecord},
+    wal::{recover, Wal},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::oneshot,
+};
+use tokio_rustls::{
+    rustls::{
+        crypto::ring,
+        pki_types::{pem::PemObject, CertificateDer, ServerName},
+        ClientConfig, RootCertStore,
+    },
+    TlsConnector,
+};
+
+fn resource(name: &str) -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests/resources");
+    path.push(name);
+    path
+}
+
+fn connector() -> TlsConnector {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(resource("cert.pem")).unwrap() {
+        roots.add(cert.unwrap()).unwrap();
+    }
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    TlsConnector::from(Arc::new(config))
+}
+
+fn deposit(client: u16, tx: u32, amount: &str) -> TransactionRecord {
+    let record = InputTransactionRecord {
+        transaction_type: "deposit".into(),
+        client,
+        tx,
+        amount: Some(amount.parse().unwrap()),
+        expires_after: None,
+        idempotency_key: None,
+        tenant: None,
+    };
+    record.into_record().unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn https_requests_should_be_served_until_the_shutdown_signal() {
+    let tls = TlsIdentity::from_files(resource("cert.pem"), resource("key.pem")).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let state = AppState::default();
+    state.apply(deposit(1, 1, "2.5")).await.unwrap();
+    let (shutdown, signal) = oneshot::channel::<()>();
+    let server = tokio::spawn(serve_with_shutdown(
+        listener,
+        state.clone(),
+        Some(tls),
+        async move {
+            let _ = signal.await;
+        },
+    ));
+
+    let stream = TcpStream::connect(address).await.unwrap();
+    let name = ServerName::try_from("localhost").unwrap();
+    let mut stream = connector().connect(name, stream).await.unwrap();
+    stream
+        .write_all(b"GET /accounts/1 HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.contains("\"available\":\"2.5\""), "{}", response);
+
+    // a plain HTTP client fails the handshake and doesn't stop the server
+    let mut plain = TcpStream::connect(address).await.unwrap();
+    plain
+        .write_all(b"GET /accounts HTTP/1.1\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buffer = Vec::new();
+    let _ = plain.read_to_end(&mut buffer).await;
+    assert!(!String::from_utf8_lossy(&buffer).contains("200 OK"));
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+    assert!(TcpStream::connect(address).await.is_err());
+}
+
+#[tokio::test]
+async fn submitted_transactions_should_be_logged_to_the_wal_before_being_applied() {
+    let path = std::env::temp_dir().join(format!("serve_{}.wal", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let state = AppState::default().with_wal(Wal::open(&path, 1024).unwrap());
+
+    state.apply(deposit(1, 1, "2.5")).await.unwrap();
+    state.apply(deposit(1, 2, "1")).await.unwrap();
+    state.drain().await;
+    state.sync_wal(false).unwrap();
+    let recovered = recover(&path).unwrap();
+    assert_eq!(
+        recovered.get_user_account(1).unwrap().available.to_string(),
+        "3.5"
+    );
+
+    // once the accounts are persisted, the committed entries aren't replayed anymore
+    state.sync_wal(true).unwrap();
+    assert!(recover(&path).unwrap().is_empty());
+    std::fs::remove_file(&path).unwrap();
+}
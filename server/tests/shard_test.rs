@@ -0,0 +1,99 @@
+use domain::domain::Transaction;
+use rust_decimal::Decimal;
+use server::{
+    auth::{ApiKeys, Scope},
+    grpc,
+    server::AppState,
+    shard::{shard_of, Shard, ShardReport, ShardRouter},
+};
+use service::service::TransactionRecord;
+use tokio::net::TcpListener;
+
+fn deposit(client: u16, tx: u32, amount: i64) -> TransactionRecord {
+    TransactionRecord {
+        client,
+        tx,
+        transaction: Transaction::Deposit {
+            amount: Decimal::from(amount),
+        },
+        idempotency_key: None,
+    }
+}
+
+fn withdrawal(client: u16, tx: u32, amount: i64) -> TransactionRecord {
+    TransactionRecord {
+        transaction: Transaction::Withdrawal {
+            amount: Decimal::from(amount),
+        },
+        ..deposit(client, tx, 0)
+    }
+}
+
+fn local_accounts(router: &ShardRouter, shard: usize) -> Vec<u16> {
+    let Shard::Local(state) = &router.shards()[shard] else {
+        panic!("shard {} isn't local", shard);
+    };
+    let mut clients: Vec<_> = state
+        .accounts
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(client, _)| client)
+        .collect();
+    clients.sort();
+    clients
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn local_shards_should_each_apply_the_transactions_of_their_clients_in_order() {
+    let mut router = ShardRouter::local(3);
+    let mut records = Vec::new();
+    for client in 1..=6 {
+        records.push(deposit(client, u32::from(client), 5));
+        records.push(withdrawal(client, u32::from(client) + 100, 2));
+    }
+    // more than the client has, rejected
+    records.push(withdrawal(4, 200, 10));
+
+    let reports = router.route(records).await.unwrap();
+    let report = |applied, rejected| ShardReport { applied, rejected };
+    assert_eq!(reports, [report(4, 0), report(4, 1), report(4, 0)]);
+    assert_eq!(local_accounts(&router, 0), [3, 6]);
+    assert_eq!(local_accounts(&router, 1), [1, 4]);
+    assert_eq!(local_accounts(&router, 2), [2, 5]);
+    assert_eq!(shard_of(4, 3), 1);
+    let account = router.account(4).await.unwrap().unwrap();
+    assert_eq!(account.available, "3");
+    assert!(router.account(7).await.unwrap().is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn remote_shards_should_be_reached_over_grpc_with_the_api_key() {
+    let mut api_keys = ApiKeys::new();
+    api_keys.insert("secret", Scope::Submit);
+    let mut urls = Vec::new();
+    let mut states = Vec::new();
+    for _ in 0..2 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        urls.push(format!("http://{}", listener.local_addr().unwrap()));
+        let state = AppState::default().with_api_keys(api_keys.clone());
+        tokio::spawn(grpc::serve(listener, state.clone()));
+        states.push(state);
+    }
+
+    let mut router = ShardRouter::connect(&urls, Some(String::from("secret")))
+        .await
+        .unwrap();
+    let records = (1..=4).map(|client| deposit(client, u32::from(client), 1));
+    let reports = router.route(records).await.unwrap();
+    assert_eq!(reports.iter().map(|x| x.applied).sum::<u64>(), 4);
+    assert!(states[0].account(2).is_some() && states[0].account(1).is_none());
+    assert!(states[1].account(3).is_some() && states[1].account(4).is_none());
+    let outcome = router.submit(withdrawal(3, 9, 5)).await.unwrap();
+    assert_eq!(outcome.outcome, "rejected");
+    assert_eq!(router.account(3).await.unwrap().unwrap().available, "1");
+
+    // without the key the peers refuse the transactions
+    let mut router = ShardRouter::connect(&urls, None).await.unwrap();
+    assert!(router.route([deposit(5, 5, 1)]).await.is_err());
+}
@@ -0,0 +1,101 @@
+use domain::domain::{Accounts, TransactionOutcome, UserAccount};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    error::ServiceError,
+    service::{apply_record, TransactionRecord},
+};
+
+pub enum Command {
+    ApplyTransaction {
+        record: TransactionRecord,
+        reply: oneshot::Sender<Result<TransactionOutcome, ServiceError>>,
+    },
+    GetAccount {
+        client: u16,
+        reply: oneshot::Sender<Option<UserAccount>>,
+    },
+    Snapshot {
+        reply: oneshot::Sender<Accounts>,
+    },
+}
+
+// The actor is the only writer of the accounts. Commands are handled one at a time in the
+// order they were sent, so a caller always sees the effects of its earlier commands.
+pub struct AccountsActor {
+    accounts: Accounts,
+    commands: mpsc::Receiver<Command>,
+}
+
+#[derive(Clone)]
+pub struct AccountsHandle {
+    commands: mpsc::Sender<Command>,
+}
+
+impl AccountsActor {
+    // at most `capacity` commands are queued before senders wait
+    pub fn new(accounts: Accounts, capacity: usize) -> (AccountsActor, AccountsHandle) {
+        let (sender, commands) = mpsc::channel(capacity.max(1));
+        (
+            AccountsActor { accounts, commands },
+            AccountsHandle { commands: sender },
+        )
+    }
+
+    // must be called from a tokio runtime
+    pub fn spawn(accounts: Accounts, capacity: usize) -> AccountsHandle {
+        let (actor, handle) = AccountsActor::new(accounts, capacity);
+        tokio::spawn(actor.run());
+        handle
+    }
+
+    // runs until every handle is dropped and returns the final accounts
+    pub async fn run(mut self) -> Accounts {
+        while let Some(command) = self.commands.recv().await {
+            // a caller that stopped waiting for the reply is not an error
+            match command {
+                Command::ApplyTransaction { record, reply } => {
+                    let _ = reply.send(apply_record(&mut self.accounts, record));
+                }
+                Command::GetAccount { client, reply } => {
+                    let _ = reply.send(self.accounts.get_user_account(client).cloned());
+                }
+                Command::Snapshot { reply } => {
+                    let _ = reply.send(self.accounts.clone());
+                }
+            }
+        }
+        self.accounts
+    }
+}
+
+impl AccountsHandle {
+    pub async fn apply(
+        &self,
+        record: TransactionRecord,
+    ) -> Result<TransactionOutcome, ServiceError> {
+        self.request(|reply| Command::ApplyTransaction { record, reply })
+            .await?
+    }
+
+    pub async fn get_account(&self, client: u16) -> Result<Option<UserAccount>, ServiceError> {
+        self.request(|reply| Command::GetAccount { client, reply })
+            .await
+    }
+
+    pub async fn snapshot(&self) -> Result<Accounts, ServiceError> {
+        self.request(|reply| Command::Snapshot { reply }).await
+    }
+
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> Command,
+    ) -> Result<T, ServiceError> {
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .send(command(reply))
+            .await
+            .map_err(|_| ServiceError::ActorStopped)?;
+        response.await.map_err(|_| ServiceError::ActorStopped)
+    }
+}
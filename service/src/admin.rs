@@ -0,0 +1,74 @@
+use domain::domain::{AccountStore, Accounts, RejectionReason, TransactionOutcome};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::events::{AccountEvent, AccountEventKind};
+
+// A change of an operator to the accounts outside the transactions of the clients, e.g. from the
+// admin routes of the server. They are logged to the wal between the transactions, tagged with
+// `admin` so a wal entry can't be read as both.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "admin", rename_all = "snake_case")]
+pub enum AdminOperation {
+    Unlock { client: u16 },
+    Adjust { client: u16, amount: Decimal },
+    Resolve { tx: u32 },
+}
+
+impl AdminOperation {
+    // None for a resolve, whose client is the one that logged the tx
+    pub fn client(&self) -> Option<u16> {
+        match *self {
+            AdminOperation::Unlock { client } | AdminOperation::Adjust { client, .. } => {
+                Some(client)
+            }
+            AdminOperation::Resolve { .. } => None,
+        }
+    }
+
+    // An applied operation produces an event; unlocks and adjustments have no tx of their own,
+    // their events have tx 0.
+    pub fn apply<A: AccountStore>(
+        &self,
+        accounts: &mut Accounts<A>,
+    ) -> (TransactionOutcome, Option<AccountEvent>) {
+        let event = |kind, client, tx, amount| AccountEvent {
+            kind,
+            client,
+            tx,
+            amount,
+        };
+        let (outcome, event) = match *self {
+            AdminOperation::Unlock { client } => {
+                let outcome = match accounts.unlock_account(client) {
+                    true => TransactionOutcome::Applied,
+                    false => TransactionOutcome::Rejected(RejectionReason::AccountNotFound),
+                };
+                (
+                    outcome,
+                    event(AccountEventKind::AccountUnlocked, client, 0, None),
+                )
+            }
+            AdminOperation::Adjust { client, amount } => (
+                accounts.adjust_balance(client, amount),
+                event(AccountEventKind::BalanceAdjusted, client, 0, Some(amount)),
+            ),
+            AdminOperation::Resolve { tx } => match accounts.force_resolve(tx) {
+                Some((client, outcome)) => (
+                    outcome,
+                    event(AccountEventKind::DisputeResolved, client, tx, None),
+                ),
+                None => {
+                    return (
+                        TransactionOutcome::Rejected(RejectionReason::UnknownTransaction),
+                        None,
+                    )
+                }
+            },
+        };
+        (
+            outcome,
+            (outcome == TransactionOutcome::Applied).then_some(event),
+        )
+    }
+}
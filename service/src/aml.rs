@@ -0,0 +1,178 @@
+use std::{collections::VecDeque, io::Write};
+
+use domain::domain::{AccountStore, Accounts, FxHashMap};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::Serialize;
+
+use crate::{
+    compression::create_output,
+    error::ServiceError,
+    events::{AccountEvent, AccountEventKind},
+    service::{read_source_with_events, ParseMode, ParseReport, TransactionSource},
+};
+
+#[derive(Debug, Clone)]
+pub struct AmlOptions {
+    pub threshold: Decimal,
+    // in applied transactions of the whole run, the input has no timestamps
+    pub window: u64,
+    // deposits up to this share below the threshold count towards structuring
+    pub structuring_margin: Decimal,
+    pub structuring_count: usize,
+}
+
+impl Default for AmlOptions {
+    fn default() -> Self {
+        AmlOptions {
+            threshold: dec!(10000),
+            window: 100,
+            structuring_margin: dec!(0.1),
+            structuring_count: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Activity {
+    ThresholdCrossed,
+    Structuring,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SuspiciousActivity {
+    pub client: u16,
+    // the deposit that made the window suspicious
+    pub tx: u32,
+    pub activity: Activity,
+    pub amount: Decimal,
+    pub window_deposits: usize,
+    pub window_total: Decimal,
+}
+
+#[derive(Default)]
+struct Window {
+    deposits: VecDeque<(u64, Decimal)>,
+    crossed: bool,
+    structuring: bool,
+}
+
+// A client is reported once when the deposits in its window reach the threshold, and once
+// when enough of them are just below it; it is reported again only after its window dropped
+// back under the threshold or the structuring count.
+pub struct AmlMonitor {
+    options: AmlOptions,
+    seq: u64,
+    windows: FxHashMap<u16, Window>,
+    activities: Vec<SuspiciousActivity>,
+}
+
+impl AmlMonitor {
+    pub fn new(options: AmlOptions) -> AmlMonitor {
+        AmlMonitor {
+            options,
+            seq: 0,
+            windows: FxHashMap::default(),
+            activities: Vec::new(),
+        }
+    }
+
+    pub fn observe(&mut self, event: &AccountEvent) {
+        if event.kind == AccountEventKind::AccountLocked {
+            return;
+        }
+        self.seq += 1;
+        let (AccountEventKind::DepositApplied, Some(amount)) = (event.kind, event.amount) else {
+            return;
+        };
+        let options = &self.options;
+        let window = self.windows.entry(event.client).or_default();
+        window.deposits.push_back((self.seq, amount));
+        while window
+            .deposits
+            .front()
+            .is_some_and(|(seq, _)| self.seq - seq >= options.window)
+        {
+            window.deposits.pop_front();
+        }
+        let total = window
+            .deposits
+            .iter()
+            .fold(Decimal::ZERO, |sum, (_, x)| sum.saturating_add(*x));
+        let floor = options.threshold - options.threshold * options.structuring_margin;
+        let near = window
+            .deposits
+            .iter()
+            .filter(|(_, x)| *x >= floor && *x < options.threshold)
+            .count();
+
+        let crossed = total >= options.threshold;
+        let structuring = near >= options.structuring_count;
+        for (activity, now, before) in [
+            (Activity::ThresholdCrossed, crossed, window.crossed),
+            (Activity::Structuring, structuring, window.structuring),
+        ] {
+            if now && !before {
+                tracing::info!(
+                    client = event.client,
+                    tx = event.tx,
+                    ?activity,
+                    "suspicious activity"
+                );
+                self.activities.push(SuspiciousActivity {
+                    client: event.client,
+                    tx: event.tx,
+                    activity,
+                    amount,
+                    window_deposits: window.deposits.len(),
+                    window_total: total,
+                });
+            }
+        }
+        window.crossed = crossed;
+        window.structuring = structuring;
+    }
+
+    pub fn activities(&self) -> &[SuspiciousActivity] {
+        &self.activities
+    }
+
+    pub fn into_activities(self) -> Vec<SuspiciousActivity> {
+        self.activities
+    }
+}
+
+pub fn read_source_with_aml<S: TransactionSource, A: AccountStore>(
+    source: S,
+    mode: ParseMode,
+    accounts: Accounts<A>,
+    options: AmlOptions,
+) -> Result<(Accounts<A>, ParseReport, Vec<SuspiciousActivity>), ServiceError> {
+    let mut monitor = AmlMonitor::new(options);
+    let (accounts, report) = read_source_with_events(source, mode, accounts, |event| {
+        monitor.observe(&event);
+        Ok(())
+    })?;
+    Ok((accounts, report, monitor.into_activities()))
+}
+
+pub fn write_suspicious_activity(
+    file_path: String,
+    activities: &[SuspiciousActivity],
+) -> Result<(), ServiceError> {
+    write_suspicious_activity_to(create_output(file_path)?, activities)
+}
+
+pub fn write_suspicious_activity_to<W: Write>(
+    writer: W,
+    activities: &[SuspiciousActivity],
+) -> Result<(), ServiceError> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    for activity in activities {
+        wtr.serialize(activity)
+            .map_err(|e| ServiceError::Serialize(e.into()))?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,238 @@
+use std::{fs::File, sync::Arc};
+
+use arrow_array::{
+    cast::AsArray,
+    types::{Decimal128Type, UInt16Type, UInt32Type},
+    Array, ArrayRef, BooleanArray, Decimal128Array, RecordBatch, UInt16Array,
+};
+use arrow_cast::cast;
+use arrow_schema::{DataType, Field, Schema};
+use domain::domain::Accounts;
+use parquet::arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter};
+use rust_decimal::Decimal;
+
+use crate::{
+    error::ServiceError,
+    service::{
+        output_records, read_source_into, transaction_type_from, InputTransactionRecord,
+        OutputOptions, ParseMode, ProcessingSummary, RowError, SourceError, TransactionSource,
+    },
+};
+
+const AMOUNT_PRECISION: u8 = 38;
+const AMOUNT_SCALE: i8 = 4;
+
+pub fn read_parquet(file_path: String) -> Result<(Accounts, ProcessingSummary), ServiceError> {
+    read_source_into(
+        ParquetSource::new(File::open(file_path)?)?,
+        ParseMode::Strict,
+        Accounts::new(),
+    )
+    .map(|(accounts, report)| (accounts, report.summary))
+}
+
+pub fn write_parquet(file_path: String, accounts: &Accounts) -> Result<(), ServiceError> {
+    let batch = to_record_batch(accounts);
+    let mut writer = ArrowWriter::try_new(File::create(file_path)?, batch.schema(), None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+pub struct ParquetSource {
+    batches: parquet::arrow::arrow_reader::ParquetRecordBatchReader,
+    columns: Option<Columns>,
+    row: usize,
+    line_number: u64,
+}
+
+struct Columns {
+    transaction_type: ArrayRef,
+    client: ArrayRef,
+    tx: ArrayRef,
+    amount: Option<ArrayRef>,
+    expires_after: Option<ArrayRef>,
+    idempotency_key: Option<ArrayRef>,
+    tenant: Option<ArrayRef>,
+}
+
+impl ParquetSource {
+    pub fn new(file: File) -> Result<ParquetSource, ServiceError> {
+        Ok(ParquetSource {
+            batches: ParquetRecordBatchReaderBuilder::try_new(file)?.build()?,
+            columns: None,
+            row: 0,
+            line_number: 0,
+        })
+    }
+
+    fn next_columns(&mut self) -> Option<Result<(), ServiceError>> {
+        loop {
+            if self
+                .columns
+                .as_ref()
+                .is_some_and(|x| self.row < x.client.len())
+            {
+                return Some(Ok(()));
+            }
+            match self.batches.next()? {
+                Ok(batch) => match Columns::new(&batch) {
+                    Ok(columns) => {
+                        self.columns = Some(columns);
+                        self.row = 0;
+                    }
+                    Err(e) => return Some(Err(e)),
+                },
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+    }
+}
+
+impl Columns {
+    fn new(batch: &RecordBatch) -> Result<Columns, ServiceError> {
+        let required = |name: &str, data_type: &DataType| {
+            batch
+                .column_by_name(name)
+                .ok_or_else(|| ServiceError::InvalidRecord {
+                    reason: format!("missing column {}", name),
+                })
+                .and_then(|x| Ok(cast(x, data_type)?))
+        };
+        let optional = |name: &str, data_type: &DataType| {
+            batch
+                .column_by_name(name)
+                .map(|x| cast(x, data_type))
+                .transpose()
+        };
+        let amount_type = DataType::Decimal128(AMOUNT_PRECISION, AMOUNT_SCALE);
+        Ok(Columns {
+            transaction_type: required("type", &DataType::Utf8)?,
+            client: required("client", &DataType::UInt16)?,
+            tx: required("tx", &DataType::UInt32)?,
+            amount: optional("amount", &amount_type)?,
+            expires_after: optional("expires_after", &DataType::UInt32)?,
+            idempotency_key: optional("idempotency_key", &DataType::Utf8)?,
+            tenant: optional("tenant", &DataType::Utf8)?,
+        })
+    }
+
+    fn record(&self, row: usize) -> Result<InputTransactionRecord, ServiceError> {
+        let missing = |name: &str| ServiceError::InvalidRecord {
+            reason: format!("missing value for {}", name),
+        };
+        let transaction_type = self.transaction_type.as_string::<i32>();
+        let client = self.client.as_primitive::<UInt16Type>();
+        let tx = self.tx.as_primitive::<UInt32Type>();
+        if transaction_type.is_null(row) {
+            return Err(missing("type"));
+        }
+        if client.is_null(row) {
+            return Err(missing("client"));
+        }
+        if tx.is_null(row) {
+            return Err(missing("tx"));
+        }
+        Ok(InputTransactionRecord {
+            transaction_type: transaction_type_from(transaction_type.value(row)),
+            client: client.value(row),
+            tx: tx.value(row),
+            amount: self
+                .amount
+                .as_ref()
+                .map(|x| x.as_primitive::<Decimal128Type>())
+                .filter(|x| x.is_valid(row))
+                .map(|x| from_decimal128(x.value(row)))
+                .transpose()?,
+            expires_after: self
+                .expires_after
+                .as_ref()
+                .map(|x| x.as_primitive::<UInt32Type>())
+                .filter(|x| x.is_valid(row))
+                .map(|x| x.value(row)),
+            idempotency_key: self
+                .idempotency_key
+                .as_ref()
+                .map(|x| x.as_string::<i32>())
+                .filter(|x| x.is_valid(row))
+                .map(|x| x.value(row).to_string()),
+            tenant: self
+                .tenant
+                .as_ref()
+                .map(|x| x.as_string::<i32>())
+                .filter(|x| x.is_valid(row))
+                .map(|x| x.value(row).to_string()),
+        })
+    }
+}
+
+impl TransactionSource for ParquetSource {
+    fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>> {
+        if let Err(e) = self.next_columns()? {
+            return Some(Err(SourceError::Fatal(e)));
+        }
+        let row = self.row;
+        self.row += 1;
+        self.line_number += 1;
+        let columns = self.columns.as_ref()?;
+        Some(columns.record(row).map_err(|e| {
+            SourceError::Row(RowError {
+                line_number: self.line_number,
+                raw_row: format!("row {}", self.line_number),
+                error: e,
+            })
+        }))
+    }
+
+    fn line_number(&self) -> u64 {
+        self.line_number
+    }
+}
+
+fn from_decimal128(value: i128) -> Result<Decimal, ServiceError> {
+    Decimal::try_from_i128_with_scale(value, AMOUNT_SCALE as u32).map_err(|e| {
+        ServiceError::InvalidRecord {
+            reason: e.to_string(),
+        }
+    })
+}
+
+fn to_decimal128(value: Decimal) -> i128 {
+    let mut value = value.round_dp(AMOUNT_SCALE as u32);
+    value.rescale(AMOUNT_SCALE as u32);
+    value.mantissa()
+}
+
+pub fn accounts_schema() -> Schema {
+    let amount_type = DataType::Decimal128(AMOUNT_PRECISION, AMOUNT_SCALE);
+    Schema::new(vec![
+        Field::new("client", DataType::UInt16, false),
+        Field::new("available", amount_type.clone(), false),
+        Field::new("held", amount_type.clone(), false),
+        Field::new("total", amount_type.clone(), false),
+        Field::new("locked", DataType::Boolean, false),
+    ])
+}
+
+pub fn to_record_batch(accounts: &Accounts) -> RecordBatch {
+    let options = OutputOptions::default();
+    let records: Vec<_> = output_records(accounts, &options).collect();
+    let amounts = |f: fn(&crate::service::OutputRecord) -> Decimal| {
+        Decimal128Array::from_iter_values(records.iter().map(|x| to_decimal128(f(x))))
+            .with_precision_and_scale(AMOUNT_PRECISION, AMOUNT_SCALE)
+            .expect("amount precision is valid")
+    };
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt16Array::from_iter_values(
+            records.iter().map(|x| x.client),
+        )),
+        Arc::new(amounts(|x| x.available)),
+        Arc::new(amounts(|x| x.held)),
+        Arc::new(amounts(|x| x.total)),
+        Arc::new(BooleanArray::from_iter(
+            records.iter().map(|x| Some(x.locked)),
+        )),
+    ];
+    RecordBatch::try_new(Arc::new(accounts_schema()), columns)
+        .expect("account columns match the schema")
+}
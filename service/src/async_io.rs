@@ -0,0 +1,49 @@
+use domain::domain::Accounts;
+use futures_util::{Stream, StreamExt};
+use tokio::io::AsyncRead;
+
+use crate::{
+    error::ServiceError,
+    service::{apply_record, InputTransactionRecord, TransactionRecord},
+};
+
+pub fn transaction_records<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+) -> impl Stream<Item = Result<TransactionRecord, ServiceError>> {
+    csv_async::AsyncReaderBuilder::new()
+        .trim(csv_async::Trim::All)
+        .create_deserializer(reader)
+        .into_deserialize::<InputTransactionRecord>()
+        .filter_map(|result| async move {
+            match result {
+                Ok(x) => x.into_record().map(Ok),
+                Err(e) => Some(Err(ServiceError::AsyncCsv {
+                    line: e.position().map_or(1, |x| x.line()),
+                    source: e,
+                })),
+            }
+        })
+}
+
+pub async fn read_transactions_async<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+) -> Result<Accounts, ServiceError> {
+    let mut accounts = Accounts::new();
+    let records = transaction_records(reader);
+    futures_util::pin_mut!(records);
+    while let Some(record) = records.next().await {
+        apply_record(&mut accounts, record?)?;
+    }
+    Ok(accounts)
+}
+
+pub async fn apply_stream<S: Stream<Item = TransactionRecord>>(
+    records: S,
+    mut accounts: Accounts,
+) -> Result<Accounts, ServiceError> {
+    futures_util::pin_mut!(records);
+    while let Some(record) = records.next().await {
+        apply_record(&mut accounts, record)?;
+    }
+    Ok(accounts)
+}
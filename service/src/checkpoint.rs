@@ -0,0 +1,158 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use domain::domain::Accounts;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::ServiceError,
+    service::{
+        read_source_into, CsvSource, InputTransactionRecord, ParseMode, ParseReport, SourceError,
+        TransactionSource,
+    },
+};
+
+const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct InputOffset {
+    pub byte: u64,
+    pub line: u64,
+    pub record: u64,
+}
+
+impl From<&csv::Position> for InputOffset {
+    fn from(position: &csv::Position) -> Self {
+        InputOffset {
+            byte: position.byte(),
+            line: position.line(),
+            record: position.record(),
+        }
+    }
+}
+
+impl From<InputOffset> for csv::Position {
+    fn from(offset: InputOffset) -> Self {
+        let mut position = csv::Position::new();
+        position
+            .set_byte(offset.byte)
+            .set_line(offset.line)
+            .set_record(offset.record);
+        position
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint<A = Accounts> {
+    pub offset: InputOffset,
+    pub accounts: A,
+}
+
+impl Checkpoint {
+    pub fn load<P: AsRef<Path>>(checkpoint_dir: P) -> Result<Option<Checkpoint>, ServiceError> {
+        let path = checkpoint_dir.as_ref().join(CHECKPOINT_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let checkpoint =
+            serde_json::from_reader(BufReader::new(File::open(path)?)).map_err(|e| {
+                ServiceError::Json {
+                    line: e.line() as u64,
+                    source: e,
+                }
+            })?;
+        Ok(Some(checkpoint))
+    }
+}
+
+// written to a temporary file first so a crash never leaves a partial checkpoint
+pub fn save<P: AsRef<Path>>(
+    checkpoint_dir: P,
+    offset: InputOffset,
+    accounts: &Accounts,
+) -> Result<(), ServiceError> {
+    fs::create_dir_all(&checkpoint_dir)?;
+    let path = checkpoint_dir.as_ref().join(CHECKPOINT_FILE);
+    let temp_path = PathBuf::from(format!("{}.tmp", path.display()));
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    serde_json::to_writer(&mut writer, &Checkpoint { offset, accounts })
+        .map_err(|e| ServiceError::Serialize(e.into()))?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+// None when there is no checkpoint or it has no such client
+pub fn forget_client<P: AsRef<Path>>(
+    checkpoint_dir: P,
+    client: u16,
+) -> Result<Option<usize>, ServiceError> {
+    let Some(mut checkpoint) = Checkpoint::load(&checkpoint_dir)? else {
+        return Ok(None);
+    };
+    let erased = checkpoint.accounts.forget_client(client);
+    if erased.is_some() {
+        save(checkpoint_dir, checkpoint.offset, &checkpoint.accounts)?;
+    }
+    Ok(erased)
+}
+
+struct Take<S> {
+    source: S,
+    remaining: u64,
+}
+
+impl<S: TransactionSource> TransactionSource for Take<S> {
+    fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.source.next_record()
+    }
+
+    fn line_number(&self) -> u64 {
+        self.source.line_number()
+    }
+}
+
+// the returned report only covers the records processed by this call; the policies of
+// `initial_state` also apply to accounts resumed from a checkpoint.
+pub fn resume<P: AsRef<Path>, Q: AsRef<Path>>(
+    checkpoint_dir: P,
+    input_path: Q,
+    every: u64,
+    initial_state: Accounts,
+) -> Result<(Accounts, ParseReport), ServiceError> {
+    let mut source = CsvSource::new(File::open(input_path)?);
+    let mut accounts = match Checkpoint::load(&checkpoint_dir)? {
+        Some(checkpoint) => {
+            source.seek(checkpoint.offset.into())?;
+            let mut accounts = checkpoint.accounts;
+            accounts.set_policies(*initial_state.policies());
+            accounts
+        }
+        None => initial_state,
+    };
+
+    let every = every.max(1);
+    let mut report = ParseReport::default();
+    loop {
+        let chunk = Take {
+            source: &mut source,
+            remaining: every,
+        };
+        let (result, chunk_report) = read_source_into(chunk, ParseMode::Strict, accounts)?;
+        accounts = result;
+        let finished = chunk_report.summary.total_rows < every;
+        report.extend(chunk_report);
+        save(&checkpoint_dir, source.position().into(), &accounts)?;
+        if finished {
+            return Ok((accounts, report));
+        }
+    }
+}
@@ -0,0 +1,209 @@
+#[cfg(feature = "avro")]
+pub mod avro {
+    use std::{io::Read, sync::LazyLock};
+
+    use apache_avro::{reader::datum::GenericDatumReader, Reader, Schema};
+
+    use crate::{
+        error::ServiceError,
+        service::{InputTransactionRecord, RowError, SourceError, TransactionSource},
+    };
+
+    pub const TRANSACTION_SCHEMA: &str = r#"{
+        "type": "record",
+        "name": "Transaction",
+        "fields": [
+            {"name": "type", "type": "string"},
+            {"name": "client", "type": "int"},
+            {"name": "tx", "type": "long"},
+            {"name": "amount", "type": ["null", "string"], "default": null},
+            {"name": "expires_after", "type": ["null", "long"], "default": null},
+            {"name": "idempotency_key", "type": ["null", "string"], "default": null},
+            {"name": "tenant", "type": ["null", "string"], "default": null}
+        ]
+    }"#;
+
+    pub static SCHEMA: LazyLock<Schema> =
+        LazyLock::new(|| Schema::parse_str(TRANSACTION_SCHEMA).expect("valid transaction schema"));
+
+    pub fn decode(mut bytes: &[u8]) -> Result<InputTransactionRecord, ServiceError> {
+        let value = GenericDatumReader::builder(&SCHEMA)
+            .build()?
+            .read_value(&mut bytes)?;
+        Ok(apache_avro::from_value(&value)?)
+    }
+
+    pub struct AvroSource<'a, R> {
+        reader: Reader<'a, R>,
+        line_number: u64,
+    }
+
+    impl<R: Read> AvroSource<'_, R> {
+        pub fn new(reader: R) -> Result<Self, ServiceError> {
+            Ok(AvroSource {
+                reader: Reader::builder(reader).reader_schema(&SCHEMA).build()?,
+                line_number: 0,
+            })
+        }
+    }
+
+    impl<R: Read> TransactionSource for AvroSource<'_, R> {
+        fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>> {
+            let value = self.reader.next()?;
+            self.line_number += 1;
+            Some(
+                value
+                    .and_then(|x| apache_avro::from_value(&x))
+                    .map_err(|e| {
+                        SourceError::Row(RowError {
+                            line_number: self.line_number,
+                            raw_row: format!("record {}", self.line_number),
+                            error: e.into(),
+                        })
+                    }),
+            )
+        }
+
+        fn line_number(&self) -> u64 {
+            self.line_number
+        }
+    }
+}
+
+#[cfg(feature = "protobuf")]
+pub mod protobuf {
+    use std::{
+        io::{self, Read},
+        str::FromStr,
+    };
+
+    use prost::Message;
+    use rust_decimal::Decimal;
+
+    use crate::{
+        error::ServiceError,
+        service::{InputTransactionRecord, RowError, SourceError, TransactionSource},
+    };
+
+    // Mirrors proto/transaction.proto.
+    #[derive(Clone, PartialEq, Message)]
+    pub struct TransactionMessage {
+        #[prost(string, tag = "1")]
+        pub r#type: String,
+        #[prost(uint32, tag = "2")]
+        pub client: u32,
+        #[prost(uint32, tag = "3")]
+        pub tx: u32,
+        #[prost(string, optional, tag = "4")]
+        pub amount: Option<String>,
+        #[prost(uint32, optional, tag = "5")]
+        pub expires_after: Option<u32>,
+        #[prost(string, optional, tag = "6")]
+        pub idempotency_key: Option<String>,
+        #[prost(string, optional, tag = "7")]
+        pub tenant: Option<String>,
+    }
+
+    impl TryFrom<TransactionMessage> for InputTransactionRecord {
+        type Error = ServiceError;
+
+        fn try_from(message: TransactionMessage) -> Result<Self, Self::Error> {
+            let invalid = |reason: String| ServiceError::InvalidRecord { reason };
+            Ok(InputTransactionRecord {
+                transaction_type: message.r#type.into(),
+                client: u16::try_from(message.client)
+                    .map_err(|_| invalid(format!("client {} is out of range", message.client)))?,
+                tx: message.tx,
+                amount: message
+                    .amount
+                    .map(|x| Decimal::from_str(x.trim()).map_err(|e| invalid(e.to_string())))
+                    .transpose()?,
+                expires_after: message.expires_after,
+                idempotency_key: message.idempotency_key,
+                tenant: message.tenant,
+            })
+        }
+    }
+
+    impl From<InputTransactionRecord> for TransactionMessage {
+        fn from(record: InputTransactionRecord) -> Self {
+            TransactionMessage {
+                r#type: record.transaction_type.into_owned(),
+                client: u32::from(record.client),
+                tx: record.tx,
+                amount: record.amount.map(|x| x.to_string()),
+                expires_after: record.expires_after,
+                idempotency_key: record.idempotency_key,
+                tenant: record.tenant,
+            }
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<InputTransactionRecord, ServiceError> {
+        TransactionMessage::decode(bytes)?.try_into()
+    }
+
+    // Reads varint length-delimited messages, as written by `encode_length_delimited`.
+    pub struct ProtobufSource<R> {
+        reader: R,
+        buffer: Vec<u8>,
+        line_number: u64,
+    }
+
+    impl<R: Read> ProtobufSource<R> {
+        pub fn new(reader: R) -> Self {
+            ProtobufSource {
+                reader,
+                buffer: Vec::new(),
+                line_number: 0,
+            }
+        }
+
+        fn read_length(&mut self) -> io::Result<Option<usize>> {
+            let mut length = 0usize;
+            for shift in (0..64).step_by(7) {
+                let mut byte = [0u8];
+                if self.reader.read(&mut byte)? == 0 {
+                    return if shift == 0 {
+                        Ok(None)
+                    } else {
+                        Err(io::ErrorKind::UnexpectedEof.into())
+                    };
+                }
+                length |= ((byte[0] & 0x7f) as usize) << shift;
+                if byte[0] & 0x80 == 0 {
+                    return Ok(Some(length));
+                }
+            }
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid length delimiter",
+            ))
+        }
+    }
+
+    impl<R: Read> TransactionSource for ProtobufSource<R> {
+        fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>> {
+            let length = match self.read_length() {
+                Ok(length) => length?,
+                Err(e) => return Some(Err(SourceError::Fatal(e.into()))),
+            };
+            self.buffer.resize(length, 0);
+            if let Err(e) = self.reader.read_exact(&mut self.buffer) {
+                return Some(Err(SourceError::Fatal(e.into())));
+            }
+            self.line_number += 1;
+            Some(decode(&self.buffer).map_err(|e| {
+                SourceError::Row(RowError {
+                    line_number: self.line_number,
+                    raw_row: format!("message {}", self.line_number),
+                    error: e,
+                })
+            }))
+        }
+
+        fn line_number(&self) -> u64 {
+            self.line_number
+        }
+    }
+}
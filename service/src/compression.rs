@@ -0,0 +1,94 @@
+use crate::error::ServiceError;
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+// the path that stands for stdin when reading and stdout when writing
+pub const STDIO_PATH: &str = "-";
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Compression {
+        match path.as_ref().extension().and_then(|x| x.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    pub fn from_magic_bytes(bytes: &[u8]) -> Compression {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else if bytes.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+pub fn decompress<'a, R: Read + 'a>(reader: R) -> Result<Box<dyn Read + 'a>, ServiceError> {
+    let mut reader = BufReader::new(reader);
+    let compression = Compression::from_magic_bytes(reader.fill_buf()?);
+    Ok(match compression {
+        Compression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(reader)),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Box::new(zstd::Decoder::with_buffer(reader)?),
+        #[cfg(not(feature = "zstd"))]
+        Compression::Zstd => return Err(zstd_unsupported()),
+        Compression::None => Box::new(reader),
+    })
+}
+
+pub fn compress<'a, W: Write + 'a>(
+    writer: W,
+    compression: Compression,
+) -> Result<Box<dyn Write + 'a>, ServiceError> {
+    Ok(match compression {
+        Compression::Gzip => Box::new(flate2::write::GzEncoder::new(
+            writer,
+            flate2::Compression::default(),
+        )),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Box::new(zstd::Encoder::new(writer, 0)?.auto_finish()),
+        #[cfg(not(feature = "zstd"))]
+        Compression::Zstd => return Err(zstd_unsupported()),
+        Compression::None => Box::new(writer),
+    })
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_unsupported() -> ServiceError {
+    io::Error::new(io::ErrorKind::Unsupported, "built without the zstd feature").into()
+}
+
+pub fn is_stdio<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref() == Path::new(STDIO_PATH)
+}
+
+pub fn open_input<P: AsRef<Path>>(path: P) -> Result<Box<dyn Read>, ServiceError> {
+    if is_stdio(&path) {
+        return decompress(io::stdin().lock());
+    }
+    decompress(File::open(path)?)
+}
+
+pub fn create_output<P: AsRef<Path>>(path: P) -> Result<Box<dyn Write>, ServiceError> {
+    if is_stdio(&path) {
+        return Ok(Box::new(BufWriter::new(io::stdout().lock())));
+    }
+    let compression = Compression::from_path(&path);
+    compress(BufWriter::new(File::create(path)?), compression)
+}
@@ -0,0 +1,158 @@
+use std::{collections::HashMap, io::Read};
+
+use domain::domain::{AccountStore, Accounts};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::ServiceError, service::ParseMode, tenants::Tenants};
+
+// Accounts hold a single, unnamed currency; converting only changes how the totals are
+// reported and never touches the balances.
+pub trait RateProvider {
+    // how much of `to` one unit of `from` is worth
+    fn rate(&self, from: &str, to: &str) -> Option<Decimal>;
+}
+
+impl<F: Fn(&str, &str) -> Option<Decimal>> RateProvider for F {
+    fn rate(&self, from: &str, to: &str) -> Option<Decimal> {
+        self(from, to)
+    }
+}
+
+// the inverse of a known rate is used when only the other direction is given
+#[derive(Debug, Clone, Default)]
+pub struct StaticRates {
+    rates: HashMap<(String, String), Decimal>,
+}
+
+#[derive(Deserialize)]
+struct RateRow {
+    from: String,
+    to: String,
+    rate: Decimal,
+}
+
+impl StaticRates {
+    pub fn new() -> StaticRates {
+        StaticRates::default()
+    }
+
+    pub fn insert(&mut self, from: &str, to: &str, rate: Decimal) {
+        self.rates.insert((from.to_string(), to.to_string()), rate);
+    }
+
+    // a csv with `from`, `to` and `rate` columns; in lenient mode rows that don't parse and
+    // non-positive rates are skipped
+    pub fn from_csv<R: Read>(reader: R, mode: ParseMode) -> Result<StaticRates, ServiceError> {
+        let mut rates = StaticRates::new();
+        let mut csv = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        for row in csv.deserialize::<RateRow>() {
+            let row = match row {
+                Ok(x) if x.rate > Decimal::ZERO => x,
+                Ok(x) if mode == ParseMode::Strict => {
+                    return Err(ServiceError::InvalidRecord {
+                        reason: format!("rate from {} to {} is not positive", x.from, x.to),
+                    })
+                }
+                Err(e) if mode == ParseMode::Strict => return Err(ServiceError::from_csv(e)),
+                _ => continue,
+            };
+            rates.insert(&row.from, &row.to, row.rate);
+        }
+        Ok(rates)
+    }
+}
+
+impl RateProvider for StaticRates {
+    fn rate(&self, from: &str, to: &str) -> Option<Decimal> {
+        if from == to {
+            return Some(Decimal::ONE);
+        }
+        let key = |a: &str, b: &str| (a.to_string(), b.to_string());
+        self.rates.get(&key(from, to)).copied().or_else(|| {
+            self.rates
+                .get(&key(to, from))
+                .and_then(|x| Decimal::ONE.checked_div(*x))
+        })
+    }
+}
+
+pub fn convert<R: RateProvider + ?Sized>(
+    amount: Decimal,
+    from: &str,
+    to: &str,
+    rates: &R,
+) -> Result<Decimal, ServiceError> {
+    let missing = || ServiceError::MissingRate {
+        from: from.to_string(),
+        to: to.to_string(),
+    };
+    let rate = if from == to {
+        Decimal::ONE
+    } else {
+        rates.rate(from, to).ok_or_else(missing)?
+    };
+    amount.checked_mul(rate).ok_or_else(missing)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ConvertedTotals {
+    pub currency: String,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+}
+
+impl ConvertedTotals {
+    fn add(&mut self, available: Decimal, held: Decimal) {
+        self.available += available;
+        self.held += held;
+        self.total += available + held;
+    }
+}
+
+// the sums over all accounts, each converted once, so no per-account rounding adds up
+pub fn convert_totals<A: AccountStore, R: RateProvider + ?Sized>(
+    accounts: &Accounts<A>,
+    currency: &str,
+    reporting_currency: &str,
+    rates: &R,
+) -> Result<ConvertedTotals, ServiceError> {
+    let (mut available, mut held) = (Decimal::ZERO, Decimal::ZERO);
+    for (_, account) in accounts.iter() {
+        available += account.available;
+        held += account.held;
+    }
+    let mut totals = ConvertedTotals {
+        currency: reporting_currency.to_string(),
+        ..Default::default()
+    };
+    totals.add(
+        convert(available, currency, reporting_currency, rates)?,
+        convert(held, currency, reporting_currency, rates)?,
+    );
+    Ok(totals)
+}
+
+// every tenant keeps its books in its own currency, looked up by `currency_of`
+pub fn convert_tenant_totals<R: RateProvider + ?Sized>(
+    tenants: &Tenants,
+    currency_of: impl Fn(&str) -> Option<String>,
+    reporting_currency: &str,
+    rates: &R,
+) -> Result<ConvertedTotals, ServiceError> {
+    let mut totals = ConvertedTotals {
+        currency: reporting_currency.to_string(),
+        ..Default::default()
+    };
+    for (tenant, accounts) in tenants.iter() {
+        let currency = currency_of(tenant).ok_or_else(|| ServiceError::InvalidRecord {
+            reason: format!("no currency for tenant {}", tenant),
+        })?;
+        let tenant_totals = convert_totals(accounts, &currency, reporting_currency, rates)?;
+        totals.add(tenant_totals.available, tenant_totals.held);
+    }
+    Ok(totals)
+}
@@ -0,0 +1,173 @@
+use domain::domain::Accounts;
+use polars::prelude::*;
+use rust_decimal::Decimal;
+
+use crate::{
+    error::ServiceError,
+    service::{
+        output_records, read_source_into, transaction_type_from, InputTransactionRecord,
+        OutputOptions, OutputRecord, ParseMode, ProcessingSummary, RowError, SourceError,
+        TransactionSource,
+    },
+};
+
+// the precision and scale of the arrow and parquet amounts
+const AMOUNT_PRECISION: usize = 38;
+const AMOUNT_SCALE: usize = 4;
+
+// the columns of the csv output, with decimal amounts
+pub fn to_dataframe(accounts: &Accounts) -> DataFrame {
+    let options = OutputOptions::default();
+    let records: Vec<_> = output_records(accounts, &options).collect();
+    let amounts = |name: &str, f: fn(&OutputRecord) -> Decimal| {
+        let values = records.iter().map(|x| to_decimal128(f(x))).collect();
+        Column::from(
+            Int128Chunked::from_vec(name.into(), values)
+                .into_decimal_unchecked(AMOUNT_PRECISION, AMOUNT_SCALE)
+                .into_series(),
+        )
+    };
+    let columns = vec![
+        Column::new(
+            "client".into(),
+            records.iter().map(|x| x.client).collect::<Vec<_>>(),
+        ),
+        amounts("available", |x| x.available),
+        amounts("held", |x| x.held),
+        amounts("total", |x| x.total),
+        Column::new(
+            "locked".into(),
+            records.iter().map(|x| x.locked).collect::<Vec<_>>(),
+        ),
+    ];
+    DataFrame::new(records.len(), columns).expect("account columns have the same length")
+}
+
+// A row per transaction, with the columns of the csv input. `type`, `client` and `tx` are
+// required; columns are cast to their types first, so e.g. float amounts are read as decimals.
+pub fn from_dataframe(frame: &DataFrame) -> Result<(Accounts, ProcessingSummary), ServiceError> {
+    read_source_into(
+        DataFrameSource::new(frame)?,
+        ParseMode::Strict,
+        Accounts::new(),
+    )
+    .map(|(accounts, report)| (accounts, report.summary))
+}
+
+pub struct DataFrameSource {
+    transaction_type: StringChunked,
+    client: UInt16Chunked,
+    tx: UInt32Chunked,
+    amount: Option<DecimalChunked>,
+    expires_after: Option<UInt32Chunked>,
+    idempotency_key: Option<StringChunked>,
+    tenant: Option<StringChunked>,
+    row: usize,
+}
+
+impl DataFrameSource {
+    pub fn new(frame: &DataFrame) -> Result<DataFrameSource, ServiceError> {
+        let required = |name: &str, data_type: &DataType| {
+            let column = frame
+                .column(name)
+                .map_err(|_| ServiceError::InvalidRecord {
+                    reason: format!("missing column {}", name),
+                })?;
+            Ok::<_, ServiceError>(column.cast(data_type)?)
+        };
+        let optional = |name: &str, data_type: &DataType| {
+            frame
+                .column(name)
+                .ok()
+                .map(|x| x.cast(data_type))
+                .transpose()
+        };
+        let amount_type = DataType::Decimal(AMOUNT_PRECISION, AMOUNT_SCALE);
+        Ok(DataFrameSource {
+            transaction_type: required("type", &DataType::String)?.str()?.clone(),
+            client: required("client", &DataType::UInt16)?.u16()?.clone(),
+            tx: required("tx", &DataType::UInt32)?.u32()?.clone(),
+            amount: optional("amount", &amount_type)?
+                .map(|x| x.decimal().cloned())
+                .transpose()?,
+            expires_after: optional("expires_after", &DataType::UInt32)?
+                .map(|x| x.u32().cloned())
+                .transpose()?,
+            idempotency_key: optional("idempotency_key", &DataType::String)?
+                .map(|x| x.str().cloned())
+                .transpose()?,
+            tenant: optional("tenant", &DataType::String)?
+                .map(|x| x.str().cloned())
+                .transpose()?,
+            row: 0,
+        })
+    }
+
+    fn record(&self, row: usize) -> Result<InputTransactionRecord, ServiceError> {
+        let missing = |name: &str| ServiceError::InvalidRecord {
+            reason: format!("missing value for {}", name),
+        };
+        Ok(InputTransactionRecord {
+            transaction_type: transaction_type_from(
+                self.transaction_type
+                    .get(row)
+                    .ok_or_else(|| missing("type"))?,
+            ),
+            client: self.client.get(row).ok_or_else(|| missing("client"))?,
+            tx: self.tx.get(row).ok_or_else(|| missing("tx"))?,
+            amount: self
+                .amount
+                .as_ref()
+                .and_then(|x| x.physical().get(row))
+                .map(from_decimal128)
+                .transpose()?,
+            expires_after: self.expires_after.as_ref().and_then(|x| x.get(row)),
+            idempotency_key: self
+                .idempotency_key
+                .as_ref()
+                .and_then(|x| x.get(row))
+                .map(str::to_string),
+            tenant: self
+                .tenant
+                .as_ref()
+                .and_then(|x| x.get(row))
+                .map(str::to_string),
+        })
+    }
+}
+
+impl TransactionSource for DataFrameSource {
+    fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>> {
+        if self.row >= self.client.len() {
+            return None;
+        }
+        let row = self.row;
+        self.row += 1;
+        let line_number = self.row as u64;
+        Some(self.record(row).map_err(|e| {
+            SourceError::Row(RowError {
+                line_number,
+                raw_row: format!("row {}", line_number),
+                error: e,
+            })
+        }))
+    }
+
+    fn line_number(&self) -> u64 {
+        self.row as u64
+    }
+}
+
+fn from_decimal128(value: i128) -> Result<Decimal, ServiceError> {
+    Decimal::try_from_i128_with_scale(value, AMOUNT_SCALE as u32).map_err(|e| {
+        ServiceError::InvalidRecord {
+            reason: e.to_string(),
+        }
+    })
+}
+
+fn to_decimal128(value: Decimal) -> i128 {
+    let mut value = value.round_dp(AMOUNT_SCALE as u32);
+    value.rescale(AMOUNT_SCALE as u32);
+    value.mantissa()
+}
@@ -0,0 +1,117 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt,
+};
+
+use domain::domain::{AccountStore, Accounts, UserAccount};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::service::RoundingConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffStatus {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl fmt::Display for DiffStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DiffStatus::Added => "added",
+            DiffStatus::Removed => "removed",
+            DiffStatus::Changed => "changed",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change<T> {
+    pub before: T,
+    pub after: T,
+}
+
+// unchanged fields are None; a missing account counts as an empty, unlocked one
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccountDiff {
+    pub client: u16,
+    pub status: DiffStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available: Option<Change<Decimal>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub held: Option<Change<Decimal>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<Change<Decimal>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locked: Option<Change<bool>>,
+}
+
+fn change<T: PartialEq>(before: T, after: T) -> Option<Change<T>> {
+    (before != after).then_some(Change { before, after })
+}
+
+// sorted by client, clients without any difference are left out
+pub fn diff_accounts<A: AccountStore, B: AccountStore>(
+    before: &Accounts<A>,
+    after: &Accounts<B>,
+) -> Vec<AccountDiff> {
+    let clients: BTreeSet<u16> = before
+        .iter()
+        .map(|(client, _)| client)
+        .chain(after.iter().map(|(client, _)| client))
+        .collect();
+    let empty = UserAccount::default();
+    clients
+        .into_iter()
+        .filter_map(|client| {
+            let old = before.get_user_account(client);
+            let new = after.get_user_account(client);
+            let status = match (&old, &new) {
+                (None, _) => DiffStatus::Added,
+                (_, None) => DiffStatus::Removed,
+                _ => DiffStatus::Changed,
+            };
+            let old = old.as_deref().unwrap_or(&empty);
+            let new = new.as_deref().unwrap_or(&empty);
+            let diff = AccountDiff {
+                client,
+                status,
+                available: change(old.available, new.available),
+                held: change(old.held, new.held),
+                total: change(old.available + old.held, new.available + new.held),
+                locked: change(old.locked, new.locked),
+            };
+            let changed = diff.available.is_some() || diff.held.is_some() || diff.locked.is_some();
+            (changed || status != DiffStatus::Changed).then_some(diff)
+        })
+        .collect()
+}
+
+// The clients of `after` that are new or whose balances or lock differ from `before`, e.g. the
+// output of the previous run. The balances are compared rounded, as they are written, so
+// changes below the written decimal places don't count.
+pub fn changed_clients<A: AccountStore, B: AccountStore>(
+    before: &Accounts<A>,
+    after: &Accounts<B>,
+    rounding: &RoundingConfig,
+) -> HashSet<u16> {
+    let written = |x: &UserAccount| {
+        (
+            rounding.round(x.available),
+            rounding.round(x.held),
+            rounding.round(x.available + x.held),
+            x.locked,
+        )
+    };
+    after
+        .iter()
+        .filter(|(client, account)| {
+            before
+                .get_user_account(*client)
+                .is_none_or(|x| written(&x) != written(account))
+        })
+        .map(|(client, _)| client)
+        .collect()
+}
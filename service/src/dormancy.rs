@@ -0,0 +1,154 @@
+use domain::domain::{
+    AccountStore, Accounts, FxHashMap, Policies, RejectionReason, Transaction, TransactionOutcome,
+};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    error::ServiceError,
+    events::{AccountEvent, AccountEventKind},
+    service::{read_source_observed, ParseMode, ParseReport, TransactionSource},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SweptAccount {
+    pub client: u16,
+    pub amount: Decimal,
+    pub withdrawal_tx: u32,
+    pub deposit_tx: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct DormancyReport {
+    pub dormant: Vec<u16>,
+    pub swept: Vec<SweptAccount>,
+}
+
+// Dormancy is measured in applied transactions of the run, the input has no timestamps. A
+// client without any applied transaction in the run, e.g. one from the initial state, was last
+// active before its first transaction.
+#[derive(Default)]
+pub struct DormancyTracker {
+    seq: u64,
+    last_active: FxHashMap<u16, u64>,
+}
+
+impl DormancyTracker {
+    pub fn new() -> DormancyTracker {
+        DormancyTracker::default()
+    }
+
+    pub fn observe(&mut self, event: &AccountEvent) {
+        if event.kind == AccountEventKind::AccountLocked {
+            return;
+        }
+        self.seq += 1;
+        self.last_active.insert(event.client, self.seq);
+    }
+
+    // the clients without an applied transaction in the last `after` ones, sorted
+    pub fn dormant<A: AccountStore>(&self, accounts: &Accounts<A>, after: u64) -> Vec<u16> {
+        let mut dormant: Vec<_> = accounts
+            .iter()
+            .map(|(client, _)| client)
+            .filter(|x| self.seq - self.last_active.get(x).copied().unwrap_or(0) >= after)
+            .collect();
+        dormant.sort_unstable();
+        dormant
+    }
+}
+
+// Moves the available funds of the dormant clients to the escheatment account, as a withdrawal
+// from the client and a deposit to the escheatment account. Their tx ids count down from
+// u32::MAX, skipping the ids already used. The policies of the accounts (e.g. their limits)
+// don't apply to the sweep; locked and empty accounts and the escheatment account itself are
+// not swept, nor is anything when the escheatment account is locked.
+pub fn sweep<A: AccountStore>(
+    accounts: &mut Accounts<A>,
+    dormant: &[u16],
+    escheatment: u16,
+) -> Vec<SweptAccount> {
+    if accounts
+        .get_user_account(escheatment)
+        .is_some_and(|x| x.locked)
+    {
+        tracing::warn!(
+            client = escheatment,
+            "escheatment account is locked, nothing swept"
+        );
+        return Vec::new();
+    }
+    let policies = *accounts.policies();
+    accounts.set_policies(Policies::default());
+    let mut next_tx = u32::MAX;
+    let mut swept = Vec::new();
+    for &client in dormant.iter().filter(|x| **x != escheatment) {
+        let amount = match accounts.get_user_account(client) {
+            Some(x) if !x.locked && x.available > Decimal::ZERO => x.available,
+            _ => continue,
+        };
+        let Some((withdrawal_tx, TransactionOutcome::Applied)) = add_with_free_tx(
+            accounts,
+            &mut next_tx,
+            client,
+            Transaction::Withdrawal { amount },
+        ) else {
+            continue;
+        };
+        let deposit = Transaction::Deposit { amount };
+        match add_with_free_tx(accounts, &mut next_tx, escheatment, deposit.clone()) {
+            Some((deposit_tx, TransactionOutcome::Applied)) => {
+                tracing::info!(client, %amount, "dormant account swept");
+                swept.push(SweptAccount {
+                    client,
+                    amount,
+                    withdrawal_tx,
+                    deposit_tx,
+                });
+            }
+            // puts the funds back, the client wasn't locked so this can't be rejected
+            _ => {
+                add_with_free_tx(accounts, &mut next_tx, client, deposit);
+            }
+        }
+    }
+    accounts.set_policies(policies);
+    swept
+}
+
+// None when the tx ids ran out
+fn add_with_free_tx<A: AccountStore>(
+    accounts: &mut Accounts<A>,
+    next_tx: &mut u32,
+    client: u16,
+    transaction: Transaction,
+) -> Option<(u32, TransactionOutcome)> {
+    loop {
+        let tx = *next_tx;
+        *next_tx = next_tx.checked_sub(1)?;
+        match accounts.add_transaction(client, tx, transaction.clone()) {
+            TransactionOutcome::Rejected(RejectionReason::DuplicateTransaction) => continue,
+            outcome => return Some((tx, outcome)),
+        }
+    }
+}
+
+// the sweep runs after the whole source, when `escheatment` is given
+pub fn read_source_with_dormancy<S: TransactionSource, A: AccountStore>(
+    source: S,
+    mode: ParseMode,
+    accounts: Accounts<A>,
+    after: u64,
+    escheatment: Option<u16>,
+) -> Result<(Accounts<A>, ParseReport, DormancyReport), ServiceError> {
+    let mut tracker = DormancyTracker::new();
+    let (mut accounts, report) = read_source_observed(source, mode, accounts, |_, event| {
+        tracker.observe(&event);
+        Ok(())
+    })?;
+    let dormant = tracker.dormant(&accounts, after);
+    let swept = escheatment
+        .map(|x| sweep(&mut accounts, &dormant, x))
+        .unwrap_or_default();
+    Ok((accounts, report, DormancyReport { dormant, swept }))
+}
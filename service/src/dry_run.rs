@@ -0,0 +1,28 @@
+use domain::domain::{AccountStore, Accounts};
+
+use crate::{
+    diff::{diff_accounts, AccountDiff},
+    error::ServiceError,
+    service::{read_source_into, ParseMode, ParseReport, TransactionSource},
+};
+
+#[derive(Debug)]
+pub struct DryRun {
+    pub report: ParseReport,
+    // what the run would change, see `diff_accounts`
+    pub changes: Vec<AccountDiff>,
+}
+
+// Applies the source to a copy of the accounts, so a file can be previewed before it is applied
+// for real; the accounts themselves are left as they are.
+pub fn dry_run<S: TransactionSource, A: AccountStore + Clone>(
+    source: S,
+    mode: ParseMode,
+    accounts: &Accounts<A>,
+) -> Result<DryRun, ServiceError> {
+    let (after, report) = read_source_into(source, mode, accounts.clone())?;
+    Ok(DryRun {
+        changes: diff_accounts(accounts, &after),
+        report,
+    })
+}
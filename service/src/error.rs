@@ -0,0 +1,103 @@
+use std::io;
+
+use domain::{domain::IdempotencyConflict, invariants::Violation};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ServiceError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("line {line}: {source}")]
+    Csv {
+        line: u64,
+        #[source]
+        source: csv::Error,
+    },
+    #[cfg(feature = "tokio")]
+    #[error("line {line}: {source}")]
+    AsyncCsv {
+        line: u64,
+        #[source]
+        source: csv_async::Error,
+    },
+    #[error("line {line}: {source}")]
+    Json {
+        line: u64,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[cfg(feature = "arrow")]
+    #[error("arrow error: {0}")]
+    Arrow(#[from] arrow_schema::ArrowError),
+    #[cfg(feature = "arrow")]
+    #[error("parquet error: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[cfg(feature = "polars")]
+    #[error("polars error: {0}")]
+    Polars(#[from] polars::error::PolarsError),
+    #[cfg(feature = "avro")]
+    #[error("avro error: {0}")]
+    Avro(#[from] apache_avro::Error),
+    #[cfg(feature = "protobuf")]
+    #[error("protobuf error: {0}")]
+    Protobuf(#[from] prost::DecodeError),
+    #[cfg(feature = "kafka")]
+    #[error("kafka error: {0}")]
+    Kafka(#[from] rdkafka::error::KafkaError),
+    #[cfg(feature = "sled")]
+    #[error("store error: {0}")]
+    Sled(#[from] sled::Error),
+    #[cfg(feature = "sqlite")]
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[cfg(feature = "postgres")]
+    #[error("postgres error: {0}")]
+    Postgres(#[from] ::postgres::Error),
+    #[cfg(feature = "tokio")]
+    #[error("accounts actor stopped")]
+    ActorStopped,
+    #[error("no exchange rate from {from} to {to}")]
+    MissingRate { from: String, to: String },
+    #[error("invalid record: {reason}")]
+    InvalidRecord { reason: String },
+    // a row error without a line of its own, see `RowError::into_error`
+    #[error("line {line}: {source}")]
+    Row {
+        line: u64,
+        #[source]
+        source: Box<ServiceError>,
+    },
+    #[error("apply queue is full ({depth} pending)")]
+    QueueFull { depth: usize },
+    #[error("the accounts are still being recovered")]
+    NotReady,
+    #[error("client {client} exceeded its rate limit")]
+    RateLimited { client: u16 },
+    #[error("invalid snapshot: {reason}")]
+    InvalidSnapshot { reason: String },
+    #[error("invariant violated after tx {tx}: {violation}")]
+    Invariant {
+        tx: u32,
+        #[source]
+        violation: Violation,
+    },
+    #[error("fail to serialize: {0}")]
+    Serialize(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl ServiceError {
+    pub(crate) fn from_csv(error: csv::Error) -> ServiceError {
+        ServiceError::Csv {
+            line: error.position().map_or(1, |x| x.line()),
+            source: error,
+        }
+    }
+}
+
+impl From<IdempotencyConflict> for ServiceError {
+    fn from(error: IdempotencyConflict) -> Self {
+        ServiceError::InvalidRecord {
+            reason: error.to_string(),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use domain::domain::{AccountStore, Accounts, Transaction, TransactionOutcome};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    error::ServiceError,
+    service::{apply_record, TransactionRecord},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountEventKind {
+    DepositApplied,
+    WithdrawalApplied,
+    DisputeOpened,
+    DisputeResolved,
+    ChargebackApplied,
+    HoldPlaced,
+    HoldCaptured,
+    HoldReleased,
+    AccountLocked,
+    // from `admin::AdminOperation`, with tx 0
+    AccountUnlocked,
+    BalanceAdjusted,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccountEvent {
+    #[serde(rename = "event")]
+    pub kind: AccountEventKind,
+    pub client: u16,
+    pub tx: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<Decimal>,
+}
+
+// rejected transactions produce no events, a transaction that locks the account adds `AccountLocked`
+pub fn apply_record_with_events<A: AccountStore>(
+    accounts: &mut Accounts<A>,
+    record: TransactionRecord,
+) -> Result<(TransactionOutcome, Vec<AccountEvent>), ServiceError> {
+    let (client, tx) = (record.client, record.tx);
+    let was_locked = is_locked(accounts, client);
+    let (kind, amount) = match record.transaction {
+        Transaction::Deposit { amount } => (AccountEventKind::DepositApplied, Some(amount)),
+        Transaction::Withdrawal { amount } => (AccountEventKind::WithdrawalApplied, Some(amount)),
+        Transaction::Dispute => (AccountEventKind::DisputeOpened, None),
+        Transaction::Resolve => (AccountEventKind::DisputeResolved, None),
+        Transaction::Chargeback => (AccountEventKind::ChargebackApplied, None),
+        Transaction::Hold { amount, .. } => (AccountEventKind::HoldPlaced, Some(amount)),
+        Transaction::Capture => (AccountEventKind::HoldCaptured, None),
+        Transaction::Release => (AccountEventKind::HoldReleased, None),
+    };
+
+    let outcome = apply_record(accounts, record)?;
+    let mut events = Vec::new();
+    if outcome == TransactionOutcome::Applied {
+        events.push(AccountEvent {
+            kind,
+            client,
+            tx,
+            amount,
+        });
+        if !was_locked && is_locked(accounts, client) {
+            events.push(AccountEvent {
+                kind: AccountEventKind::AccountLocked,
+                client,
+                tx,
+                amount: None,
+            });
+        }
+    }
+    Ok((outcome, events))
+}
+
+fn is_locked<A: AccountStore>(accounts: &Accounts<A>, client: u16) -> bool {
+    accounts.get_user_account(client).is_some_and(|x| x.locked)
+}
@@ -0,0 +1,133 @@
+use std::{collections::HashSet, io::Write};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rust_decimal::Decimal;
+
+use crate::error::ServiceError;
+
+// share of the remaining rows that are withdrawals, when the client has funds
+const WITHDRAWAL_RATE: f64 = 0.3;
+// share of the closed disputes that end in a chargeback instead of a resolve
+const CHARGEBACK_RATE: f64 = 0.2;
+// 1000.0000 with 4 decimal places
+const MAX_AMOUNT: i64 = 10_000_000;
+
+#[derive(Debug, Clone)]
+pub struct GeneratorOptions {
+    pub clients: u16,
+    pub transactions: u64,
+    pub dispute_rate: f64,
+    pub seed: u64,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        GeneratorOptions {
+            clients: 100,
+            transactions: 10_000,
+            dispute_rate: 0.01,
+            seed: 0,
+        }
+    }
+}
+
+struct Deposit {
+    client: u16,
+    tx: u32,
+    amount: Decimal,
+}
+
+// the same options always produce the same rows; withdrawals never exceed the available funds,
+// disputes only refer to deposits of the same client, and locked clients only get deposits
+// once every client is locked
+pub fn generate<W: Write>(writer: W, options: &GeneratorOptions) -> Result<(), ServiceError> {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let clients = options.clients.max(1);
+    let dispute_rate = options.dispute_rate.clamp(0.0, 0.5);
+    let mut available = vec![Decimal::ZERO; clients as usize + 1];
+    let mut locked = HashSet::new();
+    let mut deposits: Vec<Deposit> = Vec::new();
+    let mut disputes: Vec<Deposit> = Vec::new();
+    let mut next_tx = 1;
+
+    let mut writer = csv::Writer::from_writer(writer);
+    writer
+        .write_record(["type", "client", "tx", "amount"])
+        .map_err(ServiceError::from_csv)?;
+    for _ in 0..options.transactions {
+        let roll: f64 = rng.random();
+        if roll < dispute_rate && !deposits.is_empty() {
+            let deposit = deposits.swap_remove(rng.random_range(0..deposits.len()));
+            available[deposit.client as usize] -= deposit.amount;
+            write_row(&mut writer, "dispute", deposit.client, deposit.tx, None)?;
+            disputes.push(deposit);
+            continue;
+        }
+        if roll < dispute_rate * 2.0 && !disputes.is_empty() {
+            let deposit = disputes.swap_remove(rng.random_range(0..disputes.len()));
+            if rng.random_bool(CHARGEBACK_RATE) {
+                locked.insert(deposit.client);
+                deposits.retain(|x| x.client != deposit.client);
+                disputes.retain(|x| x.client != deposit.client);
+                write_row(&mut writer, "chargeback", deposit.client, deposit.tx, None)?;
+            } else {
+                available[deposit.client as usize] += deposit.amount;
+                write_row(&mut writer, "resolve", deposit.client, deposit.tx, None)?;
+                deposits.push(deposit);
+            }
+            continue;
+        }
+
+        let mut client = rng.random_range(1..=clients);
+        if locked.len() == clients as usize {
+            write_row(
+                &mut writer,
+                "deposit",
+                client,
+                next_tx,
+                Some(random_amount(&mut rng)),
+            )?;
+            next_tx += 1;
+            continue;
+        }
+        while locked.contains(&client) {
+            client = rng.random_range(1..=clients);
+        }
+        let tx = next_tx;
+        next_tx += 1;
+        let balance = available[client as usize];
+        if balance > Decimal::ZERO && rng.random_bool(WITHDRAWAL_RATE) {
+            let amount = random_amount(&mut rng).min(balance);
+            available[client as usize] -= amount;
+            write_row(&mut writer, "withdrawal", client, tx, Some(amount))?;
+        } else {
+            let amount = random_amount(&mut rng);
+            available[client as usize] += amount;
+            deposits.push(Deposit { client, tx, amount });
+            write_row(&mut writer, "deposit", client, tx, Some(amount))?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn random_amount(rng: &mut StdRng) -> Decimal {
+    Decimal::new(rng.random_range(1..=MAX_AMOUNT), 4)
+}
+
+fn write_row<W: Write>(
+    writer: &mut csv::Writer<W>,
+    transaction_type: &str,
+    client: u16,
+    tx: u32,
+    amount: Option<Decimal>,
+) -> Result<(), ServiceError> {
+    writer
+        .write_record([
+            transaction_type,
+            &client.to_string(),
+            &tx.to_string(),
+            &amount.map(|x| x.to_string()).unwrap_or_default(),
+        ])
+        .map_err(ServiceError::from_csv)
+}
@@ -0,0 +1,84 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use domain::domain::Accounts;
+use rdkafka::{
+    consumer::{BaseConsumer, CommitMode, Consumer},
+    ClientConfig, Message,
+};
+
+use crate::{
+    error::ServiceError,
+    service::{apply_record, InputTransactionRecord},
+};
+
+pub type Decoder = fn(&[u8]) -> Result<InputTransactionRecord, ServiceError>;
+
+#[derive(Debug, Clone)]
+pub struct KafkaOptions {
+    pub brokers: String,
+    pub group_id: String,
+    pub topic: String,
+    pub poll_timeout: Duration,
+    pub snapshot_interval: Duration,
+}
+
+impl Default for KafkaOptions {
+    fn default() -> Self {
+        KafkaOptions {
+            brokers: String::from("localhost:9092"),
+            group_id: String::from("transactions"),
+            topic: String::from("transactions"),
+            poll_timeout: Duration::from_millis(100),
+            snapshot_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+pub fn decode_json(bytes: &[u8]) -> Result<InputTransactionRecord, ServiceError> {
+    serde_json::from_slice(bytes).map_err(|e| ServiceError::Json {
+        line: e.line() as u64,
+        source: e,
+    })
+}
+
+pub fn create_consumer(options: &KafkaOptions) -> Result<BaseConsumer, ServiceError> {
+    let consumer: BaseConsumer = ClientConfig::new()
+        .set("bootstrap.servers", &options.brokers)
+        .set("group.id", &options.group_id)
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .create()?;
+    consumer.subscribe(&[&options.topic])?;
+    Ok(consumer)
+}
+
+// Offsets are committed only after the record was applied, so a failure leaves
+// the message to be consumed again on restart.
+pub fn consume<F: FnMut(&Accounts)>(
+    consumer: &BaseConsumer,
+    options: &KafkaOptions,
+    decode: Decoder,
+    mut accounts: Accounts,
+    running: &AtomicBool,
+    mut on_snapshot: F,
+) -> Result<Accounts, ServiceError> {
+    let mut last_snapshot = Instant::now();
+    while running.load(Ordering::Relaxed) {
+        if let Some(message) = consumer.poll(options.poll_timeout) {
+            let message = message?;
+            if let Some(record) = decode(message.payload().unwrap_or_default())?.into_record() {
+                apply_record(&mut accounts, record)?;
+            }
+            consumer.commit_message(&message, CommitMode::Async)?;
+        }
+        if last_snapshot.elapsed() >= options.snapshot_interval {
+            on_snapshot(&accounts);
+            last_snapshot = Instant::now();
+        }
+    }
+    on_snapshot(&accounts);
+    Ok(accounts)
+}
@@ -1,39 +1,226 @@
+#[cfg(feature = "tokio")]
+pub mod actor;
+pub mod admin;
+pub mod aml;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "tokio")]
+pub mod async_io;
+pub mod checkpoint;
+#[cfg(any(feature = "avro", feature = "protobuf"))]
+pub mod codecs;
+pub mod compression;
+pub mod currency;
+#[cfg(feature = "polars")]
+pub mod dataframe;
+pub mod diff;
+pub mod dormancy;
+pub mod dry_run;
+pub mod error;
+pub mod events;
+pub mod generate;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod monitor;
+pub mod parallel;
+pub mod pipeline;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod progress;
+pub mod reconcile;
+pub mod reporting;
+pub mod risk;
+pub mod scenario;
+pub mod schema;
+pub mod settlement;
+#[cfg(feature = "rayon")]
+pub mod sharded;
+pub mod snapshot;
+pub mod spill;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
+#[cfg(feature = "sled")]
+pub mod store;
+pub mod tenants;
+pub mod validate;
+pub mod wal;
+pub mod watch;
+#[cfg(feature = "webhooks")]
+pub mod webhooks;
+
 pub mod service {
-    use domain::domain::{Accounts, Transaction};
+    #[cfg(feature = "arrow")]
+    pub use crate::arrow::to_record_batch;
+    #[cfg(feature = "polars")]
+    pub use crate::dataframe::{from_dataframe, to_dataframe};
+    pub use crate::error::ServiceError;
+    use crate::events::{apply_record_with_events, AccountEvent};
+
+    use crate::compression::{create_output, is_stdio, open_input};
+    use domain::domain::{
+        AccountStore, Accounts, MemoryStore, RejectionReason, Transaction, TransactionActionState,
+        TransactionOutcome, UserAccount,
+    };
     use rust_decimal::Decimal;
-    use serde::{Deserialize, Serialize};
-    use std::{error::Error, fs::File};
+    pub use rust_decimal::RoundingStrategy;
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize, Serializer};
+    use std::{
+        borrow::Cow,
+        collections::HashSet,
+        fmt,
+        io::{BufRead, BufReader, Lines, Read, Seek, Write},
+        path::PathBuf,
+        str::FromStr,
+        time::{Duration, Instant},
+    };
 
     const DEPOSIT: &str = "deposit";
     const WITHDRAWAL: &str = "withdrawal";
     const DISPUTE: &str = "dispute";
     const RESOLVE: &str = "resolve";
     const CHARGEBACK: &str = "chargeback";
+    const HOLD: &str = "hold";
+    const CAPTURE: &str = "capture";
+    const RELEASE: &str = "release";
 
-    #[derive(Debug, Deserialize)]
-    struct InputTransactionRecord {
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "TransactionRecord")]
+    pub struct InputTransactionRecord {
         #[serde(rename = "type")]
-        transaction_type: String,
+        pub transaction_type: Cow<'static, str>,
         #[serde(rename = "client")]
-        client: u16,
-        tx: u32,
-        amount: Option<Decimal>,
+        pub client: u16,
+        pub tx: u32,
+        pub amount: Option<Decimal>,
+        #[serde(default)]
+        pub expires_after: Option<u32>,
+        #[serde(default)]
+        pub idempotency_key: Option<String>,
+        #[serde(default)]
+        pub tenant: Option<String>,
     }
     impl InputTransactionRecord {
-        fn convert(&self) -> Option<Transaction> {
-            match self.transaction_type.as_str() {
+        pub(crate) fn convert(&self) -> Option<Transaction> {
+            match self.transaction_type.as_ref() {
                 DEPOSIT => self.amount.map(|x| Transaction::Deposit { amount: x }),
                 WITHDRAWAL => self.amount.map(|x| Transaction::Withdrawal { amount: x }),
                 DISPUTE => Option::Some(Transaction::Dispute),
                 RESOLVE => Option::Some(Transaction::Resolve),
                 CHARGEBACK => Option::Some(Transaction::Chargeback),
+                HOLD => self
+                    .amount
+                    .zip(self.expires_after)
+                    .map(|(amount, expires_after)| Transaction::Hold {
+                        amount,
+                        expires_after,
+                    }),
+                CAPTURE => Option::Some(Transaction::Capture),
+                RELEASE => Option::Some(Transaction::Release),
                 _ => Option::None,
             }
         }
+
+        pub(crate) fn is_known_type(&self) -> bool {
+            matches!(
+                self.transaction_type.as_ref(),
+                DEPOSIT | WITHDRAWAL | DISPUTE | RESOLVE | CHARGEBACK | HOLD | CAPTURE | RELEASE
+            )
+        }
+
+        pub fn into_record(self) -> Option<TransactionRecord> {
+            self.convert().map(|transaction| TransactionRecord {
+                client: self.client,
+                tx: self.tx,
+                transaction,
+                idempotency_key: self.idempotency_key,
+            })
+        }
     }
 
-    #[derive(Debug, Serialize)]
-    struct OutputRecord {
+    // known types borrow the constant, so only unknown ones allocate
+    pub fn transaction_type_from(name: &str) -> Cow<'static, str> {
+        match name {
+            DEPOSIT => Cow::Borrowed(DEPOSIT),
+            WITHDRAWAL => Cow::Borrowed(WITHDRAWAL),
+            DISPUTE => Cow::Borrowed(DISPUTE),
+            RESOLVE => Cow::Borrowed(RESOLVE),
+            CHARGEBACK => Cow::Borrowed(CHARGEBACK),
+            HOLD => Cow::Borrowed(HOLD),
+            CAPTURE => Cow::Borrowed(CAPTURE),
+            RELEASE => Cow::Borrowed(RELEASE),
+            x => Cow::Owned(x.to_string()),
+        }
+    }
+
+    pub fn transaction_type_name(transaction: &Transaction) -> &'static str {
+        match transaction {
+            Transaction::Deposit { .. } => DEPOSIT,
+            Transaction::Withdrawal { .. } => WITHDRAWAL,
+            Transaction::Dispute => DISPUTE,
+            Transaction::Resolve => RESOLVE,
+            Transaction::Chargeback => CHARGEBACK,
+            Transaction::Hold { .. } => HOLD,
+            Transaction::Capture => CAPTURE,
+            Transaction::Release => RELEASE,
+        }
+    }
+
+    fn transaction_amount(transaction: &Transaction) -> Option<Decimal> {
+        match transaction {
+            Transaction::Deposit { amount }
+            | Transaction::Withdrawal { amount }
+            | Transaction::Hold { amount, .. } => Some(*amount),
+            _ => None,
+        }
+    }
+
+    // e.g. to log a record that was already converted
+    impl From<&TransactionRecord> for InputTransactionRecord {
+        fn from(record: &TransactionRecord) -> Self {
+            InputTransactionRecord {
+                transaction_type: Cow::Borrowed(transaction_type_name(&record.transaction)),
+                client: record.client,
+                tx: record.tx,
+                amount: transaction_amount(&record.transaction),
+                expires_after: match record.transaction {
+                    Transaction::Hold { expires_after, .. } => Some(expires_after),
+                    _ => None,
+                },
+                idempotency_key: record.idempotency_key.clone(),
+                tenant: None,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct TransactionRecord {
+        pub client: u16,
+        pub tx: u32,
+        pub transaction: Transaction,
+        pub idempotency_key: Option<String>,
+    }
+
+    pub fn apply_record<A: AccountStore>(
+        accounts: &mut Accounts<A>,
+        record: TransactionRecord,
+    ) -> Result<TransactionOutcome, ServiceError> {
+        Ok(match &record.idempotency_key {
+            Some(key) => accounts.add_idempotent_transaction(
+                key,
+                record.client,
+                record.tx,
+                record.transaction,
+            )?,
+            None => accounts.add_transaction(record.client, record.tx, record.transaction),
+        })
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct AccountStateRecord {
         client: u16,
         available: Decimal,
         held: Decimal,
@@ -41,43 +228,1241 @@ pub mod service {
         locked: bool,
     }
 
-    pub fn read_csv(file_path: String) -> Result<Accounts, Box<dyn Error>> {
+    #[derive(Debug, Serialize, JsonSchema)]
+    #[schemars(rename = "Account")]
+    pub struct OutputRecord {
+        pub client: u16,
+        pub available: Decimal,
+        pub held: Decimal,
+        pub total: Decimal,
+        pub locked: bool,
+        // only written with `OutputOptions::extended`
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub open_disputes: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub chargebacks: Option<usize>,
+    }
+
+    impl OutputRecord {
+        pub fn new(client: u16, account: &UserAccount, rounding: &RoundingConfig) -> OutputRecord {
+            OutputRecord {
+                client,
+                available: rounding.round(account.available),
+                held: rounding.round(account.held),
+                total: rounding.round(account.available + account.held),
+                locked: account.locked,
+                open_disputes: None,
+                chargebacks: None,
+            }
+        }
+
+        pub fn extended(
+            client: u16,
+            account: &UserAccount,
+            rounding: &RoundingConfig,
+        ) -> OutputRecord {
+            OutputRecord {
+                open_disputes: Some(account.open_disputes()),
+                chargebacks: Some(account.chargeback_count()),
+                ..OutputRecord::new(client, account, rounding)
+            }
+        }
+    }
+
+    // a row like `deposit, 1, 1, 1.0` is about 20 bytes
+    const ESTIMATED_ROW_BYTES: u64 = 24;
+    const ESTIMATED_TRANSACTIONS_PER_CLIENT: usize = 100;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Default)]
+    pub struct CapacityHint {
+        pub clients: usize,
+        pub transactions: usize,
+    }
+
+    impl CapacityHint {
+        pub fn from_input_size(bytes: u64) -> CapacityHint {
+            let transactions = (bytes / ESTIMATED_ROW_BYTES) as usize;
+            CapacityHint {
+                clients: (transactions / ESTIMATED_TRANSACTIONS_PER_CLIENT)
+                    .clamp(1, u16::MAX as usize + 1),
+                transactions,
+            }
+        }
+
+        // stdin and missing files get no hint, opening them reports the error
+        pub fn from_input_path(file_path: &str) -> CapacityHint {
+            match std::fs::metadata(file_path) {
+                Ok(x) if !is_stdio(file_path) => CapacityHint::from_input_size(x.len()),
+                _ => CapacityHint::default(),
+            }
+        }
+
+        pub fn accounts(&self) -> Accounts {
+            Accounts::with_capacity(self.clients, self.transactions)
+        }
+    }
+
+    pub fn read_csv(file_path: String) -> Result<(Accounts, ProcessingSummary), ServiceError> {
+        let _span = tracing::info_span!("read_csv", path = %file_path).entered();
+        let accounts = CapacityHint::from_input_path(&file_path).accounts();
+        read_source_into(
+            CsvSource::new(open_input(file_path)?),
+            ParseMode::Strict,
+            accounts,
+        )
+        .map(|(accounts, report)| (accounts, report.summary))
+    }
+
+    pub fn read_csv_with_state(
+        file_path: String,
+        initial_state: Option<Accounts>,
+    ) -> Result<Accounts, ServiceError> {
+        read_source_into(
+            CsvSource::new(open_input(file_path)?),
+            ParseMode::Strict,
+            initial_state.unwrap_or_default(),
+        )
+        .map(|(accounts, _)| accounts)
+    }
+
+    pub fn load_accounts_state(file_path: String) -> Result<Accounts, ServiceError> {
         let mut rdr = csv::ReaderBuilder::new()
             .trim(csv::Trim::All)
-            .from_reader(File::open(file_path)?);
+            .from_reader(open_input(file_path)?);
 
         let mut accounts = Accounts::new();
 
         for result in rdr.deserialize() {
-            let record: InputTransactionRecord = result?;
-            if let Some(transaction) = record.convert() {
-                accounts.add_transaction(record.client, record.tx, transaction);
+            let record: AccountStateRecord = result.map_err(ServiceError::from_csv)?;
+            if record.available.checked_add(record.held) != Some(record.total) {
+                return Err(ServiceError::InvalidRecord {
+                    reason: format!(
+                        "total of client {} is not equal to available + held",
+                        record.client
+                    ),
+                });
             }
+            accounts.restore_user_account(
+                record.client,
+                record.available,
+                record.held,
+                record.locked,
+            );
         }
 
         Ok(accounts)
     }
 
-    pub fn write_csv(file_path: String, accounts: &Accounts) -> Result<(), Box<dyn Error>> {
-        println!("client,available,held,total,locked");
-        let mut wtr = csv::Writer::from_path(file_path)?;
+    // the accounts csvs of shards that each had clients of their own, e.g. from `route`
+    pub fn merge_accounts_states(file_paths: &[String]) -> Result<Accounts, ServiceError> {
+        let mut accounts = Accounts::new();
+        for path in file_paths {
+            accounts
+                .merge(load_accounts_state(path.clone())?)
+                .map_err(|e| ServiceError::InvalidRecord {
+                    reason: format!("{}, merging {}", e, path),
+                })?;
+        }
+        Ok(accounts)
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub struct FileStatistics {
+        pub path: PathBuf,
+        pub records: u64,
+    }
+
+    struct CountingSource<S: TransactionSource> {
+        source: S,
+        records: u64,
+    }
 
-        accounts.get_user_accounts().for_each(|item| {
-            let record = OutputRecord {
-                client: item.0.clone(),
-                available: item.1.available,
-                held: item.1.held,
-                total: item.1.available + item.1.held,
-                locked: item.1.locked,
+    impl<S: TransactionSource> TransactionSource for CountingSource<S> {
+        fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>> {
+            let record = self.source.next_record();
+            if record.is_some() {
+                self.records += 1;
+            }
+            record
+        }
+
+        fn line_number(&self) -> u64 {
+            self.source.line_number()
+        }
+    }
+
+    pub fn read_csv_many(
+        paths: &[PathBuf],
+    ) -> Result<(Accounts, Vec<FileStatistics>), ServiceError> {
+        let mut accounts = Accounts::new();
+        let mut statistics = Vec::new();
+
+        for path in paths {
+            let mut source = CountingSource {
+                source: CsvSource::new(open_input(path)?),
+                records: 0,
             };
-            println!(
-                "{},{},{},{},{}",
-                record.client, record.available, record.held, record.total, record.locked
-            );
-            wtr.serialize(record).expect("fail to serialize");
-            wtr.flush().expect("fail to serialize");
-        });
+            accounts = read_source_into(&mut source, ParseMode::Strict, accounts)?.0;
+            statistics.push(FileStatistics {
+                path: path.clone(),
+                records: source.records,
+            });
+        }
+
+        Ok((accounts, statistics))
+    }
+
+    pub fn read_csv_with_mode(
+        file_path: String,
+        mode: ParseMode,
+    ) -> Result<(Accounts, ParseReport), ServiceError> {
+        let _span = tracing::info_span!("read_csv", path = %file_path).entered();
+        let accounts = CapacityHint::from_input_path(&file_path).accounts();
+        read_source_into(CsvSource::new(open_input(file_path)?), mode, accounts)
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum ParseMode {
+        Strict,
+        Lenient,
+    }
+
+    #[derive(Debug)]
+    pub struct RowError {
+        pub line_number: u64,
+        pub raw_row: String,
+        pub error: ServiceError,
+    }
+
+    impl fmt::Display for RowError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.error.fmt(f)
+        }
+    }
+
+    impl RowError {
+        // the error with the line of the row, unless it already names one or the line is unknown
+        pub fn into_error(self) -> ServiceError {
+            match self.error {
+                _ if self.line_number == 0 => self.error,
+                ServiceError::Csv { .. } | ServiceError::Json { .. } => self.error,
+                #[cfg(feature = "tokio")]
+                ServiceError::AsyncCsv { .. } => self.error,
+                error => ServiceError::Row {
+                    line: self.line_number,
+                    source: Box::new(error),
+                },
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub enum SourceError {
+        Row(RowError),
+        Fatal(ServiceError),
+    }
+
+    #[derive(Debug, Default)]
+    pub struct ParseReport {
+        pub errors: Vec<RowError>,
+        pub rejections: Vec<Rejection>,
+        pub summary: ProcessingSummary,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct Rejection {
+        #[serde(rename = "type")]
+        pub transaction_type: &'static str,
+        pub client: u16,
+        pub tx: u32,
+        pub amount: Option<Decimal>,
+        #[serde(serialize_with = "serialize_display")]
+        pub reason: RejectionReason,
+        // the transaction that used the tx id first, for a `ConflictingDuplicate`
+        pub original_client: Option<u16>,
+        pub original_type: Option<&'static str>,
+        pub original_amount: Option<Decimal>,
+    }
+
+    fn serialize_display<T: fmt::Display, S: serde::Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    // std has no clock on wasm32-unknown-unknown, where the elapsed time is reported as zero
+    pub(crate) struct Stopwatch(Option<Instant>);
+
+    impl Stopwatch {
+        pub(crate) fn start() -> Stopwatch {
+            Stopwatch(
+                (!cfg!(all(target_arch = "wasm32", target_os = "unknown"))).then(Instant::now),
+            )
+        }
+
+        pub(crate) fn elapsed(&self) -> Duration {
+            self.0.map_or(Duration::ZERO, |x| x.elapsed())
+        }
+    }
+
+    #[derive(Debug, Default, Clone, PartialEq)]
+    pub struct ProcessingSummary {
+        pub total_rows: u64,
+        pub applied: u64,
+        pub skipped_duplicates: u64,
+        pub skipped_insufficient_funds: u64,
+        pub skipped_over_limit: u64,
+        // references ahead of their transaction, only with `OrderingPolicy::Strict`
+        pub out_of_order: u64,
+        pub other_rejections: u64,
+        pub unknown_types: u64,
+        pub malformed_rows: u64,
+        pub elapsed: Duration,
+    }
+
+    impl ProcessingSummary {
+        fn count_outcome(&mut self, outcome: TransactionOutcome) {
+            match outcome {
+                TransactionOutcome::Applied => self.applied += 1,
+                TransactionOutcome::Rejected(
+                    RejectionReason::DuplicateTransaction | RejectionReason::ConflictingDuplicate,
+                ) => self.skipped_duplicates += 1,
+                TransactionOutcome::Rejected(RejectionReason::InsufficientFunds) => {
+                    self.skipped_insufficient_funds += 1
+                }
+                TransactionOutcome::Rejected(
+                    RejectionReason::AmountLimitExceeded | RejectionReason::BalanceLimitExceeded,
+                ) => self.skipped_over_limit += 1,
+                TransactionOutcome::Rejected(RejectionReason::OutOfOrder) => self.out_of_order += 1,
+                TransactionOutcome::Rejected(_) => self.other_rejections += 1,
+            }
+        }
+
+        pub fn add(&mut self, other: &ProcessingSummary) {
+            self.total_rows += other.total_rows;
+            self.applied += other.applied;
+            self.skipped_duplicates += other.skipped_duplicates;
+            self.skipped_insufficient_funds += other.skipped_insufficient_funds;
+            self.skipped_over_limit += other.skipped_over_limit;
+            self.out_of_order += other.out_of_order;
+            self.other_rejections += other.other_rejections;
+            self.unknown_types += other.unknown_types;
+            self.malformed_rows += other.malformed_rows;
+            self.elapsed += other.elapsed;
+        }
+    }
+
+    // e.g. `3 rows: 2 applied, 1 duplicates, 0 insufficient funds, ...`, without the elapsed time
+    impl fmt::Display for ProcessingSummary {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "{} rows: {} applied, {} duplicates, {} insufficient funds, {} over limit, \
+                 {} out of order, {} other rejections, {} unknown types, {} malformed",
+                self.total_rows,
+                self.applied,
+                self.skipped_duplicates,
+                self.skipped_insufficient_funds,
+                self.skipped_over_limit,
+                self.out_of_order,
+                self.other_rejections,
+                self.unknown_types,
+                self.malformed_rows
+            )
+        }
+    }
+
+    impl ParseReport {
+        pub fn extend(&mut self, other: ParseReport) {
+            self.errors.extend(other.errors);
+            self.rejections.extend(other.rejections);
+            self.summary.add(&other.summary);
+        }
+    }
+
+    pub trait TransactionSource {
+        fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>>;
+
+        // of the last returned record, numbered like the `RowError`s of the source; 0 when unknown
+        fn line_number(&self) -> u64 {
+            0
+        }
+    }
+
+    impl<S: TransactionSource + ?Sized> TransactionSource for &mut S {
+        fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>> {
+            (**self).next_record()
+        }
+
+        fn line_number(&self) -> u64 {
+            (**self).line_number()
+        }
+    }
+
+    impl<S: TransactionSource + ?Sized> TransactionSource for Box<S> {
+        fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>> {
+            (**self).next_record()
+        }
+
+        fn line_number(&self) -> u64 {
+            (**self).line_number()
+        }
+    }
+
+    pub(crate) const CSV_COLUMNS: [&str; 7] = [
+        "type",
+        "client",
+        "tx",
+        "amount",
+        "expires_after",
+        "idempotency_key",
+        "tenant",
+    ];
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ColumnMapping {
+        pub transaction_type: usize,
+        pub client: usize,
+        pub tx: usize,
+        pub amount: Option<usize>,
+        pub expires_after: Option<usize>,
+        pub idempotency_key: Option<usize>,
+        pub tenant: Option<usize>,
+    }
+
+    impl Default for ColumnMapping {
+        fn default() -> Self {
+            ColumnMapping {
+                transaction_type: 0,
+                client: 1,
+                tx: 2,
+                amount: Some(3),
+                expires_after: Some(4),
+                idempotency_key: Some(5),
+                tenant: Some(6),
+            }
+        }
+    }
+
+    impl ColumnMapping {
+        fn indices(&self) -> [Option<usize>; 7] {
+            [
+                Some(self.transaction_type),
+                Some(self.client),
+                Some(self.tx),
+                self.amount,
+                self.expires_after,
+                self.idempotency_key,
+                self.tenant,
+            ]
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct CsvOptions {
+        pub has_headers: bool,
+        pub delimiter: u8,
+        pub column_mapping: Option<ColumnMapping>,
+    }
+
+    impl Default for CsvOptions {
+        fn default() -> Self {
+            CsvOptions {
+                has_headers: true,
+                delimiter: b',',
+                column_mapping: None,
+            }
+        }
+    }
+
+    // borrows from the record buffer, which is reused for every row
+    #[derive(Deserialize)]
+    struct CsvRow<'a> {
+        #[serde(rename = "type")]
+        transaction_type: &'a str,
+        client: u16,
+        tx: u32,
+        amount: Option<Decimal>,
+        #[serde(default)]
+        expires_after: Option<u32>,
+        #[serde(default)]
+        idempotency_key: Option<&'a str>,
+        #[serde(default)]
+        tenant: Option<&'a str>,
+    }
+
+    impl From<CsvRow<'_>> for InputTransactionRecord {
+        fn from(row: CsvRow<'_>) -> Self {
+            InputTransactionRecord {
+                transaction_type: transaction_type_from(row.transaction_type),
+                client: row.client,
+                tx: row.tx,
+                amount: row.amount,
+                expires_after: row.expires_after,
+                idempotency_key: row.idempotency_key.map(str::to_string),
+                tenant: row.tenant.map(str::to_string),
+            }
+        }
+    }
+
+    pub struct CsvSource<R: Read> {
+        reader: csv::Reader<R>,
+        headers: Option<csv::StringRecord>,
+        column_mapping: Option<ColumnMapping>,
+        record: csv::ByteRecord,
+        mapped_record: csv::ByteRecord,
+    }
+
+    impl<R: Read> CsvSource<R> {
+        pub fn new(reader: R) -> CsvSource<R> {
+            CsvSource::with_options(reader, &CsvOptions::default())
+        }
+
+        pub fn with_options(reader: R, options: &CsvOptions) -> CsvSource<R> {
+            // without headers the columns can only be found by position
+            let column_mapping = options
+                .column_mapping
+                .clone()
+                .or_else(|| (!options.has_headers).then(ColumnMapping::default));
+            CsvSource {
+                reader: csv::ReaderBuilder::new()
+                    .trim(csv::Trim::All)
+                    .has_headers(options.has_headers)
+                    .delimiter(options.delimiter)
+                    .flexible(column_mapping.is_some())
+                    .from_reader(reader),
+                headers: column_mapping
+                    .as_ref()
+                    .map(|_| csv::StringRecord::from(CSV_COLUMNS.to_vec())),
+                column_mapping,
+                record: csv::ByteRecord::new(),
+                mapped_record: csv::ByteRecord::new(),
+            }
+        }
+
+        pub fn position(&self) -> &csv::Position {
+            self.reader.position()
+        }
+
+        // the line where the last record read starts
+        pub fn record_line(&self) -> u64 {
+            self.record.position().map_or(0, |x| x.line())
+        }
+
+        pub fn headers(&mut self) -> Result<&csv::StringRecord, ServiceError> {
+            if self.headers.is_none() {
+                self.headers = Some(
+                    self.reader
+                        .headers()
+                        .map_err(ServiceError::from_csv)?
+                        .clone(),
+                );
+            }
+            Ok(self.headers.as_ref().unwrap())
+        }
+    }
+
+    impl<R: Read + Seek> CsvSource<R> {
+        pub fn seek(&mut self, position: csv::Position) -> Result<(), ServiceError> {
+            self.reader.seek(position).map_err(ServiceError::from_csv)
+        }
+    }
+
+    impl<R: Read> TransactionSource for CsvSource<R> {
+        fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>> {
+            if let Err(e) = self.headers() {
+                return Some(Err(SourceError::Fatal(e)));
+            }
+
+            match self.reader.read_byte_record(&mut self.record) {
+                Ok(false) => None,
+                Ok(true) => {
+                    let record = match &self.column_mapping {
+                        Some(mapping) => {
+                            self.mapped_record.clear();
+                            for index in mapping.indices() {
+                                self.mapped_record.push_field(
+                                    index.and_then(|x| self.record.get(x)).unwrap_or(b""),
+                                );
+                            }
+                            &self.mapped_record
+                        }
+                        None => &self.record,
+                    };
+                    let headers = self.headers.as_ref().map(|x| x.as_byte_record());
+                    Some(
+                        record
+                            .deserialize::<CsvRow>(headers)
+                            .map(InputTransactionRecord::from)
+                            .map_err(|e| {
+                                SourceError::Row(RowError {
+                                    line_number: self.record.position().map_or(0, |x| x.line()),
+                                    raw_row: self
+                                        .record
+                                        .iter()
+                                        .map(String::from_utf8_lossy)
+                                        .collect::<Vec<_>>()
+                                        .join(","),
+                                    error: ServiceError::from_csv(e),
+                                })
+                            }),
+                    )
+                }
+                Err(e) if e.is_io_error() => {
+                    Some(Err(SourceError::Fatal(ServiceError::from_csv(e))))
+                }
+                Err(e) => Some(Err(SourceError::Row(RowError {
+                    line_number: e.position().map_or(0, |x| x.line()),
+                    raw_row: String::new(),
+                    error: ServiceError::from_csv(e),
+                }))),
+            }
+        }
+
+        fn line_number(&self) -> u64 {
+            self.record.position().map_or(0, |x| x.line())
+        }
+    }
+
+    pub struct NdjsonSource<R: Read> {
+        lines: Lines<BufReader<R>>,
+        line_number: u64,
+    }
+
+    impl<R: Read> NdjsonSource<R> {
+        pub fn new(reader: R) -> NdjsonSource<R> {
+            NdjsonSource {
+                lines: BufReader::new(reader).lines(),
+                line_number: 0,
+            }
+        }
+    }
+
+    impl<R: Read> TransactionSource for NdjsonSource<R> {
+        fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>> {
+            loop {
+                self.line_number += 1;
+                let line = match self.lines.next()? {
+                    Ok(x) => x,
+                    Err(e) => return Some(Err(SourceError::Fatal(e.into()))),
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                return Some(serde_json::from_str(&line).map_err(|e| {
+                    SourceError::Row(RowError {
+                        line_number: self.line_number,
+                        raw_row: line,
+                        error: ServiceError::Json {
+                            line: self.line_number,
+                            source: e,
+                        },
+                    })
+                }));
+            }
+        }
+
+        fn line_number(&self) -> u64 {
+            self.line_number
+        }
+    }
+
+    // the line number of a json array record is its position in the array
+    pub struct JsonSource {
+        records: std::iter::Enumerate<std::vec::IntoIter<serde_json::Value>>,
+        line_number: u64,
+    }
+
+    impl JsonSource {
+        pub fn new<R: Read>(reader: R) -> Result<JsonSource, ServiceError> {
+            let records: Vec<serde_json::Value> =
+                serde_json::from_reader(reader).map_err(|e| ServiceError::Json {
+                    line: e.line() as u64,
+                    source: e,
+                })?;
+            Ok(JsonSource {
+                records: records.into_iter().enumerate(),
+                line_number: 0,
+            })
+        }
+    }
+
+    impl TransactionSource for JsonSource {
+        fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>> {
+            let (index, value) = self.records.next()?;
+            self.line_number = index as u64 + 1;
+            let raw_row = value.to_string();
+            Some(serde_json::from_value(value).map_err(|e| {
+                SourceError::Row(RowError {
+                    line_number: self.line_number,
+                    raw_row,
+                    error: ServiceError::Json {
+                        line: self.line_number,
+                        source: e,
+                    },
+                })
+            }))
+        }
+
+        fn line_number(&self) -> u64 {
+            self.line_number
+        }
+    }
+
+    pub fn read_transactions<R: Read>(reader: R) -> Result<Accounts, ServiceError> {
+        read_source(CsvSource::new(reader))
+    }
+
+    pub fn read_transactions_with_options<R: Read>(
+        reader: R,
+        options: &CsvOptions,
+    ) -> Result<Accounts, ServiceError> {
+        read_source(CsvSource::with_options(reader, options))
+    }
+
+    pub fn read_transactions_with_mode<R: Read>(
+        reader: R,
+        mode: ParseMode,
+    ) -> Result<(Accounts, ParseReport), ServiceError> {
+        read_source_with_mode(CsvSource::new(reader), mode)
+    }
+
+    pub fn read_ndjson<R: Read>(reader: R) -> Result<Accounts, ServiceError> {
+        read_source(NdjsonSource::new(reader))
+    }
+
+    pub fn read_json<R: Read>(reader: R) -> Result<Accounts, ServiceError> {
+        read_source(JsonSource::new(reader)?)
+    }
+
+    pub fn read_source<S: TransactionSource>(source: S) -> Result<Accounts, ServiceError> {
+        read_source_with_mode(source, ParseMode::Strict).map(|(accounts, _)| accounts)
+    }
+
+    pub fn read_source_with_mode<S: TransactionSource>(
+        source: S,
+        mode: ParseMode,
+    ) -> Result<(Accounts, ParseReport), ServiceError> {
+        read_source_into(source, mode, Accounts::new())
+    }
+
+    pub fn read_source_into<S: TransactionSource, A: AccountStore>(
+        source: S,
+        mode: ParseMode,
+        accounts: Accounts<A>,
+    ) -> Result<(Accounts<A>, ParseReport), ServiceError> {
+        read_source_with_events(source, mode, accounts, |_| Ok(()))
+    }
+
+    // an error returned by `on_event` stops the processing
+    pub fn read_source_with_events<S, A, F>(
+        source: S,
+        mode: ParseMode,
+        accounts: Accounts<A>,
+        mut on_event: F,
+    ) -> Result<(Accounts<A>, ParseReport), ServiceError>
+    where
+        S: TransactionSource,
+        A: AccountStore,
+        F: FnMut(AccountEvent) -> Result<(), ServiceError>,
+    {
+        read_source_observed(source, mode, accounts, |_, event| on_event(event))
+    }
+
+    // like `read_source_with_events`, with the accounts passed along so `on_event` can act on them
+    pub fn read_source_observed<S, A, F>(
+        source: S,
+        mode: ParseMode,
+        mut accounts: Accounts<A>,
+        on_event: F,
+    ) -> Result<(Accounts<A>, ParseReport), ServiceError>
+    where
+        S: TransactionSource,
+        A: AccountStore,
+        F: FnMut(&mut Accounts<A>, AccountEvent) -> Result<(), ServiceError>,
+    {
+        let report = ingest_source(source, mode, &mut accounts, on_event)?;
+        Ok((accounts, report))
+    }
+
+    // like `read_source_into` with borrowed accounts, which keep the rows applied before an error
+    pub fn read_source_onto<S: TransactionSource, A: AccountStore>(
+        source: S,
+        mode: ParseMode,
+        accounts: &mut Accounts<A>,
+    ) -> Result<ParseReport, ServiceError> {
+        ingest_source(source, mode, accounts, |_, _| Ok(()))
+    }
+
+    fn ingest_source<S, A, F>(
+        mut source: S,
+        mode: ParseMode,
+        accounts: &mut Accounts<A>,
+        mut on_event: F,
+    ) -> Result<ParseReport, ServiceError>
+    where
+        S: TransactionSource,
+        A: AccountStore,
+        F: FnMut(&mut Accounts<A>, AccountEvent) -> Result<(), ServiceError>,
+    {
+        let _span = tracing::info_span!("ingest", ?mode).entered();
+        let started = Stopwatch::start();
+        let mut report = ParseReport::default();
+
+        while let Some(result) = source.next_record() {
+            if let Some(record) = accept_record(result, mode, &mut report)? {
+                let line_number = source.line_number();
+                ingest_record(
+                    accounts,
+                    record,
+                    line_number,
+                    mode,
+                    &mut report,
+                    &mut on_event,
+                )?;
+            }
+        }
+
+        report.summary.elapsed = started.elapsed();
+        tracing::info!(
+            rows = report.summary.total_rows,
+            applied = report.summary.applied,
+            malformed = report.summary.malformed_rows,
+            elapsed_ms = report.summary.elapsed.as_millis() as u64,
+            "ingestion finished"
+        );
+        Ok(report)
+    }
+
+    // counts the row; malformed rows are skipped in lenient mode
+    pub(crate) fn accept_record(
+        result: Result<InputTransactionRecord, SourceError>,
+        mode: ParseMode,
+        report: &mut ParseReport,
+    ) -> Result<Option<InputTransactionRecord>, ServiceError> {
+        report.summary.total_rows += 1;
+        match result {
+            Ok(x) => Ok(Some(x)),
+            Err(SourceError::Row(e)) => skip_row(e, mode, report).map(|_| None),
+            Err(SourceError::Fatal(e)) => Err(e),
+        }
+    }
+
+    // the row error fails the ingestion in strict mode
+    fn skip_row(
+        error: RowError,
+        mode: ParseMode,
+        report: &mut ParseReport,
+    ) -> Result<(), ServiceError> {
+        if mode == ParseMode::Strict {
+            return Err(error.into_error());
+        }
+        report_row(error, report);
+        Ok(())
+    }
+
+    fn report_row(error: RowError, report: &mut ParseReport) {
+        tracing::debug!(line = error.line_number, error = %error.error, "malformed row skipped");
+        report.summary.malformed_rows += 1;
+        report.errors.push(error);
+    }
+
+    // Rows of a known type without the fields it needs, e.g. a deposit without an amount, are
+    // reported like the rows that can't be parsed, but skipped in strict mode too.
+    pub(crate) fn ingest_record<A, F>(
+        accounts: &mut Accounts<A>,
+        record: InputTransactionRecord,
+        line_number: u64,
+        mode: ParseMode,
+        report: &mut ParseReport,
+        on_event: &mut F,
+    ) -> Result<(), ServiceError>
+    where
+        A: AccountStore,
+        F: FnMut(&mut Accounts<A>, AccountEvent) -> Result<(), ServiceError>,
+    {
+        let (client, tx) = (record.client, record.tx);
+        let (transaction_type, amount, outcome) = if !record.is_known_type() {
+            // types with a handler registered on the accounts produce no events
+            let handled = accounts.handled_type(&record.transaction_type).map(|x| {
+                (
+                    x,
+                    accounts.add_custom_transaction(x, client, tx, record.amount),
+                )
+            });
+            let Some((transaction_type, Some(outcome))) = handled else {
+                report.summary.unknown_types += 1;
+                return Ok(());
+            };
+            (transaction_type, record.amount, outcome)
+        } else if let Some(transaction) = record.convert() {
+            let transaction = TransactionRecord {
+                client,
+                tx,
+                transaction,
+                idempotency_key: record.idempotency_key.clone(),
+            };
+            let transaction_type = transaction_type_name(&transaction.transaction);
+            let amount = transaction_amount(&transaction.transaction);
+            // an idempotency key reused for another transaction
+            let (outcome, events) = match apply_record_with_events(accounts, transaction) {
+                Ok(x) => x,
+                Err(error) => {
+                    let error = RowError {
+                        line_number,
+                        raw_row: raw_row(&record),
+                        error,
+                    };
+                    return skip_row(error, mode, report);
+                }
+            };
+            for event in events {
+                on_event(accounts, event)?;
+            }
+            (transaction_type, amount, outcome)
+        } else {
+            let missing = match record.transaction_type.as_ref() {
+                HOLD => "an amount and expires_after",
+                _ => "an amount",
+            };
+            let error = RowError {
+                line_number,
+                raw_row: raw_row(&record),
+                error: ServiceError::InvalidRecord {
+                    reason: format!("{} without {}", record.transaction_type, missing),
+                },
+            };
+            report_row(error, report);
+            return Ok(());
+        };
+        report.summary.count_outcome(outcome);
+        if let TransactionOutcome::Rejected(reason) = outcome {
+            let original = (reason == RejectionReason::ConflictingDuplicate)
+                .then(|| accounts.find_transaction(tx))
+                .flatten();
+            let (original_type, original_amount) = match original.as_ref().map(|x| &x.1.amount) {
+                Some(TransactionActionState::Deposit { amount }) => {
+                    (Some("deposit"), Some(*amount))
+                }
+                Some(TransactionActionState::Withdrawal { amount }) => {
+                    (Some("withdrawal"), Some(*amount))
+                }
+                Some(TransactionActionState::Hold { amount }) => (Some("hold"), Some(*amount)),
+                None => (None, None),
+            };
+            report.rejections.push(Rejection {
+                transaction_type,
+                client,
+                tx,
+                amount,
+                reason,
+                original_client: original.map(|x| x.0),
+                original_type,
+                original_amount,
+            });
+        }
+        Ok(())
+    }
 
+    // the fields in the order of the csv columns, e.g. `deposit,1,2`
+    fn raw_row(record: &InputTransactionRecord) -> String {
+        [
+            Some(record.transaction_type.to_string()),
+            Some(record.client.to_string()),
+            Some(record.tx.to_string()),
+            record.amount.map(|x| x.to_string()),
+            record.expires_after.map(|x| x.to_string()),
+            record.idempotency_key.clone(),
+            record.tenant.clone(),
+        ]
+        .map(Option::unwrap_or_default)
+        .join(",")
+        .trim_end_matches(',')
+        .to_string()
+    }
+
+    pub fn write_csv<A: AccountStore>(
+        file_path: String,
+        accounts: &Accounts<A>,
+    ) -> Result<(), ServiceError> {
+        write_accounts(create_output(file_path)?, accounts)
+    }
+
+    pub fn write_filtered<A: AccountStore>(
+        file_path: String,
+        accounts: &Accounts<A>,
+        filter: &AccountFilter,
+    ) -> Result<(), ServiceError> {
+        CsvWriter.write_filtered(&mut create_output(file_path)?, accounts, filter)
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct AccountFilter {
+        pub clients: Option<HashSet<u16>>,
+        pub locked_only: bool,
+        pub held_only: bool,
+    }
+
+    impl AccountFilter {
+        pub fn matches(&self, client: u16, account: &UserAccount) -> bool {
+            self.clients.as_ref().is_none_or(|x| x.contains(&client))
+                && (!self.locked_only || account.locked)
+                && (!self.held_only || !account.held.is_zero())
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct RoundingConfig {
+        pub decimal_places: u32,
+        pub strategy: RoundingStrategy,
+    }
+
+    impl Default for RoundingConfig {
+        fn default() -> Self {
+            RoundingConfig {
+                decimal_places: 4,
+                strategy: RoundingStrategy::MidpointNearestEven,
+            }
+        }
+    }
+
+    impl RoundingConfig {
+        pub fn round(&self, value: Decimal) -> Decimal {
+            value.round_dp_with_strategy(self.decimal_places, self.strategy)
+        }
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct OutputOptions {
+        pub filter: AccountFilter,
+        pub rounding: RoundingConfig,
+        // adds the `open_disputes` and `chargebacks` columns
+        pub extended: bool,
+    }
+
+    pub fn write_rejections(file_path: String, report: &ParseReport) -> Result<(), ServiceError> {
+        write_rejections_to(create_output(file_path)?, report)
+    }
+
+    pub fn write_rejections_to<W: Write>(
+        writer: W,
+        report: &ParseReport,
+    ) -> Result<(), ServiceError> {
+        let mut wtr = csv::Writer::from_writer(writer);
+        for rejection in &report.rejections {
+            wtr.serialize(rejection)
+                .map_err(|e| ServiceError::Serialize(e.into()))?;
+        }
+        wtr.flush()?;
         Ok(())
     }
+
+    pub fn write_accounts<W: Write, A: AccountStore>(
+        mut writer: W,
+        accounts: &Accounts<A>,
+    ) -> Result<(), ServiceError> {
+        CsvWriter.write(&mut writer, accounts)
+    }
+
+    // records go through the csv buffer and the writer is flushed once at the end
+    pub fn write_accounts_iter<W: Write, I: IntoIterator<Item = OutputRecord>>(
+        writer: W,
+        records: I,
+    ) -> Result<(), ServiceError> {
+        let mut wtr = csv::Writer::from_writer(writer);
+        for record in records {
+            wtr.serialize(record)
+                .map_err(|e| ServiceError::Serialize(e.into()))?;
+        }
+        // into_inner flushes, and unlike dropping the writer it doesn't flush a second time
+        wtr.into_inner().map_err(|e| e.into_error())?;
+        Ok(())
+    }
+
+    pub fn output_records<'a, A: AccountStore>(
+        accounts: &'a Accounts<A>,
+        options: &'a OutputOptions,
+    ) -> impl Iterator<Item = OutputRecord> + 'a {
+        accounts
+            .iter()
+            .filter(|item| options.filter.matches(item.0, &item.1))
+            .map(|item| match options.extended {
+                true => OutputRecord::extended(item.0, &item.1, &options.rounding),
+                false => OutputRecord::new(item.0, &item.1, &options.rounding),
+            })
+    }
+
+    pub trait AccountsWriter<A: AccountStore = MemoryStore> {
+        fn write_with_options(
+            &self,
+            writer: &mut dyn Write,
+            accounts: &Accounts<A>,
+            options: &OutputOptions,
+        ) -> Result<(), ServiceError>;
+
+        fn write_filtered(
+            &self,
+            writer: &mut dyn Write,
+            accounts: &Accounts<A>,
+            filter: &AccountFilter,
+        ) -> Result<(), ServiceError> {
+            let options = OutputOptions {
+                filter: filter.clone(),
+                ..Default::default()
+            };
+            self.write_with_options(writer, accounts, &options)
+        }
+
+        fn write(
+            &self,
+            writer: &mut dyn Write,
+            accounts: &Accounts<A>,
+        ) -> Result<(), ServiceError> {
+            self.write_filtered(writer, accounts, &AccountFilter::default())
+        }
+    }
+
+    pub struct CsvWriter;
+
+    impl<A: AccountStore> AccountsWriter<A> for CsvWriter {
+        fn write_with_options(
+            &self,
+            writer: &mut dyn Write,
+            accounts: &Accounts<A>,
+            options: &OutputOptions,
+        ) -> Result<(), ServiceError> {
+            write_accounts_iter(writer, output_records(accounts, options))
+        }
+    }
+
+    pub struct JsonWriter;
+
+    impl<A: AccountStore> AccountsWriter<A> for JsonWriter {
+        fn write_with_options(
+            &self,
+            writer: &mut dyn Write,
+            accounts: &Accounts<A>,
+            options: &OutputOptions,
+        ) -> Result<(), ServiceError> {
+            serde_json::Serializer::new(&mut *writer)
+                .collect_seq(output_records(accounts, options))
+                .map_err(|e| ServiceError::Serialize(e.into()))?;
+            writeln!(writer)?;
+            writer.flush()?;
+            Ok(())
+        }
+    }
+
+    pub struct NdjsonWriter;
+
+    impl<A: AccountStore> AccountsWriter<A> for NdjsonWriter {
+        fn write_with_options(
+            &self,
+            writer: &mut dyn Write,
+            accounts: &Accounts<A>,
+            options: &OutputOptions,
+        ) -> Result<(), ServiceError> {
+            for record in output_records(accounts, options) {
+                serde_json::to_writer(&mut *writer, &record)
+                    .map_err(|e| ServiceError::Serialize(e.into()))?;
+                writeln!(writer)?;
+            }
+            writer.flush()?;
+            Ok(())
+        }
+    }
+
+    pub struct TableWriter;
+
+    impl<A: AccountStore> AccountsWriter<A> for TableWriter {
+        fn write_with_options(
+            &self,
+            writer: &mut dyn Write,
+            accounts: &Accounts<A>,
+            options: &OutputOptions,
+        ) -> Result<(), ServiceError> {
+            let mut header = vec!["client", "available", "held", "total", "locked"];
+            if options.extended {
+                header.extend(["open_disputes", "chargebacks"]);
+            }
+            let mut rows = vec![header.iter().map(|x| x.to_string()).collect::<Vec<_>>()];
+            rows.extend(output_records(accounts, options).map(|record| {
+                let mut row = vec![
+                    record.client.to_string(),
+                    record.available.to_string(),
+                    record.held.to_string(),
+                    record.total.to_string(),
+                    record.locked.to_string(),
+                ];
+                row.extend(
+                    [record.open_disputes, record.chargebacks]
+                        .into_iter()
+                        .flatten()
+                        .map(|x| x.to_string()),
+                );
+                row
+            }));
+
+            let mut widths = vec![0; header.len()];
+            for row in &rows {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.len());
+                }
+            }
+
+            for row in &rows {
+                let line: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, &width)| format!("{:>width$}", cell))
+                    .collect();
+                writeln!(writer, "{}", line.join("  "))?;
+            }
+            writer.flush()?;
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum OutputFormat {
+        Csv,
+        Json,
+        Ndjson,
+        Table,
+    }
+
+    impl OutputFormat {
+        pub fn writer<A: AccountStore>(&self) -> Box<dyn AccountsWriter<A>> {
+            match self {
+                OutputFormat::Csv => Box::new(CsvWriter),
+                OutputFormat::Json => Box::new(JsonWriter),
+                OutputFormat::Ndjson => Box::new(NdjsonWriter),
+                OutputFormat::Table => Box::new(TableWriter),
+            }
+        }
+
+        pub fn extension(&self) -> &'static str {
+            match self {
+                OutputFormat::Csv => "csv",
+                OutputFormat::Json => "json",
+                OutputFormat::Ndjson => "ndjson",
+                OutputFormat::Table => "txt",
+            }
+        }
+    }
+
+    impl FromStr for OutputFormat {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "csv" => Ok(OutputFormat::Csv),
+                "json" => Ok(OutputFormat::Json),
+                "ndjson" => Ok(OutputFormat::Ndjson),
+                "table" => Ok(OutputFormat::Table),
+                _ => Err(format!("unknown output format: {}", s)),
+            }
+        }
+    }
 }
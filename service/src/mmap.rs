@@ -0,0 +1,39 @@
+use std::{
+    fs::File,
+    io::{Cursor, Read},
+    path::Path,
+};
+
+use memmap2::Mmap;
+
+use crate::{
+    compression::{is_stdio, open_input, Compression},
+    error::ServiceError,
+};
+
+// None for stdin, empty files and anything that is not a regular file, e.g. a pipe
+pub fn map_file<P: AsRef<Path>>(path: P) -> Result<Option<Mmap>, ServiceError> {
+    if is_stdio(&path) {
+        return Ok(None);
+    }
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() || metadata.len() == 0 {
+        return Ok(None);
+    }
+    // the file must not be truncated or written to while it is mapped
+    Ok(unsafe { Mmap::map(&file) }.ok())
+}
+
+// the mapped bytes are read in place, falling back to buffered reads when the path can't be mapped
+pub fn open_input_mapped<P: AsRef<Path>>(path: P) -> Result<Box<dyn Read>, ServiceError> {
+    let Some(map) = map_file(&path)? else {
+        return open_input(path);
+    };
+    let bytes = Cursor::new(map);
+    Ok(match Compression::from_magic_bytes(bytes.get_ref()) {
+        Compression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(bytes)),
+        Compression::Zstd => Box::new(zstd::Decoder::with_buffer(bytes)?),
+        Compression::None => Box::new(bytes),
+    })
+}
@@ -0,0 +1,85 @@
+use domain::{
+    domain::{AccountStore, Accounts},
+    invariants::{self, Violation},
+};
+
+use crate::{
+    error::ServiceError,
+    events::{AccountEvent, AccountEventKind},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MonitorMode {
+    // violations are logged at error level and the run goes on
+    #[default]
+    Log,
+    // the first violation is returned as an error, which stops the ingestion
+    FailFast,
+}
+
+// Checks the account of every applied transaction right after it, so a state machine bug shows
+// up at the transaction that caused it instead of in a later reconciliation. Only the cheap
+// checks of `invariants::check_balances` run, plus `invariants::check_locked` when a
+// transaction locked the account.
+#[derive(Debug, Default)]
+pub struct InvariantMonitor {
+    mode: MonitorMode,
+    allow_negative_total: bool,
+    violations: u64,
+}
+
+impl InvariantMonitor {
+    pub fn new(mode: MonitorMode) -> InvariantMonitor {
+        InvariantMonitor {
+            mode,
+            ..InvariantMonitor::default()
+        }
+    }
+
+    // e.g. when chargebacks of deposits that were already withdrawn are expected
+    pub fn allow_negative_total(mut self, allow: bool) -> InvariantMonitor {
+        self.allow_negative_total = allow;
+        self
+    }
+
+    pub fn violations(&self) -> u64 {
+        self.violations
+    }
+
+    pub fn observe<A: AccountStore>(
+        &mut self,
+        accounts: &Accounts<A>,
+        event: &AccountEvent,
+    ) -> Result<(), ServiceError> {
+        let Some(account) = accounts.get_user_account(event.client) else {
+            return Ok(());
+        };
+        let checked = if event.kind == AccountEventKind::AccountLocked {
+            invariants::check_locked(event.client, &account)
+        } else {
+            invariants::check_balances(event.client, &account, self.allow_negative_total)
+        };
+        drop(account);
+        match checked {
+            Ok(()) => Ok(()),
+            Err(violation) => self.violated(event, violation),
+        }
+    }
+
+    fn violated(&mut self, event: &AccountEvent, violation: Violation) -> Result<(), ServiceError> {
+        self.violations += 1;
+        tracing::error!(
+            client = event.client,
+            tx = event.tx,
+            %violation,
+            "invariant violated"
+        );
+        match self.mode {
+            MonitorMode::Log => Ok(()),
+            MonitorMode::FailFast => Err(ServiceError::Invariant {
+                tx: event.tx,
+                violation,
+            }),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    panic,
+    sync::mpsc::{self, SyncSender},
+    thread::{self, JoinHandle},
+};
+
+use domain::domain::{Accounts, Transaction, TransactionRegistry};
+
+use crate::{
+    compression::open_input,
+    error::ServiceError,
+    service::{CsvSource, SourceError, TransactionSource},
+};
+
+const BATCH_SIZE: usize = 1024;
+const CHANNEL_DEPTH: usize = 16;
+
+type Batch = Vec<(u16, u32, Transaction)>;
+
+pub fn read_csv_parallel(file_path: String, workers: usize) -> Result<Accounts, ServiceError> {
+    read_source_parallel(CsvSource::new(open_input(file_path)?), workers)
+}
+
+// records are routed by client % workers, so every client is handled by exactly one worker
+// in input order. tx id and idempotency key deduplication is global and done before routing.
+// The sequence numbers in the logs count per worker.
+pub fn read_source_parallel<S: TransactionSource>(
+    mut source: S,
+    workers: usize,
+) -> Result<Accounts, ServiceError> {
+    let workers = workers.max(1);
+    let (senders, handles): (Vec<SyncSender<Batch>>, Vec<JoinHandle<Accounts>>) =
+        (0..workers).map(|_| spawn_worker()).unzip();
+
+    let mut registry = TransactionRegistry::new();
+    let mut batches: Vec<Batch> = (0..workers)
+        .map(|_| Vec::with_capacity(BATCH_SIZE))
+        .collect();
+    let result = route(&mut source, &mut registry, &senders, &mut batches);
+
+    for (sender, batch) in senders.iter().zip(batches) {
+        if !batch.is_empty() {
+            let _ = sender.send(batch);
+        }
+    }
+    drop(senders);
+
+    let mut accounts = Accounts::with_registry(registry);
+    for handle in handles {
+        let shard = handle.join().unwrap_or_else(|e| panic::resume_unwind(e));
+        accounts
+            .merge(shard)
+            .map_err(|e| ServiceError::InvalidRecord {
+                reason: e.to_string(),
+            })?;
+    }
+
+    result.map(|_| accounts)
+}
+
+fn spawn_worker() -> (SyncSender<Batch>, JoinHandle<Accounts>) {
+    let (sender, receiver) = mpsc::sync_channel::<Batch>(CHANNEL_DEPTH);
+    let handle = thread::spawn(move || {
+        let mut accounts = Accounts::new();
+        for batch in receiver {
+            for (client, tx, transaction) in batch {
+                accounts.add_transaction(client, tx, transaction);
+            }
+        }
+        accounts
+    });
+    (sender, handle)
+}
+
+fn route<S: TransactionSource>(
+    source: &mut S,
+    registry: &mut TransactionRegistry,
+    senders: &[SyncSender<Batch>],
+    batches: &mut [Batch],
+) -> Result<(), ServiceError> {
+    while let Some(result) = source.next_record() {
+        let record = match result {
+            Ok(x) => x,
+            Err(SourceError::Row(e)) => return Err(e.into_error()),
+            Err(SourceError::Fatal(e)) => return Err(e),
+        };
+        let Some(transaction) = record.convert() else {
+            continue;
+        };
+        if let Some(key) = &record.idempotency_key {
+            if !registry.register_idempotency_key(key, record.client, record.tx, &transaction)? {
+                continue;
+            }
+        }
+        if !registry.register(record.tx, &transaction) {
+            continue;
+        }
+
+        let shard = record.client as usize % senders.len();
+        batches[shard].push((record.client, record.tx, transaction));
+        if batches[shard].len() == BATCH_SIZE {
+            let batch = std::mem::replace(&mut batches[shard], Vec::with_capacity(BATCH_SIZE));
+            if senders[shard].send(batch).is_err() {
+                // the worker is gone, its panic is raised when it is joined
+                break;
+            }
+        }
+    }
+    Ok(())
+}
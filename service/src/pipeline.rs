@@ -0,0 +1,116 @@
+use std::{
+    panic,
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread, vec,
+};
+
+use domain::domain::{AccountStore, Accounts};
+
+use crate::{
+    compression::open_input,
+    error::ServiceError,
+    service::{
+        read_source_into, CsvSource, InputTransactionRecord, ParseMode, ParseReport, SourceError,
+        TransactionSource,
+    },
+};
+
+const BATCH_SIZE: usize = 1024;
+
+// the records with their line numbers
+type Batch = Vec<(u64, Result<InputTransactionRecord, SourceError>)>;
+
+struct ChannelSource {
+    receiver: Receiver<Batch>,
+    batch: vec::IntoIter<(u64, Result<InputTransactionRecord, SourceError>)>,
+    line_number: u64,
+}
+
+impl TransactionSource for ChannelSource {
+    fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>> {
+        loop {
+            if let Some((line_number, record)) = self.batch.next() {
+                self.line_number = line_number;
+                return Some(record);
+            }
+            self.batch = self.receiver.recv().ok()?.into_iter();
+        }
+    }
+
+    fn line_number(&self) -> u64 {
+        self.line_number
+    }
+}
+
+pub fn read_csv_pipelined(
+    file_path: String,
+    mode: ParseMode,
+    depth: usize,
+) -> Result<(Accounts, ParseReport), ServiceError> {
+    read_pipelined(
+        || Ok(CsvSource::new(open_input(file_path)?)),
+        mode,
+        Accounts::new(),
+        depth,
+    )
+}
+
+// the source is opened and read on a parsing thread, which sends the records in batches over a
+// channel holding at most `depth` batches; the accounts are updated on the calling thread.
+// the source is opened on the parsing thread so it does not have to be Send.
+pub fn read_pipelined<S, F, A>(
+    open: F,
+    mode: ParseMode,
+    accounts: Accounts<A>,
+    depth: usize,
+) -> Result<(Accounts<A>, ParseReport), ServiceError>
+where
+    S: TransactionSource,
+    F: FnOnce() -> Result<S, ServiceError> + Send,
+    A: AccountStore,
+{
+    let (sender, receiver) = mpsc::sync_channel(depth.max(1));
+    thread::scope(|scope| {
+        let parser = scope.spawn(move || parse(open, sender));
+        let source = ChannelSource {
+            receiver,
+            batch: Vec::new().into_iter(),
+            line_number: 0,
+        };
+        // dropping the source on an early return stops the parser at its next send
+        let result = read_source_into(source, mode, accounts);
+        parser.join().unwrap_or_else(|e| panic::resume_unwind(e));
+        result
+    })
+}
+
+fn parse<S, F>(open: F, sender: SyncSender<Batch>)
+where
+    S: TransactionSource,
+    F: FnOnce() -> Result<S, ServiceError>,
+{
+    let mut source = match open() {
+        Ok(x) => x,
+        Err(e) => {
+            let _ = sender.send(vec![(0, Err(SourceError::Fatal(e)))]);
+            return;
+        }
+    };
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while let Some(record) = source.next_record() {
+        let fatal = matches!(record, Err(SourceError::Fatal(_)));
+        batch.push((source.line_number(), record));
+        if fatal {
+            break;
+        }
+        if batch.len() == BATCH_SIZE {
+            let full = std::mem::replace(&mut batch, Vec::with_capacity(BATCH_SIZE));
+            if sender.send(full).is_err() {
+                return;
+            }
+        }
+    }
+    if !batch.is_empty() {
+        let _ = sender.send(batch);
+    }
+}
@@ -0,0 +1,88 @@
+use ::postgres::{types::ToSql, Client};
+use domain::domain::{AccountStore, Accounts};
+
+use crate::{
+    error::ServiceError,
+    service::{output_records, OutputOptions},
+};
+
+#[derive(Debug, Clone)]
+pub struct PostgresSink {
+    pub table: String,
+    pub batch_size: usize,
+}
+
+impl Default for PostgresSink {
+    fn default() -> Self {
+        PostgresSink {
+            table: String::from("accounts"),
+            batch_size: 1000,
+        }
+    }
+}
+
+impl PostgresSink {
+    // all batches are written in one transaction, so readers never see a partial run
+    pub fn write<A: AccountStore>(
+        &self,
+        client: &mut Client,
+        accounts: &Accounts<A>,
+    ) -> Result<(), ServiceError> {
+        let mut transaction = client.transaction()?;
+        transaction.batch_execute(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                client INTEGER PRIMARY KEY,
+                available NUMERIC NOT NULL,
+                held NUMERIC NOT NULL,
+                total NUMERIC NOT NULL,
+                locked BOOLEAN NOT NULL
+            )",
+            self.table
+        ))?;
+
+        let options = OutputOptions::default();
+        let records: Vec<_> = output_records(accounts, &options).collect();
+        for batch in records.chunks(self.batch_size.max(1)) {
+            let clients: Vec<i32> = batch.iter().map(|x| x.client as i32).collect();
+            let mut params: Vec<&(dyn ToSql + Sync)> = Vec::with_capacity(batch.len() * 5);
+            for (record, client) in batch.iter().zip(&clients) {
+                params.extend([
+                    client as &(dyn ToSql + Sync),
+                    &record.available,
+                    &record.held,
+                    &record.total,
+                    &record.locked,
+                ]);
+            }
+            transaction.execute(&self.upsert_statement(batch.len()), &params)?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn upsert_statement(&self, rows: usize) -> String {
+        let values: Vec<String> = (0..rows)
+            .map(|row| {
+                let base = row * 5;
+                format!(
+                    "(${}, ${}, ${}, ${}, ${})",
+                    base + 1,
+                    base + 2,
+                    base + 3,
+                    base + 4,
+                    base + 5
+                )
+            })
+            .collect();
+        format!(
+            "INSERT INTO {} (client, available, held, total, locked) VALUES {}
+            ON CONFLICT (client) DO UPDATE SET
+                available = EXCLUDED.available,
+                held = EXCLUDED.held,
+                total = EXCLUDED.total,
+                locked = EXCLUDED.locked",
+            self.table,
+            values.join(", ")
+        )
+    }
+}
@@ -0,0 +1,112 @@
+use std::{cell::Cell, io::Read, rc::Rc};
+
+use domain::domain::Accounts;
+
+use crate::{
+    compression::decompress,
+    error::ServiceError,
+    service::{
+        read_source_into, CsvSource, InputTransactionRecord, ParseMode, SourceError,
+        TransactionSource,
+    },
+};
+
+pub struct ProgressReader<R: Read> {
+    reader: R,
+    bytes_read: Rc<Cell<u64>>,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(reader: R) -> ProgressReader<R> {
+        ProgressReader {
+            reader,
+            bytes_read: Rc::new(Cell::new(0)),
+        }
+    }
+
+    pub fn bytes_read(&self) -> Rc<Cell<u64>> {
+        self.bytes_read.clone()
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.bytes_read.set(self.bytes_read.get() + n as u64);
+        Ok(n)
+    }
+}
+
+pub struct ProgressSource<S: TransactionSource, F: FnMut(u64, u64)> {
+    source: S,
+    bytes_read: Rc<Cell<u64>>,
+    every: u64,
+    records: u64,
+    on_progress: F,
+}
+
+impl<S: TransactionSource, F: FnMut(u64, u64)> ProgressSource<S, F> {
+    pub fn new(
+        source: S,
+        bytes_read: Rc<Cell<u64>>,
+        every: u64,
+        on_progress: F,
+    ) -> ProgressSource<S, F> {
+        ProgressSource {
+            source,
+            bytes_read,
+            every: every.max(1),
+            records: 0,
+            on_progress,
+        }
+    }
+}
+
+impl<S: TransactionSource, F: FnMut(u64, u64)> TransactionSource for ProgressSource<S, F> {
+    fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>> {
+        let record = self.source.next_record();
+        match record {
+            Some(_) => {
+                self.records += 1;
+                if self.records.is_multiple_of(self.every) {
+                    (self.on_progress)(self.records, self.bytes_read.get());
+                }
+            }
+            // the last call always reports the final counts, even if it is not a multiple of every
+            None if !self.records.is_multiple_of(self.every) => {
+                (self.on_progress)(self.records, self.bytes_read.get());
+            }
+            None => {}
+        }
+        record
+    }
+
+    fn line_number(&self) -> u64 {
+        self.source.line_number()
+    }
+}
+
+pub fn read_csv_with_progress<F: FnMut(u64, u64)>(
+    file_path: String,
+    every: u64,
+    on_progress: F,
+) -> Result<Accounts, ServiceError> {
+    read_transactions_with_progress(std::fs::File::open(file_path)?, every, on_progress)
+}
+
+// bytes_read counts the bytes read from the given reader, i.e. before decompression
+pub fn read_transactions_with_progress<R: Read, F: FnMut(u64, u64)>(
+    reader: R,
+    every: u64,
+    on_progress: F,
+) -> Result<Accounts, ServiceError> {
+    let reader = ProgressReader::new(reader);
+    let bytes_read = reader.bytes_read();
+    let source = ProgressSource::new(
+        CsvSource::new(decompress(reader)?),
+        bytes_read,
+        every,
+        on_progress,
+    );
+    read_source_into(source, ParseMode::Strict, Accounts::new()).map(|(accounts, _)| accounts)
+}
@@ -0,0 +1,207 @@
+use std::{collections::BTreeMap, fmt};
+
+use domain::domain::{AccountStore, Accounts, FxHashMap};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    error::ServiceError,
+    events::{AccountEvent, AccountEventKind},
+    service::{read_source_with_events, ParseMode, ParseReport, TransactionSource},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Deposit,
+    Withdrawal,
+    Hold,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Flows {
+    pub opening: Decimal,
+    pub deposits: Decimal,
+    pub withdrawals: Decimal,
+    // deposits that were charged back
+    pub chargebacks: Decimal,
+    pub captured_holds: Decimal,
+    // a disputed withdrawal is held on top of the balance until it is resolved or charged back
+    pub disputed_withdrawals: Decimal,
+}
+
+impl Flows {
+    pub fn expected_total(&self) -> Decimal {
+        self.opening + self.deposits - self.withdrawals - self.chargebacks - self.captured_holds
+            + self.disputed_withdrawals
+    }
+
+    fn add(&mut self, other: &Flows) {
+        self.opening += other.opening;
+        self.deposits += other.deposits;
+        self.withdrawals += other.withdrawals;
+        self.chargebacks += other.chargebacks;
+        self.captured_holds += other.captured_holds;
+        self.disputed_withdrawals += other.disputed_withdrawals;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Imbalance {
+    pub client: u16,
+    pub expected: Decimal,
+    pub actual: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReconciliationReport {
+    pub flows: Flows,
+    pub expected_total: Decimal,
+    pub accounts_total: Decimal,
+    pub imbalances: Vec<Imbalance>,
+}
+
+impl ReconciliationReport {
+    pub fn is_balanced(&self) -> bool {
+        self.imbalances.is_empty()
+    }
+}
+
+impl fmt::Display for ReconciliationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "opening {} + deposits {} - withdrawals {} - chargebacks {} - captured holds {} + disputed withdrawals {} = {}, accounts total {}",
+            self.flows.opening,
+            self.flows.deposits,
+            self.flows.withdrawals,
+            self.flows.chargebacks,
+            self.flows.captured_holds,
+            self.flows.disputed_withdrawals,
+            self.expected_total,
+            self.accounts_total
+        )?;
+        if self.is_balanced() {
+            return writeln!(f, "balanced");
+        }
+        for x in &self.imbalances {
+            writeln!(
+                f,
+                "client {}: expected {}, actual {}, off by {}",
+                x.client,
+                x.expected,
+                x.actual,
+                x.actual - x.expected
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Follows the applied transactions through their events, independently of the account state,
+// and compares what every client should have with what the accounts say. The amounts of
+// deposits, withdrawals and holds are kept by tx id to value the disputes that refer to them.
+#[derive(Default)]
+pub struct Reconciler {
+    clients: BTreeMap<u16, Flows>,
+    amounts: FxHashMap<u32, (Kind, Decimal)>,
+}
+
+impl Reconciler {
+    // the balances of `accounts` are the opening balances
+    pub fn new<A: AccountStore>(accounts: &Accounts<A>) -> Reconciler {
+        let mut reconciler = Reconciler::default();
+        for (client, account) in accounts.iter() {
+            reconciler.clients.entry(client).or_default().opening =
+                account.available + account.held;
+        }
+        reconciler
+    }
+
+    pub fn observe(&mut self, event: &AccountEvent) {
+        let flows = self.clients.entry(event.client).or_default();
+        let logged = self.amounts.get(&event.tx).copied();
+        match (event.kind, event.amount, logged) {
+            (AccountEventKind::DepositApplied, Some(amount), _) => {
+                flows.deposits += amount;
+                self.amounts.insert(event.tx, (Kind::Deposit, amount));
+            }
+            (AccountEventKind::WithdrawalApplied, Some(amount), _) => {
+                flows.withdrawals += amount;
+                self.amounts.insert(event.tx, (Kind::Withdrawal, amount));
+            }
+            (AccountEventKind::HoldPlaced, Some(amount), _) => {
+                self.amounts.insert(event.tx, (Kind::Hold, amount));
+            }
+            (AccountEventKind::DisputeOpened, _, Some((Kind::Withdrawal, amount))) => {
+                flows.disputed_withdrawals += amount
+            }
+            (
+                AccountEventKind::DisputeResolved | AccountEventKind::ChargebackApplied,
+                _,
+                Some((Kind::Withdrawal, amount)),
+            ) => flows.disputed_withdrawals -= amount,
+            (AccountEventKind::ChargebackApplied, _, Some((Kind::Deposit, amount))) => {
+                flows.chargebacks += amount
+            }
+            (AccountEventKind::HoldCaptured, _, Some((Kind::Hold, amount))) => {
+                flows.captured_holds += amount
+            }
+            _ => {}
+        }
+    }
+
+    pub fn reconcile<A: AccountStore>(&self, accounts: &Accounts<A>) -> ReconciliationReport {
+        let mut flows = Flows::default();
+        let mut actual_totals: BTreeMap<u16, Decimal> = accounts
+            .iter()
+            .map(|(client, account)| (client, account.available + account.held))
+            .collect();
+        let mut imbalances = Vec::new();
+        for (client, client_flows) in &self.clients {
+            flows.add(client_flows);
+            let expected = client_flows.expected_total();
+            let actual = actual_totals.remove(client).unwrap_or_default();
+            if expected != actual {
+                imbalances.push(Imbalance {
+                    client: *client,
+                    expected,
+                    actual,
+                });
+            }
+        }
+        // accounts that no transaction went through
+        for (client, actual) in actual_totals {
+            if !actual.is_zero() {
+                imbalances.push(Imbalance {
+                    client,
+                    expected: Decimal::ZERO,
+                    actual,
+                });
+            }
+        }
+        imbalances.sort_by_key(|x| x.client);
+        ReconciliationReport {
+            expected_total: flows.expected_total(),
+            accounts_total: accounts
+                .iter()
+                .map(|(_, account)| account.available + account.held)
+                .sum(),
+            flows,
+            imbalances,
+        }
+    }
+}
+
+pub fn read_source_reconciled<S: TransactionSource, A: AccountStore>(
+    source: S,
+    mode: ParseMode,
+    accounts: Accounts<A>,
+) -> Result<(Accounts<A>, ParseReport, ReconciliationReport), ServiceError> {
+    let mut reconciler = Reconciler::new(&accounts);
+    let (accounts, report) = read_source_with_events(source, mode, accounts, |event| {
+        reconciler.observe(&event);
+        Ok(())
+    })?;
+    let reconciliation = reconciler.reconcile(&accounts);
+    Ok((accounts, report, reconciliation))
+}
@@ -0,0 +1,203 @@
+use std::{collections::HashSet, fmt};
+
+use domain::domain::{
+    Accounts, Transaction, TransactionActionState, TransactionOutcome, UserAccount,
+};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    error::ServiceError,
+    service::{apply_record, transaction_type_name, ParseMode, SourceError, TransactionSource},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Balance {
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    pub locked: bool,
+}
+
+impl Balance {
+    fn of(account: Option<&UserAccount>) -> Balance {
+        account.map_or_else(Balance::default, |x| Balance {
+            available: x.available,
+            held: x.held,
+            total: x.available + x.held,
+            locked: x.locked,
+        })
+    }
+}
+
+// disputes, resolves, chargebacks, captures and releases show the amount of the transaction they refer to
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatementLine {
+    pub tx: u32,
+    #[serde(rename = "type")]
+    pub transaction_type: &'static str,
+    pub amount: Option<Decimal>,
+    // `applied` or the rejection reason
+    pub outcome: String,
+    pub balance: Balance,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Statement {
+    pub client: u16,
+    pub opening: Balance,
+    pub lines: Vec<StatementLine>,
+    pub disputes_opened: u64,
+    // resolved or charged back
+    pub disputes_closed: u64,
+    pub closing: Balance,
+}
+
+impl Statement {
+    fn new(client: u16, opening: Balance) -> Statement {
+        Statement {
+            client,
+            opening,
+            lines: Vec::new(),
+            disputes_opened: 0,
+            disputes_closed: 0,
+            closing: opening,
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "statement of client {}", self.client)?;
+        writeln!(f, "opening  {}", self.opening)?;
+        let header = [
+            "tx",
+            "type",
+            "amount",
+            "outcome",
+            "available",
+            "held",
+            "total",
+        ];
+        let mut rows = vec![header.map(String::from)];
+        rows.extend(self.lines.iter().map(|x| {
+            [
+                x.tx.to_string(),
+                x.transaction_type.to_string(),
+                x.amount.map(|x| x.to_string()).unwrap_or_default(),
+                x.outcome.clone(),
+                x.balance.available.to_string(),
+                x.balance.held.to_string(),
+                x.balance.total.to_string(),
+            ]
+        }));
+        let mut widths = [0; 7];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        for row in &rows {
+            let line: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:>width$}", cell))
+                .collect();
+            writeln!(f, "  {}", line.join("  "))?;
+        }
+        writeln!(
+            f,
+            "disputes opened {}, closed {}",
+            self.disputes_opened, self.disputes_closed
+        )?;
+        writeln!(f, "closing  {}", self.closing)
+    }
+}
+
+impl fmt::Display for Balance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "available {}  held {}  total {}{}",
+            self.available,
+            self.held,
+            self.total,
+            if self.locked { "  locked" } else { "" }
+        )
+    }
+}
+
+// Applies the source to the accounts and returns a statement for every client in `clients`
+// (every client when None) that has transactions in the source, in client order. The opening
+// balance is the one in `accounts` before the first transaction. Rows that can't be read are
+// skipped in lenient mode and fail in strict mode, unknown types are skipped.
+pub fn statements<S: TransactionSource>(
+    mut source: S,
+    mode: ParseMode,
+    mut accounts: Accounts,
+    clients: Option<&HashSet<u16>>,
+) -> Result<Vec<Statement>, ServiceError> {
+    let mut statements: Vec<Statement> = Vec::new();
+    while let Some(result) = source.next_record() {
+        let record = match result {
+            Ok(x) => x,
+            Err(SourceError::Row(_)) if mode == ParseMode::Lenient => continue,
+            Err(SourceError::Row(e)) => return Err(e.into_error()),
+            Err(SourceError::Fatal(e)) => return Err(e),
+        };
+        let Some(record) = record.into_record() else {
+            continue;
+        };
+        let (client, tx) = (record.client, record.tx);
+        if clients.is_some_and(|x| !x.contains(&client)) {
+            apply_record(&mut accounts, record)?;
+            continue;
+        }
+
+        let account = accounts.get_user_account(client);
+        let amount = match record.transaction {
+            Transaction::Deposit { amount }
+            | Transaction::Withdrawal { amount }
+            | Transaction::Hold { amount, .. } => Some(amount),
+            _ => account
+                .and_then(|x| x.transaction_log.get(&tx))
+                .map(|x| match x.amount {
+                    TransactionActionState::Deposit { amount }
+                    | TransactionActionState::Withdrawal { amount }
+                    | TransactionActionState::Hold { amount } => amount,
+                }),
+        };
+        let index = match statements.binary_search_by_key(&client, |x| x.client) {
+            Ok(x) => x,
+            Err(x) => {
+                statements.insert(x, Statement::new(client, Balance::of(account)));
+                x
+            }
+        };
+        let transaction_type = transaction_type_name(&record.transaction);
+        let opens_dispute = record.transaction == Transaction::Dispute;
+        let closes_dispute = matches!(
+            record.transaction,
+            Transaction::Resolve | Transaction::Chargeback
+        );
+
+        let outcome = apply_record(&mut accounts, record)?;
+        let statement = &mut statements[index];
+        if outcome == TransactionOutcome::Applied {
+            statement.disputes_opened += opens_dispute as u64;
+            statement.disputes_closed += closes_dispute as u64;
+        }
+        statement.closing = Balance::of(accounts.get_user_account(client));
+        statement.lines.push(StatementLine {
+            tx,
+            transaction_type,
+            amount,
+            outcome: match outcome {
+                TransactionOutcome::Applied => String::from("applied"),
+                TransactionOutcome::Rejected(reason) => reason.to_string(),
+            },
+            balance: statement.closing,
+        });
+    }
+    Ok(statements)
+}
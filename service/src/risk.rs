@@ -0,0 +1,291 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt,
+};
+
+use domain::domain::{AccountStore, Accounts, FxHashMap};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::Serialize;
+
+use crate::{
+    error::ServiceError,
+    events::{AccountEvent, AccountEventKind},
+    service::{read_source_observed, ParseMode, ParseReport, TransactionSource},
+};
+
+// A rule sees the events of every applied transaction in input order and returns the reason
+// when the event makes the client suspicious. Rules only flag; they never reject a transaction.
+pub trait Rule {
+    fn name(&self) -> &'static str;
+    fn evaluate(&mut self, event: &AccountEvent) -> Option<String>;
+}
+
+// a withdrawal taking most of a deposit of the same client made only a few transactions earlier
+pub struct RapidDepositWithdrawal {
+    pub window: u64,
+    pub share: Decimal,
+    seq: u64,
+    deposits: FxHashMap<u16, (u64, Decimal)>,
+}
+
+impl RapidDepositWithdrawal {
+    pub fn new(window: u64, share: Decimal) -> RapidDepositWithdrawal {
+        RapidDepositWithdrawal {
+            window,
+            share,
+            seq: 0,
+            deposits: FxHashMap::default(),
+        }
+    }
+}
+
+impl Default for RapidDepositWithdrawal {
+    fn default() -> Self {
+        RapidDepositWithdrawal::new(10, dec!(0.9))
+    }
+}
+
+impl Rule for RapidDepositWithdrawal {
+    fn name(&self) -> &'static str {
+        "rapid_deposit_withdrawal"
+    }
+
+    fn evaluate(&mut self, event: &AccountEvent) -> Option<String> {
+        if event.kind == AccountEventKind::AccountLocked {
+            return None;
+        }
+        self.seq += 1;
+        match (event.kind, event.amount) {
+            (AccountEventKind::DepositApplied, Some(amount)) => {
+                self.deposits.insert(event.client, (self.seq, amount));
+                None
+            }
+            (AccountEventKind::WithdrawalApplied, Some(amount)) => {
+                let (seq, deposit) = *self.deposits.get(&event.client)?;
+                (self.seq - seq <= self.window && amount >= deposit * self.share).then(|| {
+                    format!(
+                        "withdrew {} of a deposit of {} {} transactions later",
+                        amount,
+                        deposit,
+                        self.seq - seq
+                    )
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+// more disputes than `threshold` times the deposits and withdrawals of the client
+pub struct DisputeRatio {
+    pub threshold: Decimal,
+    pub min_transactions: u32,
+    counts: FxHashMap<u16, (u32, u32)>,
+}
+
+impl DisputeRatio {
+    pub fn new(threshold: Decimal, min_transactions: u32) -> DisputeRatio {
+        DisputeRatio {
+            threshold,
+            min_transactions,
+            counts: FxHashMap::default(),
+        }
+    }
+}
+
+impl Default for DisputeRatio {
+    fn default() -> Self {
+        DisputeRatio::new(dec!(0.2), 5)
+    }
+}
+
+impl Rule for DisputeRatio {
+    fn name(&self) -> &'static str {
+        "dispute_ratio"
+    }
+
+    fn evaluate(&mut self, event: &AccountEvent) -> Option<String> {
+        let (transactions, disputes) = self.counts.entry(event.client).or_default();
+        match event.kind {
+            AccountEventKind::DepositApplied | AccountEventKind::WithdrawalApplied => {
+                *transactions += 1
+            }
+            AccountEventKind::DisputeOpened => *disputes += 1,
+            _ => return None,
+        }
+        let ratio = Decimal::from(*disputes) / Decimal::from((*transactions).max(1));
+        (*transactions >= self.min_transactions && ratio > self.threshold)
+            .then(|| format!("{} disputes over {} transactions", disputes, transactions))
+    }
+}
+
+// a deposit or withdrawal more than `factor` times the mean amount seen so far over all clients
+pub struct AmountOutlier {
+    pub factor: Decimal,
+    pub min_samples: u64,
+    samples: u64,
+    sum: Decimal,
+}
+
+impl AmountOutlier {
+    pub fn new(factor: Decimal, min_samples: u64) -> AmountOutlier {
+        AmountOutlier {
+            factor,
+            min_samples,
+            samples: 0,
+            sum: Decimal::ZERO,
+        }
+    }
+}
+
+impl Default for AmountOutlier {
+    fn default() -> Self {
+        AmountOutlier::new(dec!(10), 100)
+    }
+}
+
+impl Rule for AmountOutlier {
+    fn name(&self) -> &'static str {
+        "amount_outlier"
+    }
+
+    fn evaluate(&mut self, event: &AccountEvent) -> Option<String> {
+        let amount = match (event.kind, event.amount) {
+            (AccountEventKind::DepositApplied | AccountEventKind::WithdrawalApplied, Some(x)) => x,
+            _ => return None,
+        };
+        let mean = (self.samples > 0).then(|| self.sum / Decimal::from(self.samples));
+        // saturating so a huge amount is still flagged instead of overflowing the sum
+        self.sum = self.sum.saturating_add(amount);
+        self.samples += 1;
+        let mean = mean.filter(|_| self.samples > self.min_samples)?;
+        (amount > mean.saturating_mul(self.factor)).then(|| {
+            format!(
+                "{} is more than {} times the mean of {}",
+                amount, self.factor, mean
+            )
+        })
+    }
+}
+
+pub fn default_rules() -> Vec<Box<dyn Rule + Send>> {
+    vec![
+        Box::new(RapidDepositWithdrawal::default()),
+        Box::new(DisputeRatio::default()),
+        Box::new(AmountOutlier::default()),
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Flag {
+    pub client: u16,
+    pub tx: u32,
+    pub rule: &'static str,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RiskReport {
+    // the first transaction of every client a rule flagged, in input order
+    pub flags: Vec<Flag>,
+    pub frozen: Vec<u16>,
+}
+
+impl RiskReport {
+    pub fn flagged_clients(&self) -> BTreeSet<u16> {
+        self.flags.iter().map(|x| x.client).collect()
+    }
+}
+
+impl fmt::Display for RiskReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for flag in &self.flags {
+            writeln!(
+                f,
+                "client {} tx {}: {}: {}",
+                flag.client, flag.tx, flag.rule, flag.reason
+            )?;
+        }
+        if !self.frozen.is_empty() {
+            let frozen: Vec<_> = self.frozen.iter().map(u16::to_string).collect();
+            writeln!(f, "frozen: {}", frozen.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+pub struct RiskEngine {
+    rules: Vec<Box<dyn Rule + Send>>,
+    auto_freeze: bool,
+    flagged: HashSet<(u16, &'static str)>,
+    report: RiskReport,
+}
+
+impl Default for RiskEngine {
+    fn default() -> Self {
+        RiskEngine::new(default_rules())
+    }
+}
+
+impl RiskEngine {
+    pub fn new(rules: Vec<Box<dyn Rule + Send>>) -> RiskEngine {
+        RiskEngine {
+            rules,
+            auto_freeze: false,
+            flagged: HashSet::new(),
+            report: RiskReport::default(),
+        }
+    }
+
+    // flagged accounts are locked right away, so they reject their later transactions
+    pub fn auto_freeze(mut self, auto_freeze: bool) -> RiskEngine {
+        self.auto_freeze = auto_freeze;
+        self
+    }
+
+    pub fn observe<A: AccountStore>(&mut self, accounts: &mut Accounts<A>, event: &AccountEvent) {
+        for rule in &mut self.rules {
+            let Some(reason) = rule.evaluate(event) else {
+                continue;
+            };
+            if !self.flagged.insert((event.client, rule.name())) {
+                continue;
+            }
+            tracing::info!(client = event.client, tx = event.tx, rule = rule.name(), %reason, "client flagged");
+            self.report.flags.push(Flag {
+                client: event.client,
+                tx: event.tx,
+                rule: rule.name(),
+                reason,
+            });
+            if self.auto_freeze
+                && !self.report.frozen.contains(&event.client)
+                && accounts.lock_account(event.client)
+            {
+                self.report.frozen.push(event.client);
+            }
+        }
+    }
+
+    pub fn report(&self) -> &RiskReport {
+        &self.report
+    }
+
+    pub fn into_report(self) -> RiskReport {
+        self.report
+    }
+}
+
+pub fn read_source_with_risk<S: TransactionSource, A: AccountStore>(
+    source: S,
+    mode: ParseMode,
+    accounts: Accounts<A>,
+    mut engine: RiskEngine,
+) -> Result<(Accounts<A>, ParseReport, RiskReport), ServiceError> {
+    let (accounts, report) = read_source_observed(source, mode, accounts, |accounts, event| {
+        engine.observe(accounts, &event);
+        Ok(())
+    })?;
+    Ok((accounts, report, engine.into_report()))
+}
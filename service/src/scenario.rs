@@ -0,0 +1,113 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{
+    compression::open_input,
+    error::ServiceError,
+    service::{
+        output_records, read_source_into, write_accounts_iter, write_rejections_to, CsvSource,
+        OutputOptions, ParseMode,
+    },
+};
+
+pub const INPUT: &str = "input.csv";
+pub const EXPECTED_ACCOUNTS: &str = "expected_accounts.csv";
+pub const EXPECTED_REJECTIONS: &str = "expected_rejections.csv";
+
+#[derive(Debug, Error)]
+pub enum ScenarioError {
+    #[error("scenario {scenario}: {source}")]
+    Service {
+        scenario: String,
+        #[source]
+        source: ServiceError,
+    },
+    #[error("scenario {scenario}: {file} differs\nexpected:\n{expected}\nactual:\n{actual}")]
+    Mismatch {
+        scenario: String,
+        file: &'static str,
+        expected: String,
+        actual: String,
+    },
+}
+
+// A scenario is a directory with an input.csv, read in lenient mode, and the expected outputs:
+// the accounts sorted by client, and the rejections in input order (a missing file means none).
+// With UPDATE_SCENARIOS=1 the expected files are rewritten from the actual outputs instead.
+pub fn run_scenario<P: AsRef<Path>>(dir: P) -> Result<(), ScenarioError> {
+    let dir = dir.as_ref();
+    let scenario = dir.file_name().map_or_else(
+        || dir.display().to_string(),
+        |x| x.to_string_lossy().into_owned(),
+    );
+    let service_error = |source| ScenarioError::Service {
+        scenario: scenario.clone(),
+        source,
+    };
+
+    let (accounts, report) = open_input(dir.join(INPUT).to_string_lossy().into_owned())
+        .and_then(|x| read_source_into(CsvSource::new(x), ParseMode::Lenient, Default::default()))
+        .map_err(service_error)?;
+    let mut records: Vec<_> = output_records(&accounts, &OutputOptions::default()).collect();
+    records.sort_by_key(|x| x.client);
+    let mut actual_accounts = Vec::new();
+    write_accounts_iter(&mut actual_accounts, records).map_err(service_error)?;
+    let mut actual_rejections = Vec::new();
+    write_rejections_to(&mut actual_rejections, &report).map_err(service_error)?;
+
+    for (file, actual) in [
+        (EXPECTED_ACCOUNTS, actual_accounts),
+        (EXPECTED_REJECTIONS, actual_rejections),
+    ] {
+        let path = dir.join(file);
+        let actual = String::from_utf8_lossy(&actual).into_owned();
+        if env::var_os("UPDATE_SCENARIOS").is_some_and(|x| x == "1") {
+            fs::write(&path, &actual).map_err(|e| service_error(e.into()))?;
+            continue;
+        }
+        let expected = match fs::read_to_string(&path) {
+            Ok(x) => x,
+            Err(_) if file == EXPECTED_REJECTIONS && !path.exists() => String::new(),
+            Err(e) => return Err(service_error(e.into())),
+        };
+        if normalize(&expected) != normalize(&actual) {
+            return Err(ScenarioError::Mismatch {
+                scenario,
+                file,
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
+
+// runs every subdirectory of `root` in name order and returns how many ran
+pub fn run_scenarios<P: AsRef<Path>>(root: P) -> Result<usize, ScenarioError> {
+    let root = root.as_ref();
+    let mut dirs: Vec<PathBuf> = fs::read_dir(root)
+        .and_then(|x| x.map(|x| x.map(|x| x.path())).collect())
+        .map_err(|e| ScenarioError::Service {
+            scenario: root.display().to_string(),
+            source: e.into(),
+        })?;
+    dirs.retain(|x| x.is_dir());
+    dirs.sort();
+    for dir in &dirs {
+        run_scenario(dir)?;
+    }
+    Ok(dirs.len())
+}
+
+// line endings and trailing whitespace don't matter
+fn normalize(text: &str) -> Vec<&str> {
+    let mut lines: Vec<_> = text.lines().map(str::trim_end).collect();
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+    lines
+}
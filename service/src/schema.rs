@@ -0,0 +1,20 @@
+use schemars::{generate::SchemaSettings, Schema};
+
+use crate::service::{InputTransactionRecord, OutputRecord};
+
+// a transaction as read from a json or ndjson input, or posted to the server; csv rows have the
+// same fields as columns
+pub fn transaction_schema() -> Schema {
+    SchemaSettings::draft2020_12()
+        .for_deserialize()
+        .into_generator()
+        .into_root_schema_for::<InputTransactionRecord>()
+}
+
+// an account as written by the json and ndjson outputs and returned by the server
+pub fn account_schema() -> Schema {
+    SchemaSettings::draft2020_12()
+        .for_serialize()
+        .into_generator()
+        .into_root_schema_for::<OutputRecord>()
+}
@@ -0,0 +1,144 @@
+use std::io::Write;
+
+use domain::domain::{AccountStore, Accounts, TransactionActionState, TransactionState};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{compression::create_output, error::ServiceError, tenants::Tenants};
+
+#[derive(Debug, Clone)]
+pub struct SettlementOptions {
+    pub batch_prefix: String,
+    // lines per batch, the last batch may have fewer
+    pub batch_size: usize,
+}
+
+impl Default for SettlementOptions {
+    fn default() -> Self {
+        SettlementOptions {
+            batch_prefix: String::from("batch"),
+            batch_size: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    // the client owes the amount
+    Debit,
+    // the amount is owed to the client
+    Credit,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SettlementLine {
+    pub batch_id: String,
+    pub tenant: Option<String>,
+    pub client: u16,
+    pub charged_back_deposits: Decimal,
+    pub charged_back_withdrawals: Decimal,
+    pub captured_holds: Decimal,
+    // always positive, `direction` says who pays
+    pub amount: Decimal,
+    pub direction: Direction,
+}
+
+// Charged back deposits and captured holds are collected from the client, a charged back
+// withdrawal is paid back to it. Only the transaction logs are read, so accounts restored
+// from a previous output settle nothing for the transactions before it.
+fn settle_accounts<A: AccountStore>(
+    accounts: &Accounts<A>,
+    tenant: Option<&str>,
+    lines: &mut Vec<SettlementLine>,
+) {
+    let mut clients: Vec<_> = accounts
+        .iter()
+        .filter_map(|(client, account)| {
+            let (mut deposits, mut withdrawals, mut holds) =
+                (Decimal::ZERO, Decimal::ZERO, Decimal::ZERO);
+            for log in account.transaction_log.values() {
+                match (&log.amount, &log.state) {
+                    (TransactionActionState::Deposit { amount }, TransactionState::Chargeback) => {
+                        deposits += amount
+                    }
+                    (
+                        TransactionActionState::Withdrawal { amount },
+                        TransactionState::Chargeback | TransactionState::Refunded,
+                    ) => withdrawals += amount,
+                    (TransactionActionState::Hold { amount }, TransactionState::Captured) => {
+                        holds += amount
+                    }
+                    _ => {}
+                }
+            }
+            let net = withdrawals - deposits - holds;
+            (!(deposits.is_zero() && withdrawals.is_zero() && holds.is_zero())).then(|| {
+                SettlementLine {
+                    batch_id: String::new(),
+                    tenant: tenant.map(str::to_string),
+                    client,
+                    charged_back_deposits: deposits,
+                    charged_back_withdrawals: withdrawals,
+                    captured_holds: holds,
+                    amount: net.abs(),
+                    direction: if net.is_sign_negative() {
+                        Direction::Debit
+                    } else {
+                        Direction::Credit
+                    },
+                }
+            })
+        })
+        .collect();
+    clients.sort_by_key(|x| x.client);
+    lines.extend(clients);
+}
+
+fn assign_batches(lines: &mut [SettlementLine], options: &SettlementOptions) {
+    for (index, line) in lines.iter_mut().enumerate() {
+        line.batch_id = format!(
+            "{}-{:04}",
+            options.batch_prefix,
+            index / options.batch_size.max(1) + 1
+        );
+    }
+}
+
+// a line for every client with something to settle, in client order
+pub fn settle<A: AccountStore>(
+    accounts: &Accounts<A>,
+    options: &SettlementOptions,
+) -> Vec<SettlementLine> {
+    let mut lines = Vec::new();
+    settle_accounts(accounts, None, &mut lines);
+    assign_batches(&mut lines, options);
+    lines
+}
+
+// in tenant, then client order; the batches run across tenants
+pub fn settle_tenants(tenants: &Tenants, options: &SettlementOptions) -> Vec<SettlementLine> {
+    let mut lines = Vec::new();
+    for (tenant, accounts) in tenants.iter() {
+        settle_accounts(accounts, Some(tenant), &mut lines);
+    }
+    assign_batches(&mut lines, options);
+    lines
+}
+
+pub fn write_settlement(file_path: String, lines: &[SettlementLine]) -> Result<(), ServiceError> {
+    write_settlement_to(create_output(file_path)?, lines)
+}
+
+pub fn write_settlement_to<W: Write>(
+    writer: W,
+    lines: &[SettlementLine],
+) -> Result<(), ServiceError> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    for line in lines {
+        wtr.serialize(line)
+            .map_err(|e| ServiceError::Serialize(e.into()))?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Mutex,
+};
+
+use domain::domain::{Accounts, Transaction, TransactionRegistry};
+use rayon::prelude::*;
+
+use crate::{
+    compression::open_input,
+    error::ServiceError,
+    service::{CsvSource, SourceError, TransactionSource},
+};
+
+const DEDUP_SHARDS: usize = 64;
+
+struct Record {
+    sequence: u64,
+    client: u16,
+    tx: u32,
+    transaction: Transaction,
+}
+
+// the first input position of every tx id, sharded by tx so workers rarely contend
+struct FirstSeen {
+    shards: Vec<Mutex<HashMap<u32, u64>>>,
+}
+
+impl FirstSeen {
+    fn new() -> FirstSeen {
+        FirstSeen {
+            shards: (0..DEDUP_SHARDS).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    fn insert(&self, tx: u32, sequence: u64) {
+        let mut shard = self.shards[tx as usize % DEDUP_SHARDS].lock().unwrap();
+        match shard.entry(tx) {
+            Entry::Occupied(mut x) => {
+                if sequence < *x.get() {
+                    x.insert(sequence);
+                }
+            }
+            Entry::Vacant(x) => {
+                x.insert(sequence);
+            }
+        }
+    }
+
+    fn is_first(&self, tx: u32, sequence: u64) -> bool {
+        self.shards[tx as usize % DEDUP_SHARDS].lock().unwrap()[&tx] == sequence
+    }
+}
+
+fn creates_tx(transaction: &Transaction) -> bool {
+    matches!(
+        transaction,
+        Transaction::Deposit { .. } | Transaction::Withdrawal { .. } | Transaction::Hold { .. }
+    )
+}
+
+pub fn read_csv_sharded(file_path: String, shards: usize) -> Result<Accounts, ServiceError> {
+    read_source_sharded(CsvSource::new(open_input(file_path)?), shards)
+}
+
+// records are parsed sequentially and partitioned by client % shards. every shard is then
+// applied on the rayon pool; a tx id is only kept at its first position in the input, so the
+// result is the same as processing the records in order, except for the sequence numbers in
+// the logs, which count per shard.
+pub fn read_source_sharded<S: TransactionSource>(
+    mut source: S,
+    shards: usize,
+) -> Result<Accounts, ServiceError> {
+    let shards = shards.max(1);
+    let mut registry = TransactionRegistry::new();
+    let mut partitions: Vec<Vec<Record>> = (0..shards).map(|_| Vec::new()).collect();
+    let mut sequence = 0;
+    while let Some(result) = source.next_record() {
+        let record = match result {
+            Ok(x) => x,
+            Err(SourceError::Row(e)) => return Err(e.into_error()),
+            Err(SourceError::Fatal(e)) => return Err(e),
+        };
+        let Some(transaction) = record.convert() else {
+            continue;
+        };
+        if let Some(key) = &record.idempotency_key {
+            if !registry.register_idempotency_key(key, record.client, record.tx, &transaction)? {
+                continue;
+            }
+        }
+        partitions[record.client as usize % shards].push(Record {
+            sequence,
+            client: record.client,
+            tx: record.tx,
+            transaction,
+        });
+        sequence += 1;
+    }
+
+    let first_seen = FirstSeen::new();
+    partitions.par_iter().for_each(|partition| {
+        for record in partition.iter().filter(|x| creates_tx(&x.transaction)) {
+            first_seen.insert(record.tx, record.sequence);
+        }
+    });
+
+    let results: Vec<Accounts> = partitions
+        .into_par_iter()
+        .map(|partition| {
+            let mut accounts = Accounts::new();
+            for record in partition {
+                if creates_tx(&record.transaction)
+                    && !first_seen.is_first(record.tx, record.sequence)
+                {
+                    continue;
+                }
+                accounts.add_transaction(record.client, record.tx, record.transaction);
+            }
+            accounts
+        })
+        .collect();
+
+    let mut accounts = Accounts::with_registry(registry);
+    for shard in results {
+        accounts
+            .merge(shard)
+            .map_err(|e| ServiceError::InvalidRecord {
+                reason: e.to_string(),
+            })?;
+    }
+    Ok(accounts)
+}
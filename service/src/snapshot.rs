@@ -0,0 +1,62 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use domain::domain::Accounts;
+
+use crate::error::ServiceError;
+
+const MAGIC: &[u8; 6] = b"TXSNAP";
+// bumped whenever the layout of the accounts changes, older snapshots are refused
+pub const SNAPSHOT_VERSION: u16 = 1;
+
+// The accounts with their transaction logs and tx id registry, as MessagePack after a header of
+// the magic bytes and the little-endian schema version. Unlike the csv outputs nothing is lost,
+// so disputes of earlier transactions still work once loaded. The policies, handlers and
+// middleware are not part of the snapshot.
+pub fn write_snapshot<W: Write>(mut writer: W, accounts: &Accounts) -> Result<(), ServiceError> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+    rmp_serde::encode::write(&mut writer, accounts)
+        .map_err(|e| ServiceError::Serialize(e.into()))?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn read_snapshot<R: Read>(mut reader: R) -> Result<Accounts, ServiceError> {
+    let mut header = [0; MAGIC.len() + 2];
+    reader.read_exact(&mut header)?;
+    if header[..MAGIC.len()] != *MAGIC {
+        return Err(ServiceError::InvalidSnapshot {
+            reason: String::from("not a snapshot"),
+        });
+    }
+    let version = u16::from_le_bytes([header[MAGIC.len()], header[MAGIC.len() + 1]]);
+    if version != SNAPSHOT_VERSION {
+        return Err(ServiceError::InvalidSnapshot {
+            reason: format!(
+                "version {} is not supported, expected {}",
+                version, SNAPSHOT_VERSION
+            ),
+        });
+    }
+    rmp_serde::from_read(reader).map_err(|e| ServiceError::InvalidSnapshot {
+        reason: e.to_string(),
+    })
+}
+
+// written to a temporary file first, like the checkpoints
+pub fn save_snapshot<P: AsRef<Path>>(path: P, accounts: &Accounts) -> Result<(), ServiceError> {
+    let temp_path = PathBuf::from(format!("{}.tmp", path.as_ref().display()));
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    write_snapshot(&mut writer, accounts)?;
+    writer.get_ref().sync_all()?;
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+pub fn load_snapshot<P: AsRef<Path>>(path: P) -> Result<Accounts, ServiceError> {
+    read_snapshot(BufReader::new(File::open(path)?))
+}
@@ -0,0 +1,204 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    mem,
+    path::PathBuf,
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use domain::domain::{AccountStore, FxHashMap, TransactionLog, UserAccount};
+use serde::{Deserialize, Serialize};
+
+use crate::error::ServiceError;
+
+static SPILL_FILES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize, Deserialize)]
+struct SpilledLog {
+    transaction_log: FxHashMap<u32, TransactionLog>,
+    pending_holds: FxHashMap<u32, u32>,
+}
+
+struct Entry {
+    account: UserAccount,
+    touched: u64,
+    resident: usize,
+    spilled: Option<(u64, usize)>,
+}
+
+// Balances always stay in memory; when the estimated size of the resident transaction logs
+// exceeds the budget, the logs of the least recently touched accounts are appended to a spill
+// file and read back the next time the account is touched. The spill file only grows and is
+// removed when the store is dropped. Like the sled store, disk failures after opening panic.
+pub struct SpillStore {
+    entries: FxHashMap<u16, Entry>,
+    recency: BTreeMap<u64, u16>,
+    clock: u64,
+    budget: usize,
+    resident: usize,
+    path: PathBuf,
+    file: Mutex<File>,
+    end: u64,
+}
+
+impl SpillStore {
+    pub fn new(budget: usize) -> Result<SpillStore, ServiceError> {
+        let path = std::env::temp_dir().join(format!(
+            "txengine-spill-{}-{}.json",
+            process::id(),
+            SPILL_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        SpillStore::with_path(path, budget)
+    }
+
+    pub fn with_path<P: Into<PathBuf>>(path: P, budget: usize) -> Result<SpillStore, ServiceError> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        Ok(SpillStore {
+            entries: FxHashMap::default(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            budget,
+            resident: 0,
+            path,
+            file: Mutex::new(file),
+            end: 0,
+        })
+    }
+
+    pub fn resident_bytes(&self) -> usize {
+        self.resident
+    }
+
+    pub fn spilled_accounts(&self) -> usize {
+        self.entries
+            .values()
+            .filter(|x| x.spilled.is_some())
+            .count()
+    }
+
+    fn touch(&mut self, client: u16) {
+        let entry = self.entries.get_mut(&client).unwrap();
+        self.recency.remove(&entry.touched);
+        self.clock += 1;
+        entry.touched = self.clock;
+        self.recency.insert(self.clock, client);
+        if let Some((offset, len)) = entry.spilled.take() {
+            let log = read_log(self.file.get_mut().unwrap(), offset, len);
+            entry.account.transaction_log = log.transaction_log;
+            entry.account.pending_holds = log.pending_holds;
+            entry.resident = log_bytes(&entry.account);
+            self.resident += entry.resident;
+        }
+    }
+
+    // the most recently touched account is never spilled, even if it alone exceeds the budget
+    fn evict(&mut self) {
+        while self.resident > self.budget && self.recency.len() > 1 {
+            let (_, client) = self.recency.pop_first().unwrap();
+            let entry = self.entries.get_mut(&client).unwrap();
+            self.resident -= entry.resident;
+            entry.resident = 0;
+            let log = SpilledLog {
+                transaction_log: mem::take(&mut entry.account.transaction_log),
+                pending_holds: mem::take(&mut entry.account.pending_holds),
+            };
+            let bytes = serde_json::to_vec(&log).expect("log is serializable");
+            let file = self.file.get_mut().unwrap();
+            file.seek(SeekFrom::Start(self.end))
+                .and_then(|_| file.write_all(&bytes))
+                .expect("spill file write");
+            entry.spilled = Some((self.end, bytes.len()));
+            self.end += bytes.len() as u64;
+        }
+    }
+}
+
+impl Drop for SpillStore {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn read_log(file: &mut File, offset: u64, len: usize) -> SpilledLog {
+    let mut buffer = vec![0; len];
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.read_exact(&mut buffer))
+        .expect("spill file read");
+    serde_json::from_slice(&buffer).expect("spilled log is valid")
+}
+
+fn log_bytes(account: &UserAccount) -> usize {
+    account.transaction_log.capacity() * (mem::size_of::<(u32, TransactionLog)>() + 1)
+        + account.pending_holds.capacity() * (mem::size_of::<(u32, u32)>() + 1)
+}
+
+impl AccountStore for SpillStore {
+    type Ref<'a> = Cow<'a, UserAccount>;
+
+    fn get(&self, client: u16) -> Option<Cow<'_, UserAccount>> {
+        let entry = self.entries.get(&client)?;
+        Some(match entry.spilled {
+            Some((offset, len)) => {
+                let log = read_log(&mut self.file.lock().unwrap(), offset, len);
+                let mut account = entry.account.clone();
+                account.transaction_log = log.transaction_log;
+                account.pending_holds = log.pending_holds;
+                Cow::Owned(account)
+            }
+            None => Cow::Borrowed(&entry.account),
+        })
+    }
+
+    fn update<R>(&mut self, client: u16, f: impl FnOnce(Option<&mut UserAccount>) -> R) -> R {
+        if !self.entries.contains_key(&client) {
+            return f(None);
+        }
+        self.touch(client);
+        let entry = self.entries.get_mut(&client).unwrap();
+        let result = f(Some(&mut entry.account));
+        let resident = log_bytes(&entry.account);
+        self.resident = self.resident - entry.resident + resident;
+        entry.resident = resident;
+        self.evict();
+        result
+    }
+
+    fn insert(&mut self, client: u16, account: UserAccount) {
+        if let Some(old) = self.entries.remove(&client) {
+            self.resident -= old.resident;
+            self.recency.remove(&old.touched);
+        }
+        self.clock += 1;
+        self.recency.insert(self.clock, client);
+        let resident = log_bytes(&account);
+        self.resident += resident;
+        self.entries.insert(
+            client,
+            Entry {
+                account,
+                touched: self.clock,
+                resident,
+                spilled: None,
+            },
+        );
+        self.evict();
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (u16, Cow<'_, UserAccount>)> {
+        self.entries
+            .keys()
+            .map(|client| (*client, self.get(*client).unwrap()))
+    }
+}
@@ -0,0 +1,159 @@
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use domain::domain::{
+    AccountStore, Accounts, TransactionActionState, TransactionLog, TransactionState, UserAccount,
+};
+use rusqlite::{params, Connection};
+use rust_decimal::Decimal;
+
+use crate::error::ServiceError;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS accounts (
+    client INTEGER PRIMARY KEY,
+    available TEXT NOT NULL,
+    held TEXT NOT NULL,
+    total TEXT NOT NULL,
+    locked INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS transactions (
+    client INTEGER NOT NULL REFERENCES accounts (client),
+    tx INTEGER NOT NULL,
+    type TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    state TEXT NOT NULL,
+    hold_remaining INTEGER,
+    PRIMARY KEY (client, tx)
+);
+";
+
+// replaces the accounts and transactions already stored in the database
+pub fn save_sqlite<P: AsRef<Path>, A: AccountStore>(
+    path: P,
+    accounts: &Accounts<A>,
+) -> Result<(), ServiceError> {
+    let mut connection = Connection::open(path)?;
+    connection.execute_batch(SCHEMA)?;
+    let transaction = connection.transaction()?;
+    transaction.execute("DELETE FROM transactions", [])?;
+    transaction.execute("DELETE FROM accounts", [])?;
+    {
+        let mut insert_account = transaction.prepare(
+            "INSERT INTO accounts (client, available, held, total, locked) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        let mut insert_transaction = transaction.prepare(
+            "INSERT INTO transactions (client, tx, type, amount, state, hold_remaining) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        for (client, account) in accounts.iter() {
+            insert_account.execute(params![
+                client,
+                account.available.to_string(),
+                account.held.to_string(),
+                (account.available + account.held).to_string(),
+                account.locked,
+            ])?;
+            for (tx, log) in &account.transaction_log {
+                let (transaction_type, amount) = match log.amount {
+                    TransactionActionState::Deposit { amount } => ("deposit", amount),
+                    TransactionActionState::Withdrawal { amount } => ("withdrawal", amount),
+                    TransactionActionState::Hold { amount } => ("hold", amount),
+                };
+                insert_transaction.execute(params![
+                    client,
+                    tx,
+                    transaction_type,
+                    amount.to_string(),
+                    log.state.to_string(),
+                    account.pending_holds.get(tx),
+                ])?;
+            }
+        }
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+// deletes the stored transactions of the client, its account row keeps the balances
+pub fn forget_client_sqlite<P: AsRef<Path>>(path: P, client: u16) -> Result<usize, ServiceError> {
+    let connection = Connection::open(path)?;
+    connection.execute_batch(SCHEMA)?;
+    Ok(connection.execute(
+        "DELETE FROM transactions WHERE client = ?1",
+        params![client],
+    )?)
+}
+
+pub fn load_sqlite<P: AsRef<Path>>(path: P) -> Result<Accounts, ServiceError> {
+    let connection = Connection::open(path)?;
+    let mut user_accounts = HashMap::new();
+    let mut statement =
+        connection.prepare("SELECT client, available, held, locked FROM accounts")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        user_accounts.insert(
+            row.get::<_, u16>(0)?,
+            UserAccount::with_balances(
+                parse_decimal(&row.get::<_, String>(1)?)?,
+                parse_decimal(&row.get::<_, String>(2)?)?,
+                row.get(3)?,
+            ),
+        );
+    }
+
+    let mut statement = connection
+        .prepare("SELECT client, tx, type, amount, state, hold_remaining FROM transactions")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let client: u16 = row.get(0)?;
+        let tx: u32 = row.get(1)?;
+        let amount = parse_decimal(&row.get::<_, String>(3)?)?;
+        let amount = match row.get::<_, String>(2)?.as_str() {
+            "deposit" => TransactionActionState::Deposit { amount },
+            "withdrawal" => TransactionActionState::Withdrawal { amount },
+            "hold" => TransactionActionState::Hold { amount },
+            x => return Err(invalid(format!("unknown transaction type {}", x))),
+        };
+        let state = parse_state(&row.get::<_, String>(4)?)?;
+        let account = user_accounts
+            .get_mut(&client)
+            .ok_or_else(|| invalid(format!("transaction {} has no account", tx)))?;
+        if let Some(remaining) = row.get::<_, Option<u32>>(5)? {
+            account.pending_holds.insert(tx, remaining);
+        }
+        account.transaction_log.insert(
+            tx,
+            TransactionLog {
+                amount,
+                state,
+                seq: 0,
+            },
+        );
+    }
+
+    let mut accounts = Accounts::new();
+    for (client, account) in user_accounts {
+        accounts.restore_account(client, account);
+    }
+    Ok(accounts)
+}
+
+fn parse_state(name: &str) -> Result<TransactionState, ServiceError> {
+    Ok(match name {
+        "resolve" => TransactionState::Resolve,
+        "dispute" => TransactionState::Dispute,
+        "chargeback" => TransactionState::Chargeback,
+        "refunded" => TransactionState::Refunded,
+        "held" => TransactionState::Held,
+        "captured" => TransactionState::Captured,
+        "released" => TransactionState::Released,
+        x => return Err(invalid(format!("unknown transaction state {}", x))),
+    })
+}
+
+fn parse_decimal(value: &str) -> Result<Decimal, ServiceError> {
+    Decimal::from_str(value).map_err(|e| invalid(e.to_string()))
+}
+
+fn invalid(reason: String) -> ServiceError {
+    ServiceError::InvalidRecord { reason }
+}
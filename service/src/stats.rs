@@ -0,0 +1,35 @@
+use domain::domain::{AccountStore, Accounts};
+
+use crate::service::{OutputRecord, RoundingConfig};
+
+#[derive(Debug, Default)]
+pub struct AccountStatistics {
+    pub accounts: usize,
+    pub locked_accounts: usize,
+    pub open_disputes: usize,
+    pub top_held: Vec<OutputRecord>,
+}
+
+// `top_held` has at most `top` accounts with held funds, the largest first
+pub fn account_statistics<A: AccountStore>(
+    accounts: &Accounts<A>,
+    top: usize,
+) -> AccountStatistics {
+    let mut statistics = AccountStatistics::default();
+    let rounding = RoundingConfig::default();
+    let mut held = Vec::new();
+    for (client, account) in accounts.iter() {
+        statistics.accounts += 1;
+        if account.locked {
+            statistics.locked_accounts += 1;
+        }
+        statistics.open_disputes += account.open_disputes();
+        if !account.held.is_zero() {
+            held.push(OutputRecord::new(client, &account, &rounding));
+        }
+    }
+    held.sort_by(|a, b| b.held.cmp(&a.held).then(a.client.cmp(&b.client)));
+    held.truncate(top);
+    statistics.top_held = held;
+    statistics
+}
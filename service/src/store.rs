@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use domain::domain::{AccountStore, UserAccount};
+
+use crate::error::ServiceError;
+
+// Accounts are kept as JSON values keyed by the big-endian client id.
+// The store API is infallible, so disk failures after opening panic.
+pub struct SledStore {
+    db: sled::Db,
+}
+
+impl SledStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SledStore, ServiceError> {
+        Ok(SledStore {
+            db: sled::open(path)?,
+        })
+    }
+
+    pub fn temporary() -> Result<SledStore, ServiceError> {
+        Ok(SledStore {
+            db: sled::Config::new().temporary(true).open()?,
+        })
+    }
+
+    pub fn flush(&self) -> Result<(), ServiceError> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn decode(value: &[u8]) -> Box<UserAccount> {
+        Box::new(serde_json::from_slice(value).expect("stored account is valid"))
+    }
+}
+
+impl AccountStore for SledStore {
+    type Ref<'a> = Box<UserAccount>;
+
+    fn get(&self, client: u16) -> Option<Box<UserAccount>> {
+        self.db
+            .get(client.to_be_bytes())
+            .expect("account store read")
+            .map(|x| SledStore::decode(&x))
+    }
+
+    fn update<R>(&mut self, client: u16, f: impl FnOnce(Option<&mut UserAccount>) -> R) -> R {
+        match self.get(client) {
+            Some(mut account) => {
+                let result = f(Some(&mut account));
+                self.insert(client, *account);
+                result
+            }
+            None => f(None),
+        }
+    }
+
+    fn insert(&mut self, client: u16, account: UserAccount) {
+        let value = serde_json::to_vec(&account).expect("account is serializable");
+        self.db
+            .insert(client.to_be_bytes(), value)
+            .expect("account store write");
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (u16, Box<UserAccount>)> {
+        self.db.iter().map(|x| {
+            let (key, value) = x.expect("account store read");
+            let client = u16::from_be_bytes([key[0], key[1]]);
+            (client, SledStore::decode(&value))
+        })
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use domain::domain::Accounts;
+
+use crate::{
+    error::ServiceError,
+    service::{
+        accept_record, ingest_record, OutputFormat, OutputOptions, ParseMode, ParseReport,
+        Stopwatch, TransactionSource,
+    },
+};
+
+// rows without a `tenant` value, from inputs that are not configured for a tenant
+pub const DEFAULT_TENANT: &str = "default";
+
+// Every tenant has its own accounts and tx id registry, so client and tx ids of different
+// tenants never collide.
+#[derive(Default)]
+pub struct Tenants {
+    tenants: BTreeMap<String, Accounts>,
+}
+
+impl Tenants {
+    pub fn new() -> Tenants {
+        Tenants::default()
+    }
+
+    pub fn get(&self, tenant: &str) -> Option<&Accounts> {
+        self.tenants.get(tenant)
+    }
+
+    // the accounts of a tenant seen for the first time are empty
+    pub fn accounts_mut(&mut self, tenant: &str) -> &mut Accounts {
+        if !self.tenants.contains_key(tenant) {
+            self.tenants.insert(tenant.to_string(), Accounts::new());
+        }
+        self.tenants.get_mut(tenant).unwrap()
+    }
+
+    pub fn insert(&mut self, tenant: String, accounts: Accounts) -> Option<Accounts> {
+        self.tenants.insert(tenant, accounts)
+    }
+
+    // in tenant order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Accounts)> {
+        self.tenants.iter().map(|(tenant, x)| (tenant.as_str(), x))
+    }
+
+    pub fn len(&self) -> usize {
+        self.tenants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tenants.is_empty()
+    }
+}
+
+// With a `tenant` every row of the source belongs to it; otherwise the `tenant` column of
+// each row decides, falling back to `DEFAULT_TENANT`.
+pub fn read_source_by_tenant<S: TransactionSource>(
+    mut source: S,
+    mode: ParseMode,
+    mut tenants: Tenants,
+    tenant: Option<&str>,
+) -> Result<(Tenants, ParseReport), ServiceError> {
+    let _span = tracing::info_span!("ingest", ?mode, tenant).entered();
+    let started = Stopwatch::start();
+    let mut report = ParseReport::default();
+    while let Some(result) = source.next_record() {
+        if let Some(record) = accept_record(result, mode, &mut report)? {
+            let accounts = tenants.accounts_mut(
+                tenant
+                    .or(record.tenant.as_deref())
+                    .unwrap_or(DEFAULT_TENANT),
+            );
+            let line_number = source.line_number();
+            ingest_record(
+                accounts,
+                record,
+                line_number,
+                mode,
+                &mut report,
+                &mut |_, _| Ok(()),
+            )?;
+        }
+    }
+    report.summary.elapsed = started.elapsed();
+    Ok((tenants, report))
+}
+
+// tenant ids are used as file names, so only ascii letters, digits, `-`, `_` and `.` are allowed
+pub fn tenant_output_path(
+    dir: &Path,
+    tenant: &str,
+    format: &OutputFormat,
+) -> Result<PathBuf, ServiceError> {
+    let valid = !tenant.is_empty()
+        && !tenant.starts_with('.')
+        && tenant
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || matches!(x, '-' | '_' | '.'));
+    if !valid {
+        return Err(ServiceError::InvalidRecord {
+            reason: format!("tenant {:?} can't be used as a file name", tenant),
+        });
+    }
+    Ok(dir.join(format!("{}.{}", tenant, format.extension())))
+}
+
+// writes `DIR/TENANT.EXT` for every tenant and returns the written paths in tenant order
+pub fn write_tenant_outputs<P: AsRef<Path>>(
+    dir: P,
+    tenants: &Tenants,
+    format: &OutputFormat,
+    options: &OutputOptions,
+) -> Result<Vec<PathBuf>, ServiceError> {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir)?;
+    let writer = format.writer();
+    let mut paths = Vec::with_capacity(tenants.len());
+    for (tenant, accounts) in tenants.iter() {
+        let path = tenant_output_path(dir, tenant, format)?;
+        let mut output = BufWriter::new(File::create(&path)?);
+        writer.write_with_options(&mut output, accounts, options)?;
+        output.flush()?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
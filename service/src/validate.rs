@@ -0,0 +1,174 @@
+use std::{collections::HashMap, io::Read};
+
+use domain::domain::Transaction;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    error::ServiceError,
+    service::{CsvSource, InputTransactionRecord, SourceError, TransactionSource, CSV_COLUMNS},
+};
+
+pub const MAX_DECIMAL_PLACES: u32 = 4;
+
+const REQUIRED_COLUMNS: [&str; 4] = ["type", "client", "tx", "amount"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    MissingColumn,
+    UnknownColumn,
+    MalformedRow,
+    UnknownType,
+    MissingField,
+    NonPositiveAmount,
+    ExcessPrecision,
+    DuplicateTx,
+    UnknownTxReference,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationIssue {
+    pub line: u64,
+    pub kind: IssueKind,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationReport {
+    pub rows: u64,
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    fn push(&mut self, line: u64, kind: IssueKind, message: String) {
+        self.issues.push(ValidationIssue {
+            line,
+            kind,
+            message,
+        });
+    }
+}
+
+// only reads the input, references are checked against the transactions seen earlier in the file
+pub fn validate<R: Read>(mut source: CsvSource<R>) -> Result<ValidationReport, ServiceError> {
+    let mut report = ValidationReport::default();
+    let headers = source.headers()?;
+    for column in REQUIRED_COLUMNS {
+        if !headers.iter().any(|x| x == column) {
+            report.push(
+                1,
+                IssueKind::MissingColumn,
+                format!("missing column {}", column),
+            );
+        }
+    }
+    for column in headers.iter() {
+        if !CSV_COLUMNS.contains(&column) {
+            report.push(
+                1,
+                IssueKind::UnknownColumn,
+                format!("unknown column {}", column),
+            );
+        }
+    }
+
+    // tx id => client of the deposits, withdrawals and holds
+    let mut transactions: HashMap<u32, u16> = HashMap::new();
+    while let Some(result) = source.next_record() {
+        report.rows += 1;
+        let record = match result {
+            Ok(x) => x,
+            Err(SourceError::Row(e)) => {
+                report.push(e.line_number, IssueKind::MalformedRow, e.to_string());
+                continue;
+            }
+            Err(SourceError::Fatal(e)) => return Err(e),
+        };
+        validate_record(&mut report, &mut transactions, source.record_line(), record);
+    }
+    Ok(report)
+}
+
+fn validate_record(
+    report: &mut ValidationReport,
+    transactions: &mut HashMap<u32, u16>,
+    line: u64,
+    record: InputTransactionRecord,
+) {
+    if !record.is_known_type() {
+        report.push(
+            line,
+            IssueKind::UnknownType,
+            format!("unknown transaction type {}", record.transaction_type),
+        );
+        return;
+    }
+    if let Some(amount) = record.amount {
+        check_amount(report, line, amount);
+    }
+    let (client, tx) = (record.client, record.tx);
+    match record.convert() {
+        None => report.push(
+            line,
+            IssueKind::MissingField,
+            format!(
+                "{} {} has no {}",
+                record.transaction_type,
+                tx,
+                if record.amount.is_none() {
+                    "amount"
+                } else {
+                    "expires_after"
+                }
+            ),
+        ),
+        Some(
+            Transaction::Deposit { .. } | Transaction::Withdrawal { .. } | Transaction::Hold { .. },
+        ) => {
+            if transactions.insert(tx, client).is_some() && record.idempotency_key.is_none() {
+                report.push(
+                    line,
+                    IssueKind::DuplicateTx,
+                    format!("tx {} is used more than once", tx),
+                );
+            }
+        }
+        Some(_) => {
+            if transactions.get(&tx) != Some(&client) {
+                report.push(
+                    line,
+                    IssueKind::UnknownTxReference,
+                    format!(
+                        "{} refers to tx {}, which client {} does not have",
+                        record.transaction_type, tx, client
+                    ),
+                );
+            }
+        }
+    }
+}
+
+fn check_amount(report: &mut ValidationReport, line: u64, amount: Decimal) {
+    if amount <= Decimal::ZERO {
+        report.push(
+            line,
+            IssueKind::NonPositiveAmount,
+            format!("amount {} is not positive", amount),
+        );
+    }
+    if amount.scale() > MAX_DECIMAL_PLACES {
+        report.push(
+            line,
+            IssueKind::ExcessPrecision,
+            format!(
+                "amount {} has more than {} decimal places",
+                amount, MAX_DECIMAL_PLACES
+            ),
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use domain::domain::{AccountStore, Accounts};
+use serde::Serialize;
+
+use crate::{
+    admin::AdminOperation,
+    error::ServiceError,
+    service::{
+        accept_record, ingest_record, InputTransactionRecord, NdjsonSource, ParseMode, ParseReport,
+        SourceError, TransactionSource,
+    },
+};
+
+const COMMIT_MARKER: &str = "#commit";
+
+// Records are appended as ndjson lines, with the admin operations between them; everything
+// before the last commit marker is considered applied to a persisted state and is not replayed.
+pub struct Wal {
+    writer: BufWriter<File>,
+    sync_every: usize,
+    pending: usize,
+}
+
+impl Wal {
+    pub fn open<P: AsRef<Path>>(path: P, sync_every: usize) -> Result<Wal, ServiceError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Wal {
+            writer: BufWriter::new(file),
+            sync_every: sync_every.max(1),
+            pending: 0,
+        })
+    }
+
+    pub fn append(&mut self, record: &InputTransactionRecord) -> Result<(), ServiceError> {
+        self.append_entry(record)
+    }
+
+    pub fn append_admin(&mut self, operation: &AdminOperation) -> Result<(), ServiceError> {
+        self.append_entry(operation)
+    }
+
+    fn append_entry(&mut self, entry: &impl Serialize) -> Result<(), ServiceError> {
+        serde_json::to_writer(&mut self.writer, entry)
+            .map_err(|e| ServiceError::Serialize(e.into()))?;
+        self.writer.write_all(b"\n")?;
+        self.pending += 1;
+        if self.pending >= self.sync_every {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    pub fn commit(&mut self) -> Result<(), ServiceError> {
+        writeln!(self.writer, "{}", COMMIT_MARKER)?;
+        self.sync()
+    }
+
+    pub fn sync(&mut self) -> Result<(), ServiceError> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()?;
+        self.pending = 0;
+        Ok(())
+    }
+}
+
+pub struct WalSource<'a, S> {
+    source: S,
+    wal: &'a mut Wal,
+}
+
+impl<'a, S: TransactionSource> WalSource<'a, S> {
+    pub fn new(source: S, wal: &'a mut Wal) -> Self {
+        WalSource { source, wal }
+    }
+}
+
+impl<S: TransactionSource> TransactionSource for WalSource<'_, S> {
+    fn next_record(&mut self) -> Option<Result<InputTransactionRecord, SourceError>> {
+        let record = match self.source.next_record() {
+            Some(Ok(record)) => record,
+            Some(Err(e)) => return Some(Err(e)),
+            None => return self.wal.sync().err().map(|e| Err(SourceError::Fatal(e))),
+        };
+        Some(
+            self.wal
+                .append(&record)
+                .map(|_| record)
+                .map_err(SourceError::Fatal),
+        )
+    }
+
+    fn line_number(&self) -> u64 {
+        self.source.line_number()
+    }
+}
+
+pub fn recover<P: AsRef<Path>>(wal_path: P) -> Result<Accounts, ServiceError> {
+    recover_into(wal_path, Accounts::new())
+}
+
+pub fn recover_into<P: AsRef<Path>, A: AccountStore>(
+    wal_path: P,
+    accounts: Accounts<A>,
+) -> Result<Accounts<A>, ServiceError> {
+    let entries = read_entries(wal_path, true)?;
+    apply_entries(entries, accounts).map(|(accounts, _)| accounts)
+}
+
+pub fn replay<P: AsRef<Path>>(wal_path: P) -> Result<Accounts, ServiceError> {
+    replay_into(wal_path, Accounts::new()).map(|(accounts, _)| accounts)
+}
+
+// Replays every entry from the start of the log, commits included, so the accounts end up as at
+// the end of the logged runs when starting from the same state. Malformed rows are not logged,
+// rejected transactions are, so the replay reports the same rejections. Records the engine
+// refused when they were logged, e.g. for a reused idempotency key, are logged too; they are
+// refused again and reported as row errors.
+pub fn replay_into<P: AsRef<Path>, A: AccountStore>(
+    wal_path: P,
+    accounts: Accounts<A>,
+) -> Result<(Accounts<A>, ParseReport), ServiceError> {
+    let entries = read_entries(wal_path, false)?;
+    apply_entries(entries, accounts)
+}
+
+enum Entry {
+    // consecutive transaction records, as ndjson
+    Records(String),
+    Admin(AdminOperation),
+}
+
+fn admin_operation(line: &str) -> Option<AdminOperation> {
+    serde_json::from_str(line).ok()
+}
+
+fn apply_entries<A: AccountStore>(
+    entries: Vec<Entry>,
+    mut accounts: Accounts<A>,
+) -> Result<(Accounts<A>, ParseReport), ServiceError> {
+    let mut report = ParseReport::default();
+    for entry in entries {
+        match entry {
+            // a record that can't be read stops the replay, a refused one doesn't
+            Entry::Records(records) => {
+                let mut source = NdjsonSource::new(records.as_bytes());
+                while let Some(result) = source.next_record() {
+                    if let Some(record) = accept_record(result, ParseMode::Strict, &mut report)? {
+                        ingest_record(
+                            &mut accounts,
+                            record,
+                            source.line_number(),
+                            ParseMode::Lenient,
+                            &mut report,
+                            &mut |_, _| Ok(()),
+                        )?;
+                    }
+                }
+            }
+            Entry::Admin(operation) => {
+                operation.apply(&mut accounts);
+            }
+        }
+    }
+    Ok((accounts, report))
+}
+
+// Rewrites the log without the records of the client and returns how many were removed, so
+// replaying it no longer brings the client back. The log must not be open for appending.
+pub fn forget_client<P: AsRef<Path>>(wal_path: P, client: u16) -> Result<usize, ServiceError> {
+    let path = wal_path.as_ref();
+    let temp_path = PathBuf::from(format!("{}.tmp", path.display()));
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    let mut removed = 0;
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let logged_client = match admin_operation(&line) {
+            Some(x) => x.client(),
+            None => serde_json::from_str::<InputTransactionRecord>(&line)
+                .ok()
+                .map(|x| x.client),
+        };
+        if logged_client == Some(client) {
+            removed += 1;
+        } else {
+            writeln!(writer, "{}", line)?;
+        }
+    }
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    fs::rename(temp_path, path)?;
+    Ok(removed)
+}
+
+// a torn last line from a crash during append is dropped
+fn read_entries<P: AsRef<Path>>(
+    wal_path: P,
+    after_last_commit: bool,
+) -> Result<Vec<Entry>, ServiceError> {
+    let mut lines = Vec::new();
+    for line in BufReader::new(File::open(wal_path)?).lines() {
+        let line = line?;
+        if line == COMMIT_MARKER {
+            if after_last_commit {
+                lines.clear();
+            }
+        } else {
+            lines.push(line);
+        }
+    }
+    if lines.last().is_some_and(|x| {
+        admin_operation(x).is_none() && serde_json::from_str::<InputTransactionRecord>(x).is_err()
+    }) {
+        lines.pop();
+    }
+    let mut entries = Vec::new();
+    for line in lines {
+        match (admin_operation(&line), entries.last_mut()) {
+            (Some(operation), _) => entries.push(Entry::Admin(operation)),
+            (None, Some(Entry::Records(records))) => {
+                records.push('\n');
+                records.push_str(&line);
+            }
+            (None, _) => entries.push(Entry::Records(line)),
+        }
+    }
+    Ok(entries)
+}
@@ -0,0 +1,121 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use domain::domain::Accounts;
+
+use crate::{
+    compression::open_input,
+    error::ServiceError,
+    service::{read_source_into, CsvSource, ParseMode, ParseReport},
+};
+
+const EXTENSIONS: [&str; 3] = [".csv", ".csv.gz", ".csv.zst"];
+
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    pub dir: PathBuf,
+    pub archive_dir: PathBuf,
+    pub failed_dir: PathBuf,
+    pub poll_interval: Duration,
+    pub snapshot_interval: Duration,
+}
+
+impl WatchOptions {
+    pub fn new<P: AsRef<Path>>(dir: P) -> WatchOptions {
+        let dir = dir.as_ref().to_path_buf();
+        WatchOptions {
+            archive_dir: dir.join("archive"),
+            failed_dir: dir.join("failed"),
+            dir,
+            poll_interval: Duration::from_secs(1),
+            snapshot_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ProcessedFile {
+    // where the file was moved to
+    pub path: PathBuf,
+    pub result: Result<ParseReport, ServiceError>,
+}
+
+// Files are processed in name order, so they should appear under their final name at once
+// (written elsewhere and renamed). A file that fails is moved to `failed_dir` and none of its
+// transactions are applied.
+pub fn process_new_files(
+    options: &WatchOptions,
+    mode: ParseMode,
+    mut accounts: Accounts,
+) -> Result<(Accounts, Vec<ProcessedFile>), ServiceError> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(&options.dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_file() && EXTENSIONS.iter().any(|x| name.ends_with(x)) {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+
+    let mut processed = Vec::new();
+    for path in paths {
+        let _span = tracing::info_span!("watch_file", path = %path.display()).entered();
+        let previous = accounts.clone();
+        let result = open_input(&path)
+            .and_then(|input| read_source_into(CsvSource::new(input), mode, accounts));
+        let (target_dir, result) = match result {
+            Ok((result, report)) => {
+                accounts = result;
+                (&options.archive_dir, Ok(report))
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "file failed, state rolled back");
+                accounts = previous;
+                (&options.failed_dir, Err(e))
+            }
+        };
+        fs::create_dir_all(target_dir)?;
+        let target = target_dir.join(path.file_name().unwrap());
+        fs::rename(&path, &target)?;
+        processed.push(ProcessedFile {
+            path: target,
+            result,
+        });
+    }
+    Ok((accounts, processed))
+}
+
+pub fn watch<F: FnMut(&Accounts), G: FnMut(&ProcessedFile)>(
+    options: &WatchOptions,
+    mode: ParseMode,
+    mut accounts: Accounts,
+    running: &AtomicBool,
+    mut on_snapshot: F,
+    mut on_file: G,
+) -> Result<Accounts, ServiceError> {
+    let mut last_snapshot = Instant::now();
+    let mut changed = false;
+    loop {
+        let (result, processed) = process_new_files(options, mode, accounts)?;
+        accounts = result;
+        processed.iter().for_each(&mut on_file);
+        changed |= !processed.is_empty();
+        if changed && last_snapshot.elapsed() >= options.snapshot_interval {
+            on_snapshot(&accounts);
+            last_snapshot = Instant::now();
+            changed = false;
+        }
+        if !running.load(Ordering::Relaxed) {
+            break;
+        }
+        thread::sleep(options.poll_interval);
+    }
+    on_snapshot(&accounts);
+    Ok(accounts)
+}
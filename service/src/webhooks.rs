@@ -0,0 +1,127 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+use serde::Serialize;
+use ureq::Agent;
+
+use crate::{
+    error::ServiceError,
+    events::{AccountEvent, AccountEventKind},
+};
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub urls: Vec<String>,
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub timeout: Duration,
+    pub dead_letter_path: PathBuf,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            urls: Vec::new(),
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            timeout: Duration::from_secs(10),
+            dead_letter_path: PathBuf::from("webhooks.dead-letter.jsonl"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DeadLetter<'a> {
+    url: &'a str,
+    attempts: u32,
+    error: String,
+    event: &'a AccountEvent,
+}
+
+pub struct WebhookNotifier {
+    config: WebhookConfig,
+    agent: Agent,
+    dead_letters: Option<BufWriter<File>>,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: WebhookConfig) -> WebhookNotifier {
+        let agent = Agent::config_builder()
+            .timeout_global(Some(config.timeout))
+            .http_status_as_error(true)
+            .build()
+            .into();
+        WebhookNotifier {
+            config,
+            agent,
+            dead_letters: None,
+        }
+    }
+
+    pub fn is_notified(event: &AccountEvent) -> bool {
+        matches!(
+            event.kind,
+            AccountEventKind::ChargebackApplied | AccountEventKind::AccountLocked
+        )
+    }
+
+    // other events are ignored; an error is only returned when the dead-letter log cannot be written
+    pub fn notify(&mut self, event: &AccountEvent) -> Result<(), ServiceError> {
+        if !WebhookNotifier::is_notified(event) {
+            return Ok(());
+        }
+        let payload =
+            serde_json::to_string(event).map_err(|e| ServiceError::Serialize(e.into()))?;
+        for index in 0..self.config.urls.len() {
+            if let Err(error) = self.deliver(&self.config.urls[index], &payload) {
+                let dead_letter = DeadLetter {
+                    url: &self.config.urls[index],
+                    attempts: self.config.max_attempts.max(1),
+                    error: error.to_string(),
+                    event,
+                };
+                let line = serde_json::to_string(&dead_letter)
+                    .map_err(|e| ServiceError::Serialize(e.into()))?;
+                let writer = match &mut self.dead_letters {
+                    Some(writer) => writer,
+                    None => self.dead_letters.insert(BufWriter::new(
+                        OpenOptions::new()
+                            .create(true)
+                            .append(true)
+                            .open(&self.config.dead_letter_path)?,
+                    )),
+                };
+                writeln!(writer, "{}", line)?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    // the wait doubles after every failed attempt
+    fn deliver(&self, url: &str, payload: &str) -> Result<(), ureq::Error> {
+        let mut backoff = self.config.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .agent
+                .post(url)
+                .header("Content-Type", "application/json")
+                .send(payload);
+            match result {
+                Ok(_) => return Ok(()),
+                Err(e) if attempt >= self.config.max_attempts => return Err(e),
+                Err(_) => {
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
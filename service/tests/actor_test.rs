@@ -0,0 +1,99 @@
+#![cfg(feature = "tokio")]
+
+use domain::domain::{Accounts, RejectionReason, Transaction, TransactionOutcome};
+use rust_decimal_macros::dec;
+use service::{actor::AccountsActor, service::TransactionRecord};
+
+fn record(client: u16, tx: u32, transaction: Transaction) -> TransactionRecord {
+    TransactionRecord {
+        client,
+        tx,
+        transaction,
+        idempotency_key: None,
+    }
+}
+
+#[tokio::test]
+async fn actor_should_apply_commands_in_the_order_they_were_sent() {
+    let (actor, handle) = AccountsActor::new(Accounts::new(), 4);
+    let actor = tokio::spawn(actor.run());
+
+    let outcomes = vec![
+        handle
+            .apply(record(1, 1, Transaction::Deposit { amount: dec!(2.0) }))
+            .await
+            .unwrap(),
+        handle
+            .apply(record(1, 2, Transaction::Withdrawal { amount: dec!(3.0) }))
+            .await
+            .unwrap(),
+        handle
+            .apply(record(1, 1, Transaction::Dispute))
+            .await
+            .unwrap(),
+    ];
+    assert_eq!(
+        outcomes,
+        vec![
+            TransactionOutcome::Applied,
+            TransactionOutcome::Rejected(RejectionReason::InsufficientFunds),
+            TransactionOutcome::Applied,
+        ]
+    );
+    assert_eq!(
+        handle.get_account(1).await.unwrap().unwrap().held,
+        dec!(2.0)
+    );
+    assert!(handle.get_account(2).await.unwrap().is_none());
+    assert_eq!(
+        handle
+            .snapshot()
+            .await
+            .unwrap()
+            .get_user_account(1)
+            .unwrap()
+            .available,
+        dec!(0)
+    );
+
+    drop(handle);
+    let accounts = actor.await.unwrap();
+    assert_eq!(accounts.get_user_account(1).unwrap().held, dec!(2.0));
+}
+
+#[tokio::test]
+async fn handles_should_share_one_actor_and_fail_once_it_stopped() {
+    let (actor, handle) = AccountsActor::new(Accounts::new(), 1);
+    let others: Vec<_> = (0..4).map(|_| handle.clone()).collect();
+    let actor = tokio::spawn(actor.run());
+
+    let tasks: Vec<_> = others
+        .into_iter()
+        .enumerate()
+        .map(|(i, handle)| {
+            tokio::spawn(async move {
+                handle
+                    .apply(record(
+                        1,
+                        i as u32,
+                        Transaction::Deposit { amount: dec!(1.0) },
+                    ))
+                    .await
+            })
+        })
+        .collect();
+    for task in tasks {
+        assert_eq!(task.await.unwrap().unwrap(), TransactionOutcome::Applied);
+    }
+    assert_eq!(
+        handle.get_account(1).await.unwrap().unwrap().available,
+        dec!(4.0)
+    );
+
+    actor.abort();
+    let _ = actor.await;
+    assert!(matches!(
+        handle.get_account(1).await,
+        Err(service::service::ServiceError::ActorStopped)
+    ));
+}
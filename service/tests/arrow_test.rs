@@ -0,0 +1,89 @@
+#![cfg(feature = "arrow")]
+
+use std::{fs::File, path::PathBuf, sync::Arc};
+
+use arrow_array::{
+    cast::AsArray,
+    types::{Decimal128Type, UInt16Type},
+    Array, Float64Array, Int32Array, RecordBatch, StringArray,
+};
+use parquet::arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter};
+use rust_decimal_macros::dec;
+
+fn temp_path(name: &str) -> String {
+    let mut path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    path.push(name);
+    path.into_os_string().into_string().unwrap()
+}
+
+#[test]
+fn transactions_should_be_read_from_parquet() {
+    let batch = RecordBatch::try_from_iter(vec![
+        (
+            "type",
+            Arc::new(StringArray::from(vec!["deposit", "withdrawal", "dispute"])) as Arc<dyn Array>,
+        ),
+        ("client", Arc::new(Int32Array::from(vec![1, 1, 1]))),
+        ("tx", Arc::new(Int32Array::from(vec![1, 2, 1]))),
+        (
+            "amount",
+            Arc::new(Float64Array::from(vec![Some(2.5), Some(1.0), None])),
+        ),
+    ])
+    .unwrap();
+    let input_path = temp_path("transactions.parquet");
+    let mut writer =
+        ArrowWriter::try_new(File::create(&input_path).unwrap(), batch.schema(), None).unwrap();
+    writer.write(&batch).unwrap();
+    writer.close().unwrap();
+
+    let (result, summary) = service::arrow::read_parquet(input_path).unwrap();
+    let account = result.get_user_account(1).unwrap();
+    assert_eq!(account.available, dec!(-1.0));
+    assert_eq!(account.held, dec!(2.5));
+    assert_eq!(summary.applied, 3);
+}
+
+#[test]
+fn accounts_should_be_written_to_parquet() {
+    let input =
+        "type, client, tx, amount\ndeposit, 1, 1, 2.5\ndeposit, 1, 2, 1.0\ndispute, 1, 1,\n";
+    let accounts = service::service::read_transactions(input.as_bytes()).unwrap();
+    let output_path = temp_path("accounts.parquet");
+    service::arrow::write_parquet(output_path.clone(), &accounts).unwrap();
+
+    let batches: Vec<RecordBatch> =
+        ParquetRecordBatchReaderBuilder::try_new(File::open(output_path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+    let batch = &batches[0];
+    let column = |name: &str| {
+        arrow_cast::display::array_value_to_string(batch.column_by_name(name).unwrap(), 0).unwrap()
+    };
+    assert_eq!(batch.num_rows(), 1);
+    assert_eq!(column("client"), "1");
+    assert_eq!(column("available"), "1.0000");
+    assert_eq!(column("held"), "2.5000");
+    assert_eq!(column("total"), "3.5000");
+    assert_eq!(column("locked"), "false");
+}
+
+#[test]
+fn accounts_should_be_exported_as_record_batch() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 2.5\ndeposit, 2, 2, 1.0\ndispute, 2, 2,\nchargeback, 2, 2,\n";
+    let accounts = service::service::read_transactions(input.as_bytes()).unwrap();
+    let batch = service::service::to_record_batch(&accounts);
+
+    assert_eq!(batch.schema().as_ref(), &service::arrow::accounts_schema());
+    let clients = batch.column(0).as_primitive::<UInt16Type>();
+    let locked = batch.column(4).as_boolean();
+    let totals = batch.column(3).as_primitive::<Decimal128Type>();
+    let mut rows: Vec<_> = (0..batch.num_rows())
+        .map(|i| (clients.value(i), totals.value(i), locked.value(i)))
+        .collect();
+    rows.sort();
+    assert_eq!(rows, vec![(1, 25000, false), (2, 0, true)]);
+}
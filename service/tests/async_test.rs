@@ -0,0 +1,37 @@
+#![cfg(feature = "tokio")]
+
+use domain::domain::{Accounts, Transaction};
+use rust_decimal_macros::dec;
+use service::service::TransactionRecord;
+
+#[tokio::test]
+async fn transactions_should_be_read_from_async_reader() {
+    let input =
+        "type, client, tx, amount\ndeposit, 1, 1, 2.0\nwithdrawal, 1, 2, 0.5\nunknown, 1, 3, 1.0\n";
+    let result = service::async_io::read_transactions_async(input.as_bytes())
+        .await
+        .unwrap();
+    assert_eq!(result.get_user_account(1).unwrap().available, dec!(1.5));
+}
+
+#[tokio::test]
+async fn stream_of_records_should_be_applied_to_accounts() {
+    let records = futures_util::stream::iter(vec![
+        TransactionRecord {
+            client: 1,
+            tx: 1,
+            transaction: Transaction::Deposit { amount: dec!(2.0) },
+            idempotency_key: None,
+        },
+        TransactionRecord {
+            client: 1,
+            tx: 1,
+            transaction: Transaction::Dispute,
+            idempotency_key: Some(String::from("a")),
+        },
+    ]);
+    let result = service::async_io::apply_stream(records, Accounts::new())
+        .await
+        .unwrap();
+    assert_eq!(result.get_user_account(1).unwrap().held, dec!(2.0));
+}
@@ -0,0 +1,107 @@
+#![cfg(all(feature = "avro", feature = "protobuf"))]
+
+use apache_avro::{types::Value, writer::datum::GenericDatumWriter, Writer};
+use prost::Message;
+use rust_decimal_macros::dec;
+use service::codecs::{avro, protobuf};
+
+fn avro_transaction(transaction_type: &str, client: i32, tx: i64, amount: Option<&str>) -> Value {
+    Value::Record(vec![
+        ("type".into(), Value::String(transaction_type.into())),
+        ("client".into(), Value::Int(client)),
+        ("tx".into(), Value::Long(tx)),
+        (
+            "amount".into(),
+            match amount {
+                Some(x) => Value::Union(1, Box::new(Value::String(x.into()))),
+                None => Value::Union(0, Box::new(Value::Null)),
+            },
+        ),
+        (
+            "expires_after".into(),
+            Value::Union(0, Box::new(Value::Null)),
+        ),
+        (
+            "idempotency_key".into(),
+            Value::Union(0, Box::new(Value::Null)),
+        ),
+        ("tenant".into(), Value::Union(0, Box::new(Value::Null))),
+    ])
+}
+
+#[test]
+fn avro_datum_should_be_decoded_into_record() {
+    let bytes = GenericDatumWriter::builder(&avro::SCHEMA)
+        .build()
+        .unwrap()
+        .write_value_to_vec(avro_transaction("deposit", 1, 7, Some("1.5")))
+        .unwrap();
+    let record = avro::decode(&bytes).unwrap();
+    assert_eq!(record.transaction_type, "deposit");
+    assert_eq!(record.client, 1);
+    assert_eq!(record.tx, 7);
+    assert_eq!(record.amount, Some(dec!(1.5)));
+}
+
+#[test]
+fn avro_container_should_be_read_as_transaction_source() {
+    let mut writer = Writer::new(&avro::SCHEMA, Vec::new()).unwrap();
+    writer
+        .append_value(avro_transaction("deposit", 1, 1, Some("2.0")))
+        .unwrap();
+    writer
+        .append_value(avro_transaction("withdrawal", 1, 2, Some("0.5")))
+        .unwrap();
+    writer
+        .append_value(avro_transaction("dispute", 1, 1, None))
+        .unwrap();
+    let bytes = writer.into_inner().unwrap();
+
+    let result =
+        service::service::read_source(avro::AvroSource::new(bytes.as_slice()).unwrap()).unwrap();
+    let account = result.get_user_account(1).unwrap();
+    assert_eq!(account.available, dec!(-0.5));
+    assert_eq!(account.held, dec!(2.0));
+}
+
+#[test]
+fn length_delimited_protobuf_should_be_read_as_transaction_source() {
+    let mut bytes = Vec::new();
+    for (transaction_type, tx, amount) in
+        [("deposit", 1, Some("2.0")), ("withdrawal", 2, Some("0.5"))]
+    {
+        protobuf::TransactionMessage {
+            r#type: transaction_type.into(),
+            client: 1,
+            tx,
+            amount: amount.map(String::from),
+            expires_after: None,
+            idempotency_key: None,
+            tenant: None,
+        }
+        .encode_length_delimited(&mut bytes)
+        .unwrap();
+    }
+
+    let result =
+        service::service::read_source(protobuf::ProtobufSource::new(bytes.as_slice())).unwrap();
+    assert_eq!(result.get_user_account(1).unwrap().available, dec!(1.5));
+}
+
+#[test]
+fn protobuf_client_out_of_range_should_be_rejected() {
+    let bytes = protobuf::TransactionMessage {
+        r#type: "deposit".into(),
+        client: 70000,
+        tx: 1,
+        amount: Some("1.0".into()),
+        expires_after: None,
+        idempotency_key: None,
+        tenant: None,
+    }
+    .encode_to_vec();
+    assert_eq!(
+        protobuf::decode(&bytes).unwrap_err().to_string(),
+        "invalid record: client 70000 is out of range"
+    );
+}
@@ -1,32 +1,1717 @@
+use rust_decimal_macros::dec;
 use std::path::PathBuf;
 #[test]
-fn test_data1_should_be_deserialized_and_serialized_properly() {
+fn test_data3_should_fail_if_idempotency_key_is_reused_with_different_payload() {
     let mut file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    file_path.push("tests/resources/testData1.csv");
+    file_path.push("tests/resources/testData3.csv");
 
     let path_string = file_path.into_os_string().into_string().unwrap();
-    let result = service::service::read_csv(path_string).unwrap();
-    println!("{:?}", result.get_user_account(1));
-    println!("{:?}", result.get_user_account(2));
-
-    let mut w_file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    w_file_path.push("tests/resources/testDataOutput1.csv");
-    let w_path_string = w_file_path.into_os_string().into_string().unwrap();
-    service::service::write_csv(w_path_string, &result).unwrap();
+    let result = service::service::read_csv(path_string);
+    assert_eq!(
+        result.err().map(|e| e.to_string()),
+        Some(String::from(
+            "line 6: invalid record: idempotency key a is already used by a different transaction"
+        ))
+    );
 }
 
 #[test]
-fn test_data2_should_be_deserialized_and_serialized_properly() {
+fn test_data3_should_report_the_reused_idempotency_key_and_continue_in_lenient_mode() {
     let mut file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    file_path.push("tests/resources/testData2.csv");
+    file_path.push("tests/resources/testData3.csv");
 
     let path_string = file_path.into_os_string().into_string().unwrap();
-    let result = service::service::read_csv(path_string).unwrap();
-    println!("{:?}", result.get_user_account(1));
-    println!("{:?}", result.get_user_account(2));
-
-    let mut w_file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    w_file_path.push("tests/resources/testDataOutput2.csv");
-    let w_path_string = w_file_path.into_os_string().into_string().unwrap();
-    service::service::write_csv(w_path_string, &result).unwrap();
+    let (accounts, report) =
+        service::service::read_csv_with_mode(path_string, service::service::ParseMode::Lenient)
+            .unwrap();
+    assert_eq!(accounts.get_user_account(1).unwrap().held, dec!(1.0));
+    assert_eq!(report.summary.malformed_rows, 1);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].line_number, 6);
+    assert_eq!(report.errors[0].raw_row, "deposit,1,1,2,,a");
+    assert_eq!(
+        report.errors[0].to_string(),
+        "invalid record: idempotency key a is already used by a different transaction"
+    );
+}
+
+#[test]
+fn transactions_should_be_read_from_in_memory_buffer() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0\nwithdrawal, 1, 2, 0.4\n";
+    let result = service::service::read_transactions(input.as_bytes()).unwrap();
+    let account = result.get_user_account(1).unwrap();
+    assert_eq!(account.available, dec!(0.6));
+}
+
+#[test]
+fn accounts_should_be_written_to_in_memory_buffer() {
+    let input =
+        "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 1, 2, 2.0\ndispute, 1, 2,\n";
+    let result = service::service::read_transactions(input.as_bytes()).unwrap();
+
+    let mut output = Vec::new();
+    service::service::write_accounts(&mut output, &result).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "client,available,held,total,locked\n1,1,2,3,false\n"
+    );
+}
+
+#[test]
+fn transactions_should_be_read_from_ndjson() {
+    let input = r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "2.5"}
+{"type": "withdrawal", "client": 1, "tx": 2, "amount": 1}
+{"type": "dispute", "client": 1, "tx": 1}
+"#;
+    let result = service::service::read_ndjson(input.as_bytes()).unwrap();
+    let account = result.get_user_account(1).unwrap();
+    assert_eq!(account.available, dec!(-1));
+    assert_eq!(account.held, dec!(2.5));
+}
+
+#[test]
+fn transactions_should_be_read_from_json_array() {
+    let input = r#"[
+        {"type": "deposit", "client": 1, "tx": 1, "amount": "2.5"},
+        {"type": "deposit", "client": 2, "tx": 2, "amount": "1.0"},
+        {"type": "withdrawal", "client": 1, "tx": 3, "amount": "0.5", "idempotency_key": "a"}
+    ]"#;
+    let result = service::service::read_json(input.as_bytes()).unwrap();
+    assert_eq!(result.get_user_account(1).unwrap().available, dec!(2.0));
+    assert_eq!(result.get_user_account(2).unwrap().available, dec!(1.0));
+}
+
+#[test]
+fn accounts_should_be_written_in_selected_output_format() {
+    let input =
+        "type, client, tx, amount\ndeposit, 1, 1, 1.5\ndeposit, 1, 2, 2.0\ndispute, 1, 2,\n";
+    let result = service::service::read_transactions(input.as_bytes()).unwrap();
+
+    let write = |format: &str| {
+        let format: service::service::OutputFormat = format.parse().unwrap();
+        let mut output = Vec::new();
+        format.writer().write(&mut output, &result).unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    assert_eq!(
+        write("json"),
+        "[{\"client\":1,\"available\":\"1.5\",\"held\":\"2\",\"total\":\"3.5\",\"locked\":false}]\n"
+    );
+    assert_eq!(
+        write("ndjson"),
+        "{\"client\":1,\"available\":\"1.5\",\"held\":\"2\",\"total\":\"3.5\",\"locked\":false}\n"
+    );
+    assert_eq!(
+        write("table"),
+        "client  available  held  total  locked\n     1        1.5     2    3.5   false\n"
+    );
+    assert!("xml".parse::<service::service::OutputFormat>().is_err());
+}
+
+#[test]
+fn compressed_input_and_output_should_be_detected_by_magic_bytes_and_extension() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 1.5\n";
+    for extension in ["gz", "zst"] {
+        let mut input_path = std::env::temp_dir();
+        input_path.push(format!("transaction-test-input.csv.{}", extension));
+        let mut writer = service::compression::create_output(&input_path).unwrap();
+        std::io::Write::write_all(&mut writer, input.as_bytes()).unwrap();
+        drop(writer);
+
+        let mut output_path = std::env::temp_dir();
+        output_path.push(format!("transaction-test-output.{}", extension));
+        let output_path_string = output_path.to_str().unwrap().to_string();
+        let (result, _) =
+            service::service::read_csv(input_path.to_str().unwrap().to_string()).unwrap();
+        service::service::write_csv(output_path_string, &result).unwrap();
+
+        let mut output = String::new();
+        std::io::Read::read_to_string(
+            &mut service::compression::open_input(&output_path).unwrap(),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "client,available,held,total,locked\n1,1.5,0,1.5,false\n"
+        );
+    }
+}
+
+#[test]
+fn malformed_rows_should_be_reported_and_skipped_in_lenient_mode() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 1.5\ndeposit, x, 2, 2.0\ndeposit, 1, 3\ndeposit, 1, 4, 1.0\n";
+    let (result, report) = service::service::read_transactions_with_mode(
+        input.as_bytes(),
+        service::service::ParseMode::Lenient,
+    )
+    .unwrap();
+
+    assert_eq!(result.get_user_account(1).unwrap().available, dec!(2.5));
+    let errors: Vec<(u64, &str)> = report
+        .errors
+        .iter()
+        .map(|x| (x.line_number, x.raw_row.as_str()))
+        .collect();
+    assert_eq!(errors, vec![(3, "deposit,x,2,2.0"), (4, "")]);
+}
+
+#[test]
+fn malformed_row_should_abort_with_line_number_in_strict_mode() {
+    let input = "{\"type\": \"deposit\", \"client\": 1, \"tx\": 1, \"amount\": 1}\n\n{\"type\": \"deposit\", \"client\": -1, \"tx\": 2}\n";
+    let result = service::service::read_source_with_mode(
+        service::service::NdjsonSource::new(input.as_bytes()),
+        service::service::ParseMode::Strict,
+    );
+
+    let error = result.err().unwrap().to_string();
+    assert!(error.starts_with("line 3: "), "{}", error);
+}
+
+#[test]
+fn headerless_semicolon_csv_should_be_read_with_column_mapping() {
+    let input = "1;1;deposit;2.0\n1;2;withdrawal;0.5\n1;1;dispute\n";
+    let options = service::service::CsvOptions {
+        has_headers: false,
+        delimiter: b';',
+        column_mapping: Some(service::service::ColumnMapping {
+            transaction_type: 2,
+            client: 0,
+            tx: 1,
+            amount: Some(3),
+            expires_after: None,
+            idempotency_key: None,
+            tenant: None,
+        }),
+    };
+    let result =
+        service::service::read_transactions_with_options(input.as_bytes(), &options).unwrap();
+    let account = result.get_user_account(1).unwrap();
+    assert_eq!(account.available, dec!(-0.5));
+    assert_eq!(account.held, dec!(2.0));
+
+    let input = "deposit;1;1;2.0\ndeposit;2;2;1.0\n";
+    let options = service::service::CsvOptions {
+        has_headers: false,
+        delimiter: b';',
+        column_mapping: None,
+    };
+    let result =
+        service::service::read_transactions_with_options(input.as_bytes(), &options).unwrap();
+    assert_eq!(result.get_user_account(2).unwrap().available, dec!(1.0));
+}
+
+#[test]
+fn previous_output_should_be_loaded_as_initial_state() {
+    let mut state_path = std::env::temp_dir();
+    state_path.push("transaction-test-state.csv");
+    std::fs::write(
+        &state_path,
+        "client,available,held,total,locked\n1,1.5,0.5,2.0,false\n2,3,0,3,true\n",
+    )
+    .unwrap();
+    let state =
+        service::service::load_accounts_state(state_path.to_str().unwrap().to_string()).unwrap();
+
+    let mut input_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    input_path.push("tests/resources/testData2.csv");
+    let result = service::service::read_csv_with_state(
+        input_path.into_os_string().into_string().unwrap(),
+        Some(state),
+    )
+    .unwrap();
+
+    let account = result.get_user_account(1).unwrap();
+    assert_eq!(account.available, dec!(3.0));
+    assert_eq!(account.held, dec!(0.5));
+    let account = result.get_user_account(2).unwrap();
+    assert_eq!(account.available, dec!(3));
+    assert!(account.locked);
+
+    std::fs::write(
+        &state_path,
+        "client,available,held,total,locked\n1,1.5,0.5,3.0,false\n",
+    )
+    .unwrap();
+    assert!(
+        service::service::load_accounts_state(state_path.to_str().unwrap().to_string()).is_err()
+    );
+}
+
+#[test]
+fn shard_outputs_should_be_merged_unless_they_share_a_client() {
+    let dir = std::env::temp_dir();
+    let shard = |name: &str, content: &str| {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    };
+    let first = shard(
+        "transaction-test-shard-0.csv",
+        "client,available,held,total,locked\n2,1.5,0.5,2.0,false\n",
+    );
+    let second = shard(
+        "transaction-test-shard-1.csv",
+        "client,available,held,total,locked\n1,3,0,3,true\n3,1,0,1,false\n",
+    );
+
+    let merged = service::service::merge_accounts_states(&[first.clone(), second.clone()]).unwrap();
+    assert_eq!(merged.len(), 3);
+    assert_eq!(merged.get_user_account(2).unwrap().held, dec!(0.5));
+    assert!(merged.get_user_account(1).unwrap().locked);
+
+    let overlapping = shard(
+        "transaction-test-shard-2.csv",
+        "client,available,held,total,locked\n3,2,0,2,false\n",
+    );
+    let Err(error) = service::service::merge_accounts_states(&[first, second, overlapping]) else {
+        panic!("client 3 is in two shards");
+    };
+    assert!(error.to_string().contains("client 3"), "{}", error);
+}
+
+#[test]
+fn many_files_should_be_processed_in_order_with_global_deduplication() {
+    let paths: Vec<PathBuf> = [
+        "tests/resources/testData2.csv",
+        "tests/resources/testData4.csv",
+    ]
+    .iter()
+    .map(|x| {
+        let mut file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        file_path.push(x);
+        file_path
+    })
+    .collect();
+    let (result, statistics) = service::service::read_csv_many(&paths).unwrap();
+
+    let account = result.get_user_account(1).unwrap();
+    assert_eq!(account.available, dec!(0.5));
+    assert_eq!(account.held, dec!(1.0));
+    assert_eq!(result.get_user_account(2).unwrap().available, dec!(3.0));
+    assert_eq!(
+        statistics
+            .iter()
+            .map(|x| (x.path.clone(), x.records))
+            .collect::<Vec<_>>(),
+        vec![(paths[0].clone(), 5), (paths[1].clone(), 3)]
+    );
+}
+
+#[test]
+fn parallel_processing_should_give_same_result_as_sequential_processing() {
+    let mut input = String::from("type, client, tx, amount\n");
+    for tx in 0..10_000u32 {
+        let client = tx % 37;
+        match tx % 5 {
+            0 | 1 => input.push_str(&format!("deposit, {}, {}, 3.0\n", client, tx)),
+            2 => input.push_str(&format!("withdrawal, {}, {}, 2.5\n", client, tx)),
+            3 => input.push_str(&format!("dispute, {}, {},\n", client, tx - 3)),
+            _ => input.push_str(&format!(
+                "deposit, {}, {}, 1.0\n",
+                (client + 1) % 37,
+                tx - 4
+            )),
+        }
+    }
+    let sequential = service::service::read_transactions(input.as_bytes()).unwrap();
+    let parallel = service::parallel::read_source_parallel(
+        service::service::CsvSource::new(input.as_bytes()),
+        4,
+    )
+    .unwrap();
+
+    assert_eq!(
+        parallel.get_user_accounts().count(),
+        sequential.get_user_accounts().count()
+    );
+    // the sequence numbers count per worker
+    let without_seq = |account: &domain::domain::UserAccount| {
+        let mut account = account.clone();
+        account.transaction_log.values_mut().for_each(|x| x.seq = 0);
+        account
+    };
+    for (client, account) in sequential.get_user_accounts() {
+        assert_eq!(
+            parallel.get_user_account(*client).map(without_seq),
+            Some(without_seq(account))
+        );
+    }
+}
+
+#[test]
+fn progress_should_be_reported_every_n_records_and_at_the_end() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 1, 2, 1.0\ndeposit, 1, 3, 1.0\ndeposit, 1, 4, 1.0\ndeposit, 1, 5, 1.0\n";
+    let mut calls = Vec::new();
+    let result = service::progress::read_transactions_with_progress(
+        input.as_bytes(),
+        2,
+        |records, bytes_read| calls.push((records, bytes_read)),
+    )
+    .unwrap();
+
+    assert_eq!(result.get_user_account(1).unwrap().available, dec!(5.0));
+    assert_eq!(calls.iter().map(|x| x.0).collect::<Vec<_>>(), vec![2, 4, 5]);
+    assert_eq!(calls.last().unwrap().1, input.len() as u64);
+}
+
+#[test]
+fn processing_summary_should_count_every_row_by_its_result() {
+    let mut file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("tests/resources/testData5.csv");
+    let file_path = file_path.into_os_string().into_string().unwrap();
+    let (_, report) = service::service::read_csv_with_mode(
+        file_path.clone(),
+        service::service::ParseMode::Lenient,
+    )
+    .unwrap();
+    let summary = report.summary;
+
+    // the withdrawal without an amount is malformed, and skipped by a strict run too
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].line_number, 5);
+    assert_eq!(report.errors[0].raw_row, "withdrawal,1,3");
+    assert_eq!(
+        report.errors[0].to_string(),
+        "invalid record: withdrawal without an amount"
+    );
+    let (_, pipelined) = service::pipeline::read_csv_pipelined(
+        file_path.clone(),
+        service::service::ParseMode::Lenient,
+        1,
+    )
+    .unwrap();
+    assert_eq!(pipelined.errors[0].line_number, 5);
+    let (_, strict) = service::service::read_csv(file_path).unwrap();
+    assert_eq!(strict.malformed_rows, 1);
+    assert_eq!(
+        summary,
+        service::service::ProcessingSummary {
+            total_rows: 8,
+            applied: 3,
+            skipped_duplicates: 1,
+            skipped_insufficient_funds: 1,
+            skipped_over_limit: 0,
+            out_of_order: 0,
+            other_rejections: 1,
+            unknown_types: 1,
+            malformed_rows: 1,
+            elapsed: summary.elapsed,
+        }
+    );
+}
+
+#[test]
+fn rejected_transactions_should_be_written_with_reason() {
+    let mut file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("tests/resources/testData5.csv");
+    let (_, report) = service::service::read_csv_with_mode(
+        file_path.into_os_string().into_string().unwrap(),
+        service::service::ParseMode::Lenient,
+    )
+    .unwrap();
+
+    let mut output = Vec::new();
+    service::service::write_rejections_to(&mut output, &report).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "type,client,tx,amount,reason,original_client,original_type,original_amount\n\
+         deposit,1,1,1,duplicate_transaction,,,\n\
+         withdrawal,1,2,5,insufficient_funds,,,\n\
+         dispute,1,9,,unknown_transaction,,,\n"
+    );
+}
+
+#[test]
+fn conflicting_duplicates_should_be_written_with_the_original_transaction() {
+    let input =
+        "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 1, 1, 2.0\nwithdrawal, 2, 1, 3.0\n";
+    let mut accounts = domain::domain::Accounts::new();
+    accounts.set_policies(domain::domain::Policies {
+        duplicate: domain::domain::DuplicatePolicy::Error,
+        ..Default::default()
+    });
+    let (_, report) = service::service::read_source_into(
+        service::service::CsvSource::new(input.as_bytes()),
+        service::service::ParseMode::Strict,
+        accounts,
+    )
+    .unwrap();
+
+    let mut output = Vec::new();
+    service::service::write_rejections_to(&mut output, &report).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "type,client,tx,amount,reason,original_client,original_type,original_amount\n\
+         deposit,1,1,2,duplicate_transaction,,,\n\
+         withdrawal,2,1,3,conflicting_duplicate,1,deposit,2\n"
+    );
+    assert_eq!(report.summary.skipped_duplicates, 2);
+}
+
+#[test]
+fn filtered_output_should_only_contain_matching_accounts() {
+    let input = "type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 2, 2, 2.0
+deposit, 3, 3, 3.0
+dispute, 2, 2,
+dispute, 3, 3,
+chargeback, 3, 3,
+";
+    let result = service::service::read_transactions(input.as_bytes()).unwrap();
+    let write = |filter: &service::service::AccountFilter| {
+        let mut output = Vec::new();
+        service::service::AccountsWriter::write_filtered(
+            &service::service::CsvWriter,
+            &mut output,
+            &result,
+            filter,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    let header = "client,available,held,total,locked\n";
+    assert_eq!(
+        write(&service::service::AccountFilter {
+            clients: Some([1].into()),
+            ..Default::default()
+        }),
+        format!("{}1,1,0,1,false\n", header)
+    );
+    assert_eq!(
+        write(&service::service::AccountFilter {
+            locked_only: true,
+            ..Default::default()
+        }),
+        format!("{}3,0,0,0,true\n", header)
+    );
+    assert_eq!(
+        write(&service::service::AccountFilter {
+            held_only: true,
+            ..Default::default()
+        }),
+        format!("{}2,0,2,2,false\n", header)
+    );
+}
+
+#[test]
+fn output_total_should_be_rounded_from_unrounded_values() {
+    let mut accounts = domain::domain::Accounts::new();
+    accounts.restore_user_account(1, dec!(1.00005), dec!(2.00005), false);
+    let write = |options: &service::service::OutputOptions| {
+        let mut output = Vec::new();
+        service::service::AccountsWriter::write_with_options(
+            &service::service::CsvWriter,
+            &mut output,
+            &accounts,
+            options,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    assert_eq!(
+        write(&Default::default()),
+        "client,available,held,total,locked\n1,1.0000,2.0000,3.0001,false\n"
+    );
+    assert_eq!(
+        write(&service::service::OutputOptions {
+            rounding: service::service::RoundingConfig {
+                decimal_places: 2,
+                strategy: service::service::RoundingStrategy::ToZero,
+            },
+            ..Default::default()
+        }),
+        "client,available,held,total,locked\n1,1.00,2.00,3.00,false\n"
+    );
+}
+
+#[test]
+fn uncommitted_wal_entries_should_be_replayed_on_recover() {
+    let mut wal_path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    wal_path.push("recover.wal");
+    let _ = std::fs::remove_file(&wal_path);
+
+    let mut wal = service::wal::Wal::open(&wal_path, 2).unwrap();
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 2, 2, 5.0\n";
+    let source =
+        service::wal::WalSource::new(service::service::CsvSource::new(input.as_bytes()), &mut wal);
+    service::service::read_source(source).unwrap();
+    wal.commit().unwrap();
+
+    let input = "type, client, tx, amount\ndeposit, 1, 3, 2.0\nwithdrawal, 1, 4, 0.5\n";
+    let source =
+        service::wal::WalSource::new(service::service::CsvSource::new(input.as_bytes()), &mut wal);
+    service::service::read_source(source).unwrap();
+    drop(wal);
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&wal_path)
+        .unwrap();
+    std::io::Write::write_all(&mut file, b"{\"type\":\"deposit\",\"cli").unwrap();
+
+    let result = service::wal::recover(&wal_path).unwrap();
+    assert_eq!(result.get_user_account(1).unwrap().available, dec!(1.5));
+    assert_eq!(result.get_user_account(2), None);
+}
+
+#[test]
+fn admin_operations_should_be_replayed_in_their_place_between_the_wal_records() {
+    use service::admin::AdminOperation;
+    let mut wal_path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    wal_path.push("admin.wal");
+    let _ = std::fs::remove_file(&wal_path);
+    let mut wal = service::wal::Wal::open(&wal_path, 1).unwrap();
+    let record = |line: &str| {
+        let mut source = service::service::NdjsonSource::new(line.as_bytes());
+        service::service::TransactionSource::next_record(&mut source)
+            .unwrap()
+            .unwrap()
+    };
+    for line in [
+        r#"{"type":"deposit","client":1,"tx":1,"amount":"5"}"#,
+        r#"{"type":"deposit","client":1,"tx":2,"amount":"2"}"#,
+        r#"{"type":"dispute","client":1,"tx":2}"#,
+        r#"{"type":"chargeback","client":1,"tx":2}"#,
+    ] {
+        wal.append(&record(line)).unwrap();
+    }
+    wal.append_admin(&AdminOperation::Unlock { client: 1 })
+        .unwrap();
+    wal.append_admin(&AdminOperation::Adjust {
+        client: 1,
+        amount: dec!(-1),
+    })
+    .unwrap();
+    // applied only because the account was unlocked first
+    wal.append(&record(
+        r#"{"type":"deposit","client":1,"tx":3,"amount":"4"}"#,
+    ))
+    .unwrap();
+    wal.append(&record(
+        r#"{"type":"deposit","client":2,"tx":4,"amount":"1"}"#,
+    ))
+    .unwrap();
+    wal.append_admin(&AdminOperation::Adjust {
+        client: 2,
+        amount: dec!(1),
+    })
+    .unwrap();
+    drop(wal);
+
+    let account = service::wal::recover(&wal_path).unwrap();
+    let account = account.get_user_account(1).unwrap();
+    assert!(!account.locked);
+    assert_eq!(account.available, dec!(8));
+
+    assert_eq!(service::wal::forget_client(&wal_path, 2).unwrap(), 2);
+    assert!(service::wal::recover(&wal_path)
+        .unwrap()
+        .get_user_account(2)
+        .is_none());
+}
+
+#[test]
+fn replay_should_apply_every_wal_entry_including_committed_ones() {
+    let mut wal_path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    wal_path.push("replay.wal");
+    let _ = std::fs::remove_file(&wal_path);
+
+    let mut wal = service::wal::Wal::open(&wal_path, 2).unwrap();
+    for input in [
+        "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 2, 2, 5.0\n",
+        "type, client, tx, amount\ndeposit, 1, 3, 2.0\nwithdrawal, 1, 4, 9.0\ndispute, 2, 2,\n",
+    ] {
+        let source = service::wal::WalSource::new(
+            service::service::CsvSource::new(input.as_bytes()),
+            &mut wal,
+        );
+        service::service::read_source(source).unwrap();
+        wal.commit().unwrap();
+    }
+    drop(wal);
+
+    let (result, report) =
+        service::wal::replay_into(&wal_path, domain::domain::Accounts::new()).unwrap();
+    assert_eq!(result.get_user_account(1).unwrap().available, dec!(3.0));
+    assert_eq!(result.get_user_account(2).unwrap().held, dec!(5.0));
+    assert_eq!(report.summary.skipped_insufficient_funds, 1);
+    assert_eq!(
+        service::wal::recover(&wal_path)
+            .unwrap()
+            .get_user_accounts()
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn resume_should_skip_records_before_checkpoint() {
+    let mut dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    dir.push("checkpoint");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let input_path = dir.join("transactions.csv");
+    let checkpoint_dir = dir.join("state");
+
+    std::fs::write(
+        &input_path,
+        "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 1, 2, 1.0\ndeposit, 2, 3, 5.0\n",
+    )
+    .unwrap();
+    let (result, report) =
+        service::checkpoint::resume(&checkpoint_dir, &input_path, 2, Default::default()).unwrap();
+    assert_eq!(result.get_user_account(1).unwrap().available, dec!(3.0));
+    assert_eq!(report.summary.total_rows, 3);
+
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&input_path)
+        .unwrap();
+    std::io::Write::write_all(&mut file, b"dispute, 1, 1,\nwithdrawal, 2, 4, 1.0\n").unwrap();
+    let (result, report) =
+        service::checkpoint::resume(&checkpoint_dir, &input_path, 2, Default::default()).unwrap();
+    assert_eq!(report.summary.total_rows, 2);
+    let account = result.get_user_account(1).unwrap();
+    assert_eq!(account.available, dec!(1.0));
+    assert_eq!(account.held, dec!(2.0));
+    assert_eq!(result.get_user_account(2).unwrap().available, dec!(4.0));
+}
+
+#[test]
+fn chargeback_should_emit_chargeback_and_account_locked_events() {
+    use domain::domain::Transaction;
+    use service::{
+        events::{apply_record_with_events, AccountEventKind},
+        service::TransactionRecord,
+    };
+
+    let record = |tx, transaction| TransactionRecord {
+        client: 1,
+        tx,
+        transaction,
+        idempotency_key: None,
+    };
+    let mut accounts = domain::domain::Accounts::new();
+    let (_, events) = apply_record_with_events(
+        &mut accounts,
+        record(1, Transaction::Deposit { amount: dec!(2.0) }),
+    )
+    .unwrap();
+    assert_eq!(events[0].kind, AccountEventKind::DepositApplied);
+    assert_eq!(events[0].amount, Some(dec!(2.0)));
+
+    let (_, events) =
+        apply_record_with_events(&mut accounts, record(2, Transaction::Chargeback)).unwrap();
+    assert!(events.is_empty());
+
+    apply_record_with_events(&mut accounts, record(1, Transaction::Dispute)).unwrap();
+    let (_, events) =
+        apply_record_with_events(&mut accounts, record(1, Transaction::Chargeback)).unwrap();
+    let kinds: Vec<_> = events.iter().map(|x| x.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            AccountEventKind::ChargebackApplied,
+            AccountEventKind::AccountLocked
+        ]
+    );
+}
+
+#[test]
+fn validate_should_report_issues_with_line_numbers_without_applying_transactions() {
+    use service::validate::IssueKind;
+
+    let input = "type, client, tx, amount, note\ndeposit, 1, 1, 2.0,\ndeposit, 1, 1, 1.0,\nwithdrawal, 1, 2, -1.0,\ndeposit, 1, 3, 0.00001,\ntransfer, 1, 4, 1.0,\ndispute, 2, 1,,\nresolve, 1, 9,,\ndeposit, 1, 5,,\ndeposit, x, 6, 1.0,\n";
+    let report =
+        service::validate::validate(service::service::CsvSource::new(input.as_bytes())).unwrap();
+
+    let issues: Vec<_> = report.issues.iter().map(|x| (x.line, x.kind)).collect();
+    assert_eq!(
+        issues,
+        vec![
+            (1, IssueKind::UnknownColumn),
+            (3, IssueKind::DuplicateTx),
+            (4, IssueKind::NonPositiveAmount),
+            (5, IssueKind::ExcessPrecision),
+            (6, IssueKind::UnknownType),
+            (7, IssueKind::UnknownTxReference),
+            (8, IssueKind::UnknownTxReference),
+            (9, IssueKind::MissingField),
+            (10, IssueKind::MalformedRow),
+        ]
+    );
+    assert_eq!(report.rows, 9);
+    assert!(!report.is_valid());
+}
+
+#[test]
+fn diff_accounts_should_list_changed_added_and_removed_clients() {
+    use service::diff::{diff_accounts, Change, DiffStatus};
+
+    let before = service::service::read_transactions(
+        "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 2, 2, 1.0\ndeposit, 3, 3, 1.0\n"
+            .as_bytes(),
+    )
+    .unwrap();
+    let after = service::service::read_transactions(
+        "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndispute, 1, 1,\ndeposit, 2, 2, 1.0\ndeposit, 4, 4, 3.0\n"
+            .as_bytes(),
+    )
+    .unwrap();
+
+    let diffs = diff_accounts(&before, &after);
+    let statuses: Vec<_> = diffs.iter().map(|x| (x.client, x.status)).collect();
+    assert_eq!(
+        statuses,
+        vec![
+            (1, DiffStatus::Changed),
+            (3, DiffStatus::Removed),
+            (4, DiffStatus::Added)
+        ]
+    );
+    assert_eq!(
+        diffs[0].held,
+        Some(Change {
+            before: dec!(0),
+            after: dec!(2.0)
+        })
+    );
+    assert_eq!(diffs[0].total, None);
+    assert_eq!(diffs[0].locked, None);
+}
+
+#[test]
+fn changed_clients_should_ignore_changes_below_the_written_decimal_places() {
+    let before = service::service::read_transactions(
+        "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 2, 2, 1.0\ndeposit, 3, 3, 1.0\n"
+            .as_bytes(),
+    )
+    .unwrap();
+    let after = service::service::read_transactions(
+        "type, client, tx, amount\ndeposit, 1, 1, 2.00001\ndeposit, 2, 2, 1.1\ndeposit, 3, 3, 1.0\ndispute, 3, 3,\ndeposit, 4, 4, 3.0\n"
+            .as_bytes(),
+    )
+    .unwrap();
+
+    let mut changed: Vec<_> = service::diff::changed_clients(
+        &before,
+        &after,
+        &service::service::RoundingConfig::default(),
+    )
+    .into_iter()
+    .collect();
+    changed.sort_unstable();
+    assert_eq!(changed, vec![2, 3, 4]);
+}
+
+#[test]
+fn generated_transactions_should_be_reproducible_and_apply_without_rejections() {
+    use service::generate::{generate, GeneratorOptions};
+
+    let options = GeneratorOptions {
+        clients: 50,
+        transactions: 5_000,
+        dispute_rate: 0.02,
+        seed: 7,
+    };
+    let mut first = Vec::new();
+    generate(&mut first, &options).unwrap();
+    let mut second = Vec::new();
+    generate(&mut second, &options).unwrap();
+    assert_eq!(first, second);
+
+    let (accounts, report) = service::service::read_source_with_mode(
+        service::service::CsvSource::new(first.as_slice()),
+        service::service::ParseMode::Strict,
+    )
+    .unwrap();
+    assert_eq!(report.summary.total_rows, 5_000);
+    assert_eq!(report.rejections.len(), 0);
+    let text = String::from_utf8(first).unwrap();
+    assert!(text.contains("\ndispute,"));
+    assert!(text.contains("\nchargeback,"));
+    assert!(accounts.iter().any(|(_, x)| x.locked));
+}
+
+#[test]
+fn account_statistics_should_count_locks_and_open_disputes_and_rank_held_funds() {
+    let accounts = service::service::read_transactions(
+        "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 2, 2, 5.0\ndeposit, 3, 3, 1.0\ndeposit, 3, 4, 1.0\ndispute, 1, 1,\ndispute, 2, 2,\ndispute, 3, 3,\nchargeback, 3, 3,\n"
+            .as_bytes(),
+    )
+    .unwrap();
+
+    let statistics = service::stats::account_statistics(&accounts, 1);
+    assert_eq!(statistics.accounts, 3);
+    assert_eq!(statistics.locked_accounts, 1);
+    assert_eq!(statistics.open_disputes, 2);
+    assert_eq!(statistics.top_held.len(), 1);
+    assert_eq!(statistics.top_held[0].client, 2);
+    assert_eq!(statistics.top_held[0].held, dec!(5.0));
+}
+
+#[test]
+fn watched_directory_files_should_be_applied_and_archived() {
+    use service::watch::{process_new_files, watch, WatchOptions};
+    use std::sync::atomic::AtomicBool;
+
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("watch");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let options = WatchOptions::new(&dir);
+    std::fs::write(
+        dir.join("1.csv"),
+        "type, client, tx, amount\ndeposit, 1, 1, 2.0\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("2.csv"),
+        "type, client, tx, amount\ndeposit, 1, 2, 1.0\ndeposit, x, 3, 1.0\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a transaction file").unwrap();
+
+    let (accounts, processed) = process_new_files(
+        &options,
+        service::service::ParseMode::Strict,
+        Default::default(),
+    )
+    .unwrap();
+    assert_eq!(processed.len(), 2);
+    assert!(processed[0].result.is_ok());
+    assert!(processed[1].result.is_err());
+    assert!(dir.join("archive/1.csv").exists());
+    assert!(dir.join("failed/2.csv").exists());
+    assert!(dir.join("notes.txt").exists());
+    assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(2.0));
+
+    std::fs::write(
+        dir.join("3.csv"),
+        "type, client, tx, amount\nwithdrawal, 1, 4, 0.5\n",
+    )
+    .unwrap();
+    let mut snapshots = Vec::new();
+    let accounts = watch(
+        &options,
+        service::service::ParseMode::Strict,
+        accounts,
+        &AtomicBool::new(false),
+        |x| snapshots.push(x.get_user_account(1).unwrap().available),
+        |_| {},
+    )
+    .unwrap();
+    assert_eq!(snapshots, vec![dec!(1.5)]);
+    assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(1.5));
+    assert!(dir.join("archive/3.csv").exists());
+}
+
+#[test]
+fn capacity_hint_should_be_estimated_from_the_input_size() {
+    let hint = service::service::CapacityHint::from_input_size(24_000_000);
+    assert_eq!(hint.transactions, 1_000_000);
+    assert_eq!(hint.clients, 10_000);
+
+    let hint = service::service::CapacityHint::from_input_size(24_000_000_000);
+    assert_eq!(hint.clients, u16::MAX as usize + 1);
+    assert_eq!(
+        service::service::CapacityHint::from_input_path("-"),
+        service::service::CapacityHint::default()
+    );
+}
+
+#[test]
+fn spill_store_should_reload_spilled_logs_for_disputes() {
+    let mut input = String::from("type, client, tx, amount\n");
+    for tx in 0..2_000u32 {
+        input.push_str(&format!("deposit, {}, {}, 1.0\n", tx % 20, tx));
+    }
+    for tx in 0..20u32 {
+        input.push_str(&format!("dispute, {}, {},\n", tx, tx));
+    }
+    input.push_str("chargeback, 3, 3,\n");
+    let store = service::spill::SpillStore::new(16 * 1024).unwrap();
+    let (spilled, _) = service::service::read_source_into(
+        service::service::CsvSource::new(input.as_bytes()),
+        service::service::ParseMode::Strict,
+        domain::domain::Accounts::with_store(store),
+    )
+    .unwrap();
+    let expected = service::service::read_transactions(input.as_bytes()).unwrap();
+
+    assert!(spilled.store().spilled_accounts() > 0);
+    assert!(spilled.store().resident_bytes() <= 16 * 1024);
+    for (client, account) in expected.get_user_accounts() {
+        assert_eq!(*spilled.get_user_account(*client).unwrap(), *account);
+    }
+    assert_eq!(spilled.get_user_account(5).unwrap().held, dec!(1.0));
+    assert!(spilled.get_user_account(3).unwrap().locked);
+}
+
+#[test]
+fn pipelined_processing_should_give_same_result_as_sequential_processing() {
+    let mut input = String::from("type, client, tx, amount\n");
+    for tx in 0..5_000u32 {
+        match tx % 4 {
+            3 => input.push_str(&format!("dispute, {}, {},\n", tx % 7, tx - 3)),
+            _ => input.push_str(&format!("deposit, {}, {}, 2.0\n", tx % 7, tx)),
+        }
+    }
+    let sequential = service::service::read_transactions(input.as_bytes()).unwrap();
+    let (pipelined, report) = service::pipeline::read_pipelined(
+        || Ok(service::service::CsvSource::new(input.as_bytes())),
+        service::service::ParseMode::Strict,
+        domain::domain::Accounts::new(),
+        2,
+    )
+    .unwrap();
+
+    assert_eq!(report.summary.total_rows, 5_000);
+    for (client, account) in sequential.get_user_accounts() {
+        assert_eq!(pipelined.get_user_account(*client), Some(account));
+    }
+}
+
+#[test]
+fn pipelined_processing_should_stop_at_a_malformed_row_in_strict_mode() {
+    let mut input = String::from("type, client, tx, amount\ndeposit, 1, x, 1.0\n");
+    for tx in 0..5_000u32 {
+        input.push_str(&format!("deposit, 1, {}, 1.0\n", tx));
+    }
+    let result = service::pipeline::read_pipelined(
+        || Ok(service::service::CsvSource::new(input.as_bytes())),
+        service::service::ParseMode::Strict,
+        domain::domain::Accounts::new(),
+        1,
+    );
+
+    assert!(result.is_err());
+}
+
+struct FlushCounter {
+    bytes: Vec<u8>,
+    flushes: usize,
+}
+
+impl std::io::Write for FlushCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.bytes.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.flushes += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn write_accounts_iter_should_flush_once_at_the_end() {
+    let mut input = String::from("type, client, tx, amount\n");
+    for client in 0..1_000u32 {
+        input.push_str(&format!("deposit, {}, {}, 1.0\n", client, client));
+    }
+    let accounts = service::service::read_transactions(input.as_bytes()).unwrap();
+    let mut writer = FlushCounter {
+        bytes: Vec::new(),
+        flushes: 0,
+    };
+    let options = service::service::OutputOptions::default();
+    service::service::write_accounts_iter(
+        &mut writer,
+        service::service::output_records(&accounts, &options),
+    )
+    .unwrap();
+
+    assert_eq!(writer.flushes, 1);
+    let output = String::from_utf8(writer.bytes).unwrap();
+    assert_eq!(output.lines().count(), 1_001);
+    assert_eq!(
+        output.lines().next(),
+        Some("client,available,held,total,locked")
+    );
+}
+
+#[test]
+fn statement_should_list_transactions_with_running_balance_and_disputes() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 10.0\ndeposit, 2, 2, 3.0\nwithdrawal, 1, 3, 4.0\ndispute, 1, 1,\nwithdrawal, 1, 4, 1.0\nchargeback, 1, 1,\n";
+    let mut initial_state = domain::domain::Accounts::new();
+    initial_state.restore_user_account(1, dec!(2), dec!(0), false);
+    let clients = std::collections::HashSet::from([1]);
+    let statements = service::reporting::statements(
+        service::service::CsvSource::new(input.as_bytes()),
+        service::service::ParseMode::Strict,
+        initial_state,
+        Some(&clients),
+    )
+    .unwrap();
+
+    assert_eq!(statements.len(), 1);
+    let statement = &statements[0];
+    assert_eq!(statement.opening.available, dec!(2));
+    assert_eq!(
+        statement
+            .lines
+            .iter()
+            .map(|x| (x.tx, x.amount, x.outcome.as_str(), x.balance.available))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, Some(dec!(10)), "applied", dec!(12)),
+            (3, Some(dec!(4)), "applied", dec!(8)),
+            (1, Some(dec!(10)), "applied", dec!(-2)),
+            (4, Some(dec!(1)), "insufficient_funds", dec!(-2)),
+            (1, Some(dec!(10)), "applied", dec!(-2)),
+        ]
+    );
+    assert_eq!(
+        (statement.disputes_opened, statement.disputes_closed),
+        (1, 1)
+    );
+    assert_eq!(statement.closing.total, dec!(-2));
+    assert!(statement.closing.locked);
+    assert!(statement.to_string().contains("closing  available -2"));
+}
+
+#[test]
+fn reconciliation_should_balance_deposits_withdrawals_and_chargebacks_with_account_totals() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 10.0\ndeposit, 2, 2, 3.0\nwithdrawal, 1, 3, 4.0\ndispute, 2, 2,\nchargeback, 2, 2,\ndispute, 1, 3,\nwithdrawal, 2, 4, 1.0\n";
+    let mut initial_state = domain::domain::Accounts::new();
+    initial_state.restore_user_account(3, dec!(5), dec!(0), false);
+    let (accounts, _, reconciliation) = service::reconcile::read_source_reconciled(
+        service::service::CsvSource::new(input.as_bytes()),
+        service::service::ParseMode::Strict,
+        initial_state,
+    )
+    .unwrap();
+
+    assert!(reconciliation.is_balanced(), "{}", reconciliation);
+    assert_eq!(reconciliation.flows.opening, dec!(5));
+    assert_eq!(reconciliation.flows.deposits, dec!(13));
+    assert_eq!(reconciliation.flows.withdrawals, dec!(4));
+    assert_eq!(reconciliation.flows.chargebacks, dec!(3));
+    assert_eq!(reconciliation.flows.disputed_withdrawals, dec!(4));
+    assert_eq!(reconciliation.expected_total, dec!(15));
+    assert_eq!(reconciliation.accounts_total, dec!(15));
+
+    // an account changed behind the engine's back, or one the reconciler never saw opening, is an imbalance
+    let mut reconciler = service::reconcile::Reconciler::new(&domain::domain::Accounts::new());
+    let mut tampered = domain::domain::Accounts::new();
+    service::service::read_source_with_events(
+        service::service::CsvSource::new(input.as_bytes()),
+        service::service::ParseMode::Strict,
+        domain::domain::Accounts::new(),
+        |event| {
+            reconciler.observe(&event);
+            Ok(())
+        },
+    )
+    .unwrap();
+    for (client, account) in accounts.get_user_accounts() {
+        tampered.restore_user_account(*client, account.available, account.held, account.locked);
+    }
+    tampered.restore_user_account(1, dec!(2), dec!(4), false);
+    let reconciliation = reconciler.reconcile(&tampered);
+    assert_eq!(
+        reconciliation.imbalances,
+        vec![
+            service::reconcile::Imbalance {
+                client: 1,
+                expected: dec!(10),
+                actual: dec!(6),
+            },
+            service::reconcile::Imbalance {
+                client: 3,
+                expected: dec!(0),
+                actual: dec!(5),
+            },
+        ]
+    );
+    assert!(reconciliation
+        .to_string()
+        .contains("client 1: expected 10, actual 6, off by -4"));
+}
+
+#[test]
+fn risk_rules_should_flag_clients_without_blocking_their_transactions() {
+    let input = "type, client, tx, amount\ndeposit, 2, 3, 1.0\ndeposit, 2, 4, 1.0\ndeposit, 2, 5, 1.0\ndeposit, 2, 6, 1.0\ndispute, 2, 3,\ndeposit, 2, 7, 1.0\ndispute, 2, 4,\ndeposit, 3, 8, 1.0\ndeposit, 3, 9, 50.0\ndeposit, 1, 1, 60.0\nwithdrawal, 1, 2, 57.0\ndeposit, 1, 10, 1.0\n";
+    let rules: Vec<Box<dyn service::risk::Rule + Send>> = vec![
+        Box::new(service::risk::RapidDepositWithdrawal::default()),
+        Box::new(service::risk::DisputeRatio::default()),
+        Box::new(service::risk::AmountOutlier::new(dec!(10), 5)),
+    ];
+    let (accounts, report, risk) = service::risk::read_source_with_risk(
+        service::service::CsvSource::new(input.as_bytes()),
+        service::service::ParseMode::Strict,
+        domain::domain::Accounts::new(),
+        service::risk::RiskEngine::new(rules),
+    )
+    .unwrap();
+
+    assert_eq!(
+        risk.flags
+            .iter()
+            .map(|x| (x.client, x.tx, x.rule))
+            .collect::<Vec<_>>(),
+        vec![
+            (2, 4, "dispute_ratio"),
+            (3, 9, "amount_outlier"),
+            (1, 2, "rapid_deposit_withdrawal"),
+        ]
+    );
+    assert!(risk.frozen.is_empty());
+    assert_eq!(report.summary.applied, 12);
+    assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(4));
+}
+
+struct LargeWithdrawal;
+
+impl service::risk::Rule for LargeWithdrawal {
+    fn name(&self) -> &'static str {
+        "large_withdrawal"
+    }
+
+    fn evaluate(&mut self, event: &service::events::AccountEvent) -> Option<String> {
+        (event.kind == service::events::AccountEventKind::WithdrawalApplied
+            && event.amount > Some(dec!(10)))
+        .then(|| String::from("over 10"))
+    }
+}
+
+#[test]
+fn auto_freeze_should_lock_flagged_accounts_so_later_transactions_are_rejected() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 100.0\nwithdrawal, 1, 2, 20.0\nwithdrawal, 1, 3, 5.0\ndeposit, 2, 4, 100.0\nwithdrawal, 2, 5, 5.0\n";
+    let (accounts, report, risk) = service::risk::read_source_with_risk(
+        service::service::CsvSource::new(input.as_bytes()),
+        service::service::ParseMode::Strict,
+        domain::domain::Accounts::new(),
+        service::risk::RiskEngine::new(vec![Box::new(LargeWithdrawal)]).auto_freeze(true),
+    )
+    .unwrap();
+
+    assert_eq!(risk.frozen, vec![1]);
+    assert_eq!(
+        risk.flagged_clients().into_iter().collect::<Vec<_>>(),
+        vec![1]
+    );
+    assert!(risk
+        .to_string()
+        .contains("client 1 tx 2: large_withdrawal: over 10"));
+    let account = accounts.get_user_account(1).unwrap();
+    assert!(account.locked);
+    assert_eq!(account.available, dec!(80));
+    assert_eq!(
+        report.rejections.iter().map(|x| x.tx).collect::<Vec<_>>(),
+        vec![3]
+    );
+    assert!(!accounts.get_user_account(2).unwrap().locked);
+}
+
+#[test]
+fn aml_should_report_deposits_crossing_the_threshold_and_structuring_within_the_window() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 600\ndeposit, 1, 2, 500\ndeposit, 2, 3, 950\ndeposit, 2, 4, 960\ndeposit, 3, 5, 400\ndeposit, 4, 6, 1\ndeposit, 4, 7, 1\ndeposit, 4, 8, 1\ndeposit, 4, 9, 1\ndeposit, 4, 10, 1\ndeposit, 3, 11, 700\n";
+    let options = service::aml::AmlOptions {
+        threshold: dec!(1000),
+        window: 5,
+        ..Default::default()
+    };
+    let (_, _, activities) = service::aml::read_source_with_aml(
+        service::service::CsvSource::new(input.as_bytes()),
+        service::service::ParseMode::Strict,
+        domain::domain::Accounts::new(),
+        options,
+    )
+    .unwrap();
+
+    assert_eq!(
+        activities
+            .iter()
+            .map(|x| (x.client, x.tx, x.activity, x.window_total))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, 2, service::aml::Activity::ThresholdCrossed, dec!(1100)),
+            (2, 4, service::aml::Activity::ThresholdCrossed, dec!(1910)),
+            (2, 4, service::aml::Activity::Structuring, dec!(1910)),
+        ]
+    );
+    let mut csv = Vec::new();
+    service::aml::write_suspicious_activity_to(&mut csv, &activities).unwrap();
+    assert_eq!(
+        String::from_utf8(csv)
+            .unwrap()
+            .lines()
+            .take(2)
+            .collect::<Vec<_>>(),
+        vec![
+            "client,tx,activity,amount,window_deposits,window_total",
+            "1,2,threshold_crossed,500,2,1100"
+        ]
+    );
+}
+
+#[test]
+fn tenants_should_keep_colliding_client_and_tx_ids_apart() {
+    let input = "type, client, tx, amount, tenant\ndeposit, 1, 1, 2.0, a\ndeposit, 1, 1, 5.0, b\nwithdrawal, 1, 2, 1.0, a\ndeposit, 1, 3, 1.0,\n";
+    let (tenants, report) = service::tenants::read_source_by_tenant(
+        service::service::CsvSource::new(input.as_bytes()),
+        service::service::ParseMode::Strict,
+        service::tenants::Tenants::new(),
+        None,
+    )
+    .unwrap();
+    assert_eq!(report.summary.applied, 4);
+    assert_eq!(
+        tenants
+            .iter()
+            .map(|(tenant, x)| (tenant, x.get_user_account(1).unwrap().available))
+            .collect::<Vec<_>>(),
+        vec![("a", dec!(1)), ("b", dec!(5)), ("default", dec!(1))]
+    );
+
+    // a tenant configured for the file wins over the column
+    let (tenants, _) = service::tenants::read_source_by_tenant(
+        service::service::CsvSource::new(input.as_bytes()),
+        service::service::ParseMode::Strict,
+        tenants,
+        Some("c"),
+    )
+    .unwrap();
+    let c = tenants.get("c").unwrap().get_user_account(1).unwrap();
+    assert_eq!(c.available, dec!(2));
+
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("tenant_outputs");
+    let paths = service::tenants::write_tenant_outputs(
+        &dir,
+        &tenants,
+        &service::service::OutputFormat::Csv,
+        &Default::default(),
+    )
+    .unwrap();
+    assert_eq!(paths.len(), 4);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("b.csv")).unwrap(),
+        "client,available,held,total,locked\n1,5,0,5,false\n"
+    );
+    assert!(service::tenants::tenant_output_path(
+        &dir,
+        "../x",
+        &service::service::OutputFormat::Csv
+    )
+    .is_err());
+}
+
+#[test]
+fn totals_should_be_converted_to_the_reporting_currency() {
+    let rates = service::currency::StaticRates::from_csv(
+        "from, to, rate\nEUR, USD, 1.25\nGBP, EUR, 1.2\nJPY, USD, -1\n".as_bytes(),
+        service::service::ParseMode::Lenient,
+    )
+    .unwrap();
+    let input = "type, client, tx, amount, tenant\ndeposit, 1, 1, 10.0, eu\ndeposit, 2, 2, 4.0, eu\ndispute, 2, 2,, eu\ndeposit, 1, 1, 100.0, us\n";
+    let (tenants, _) = service::tenants::read_source_by_tenant(
+        service::service::CsvSource::new(input.as_bytes()),
+        service::service::ParseMode::Strict,
+        service::tenants::Tenants::new(),
+        None,
+    )
+    .unwrap();
+
+    let eu = service::currency::convert_totals(tenants.get("eu").unwrap(), "EUR", "USD", &rates)
+        .unwrap();
+    assert_eq!(
+        (eu.available, eu.held, eu.total),
+        (dec!(12.5), dec!(5), dec!(17.5))
+    );
+    // the inverse of a given rate
+    assert_eq!(
+        service::currency::convert(dec!(12), "EUR", "GBP", &rates).unwrap(),
+        dec!(10)
+    );
+    let all = service::currency::convert_tenant_totals(
+        &tenants,
+        |tenant| Some(String::from(if tenant == "eu" { "EUR" } else { "USD" })),
+        "USD",
+        &rates,
+    )
+    .unwrap();
+    assert_eq!(all.total, dec!(117.5));
+
+    let fixed = |from: &str, to: &str| (from == "USD" && to == "CHF").then_some(dec!(0.9));
+    assert_eq!(
+        service::currency::convert(dec!(10), "USD", "CHF", &fixed).unwrap(),
+        dec!(9)
+    );
+    assert_eq!(
+        service::currency::convert(dec!(1), "JPY", "USD", &rates)
+            .err()
+            .map(|e| e.to_string()),
+        Some(String::from("no exchange rate from JPY to USD"))
+    );
+}
+
+#[test]
+fn settlement_should_net_chargebacks_and_captured_holds_per_client_in_batches() {
+    let input = "type, client, tx, amount, expires_after\ndeposit, 1, 1, 10.0,\ndeposit, 1, 2, 5.0,\nhold, 1, 3, 2.0, 10\ncapture, 1, 3,,\ndispute, 1, 2,,\nchargeback, 1, 2,,\ndeposit, 2, 4, 8.0,\nwithdrawal, 2, 5, 3.0,\ndispute, 2, 5,,\nchargeback, 2, 5,,\ndeposit, 3, 6, 1.0,\n";
+    let accounts = service::service::read_transactions(input.as_bytes()).unwrap();
+    let options = service::settlement::SettlementOptions {
+        batch_prefix: String::from("run"),
+        batch_size: 1,
+    };
+    let lines = service::settlement::settle(&accounts, &options);
+
+    assert_eq!(
+        lines
+            .iter()
+            .map(|x| (x.batch_id.as_str(), x.client, x.amount, x.direction))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                "run-0001",
+                1,
+                dec!(7),
+                service::settlement::Direction::Debit
+            ),
+            (
+                "run-0002",
+                2,
+                dec!(3),
+                service::settlement::Direction::Credit
+            ),
+        ]
+    );
+    let mut csv = Vec::new();
+    service::settlement::write_settlement_to(&mut csv, &lines).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap().lines().take(2).collect::<Vec<_>>(),
+        vec![
+            "batch_id,tenant,client,charged_back_deposits,charged_back_withdrawals,captured_holds,amount,direction",
+            "run-0001,,1,5,0,2,7,debit"
+        ]
+    );
+}
+
+#[test]
+fn forget_client_should_erase_the_client_from_checkpoints_and_wals() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("forget");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let input_path = dir.join("transactions.csv");
+    let checkpoint_dir = dir.join("state");
+    let wal_path = dir.join("run.wal");
+    let input =
+        "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 2, 2, 1.0\ndispute, 1, 1,\n";
+    std::fs::write(&input_path, input).unwrap();
+    service::checkpoint::resume(&checkpoint_dir, &input_path, 1, Default::default()).unwrap();
+    let mut wal = service::wal::Wal::open(&wal_path, 1).unwrap();
+    service::service::read_source(service::wal::WalSource::new(
+        service::service::CsvSource::new(input.as_bytes()),
+        &mut wal,
+    ))
+    .unwrap();
+    wal.commit().unwrap();
+    drop(wal);
+
+    assert_eq!(
+        service::checkpoint::forget_client(&checkpoint_dir, 1).unwrap(),
+        Some(1)
+    );
+    assert_eq!(
+        service::checkpoint::forget_client(&checkpoint_dir, 3).unwrap(),
+        None
+    );
+    let checkpoint = service::checkpoint::Checkpoint::load(&checkpoint_dir)
+        .unwrap()
+        .unwrap();
+    let account = checkpoint.accounts.get_user_account(1).unwrap();
+    assert_eq!(account.held, dec!(2.0));
+    assert!(account.transaction_log.is_empty());
+
+    assert_eq!(service::wal::forget_client(&wal_path, 1).unwrap(), 2);
+    let replayed = service::wal::replay(&wal_path).unwrap();
+    assert!(replayed.get_user_account(1).is_none());
+    assert_eq!(replayed.get_user_account(2).unwrap().available, dec!(1.0));
+    assert!(std::fs::read_to_string(&wal_path)
+        .unwrap()
+        .ends_with("#commit\n"));
+}
+
+#[test]
+fn checkpoint_without_the_dispute_counters_should_recount_them_on_load() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("old-checkpoint");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let input_path = dir.join("transactions.csv");
+    let checkpoint_dir = dir.join("state");
+    std::fs::write(
+        &input_path,
+        "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 2, 2, 1.0\ndispute, 1, 1,\n\
+         dispute, 2, 2,\nchargeback, 2, 2,\n",
+    )
+    .unwrap();
+    service::checkpoint::resume(&checkpoint_dir, &input_path, 1, Default::default()).unwrap();
+
+    // checkpoints written before the counters existed have no such fields
+    fn strip_counters(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                map.remove("open_disputes");
+                map.remove("chargebacks");
+                map.values_mut().for_each(strip_counters);
+            }
+            serde_json::Value::Array(values) => values.iter_mut().for_each(strip_counters),
+            _ => {}
+        }
+    }
+    let path = checkpoint_dir.join("checkpoint.json");
+    let mut checkpoint: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    strip_counters(&mut checkpoint);
+    assert!(!checkpoint.to_string().contains("open_disputes"));
+    std::fs::write(&path, checkpoint.to_string()).unwrap();
+
+    let mut accounts = service::checkpoint::Checkpoint::load(&checkpoint_dir)
+        .unwrap()
+        .unwrap()
+        .accounts;
+    assert_eq!(accounts.get_user_account(1).unwrap().open_disputes(), 1);
+    assert_eq!(accounts.get_user_account(2).unwrap().chargeback_count(), 1);
+    assert_eq!(
+        accounts.add_transaction(1, 1, domain::domain::Transaction::Resolve),
+        domain::domain::TransactionOutcome::Applied
+    );
+    assert_eq!(accounts.get_user_account(1).unwrap().open_disputes(), 0);
+}
+
+struct Reversal;
+
+impl domain::domain::TransactionHandler for Reversal {
+    fn apply(
+        &self,
+        account: &mut domain::domain::UserAccount,
+        tx: u32,
+        _amount: Option<rust_decimal::Decimal>,
+    ) -> domain::domain::TransactionOutcome {
+        use domain::domain::{
+            RejectionReason, TransactionActionState, TransactionOutcome, TransactionState,
+        };
+        match account.transaction_log.remove(&tx) {
+            Some(log) if log.state == TransactionState::Resolve => {
+                if let TransactionActionState::Deposit { amount } = log.amount {
+                    account.available -= amount;
+                }
+                TransactionOutcome::Applied
+            }
+            Some(log) => {
+                account.transaction_log.insert(tx, log);
+                TransactionOutcome::Rejected(RejectionReason::InvalidTransactionState)
+            }
+            None => TransactionOutcome::Rejected(RejectionReason::UnknownTransaction),
+        }
+    }
+}
+
+#[test]
+fn unknown_types_should_be_applied_by_the_handler_registered_for_them() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 1, 2, 3.0\nreversal, 1, 1,\nreversal, 1, 9,\nbonus, 1, 3, 1.0\n";
+    let mut accounts = domain::domain::Accounts::new();
+    accounts.register_handler("reversal", Reversal);
+    let (accounts, report) = service::service::read_source_into(
+        service::service::CsvSource::new(input.as_bytes()),
+        service::service::ParseMode::Lenient,
+        accounts,
+    )
+    .unwrap();
+
+    let account = accounts.get_user_account(1).unwrap();
+    assert_eq!(account.available, dec!(3.0));
+    assert_eq!(account.transaction_log.len(), 1);
+    assert_eq!(report.summary.applied, 3);
+    assert_eq!(report.summary.unknown_types, 1);
+    assert_eq!(report.rejections.len(), 1);
+    assert_eq!(report.rejections[0].transaction_type, "reversal");
+    assert_eq!(report.rejections[0].tx, 9);
+}
+
+#[test]
+fn extended_output_should_add_the_dispute_and_chargeback_counts() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 1, 2, 2.0\ndispute, 1, 1,\ndeposit, 2, 3, 1.0\ndispute, 2, 3,\nchargeback, 2, 3,\n";
+    let accounts = service::service::read_transactions(input.as_bytes()).unwrap();
+    let options = service::service::OutputOptions {
+        filter: service::service::AccountFilter {
+            clients: Some([1].into()),
+            ..Default::default()
+        },
+        extended: true,
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    service::service::AccountsWriter::write_with_options(
+        &service::service::CsvWriter,
+        &mut output,
+        &accounts,
+        &options,
+    )
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "client,available,held,total,locked,open_disputes,chargebacks\n1,2,1,3,false,1,0\n"
+    );
+    let account = accounts.get_user_account(2).unwrap();
+    assert_eq!(
+        (account.open_disputes(), account.chargeback_count()),
+        (0, 1)
+    );
+}
+
+#[test]
+fn dormant_accounts_should_be_swept_to_the_escheatment_account() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 10.0\ndeposit, 2, 4294967295, 5.0\ndeposit, 3, 3, 1.0\ndeposit, 3, 4, 1.0\ndeposit, 3, 5, 1.0\n";
+    let (accounts, _, dormancy) = service::dormancy::read_source_with_dormancy(
+        service::service::CsvSource::new(input.as_bytes()),
+        service::service::ParseMode::Strict,
+        domain::domain::Accounts::new(),
+        3,
+        Some(9),
+    )
+    .unwrap();
+
+    assert_eq!(dormancy.dormant, vec![1, 2]);
+    assert_eq!(
+        dormancy.swept,
+        vec![
+            service::dormancy::SweptAccount {
+                client: 1,
+                amount: dec!(10),
+                withdrawal_tx: u32::MAX - 1,
+                deposit_tx: u32::MAX - 2,
+            },
+            service::dormancy::SweptAccount {
+                client: 2,
+                amount: dec!(5),
+                withdrawal_tx: u32::MAX - 3,
+                deposit_tx: u32::MAX - 4,
+            },
+        ]
+    );
+    assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(0));
+    assert_eq!(accounts.get_user_account(3).unwrap().available, dec!(3));
+    assert_eq!(accounts.get_user_account(9).unwrap().available, dec!(15));
+    assert_eq!(domain::invariants::check(&accounts), Ok(()));
+}
+
+#[test]
+fn invariant_monitor_should_stop_at_the_first_violation_in_fail_fast_mode() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 5.0\nwithdrawal, 1, 2, 5.0\ndispute, 1, 1,\nchargeback, 1, 1,\ndeposit, 2, 3, 1.0\n";
+    let read = |mut monitor: service::monitor::InvariantMonitor| {
+        let result = service::service::read_source_observed(
+            service::service::CsvSource::new(input.as_bytes()),
+            service::service::ParseMode::Strict,
+            domain::domain::Accounts::new(),
+            |accounts, event| monitor.observe(accounts, &event),
+        );
+        (result, monitor.violations())
+    };
+
+    let (result, violations) = read(service::monitor::InvariantMonitor::new(
+        service::monitor::MonitorMode::FailFast,
+    ));
+    assert_eq!(
+        result.err().map(|e| e.to_string()),
+        Some(String::from(
+            "invariant violated after tx 1: client 1 has a negative total -5"
+        ))
+    );
+    assert_eq!(violations, 1);
+    let (result, violations) = read(service::monitor::InvariantMonitor::new(
+        service::monitor::MonitorMode::Log,
+    ));
+    assert_eq!(result.unwrap().0.len(), 2);
+    assert_eq!(violations, 1);
+    let (result, violations) = read(
+        service::monitor::InvariantMonitor::new(service::monitor::MonitorMode::FailFast)
+            .allow_negative_total(true),
+    );
+    assert!(result.is_ok());
+    assert_eq!(violations, 0);
+}
+
+#[test]
+fn dry_run_should_report_the_changes_and_leave_the_accounts_as_they_are() {
+    let mut accounts = domain::domain::Accounts::new();
+    accounts.add_transaction(
+        1,
+        1,
+        domain::domain::Transaction::Deposit { amount: dec!(2) },
+    );
+    let input = "type, client, tx, amount\ndeposit, 2, 2, 1.0\nwithdrawal, 1, 3, 5.0\n";
+    let preview = service::dry_run::dry_run(
+        service::service::CsvSource::new(input.as_bytes()),
+        service::service::ParseMode::Strict,
+        &accounts,
+    )
+    .unwrap();
+
+    assert_eq!(accounts.len(), 1);
+    assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(2));
+    assert_eq!(preview.changes.len(), 1);
+    assert_eq!(preview.changes[0].client, 2);
+    assert_eq!(preview.changes[0].status, service::diff::DiffStatus::Added);
+    assert_eq!(preview.report.summary.skipped_insufficient_funds, 1);
+    assert_eq!(
+        preview.report.summary.to_string(),
+        "2 rows: 1 applied, 0 duplicates, 1 insufficient funds, 0 over limit, \
+         0 out of order, 0 other rejections, 0 unknown types, 0 malformed"
+    );
+}
+
+#[test]
+fn snapshot_should_keep_the_transaction_logs_and_registry() {
+    let input = "type, client, tx, amount, expires_after\ndeposit, 1, 1, 2.0,\ndeposit, 1, 2, 1.5,\ndispute, 1, 1,,\ndeposit, 2, 3, 2.0,\nhold, 2, 5, 1.0, 5\n";
+    let mut accounts = service::service::read_transactions(input.as_bytes()).unwrap();
+    accounts.add_transaction(
+        2,
+        4,
+        domain::domain::Transaction::Deposit { amount: dec!(3) },
+    );
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("accounts.snapshot");
+    service::snapshot::save_snapshot(&path, &accounts).unwrap();
+    let mut loaded = service::snapshot::load_snapshot(&path).unwrap();
+
+    for (client, account) in accounts.get_user_accounts() {
+        assert_eq!(loaded.get_user_account(*client), Some(account));
+    }
+    assert_eq!(loaded.last_seq(), accounts.last_seq());
+    assert_eq!(
+        loaded.add_transaction(
+            1,
+            2,
+            domain::domain::Transaction::Deposit { amount: dec!(1) }
+        ),
+        domain::domain::TransactionOutcome::Rejected(
+            domain::domain::RejectionReason::DuplicateTransaction
+        )
+    );
+    assert_eq!(
+        loaded.add_transaction(1, 1, domain::domain::Transaction::Resolve),
+        domain::domain::TransactionOutcome::Applied
+    );
+}
+
+#[test]
+fn snapshot_of_another_version_should_be_refused() {
+    let mut snapshot = Vec::new();
+    service::snapshot::write_snapshot(&mut snapshot, &domain::domain::Accounts::new()).unwrap();
+    snapshot[6] = 0xff;
+
+    assert_eq!(
+        service::snapshot::read_snapshot(snapshot.as_slice())
+            .err()
+            .map(|e| e.to_string()),
+        Some(String::from(
+            "invalid snapshot: version 255 is not supported, expected 1"
+        ))
+    );
 }
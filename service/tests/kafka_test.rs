@@ -0,0 +1,32 @@
+#![cfg(feature = "kafka")]
+
+use std::sync::atomic::AtomicBool;
+
+use domain::domain::Accounts;
+use rust_decimal_macros::dec;
+use service::kafka::{self, KafkaOptions};
+
+#[test]
+fn json_payload_should_be_decoded_into_record() {
+    let record =
+        kafka::decode_json(br#"{"type":"deposit","client":1,"tx":1,"amount":"1.5"}"#).unwrap();
+    assert_eq!(record.transaction_type, "deposit");
+    assert_eq!(record.amount, Some(dec!(1.5)));
+}
+
+#[test]
+fn stopped_consumer_should_emit_final_snapshot() {
+    let options = KafkaOptions::default();
+    let consumer = kafka::create_consumer(&options).unwrap();
+    let mut snapshots = 0;
+    kafka::consume(
+        &consumer,
+        &options,
+        kafka::decode_json,
+        Accounts::new(),
+        &AtomicBool::new(false),
+        |_| snapshots += 1,
+    )
+    .unwrap();
+    assert_eq!(snapshots, 1);
+}
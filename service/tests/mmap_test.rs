@@ -0,0 +1,46 @@
+#![cfg(feature = "mmap")]
+
+use std::{io::Write, path::PathBuf};
+
+use rust_decimal_macros::dec;
+use service::{
+    mmap::{map_file, open_input_mapped},
+    service::{read_source, CsvSource},
+};
+
+const INPUT: &str = "type, client, tx, amount\ndeposit, 1, 1, 2.0\nwithdrawal, 1, 2, 0.5\n";
+
+fn temp_path(name: &str) -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    path.push(name);
+    path
+}
+
+#[test]
+fn mapped_input_should_be_read_like_a_file() {
+    let path = temp_path("mapped.csv");
+    std::fs::write(&path, INPUT).unwrap();
+
+    let accounts = read_source(CsvSource::new(open_input_mapped(&path).unwrap())).unwrap();
+    assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(1.5));
+}
+
+#[test]
+fn mapped_gzip_input_should_be_decompressed() {
+    let path = temp_path("mapped.csv.gz");
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(INPUT.as_bytes()).unwrap();
+    std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+    let accounts = read_source(CsvSource::new(open_input_mapped(&path).unwrap())).unwrap();
+    assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(1.5));
+}
+
+#[test]
+fn empty_files_and_stdin_should_not_be_mapped() {
+    let path = temp_path("mapped-empty.csv");
+    std::fs::write(&path, "").unwrap();
+
+    assert!(map_file(&path).unwrap().is_none());
+    assert!(map_file("-").unwrap().is_none());
+}
@@ -0,0 +1,78 @@
+#![cfg(feature = "polars")]
+
+use polars::prelude::*;
+use rust_decimal_macros::dec;
+
+#[test]
+fn transactions_should_be_read_from_a_dataframe() {
+    let frame = DataFrame::new(
+        4,
+        vec![
+            Column::new(
+                "type".into(),
+                ["deposit", "deposit", "withdrawal", "dispute"],
+            ),
+            Column::new("client".into(), [1i64, 2, 1, 2]),
+            Column::new("tx".into(), [1i64, 2, 3, 2]),
+            Column::new("amount".into(), [Some(2.5f64), Some(1.0), Some(0.5), None]),
+        ],
+    )
+    .unwrap();
+
+    let (accounts, summary) = service::service::from_dataframe(&frame).unwrap();
+
+    assert_eq!(summary.applied, 4);
+    assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(2.0));
+    assert_eq!(accounts.get_user_account(2).unwrap().held, dec!(1.0));
+}
+
+#[test]
+fn transactions_without_a_required_column_should_fail() {
+    let frame = DataFrame::new(1, vec![Column::new("type".into(), ["deposit"])]).unwrap();
+
+    assert_eq!(
+        service::service::from_dataframe(&frame)
+            .err()
+            .map(|e| e.to_string()),
+        Some(String::from("invalid record: missing column client"))
+    );
+}
+
+#[test]
+fn accounts_should_be_exported_as_a_dataframe() {
+    let input =
+        "type, client, tx, amount\ndeposit, 1, 1, 2.5\ndeposit, 1, 2, 1.0\ndispute, 1, 2,\n";
+    let accounts = service::service::read_transactions(input.as_bytes()).unwrap();
+
+    let frame = service::service::to_dataframe(&accounts);
+
+    assert_eq!(frame.height(), 1);
+    assert_eq!(
+        frame
+            .get_column_names()
+            .into_iter()
+            .map(|x| x.as_str())
+            .collect::<Vec<_>>(),
+        ["client", "available", "held", "total", "locked"]
+    );
+    assert_eq!(
+        frame.column("client").unwrap().u16().unwrap().get(0),
+        Some(1)
+    );
+    let amount = |name: &str| {
+        frame
+            .column(name)
+            .unwrap()
+            .decimal()
+            .unwrap()
+            .physical()
+            .get(0)
+    };
+    assert_eq!(amount("available"), Some(25_000));
+    assert_eq!(amount("held"), Some(10_000));
+    assert_eq!(amount("total"), Some(35_000));
+    assert_eq!(
+        frame.column("locked").unwrap().bool().unwrap().get(0),
+        Some(false)
+    );
+}
@@ -0,0 +1,38 @@
+#![cfg(feature = "postgres")]
+
+use postgres::NoTls;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use service::postgres::PostgresSink;
+
+// needs a database, e.g. POSTGRES_URL="host=localhost user=postgres" cargo test --features postgres -- --ignored
+#[test]
+#[ignore]
+fn accounts_should_be_upserted_into_postgres() {
+    let mut client =
+        postgres::Client::connect(&std::env::var("POSTGRES_URL").unwrap(), NoTls).unwrap();
+    let sink = PostgresSink {
+        table: String::from("accounts_test"),
+        batch_size: 2,
+    };
+    client
+        .batch_execute("DROP TABLE IF EXISTS accounts_test")
+        .unwrap();
+
+    let input =
+        "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 2, 2, 2.0\ndeposit, 3, 3, 3.0\n";
+    let accounts = service::service::read_transactions(input.as_bytes()).unwrap();
+    sink.write(&mut client, &accounts).unwrap();
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 5.0\n";
+    let accounts = service::service::read_transactions(input.as_bytes()).unwrap();
+    sink.write(&mut client, &accounts).unwrap();
+
+    let rows = client
+        .query(
+            "SELECT client, total FROM accounts_test ORDER BY client",
+            &[],
+        )
+        .unwrap();
+    let totals: Vec<(i32, Decimal)> = rows.iter().map(|x| (x.get(0), x.get(1))).collect();
+    assert_eq!(totals, vec![(1, dec!(5)), (2, dec!(2)), (3, dec!(3))]);
+}
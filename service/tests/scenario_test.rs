@@ -0,0 +1,34 @@
+use std::{fs, path::PathBuf};
+
+use service::scenario::{self, ScenarioError};
+
+#[test]
+fn scenarios_should_produce_the_expected_accounts_and_rejections() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/scenarios");
+    let count = scenario::run_scenarios(root).unwrap_or_else(|e| panic!("{}", e));
+    assert_eq!(count, 4);
+}
+
+#[test]
+fn scenario_with_different_accounts_should_report_the_mismatch() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("scenario_mismatch");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join(scenario::INPUT),
+        "type, client, tx, amount\ndeposit, 1, 1, 1.0\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join(scenario::EXPECTED_ACCOUNTS),
+        "client,available,held,total,locked\n1,2.0,0,2.0,false\n",
+    )
+    .unwrap();
+
+    match scenario::run_scenario(&dir) {
+        Err(ScenarioError::Mismatch { file, actual, .. }) => {
+            assert_eq!(file, scenario::EXPECTED_ACCOUNTS);
+            assert!(actual.contains("\n1,1,0,1,false"));
+        }
+        x => panic!("expected a mismatch, got {:?}", x),
+    }
+}
@@ -0,0 +1,54 @@
+#![cfg(feature = "rayon")]
+
+use domain::domain::UserAccount;
+use rust_decimal_macros::dec;
+use service::service::{read_transactions, CsvSource};
+
+#[test]
+fn sharded_processing_should_give_same_result_as_sequential_processing() {
+    let mut input = String::from("type, client, tx, amount\n");
+    for tx in 0..10_000u32 {
+        let client = tx % 37;
+        match tx % 5 {
+            0 | 1 => input.push_str(&format!("deposit, {}, {}, 3.0\n", client, tx)),
+            2 => input.push_str(&format!("withdrawal, {}, {}, 2.5\n", client, tx)),
+            3 => input.push_str(&format!("dispute, {}, {},\n", client, tx - 3)),
+            _ => input.push_str(&format!(
+                "deposit, {}, {}, 1.0\n",
+                (client + 1) % 37,
+                tx - 4
+            )),
+        }
+    }
+    let sequential = read_transactions(input.as_bytes()).unwrap();
+    let sharded =
+        service::sharded::read_source_sharded(CsvSource::new(input.as_bytes()), 8).unwrap();
+
+    assert_eq!(
+        sharded.get_user_accounts().count(),
+        sequential.get_user_accounts().count()
+    );
+    // the sequence numbers count per shard
+    let without_seq = |account: &UserAccount| {
+        let mut account = account.clone();
+        account.transaction_log.values_mut().for_each(|x| x.seq = 0);
+        account
+    };
+    for (client, account) in sequential.get_user_accounts() {
+        assert_eq!(
+            sharded.get_user_account(*client).map(without_seq),
+            Some(without_seq(account))
+        );
+    }
+}
+
+#[test]
+fn sharded_processing_should_keep_the_first_of_duplicate_tx_ids_across_clients() {
+    let input =
+        "type, client, tx, amount\ndeposit, 2, 1, 5.0\ndeposit, 1, 1, 3.0\ndeposit, 1, 2, 1.0\n";
+    let accounts =
+        service::sharded::read_source_sharded(CsvSource::new(input.as_bytes()), 2).unwrap();
+
+    assert_eq!(accounts.get_user_account(2).unwrap().available, dec!(5.0));
+    assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(1.0));
+}
@@ -0,0 +1,49 @@
+#![cfg(feature = "sqlite")]
+
+use domain::domain::Transaction;
+use rust_decimal_macros::dec;
+
+#[test]
+fn accounts_should_be_reloaded_from_sqlite_with_transaction_logs() {
+    let path = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("accounts.sqlite");
+    let _ = std::fs::remove_file(&path);
+    let input = "type, client, tx, amount, expires_after\ndeposit, 1, 1, 2.0,\ndeposit, 1, 2, 3.0,\ndispute, 1, 1,,\nhold, 1, 3, 1.0, 5\ndeposit, 2, 4, 1.0,\ndispute, 2, 4,,\nchargeback, 2, 4,,\n";
+    let accounts = service::service::read_transactions(input.as_bytes()).unwrap();
+    service::sqlite::save_sqlite(&path, &accounts).unwrap();
+    service::sqlite::save_sqlite(&path, &accounts).unwrap();
+
+    let mut restored = service::sqlite::load_sqlite(&path).unwrap();
+    for (client, account) in accounts.get_user_accounts() {
+        assert_eq!(restored.get_user_account(*client), Some(account));
+    }
+    assert_eq!(
+        restored.add_transaction(1, 2, Transaction::Deposit { amount: dec!(1) }),
+        domain::domain::TransactionOutcome::Rejected(
+            domain::domain::RejectionReason::DuplicateTransaction
+        )
+    );
+    restored.add_transaction(1, 1, Transaction::Resolve);
+    let account = restored.get_user_account(1).unwrap();
+    assert_eq!(account.available, dec!(4.0));
+    assert_eq!(account.held, dec!(1.0));
+}
+
+#[test]
+fn forget_client_should_delete_the_stored_transactions_and_keep_the_account() {
+    let path = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("forget.sqlite");
+    let _ = std::fs::remove_file(&path);
+    let input =
+        "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 1, 2, 3.0\ndeposit, 2, 3, 1.0\n";
+    let accounts = service::service::read_transactions(input.as_bytes()).unwrap();
+    service::sqlite::save_sqlite(&path, &accounts).unwrap();
+
+    assert_eq!(service::sqlite::forget_client_sqlite(&path, 1).unwrap(), 2);
+    let restored = service::sqlite::load_sqlite(&path).unwrap();
+    let account = restored.get_user_account(1).unwrap();
+    assert_eq!(account.available, dec!(5.0));
+    assert!(account.transaction_log.is_empty());
+    assert_eq!(
+        restored.get_user_account(2).unwrap().transaction_log.len(),
+        1
+    );
+}
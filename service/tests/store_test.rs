@@ -0,0 +1,47 @@
+#![cfg(feature = "sled")]
+
+use domain::domain::Accounts;
+use rust_decimal_macros::dec;
+use service::{
+    service::{read_source_into, write_accounts, CsvSource, ParseMode},
+    store::SledStore,
+};
+
+#[test]
+fn sled_store_should_give_same_result_as_memory_store() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 2, 2, 3.0\nwithdrawal, 1, 3, 0.5\ndispute, 2, 2,\nchargeback, 2, 2,\ndeposit, 2, 4, 1.0\n";
+    let (accounts, _) = read_source_into(
+        CsvSource::new(input.as_bytes()),
+        ParseMode::Strict,
+        Accounts::with_store(SledStore::temporary().unwrap()),
+    )
+    .unwrap();
+    let memory = service::service::read_transactions(input.as_bytes()).unwrap();
+
+    for (client, account) in memory.get_user_accounts() {
+        assert_eq!(accounts.get_user_account(*client).as_deref(), Some(account));
+    }
+    let mut output = Vec::new();
+    write_accounts(&mut output, &accounts).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "client,available,held,total,locked\n1,1.5,0,1.5,false\n2,0,0,0,true\n"
+    );
+}
+
+#[test]
+fn sled_store_should_keep_accounts_after_reopen() {
+    let path = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("accounts.sled");
+    let _ = std::fs::remove_dir_all(&path);
+    {
+        let mut accounts = Accounts::with_store(SledStore::open(&path).unwrap());
+        accounts.add_transaction(
+            1,
+            1,
+            domain::domain::Transaction::Deposit { amount: dec!(2) },
+        );
+        accounts.store().flush().unwrap();
+    }
+    let accounts = Accounts::with_store(SledStore::open(&path).unwrap());
+    assert_eq!(accounts.get_user_account(1).unwrap().available, dec!(2));
+}
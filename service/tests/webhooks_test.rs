@@ -0,0 +1,91 @@
+#![cfg(feature = "webhooks")]
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use service::{
+    service::{read_source_with_events, CsvSource, ParseMode},
+    webhooks::{WebhookConfig, WebhookNotifier},
+};
+
+// answers the first request with a server error and every following one with 200
+fn spawn_flaky_endpoint() -> (String, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for (index, stream) in listener.incoming().enumerate() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let status = if index == 0 {
+                "500 Internal Server Error"
+            } else {
+                sender.send(String::from_utf8(body).unwrap()).unwrap();
+                "200 OK"
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                status
+            )
+            .unwrap();
+        }
+    });
+    (url, receiver)
+}
+
+#[test]
+fn chargebacks_and_locks_should_be_posted_with_retry_and_dead_lettered_on_failure() {
+    let (url, received) = spawn_flaky_endpoint();
+    let dead_letter_path =
+        std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("webhooks.dead-letter.jsonl");
+    let _ = std::fs::remove_file(&dead_letter_path);
+    let mut notifier = WebhookNotifier::new(WebhookConfig {
+        urls: vec![url, String::from("http://127.0.0.1:1/hook")],
+        max_attempts: 2,
+        initial_backoff: Duration::from_millis(1),
+        dead_letter_path: dead_letter_path.clone(),
+        ..Default::default()
+    });
+
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndispute, 1, 1,\nchargeback, 1, 1,\n";
+    read_source_with_events(
+        CsvSource::new(input.as_bytes()),
+        ParseMode::Strict,
+        Default::default(),
+        |event| notifier.notify(&event),
+    )
+    .unwrap();
+
+    let received: Vec<String> = received.try_iter().collect();
+    assert_eq!(
+        received,
+        vec![
+            r#"{"event":"chargeback_applied","client":1,"tx":1}"#,
+            r#"{"event":"account_locked","client":1,"tx":1}"#,
+        ]
+    );
+    let dead_letters = std::fs::read_to_string(&dead_letter_path).unwrap();
+    let dead_letters: Vec<&str> = dead_letters.lines().collect();
+    assert_eq!(dead_letters.len(), 2);
+    assert!(dead_letters[0].starts_with(r#"{"url":"http://127.0.0.1:1/hook","attempts":2,"#));
+    assert!(dead_letters[1].ends_with(r#""event":{"event":"account_locked","client":1,"tx":1}}"#));
+}
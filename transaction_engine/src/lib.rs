@@ -0,0 +1,256 @@
+// The engine as a single dependency: the types most callers need from `domain` and `service`,
+// and `Engine` and `process` on top of them. Everything else is still in those crates.
+use std::{
+    io::{Read, Write},
+    str::FromStr,
+};
+
+pub use domain::domain::{
+    Accounts, Decision, DisputePolicy, DuplicatePolicy, FirstTransactionPolicy, Limits,
+    LockedAccountPolicy, Middleware, OrderingPolicy, Policies, PrecisionPolicy, RegistryHasher,
+    RejectionReason, Transaction, TransactionHandler, TransactionOutcome, TransactionRegistry,
+    UserAccount,
+};
+pub use rust_decimal::{Decimal, RoundingStrategy};
+pub use service::error::ServiceError;
+pub use service::service::{
+    load_accounts_state, read_transactions, write_accounts, AccountFilter, AccountsWriter,
+    CsvSource, InputTransactionRecord, JsonSource, NdjsonSource, OutputFormat, OutputOptions,
+    ParseMode, ParseReport, ProcessingSummary, Rejection, RoundingConfig, TransactionRecord,
+    TransactionSource,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    Csv,
+    Ndjson,
+    Json,
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(InputFormat::Csv),
+            "ndjson" => Ok(InputFormat::Ndjson),
+            "json" => Ok(InputFormat::Json),
+            _ => Err(format!("unknown input format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub input: InputFormat,
+    pub mode: ParseMode,
+    pub output: OutputFormat,
+    pub output_options: OutputOptions,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            input: InputFormat::Csv,
+            mode: ParseMode::Strict,
+            output: OutputFormat::Csv,
+            output_options: OutputOptions::default(),
+        }
+    }
+}
+
+// what the accounts keep of the applied transactions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Retention {
+    // the transaction logs disputes and holds refer to
+    #[default]
+    Logs,
+    // also every operation with its outcome, see `Accounts::enable_history`
+    History,
+}
+
+// `Engine::new()` and `Accounts::new()` are the builder with every default
+#[derive(Debug, Clone, Default)]
+pub struct EngineBuilder {
+    policies: Policies,
+    rounding: RoundingConfig,
+    retention: Retention,
+    hasher: RegistryHasher,
+}
+
+impl EngineBuilder {
+    pub fn new() -> EngineBuilder {
+        EngineBuilder::default()
+    }
+
+    pub fn dispute_policy(mut self, policy: DisputePolicy) -> EngineBuilder {
+        self.policies.dispute = policy;
+        self
+    }
+
+    pub fn ordering_policy(mut self, policy: OrderingPolicy) -> EngineBuilder {
+        self.policies.ordering = policy;
+        self
+    }
+
+    pub fn duplicate_policy(mut self, policy: DuplicatePolicy) -> EngineBuilder {
+        self.policies.duplicate = policy;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> EngineBuilder {
+        self.policies.limits = limits;
+        self
+    }
+
+    // the rounding of the written accounts, see `Engine::output_options`
+    pub fn rounding(mut self, rounding: RoundingConfig) -> EngineBuilder {
+        self.rounding = rounding;
+        self
+    }
+
+    pub fn locked_account_policy(mut self, policy: LockedAccountPolicy) -> EngineBuilder {
+        self.policies.locked_account = policy;
+        self
+    }
+
+    pub fn first_transaction_policy(mut self, policy: FirstTransactionPolicy) -> EngineBuilder {
+        self.policies.first_transaction = policy;
+        self
+    }
+
+    pub fn precision_policy(mut self, policy: PrecisionPolicy) -> EngineBuilder {
+        self.policies.precision = policy;
+        self
+    }
+
+    pub fn retention(mut self, retention: Retention) -> EngineBuilder {
+        self.retention = retention;
+        self
+    }
+
+    pub fn hasher(mut self, hasher: RegistryHasher) -> EngineBuilder {
+        self.hasher = hasher;
+        self
+    }
+
+    // the rounding only applies to the outputs of an `Engine`
+    pub fn build_accounts(&self) -> Accounts {
+        let mut accounts =
+            Accounts::with_registry(TransactionRegistry::with_hasher(self.hasher.clone()));
+        accounts.set_policies(self.policies);
+        if self.retention == Retention::History {
+            accounts.enable_history();
+        }
+        accounts
+    }
+
+    pub fn build(&self) -> Engine {
+        Engine {
+            accounts: self.build_accounts(),
+            rounding: self.rounding,
+        }
+    }
+}
+
+// Accounts fed by single transactions or whole inputs, any number of times
+#[derive(Default)]
+pub struct Engine {
+    accounts: Accounts,
+    rounding: RoundingConfig,
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine::default()
+    }
+
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::new()
+    }
+
+    // e.g. the accounts of a previous run, from `load_accounts_state`
+    pub fn with_accounts(accounts: Accounts) -> Engine {
+        Engine {
+            accounts,
+            rounding: RoundingConfig::default(),
+        }
+    }
+
+    pub fn apply(&mut self, client: u16, tx: u32, transaction: Transaction) -> TransactionOutcome {
+        self.accounts.add_transaction(client, tx, transaction)
+    }
+
+    // records with an idempotency key are only applied once
+    pub fn apply_record(
+        &mut self,
+        record: TransactionRecord,
+    ) -> Result<TransactionOutcome, ServiceError> {
+        service::service::apply_record(&mut self.accounts, record)
+    }
+
+    // the rows before an error stay applied
+    pub fn read<R: Read>(
+        &mut self,
+        reader: R,
+        format: InputFormat,
+        mode: ParseMode,
+    ) -> Result<ParseReport, ServiceError> {
+        match format {
+            InputFormat::Csv => self.read_source(CsvSource::new(reader), mode),
+            InputFormat::Ndjson => self.read_source(NdjsonSource::new(reader), mode),
+            InputFormat::Json => self.read_source(JsonSource::new(reader)?, mode),
+        }
+    }
+
+    pub fn read_source<S: TransactionSource>(
+        &mut self,
+        source: S,
+        mode: ParseMode,
+    ) -> Result<ParseReport, ServiceError> {
+        service::service::read_source_onto(source, mode, &mut self.accounts)
+    }
+
+    pub fn write<W: Write>(
+        &self,
+        mut writer: W,
+        format: OutputFormat,
+        options: &OutputOptions,
+    ) -> Result<(), ServiceError> {
+        format
+            .writer()
+            .write_with_options(&mut writer, &self.accounts, options)
+    }
+
+    // every account, rounded as configured with `EngineBuilder::rounding`
+    pub fn output_options(&self) -> OutputOptions {
+        OutputOptions {
+            rounding: self.rounding,
+            ..OutputOptions::default()
+        }
+    }
+
+    pub fn accounts(&self) -> &Accounts {
+        &self.accounts
+    }
+
+    pub fn accounts_mut(&mut self) -> &mut Accounts {
+        &mut self.accounts
+    }
+
+    pub fn into_accounts(self) -> Accounts {
+        self.accounts
+    }
+}
+
+// reads every transaction of `reader` and writes the resulting accounts to `writer`
+pub fn process<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    options: &Options,
+) -> Result<ParseReport, ServiceError> {
+    let mut engine = Engine::new();
+    let report = engine.read(reader, options.input, options.mode)?;
+    engine.write(writer, options.output, &options.output_options)?;
+    Ok(report)
+}
@@ -0,0 +1,116 @@
+use rust_decimal_macros::dec;
+use transaction_engine::{
+    DisputePolicy, Engine, InputFormat, Options, OutputFormat, ParseMode, PrecisionPolicy,
+    RegistryHasher, RejectionReason, Retention, RoundingConfig, RoundingStrategy, Transaction,
+    TransactionOutcome,
+};
+
+#[test]
+fn process_should_write_the_accounts_of_the_input() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 2.0\nwithdrawal, 1, 2, 0.5\ntransfer, 1, 3, 1.0\n";
+    let mut output = Vec::new();
+    let report = transaction_engine::process(
+        input.as_bytes(),
+        &mut output,
+        &Options {
+            mode: ParseMode::Lenient,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "client,available,held,total,locked\n1,1.5,0,1.5,false\n"
+    );
+    assert_eq!(report.summary.applied, 2);
+    assert_eq!(report.summary.unknown_types, 1);
+}
+
+#[test]
+fn engine_should_keep_the_accounts_across_inputs_and_errors() {
+    let mut engine = Engine::new();
+    assert_eq!(
+        engine.apply(1, 1, Transaction::Deposit { amount: dec!(3) }),
+        TransactionOutcome::Applied
+    );
+    engine
+        .read(
+            "{\"type\":\"deposit\",\"client\":2,\"tx\":2,\"amount\":1.5}\n".as_bytes(),
+            InputFormat::Ndjson,
+            ParseMode::Strict,
+        )
+        .unwrap();
+    let result = engine.read(
+        "type, client, tx, amount\nwithdrawal, 1, 3, 1.0\ndeposit, x, 4, 1.0\n".as_bytes(),
+        InputFormat::Csv,
+        ParseMode::Strict,
+    );
+
+    assert!(result.is_err());
+    assert_eq!(
+        engine.apply(2, 2, Transaction::Deposit { amount: dec!(1) }),
+        TransactionOutcome::Rejected(RejectionReason::DuplicateTransaction)
+    );
+    let mut output = Vec::new();
+    engine
+        .write(&mut output, OutputFormat::Csv, &Default::default())
+        .unwrap();
+    let mut lines: Vec<_> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    lines.sort();
+    assert_eq!(
+        lines,
+        [
+            "1,2,0,2,false",
+            "2,1.5,0,1.5,false",
+            "client,available,held,total,locked"
+        ]
+    );
+}
+
+#[test]
+fn builder_should_configure_the_accounts_and_the_output_rounding() {
+    let mut engine = Engine::builder()
+        .dispute_policy(DisputePolicy::DepositsOnly)
+        .precision_policy(PrecisionPolicy::Round {
+            decimal_places: 2,
+            strategy: RoundingStrategy::MidpointAwayFromZero,
+        })
+        .rounding(RoundingConfig {
+            decimal_places: 1,
+            strategy: RoundingStrategy::ToZero,
+        })
+        .retention(Retention::History)
+        .hasher(RegistryHasher::sip())
+        .build();
+    engine.apply(
+        1,
+        1,
+        Transaction::Deposit {
+            amount: dec!(2.005),
+        },
+    );
+    engine.apply(1, 2, Transaction::Withdrawal { amount: dec!(1) });
+
+    assert_eq!(
+        engine.apply(1, 2, Transaction::Dispute),
+        TransactionOutcome::Rejected(RejectionReason::InvalidTransactionState)
+    );
+    assert_eq!(
+        engine.accounts().get_user_account(1).unwrap().available,
+        dec!(1.01)
+    );
+    assert_eq!(engine.accounts().history().map(|x| x.len()), Some(3));
+    let mut output = Vec::new();
+    engine
+        .write(&mut output, OutputFormat::Csv, &engine.output_options())
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "client,available,held,total,locked\n1,1.0,0,1.0,false\n"
+    );
+}
@@ -0,0 +1,24 @@
+// Bindings for running the engine in the browser. The input is the bytes of a csv file, gzipped
+// or not, so nothing here touches the file system; errors are thrown as JS `Error`s.
+use service::{
+    compression::decompress,
+    service::{read_transactions, write_accounts, CsvSource},
+    validate::validate,
+};
+use wasm_bindgen::prelude::*;
+
+// the accounts after applying every transaction, as the csv output of `process`
+#[wasm_bindgen]
+pub fn process_csv(bytes: &[u8]) -> Result<String, JsError> {
+    let accounts = read_transactions(decompress(bytes)?)?;
+    let mut output = Vec::new();
+    write_accounts(&mut output, &accounts)?;
+    Ok(String::from_utf8(output)?)
+}
+
+// the report of `validate --format json`, without applying the transactions
+#[wasm_bindgen]
+pub fn validate_csv(bytes: &[u8]) -> Result<String, JsError> {
+    let report = validate(CsvSource::new(decompress(bytes)?))?;
+    Ok(serde_json::to_string(&report)?)
+}
@@ -0,0 +1,19 @@
+#[test]
+fn process_csv_should_return_the_accounts_csv() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 2.0\nwithdrawal, 1, 2, 0.5\n";
+
+    assert_eq!(
+        wasm::process_csv(input.as_bytes()).unwrap(),
+        "client,available,held,total,locked\n1,1.5,0,1.5,false\n"
+    );
+}
+
+#[test]
+fn validate_csv_should_return_the_issues_as_json() {
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndispute, 1, 7,\n";
+    let report: serde_json::Value =
+        serde_json::from_str(&wasm::validate_csv(input.as_bytes()).unwrap()).unwrap();
+
+    assert_eq!(report["rows"], 2);
+    assert_eq!(report["issues"][0]["kind"], "unknown_tx_reference");
+}